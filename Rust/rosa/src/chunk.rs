@@ -0,0 +1,113 @@
+//! The chunk-based layout shared by multi-pack indexes and commit-graphs:
+//! a fixed header, a table of 4-byte chunk ids and 8-byte offsets ended by
+//! a zero id, the chunks themselves and a trailing checksum.
+
+use crate::error::Result;
+use crate::object::{HashAlgorithm, ObjectId};
+
+/// Size of one row of the chunk table.
+pub const CHUNK_ROW_LEN: usize = 12;
+
+/// Size of an object id fan-out table: 256 cumulative counts.
+pub const FANOUT_LEN: usize = 256 * 4;
+
+/// Where each chunk of a file lies.
+#[derive(Clone, Debug)]
+pub struct ChunkTable {
+    /// Id, start and length of each chunk.
+    chunks: Vec<(u32, usize, usize)>,
+}
+
+impl ChunkTable {
+    /// Reads the table of `count` chunks starting at `table_at`, checking
+    /// that every chunk lies between the table and the trailing checksum.
+    pub fn parse(
+        data: &[u8],
+        table_at: usize,
+        count: usize,
+        hash: HashAlgorithm,
+    ) -> std::result::Result<Self, String> {
+        let table_end = table_at + (count + 1) * CHUNK_ROW_LEN;
+        let body_end = data
+            .len()
+            .checked_sub(hash.raw_len())
+            .filter(|&end| end >= table_end)
+            .ok_or("chunk table is truncated")?;
+        let mut rows = Vec::with_capacity(count + 1);
+        for n in 0..=count {
+            let row = table_at + n * CHUNK_ROW_LEN;
+            let id = u32::from_be_bytes(data[row..row + 4].try_into().expect("4-byte slice"));
+            let offset =
+                u64::from_be_bytes(data[row + 4..row + 12].try_into().expect("8-byte slice"));
+            let offset = usize::try_from(offset).unwrap_or(usize::MAX);
+            if offset < table_end || offset > body_end {
+                return Err("chunk offset out of bounds".into());
+            }
+            rows.push((id, offset));
+        }
+        if rows[count].0 != 0 {
+            return Err("chunk table is not terminated".into());
+        }
+        // Each chunk runs up to where the next one (or the trailer) starts.
+        let chunks = rows
+            .windows(2)
+            .map(|w| (w[0].0, w[0].1, w[1].1.saturating_sub(w[0].1)))
+            .collect();
+        Ok(ChunkTable { chunks })
+    }
+
+    /// Start and length of the chunk `id`, if the file has one.
+    pub fn get(&self, id: u32) -> Option<(usize, usize)> {
+        self.chunks
+            .iter()
+            .find(|(chunk, _, _)| *chunk == id)
+            .map(|&(_, start, len)| (start, len))
+    }
+
+    /// Like [`get`](Self::get), for chunks the file cannot do without.
+    pub fn require(&self, id: u32) -> std::result::Result<(usize, usize), String> {
+        self.get(id).ok_or_else(|| {
+            let name = String::from_utf8_lossy(&id.to_be_bytes()).into_owned();
+            format!("missing {name} chunk")
+        })
+    }
+}
+
+/// The fan-out table for `ids`, which must be sorted: entry `b` counts the
+/// ids whose first byte is at most `b`.
+pub fn fanout<'a>(ids: impl IntoIterator<Item = &'a ObjectId>) -> Vec<u8> {
+    let mut counts = [0u32; 256];
+    for id in ids {
+        counts[usize::from(id.as_bytes()[0])] += 1;
+    }
+    let mut out = Vec::with_capacity(FANOUT_LEN);
+    let mut total = 0u32;
+    for count in counts {
+        total += count;
+        out.extend_from_slice(&total.to_be_bytes());
+    }
+    out
+}
+
+/// Lays out `header`, the chunk table, `chunks` and the checksum of it all.
+pub fn assemble(header: &[u8], chunks: &[(u32, Vec<u8>)], hash: HashAlgorithm) -> Result<Vec<u8>> {
+    let body_len: usize = chunks.iter().map(|(_, c)| c.len()).sum();
+    let mut out = Vec::with_capacity(
+        header.len() + (chunks.len() + 1) * CHUNK_ROW_LEN + body_len + hash.raw_len(),
+    );
+    out.extend_from_slice(header);
+    let mut offset = (header.len() + (chunks.len() + 1) * CHUNK_ROW_LEN) as u64;
+    for (id, chunk) in chunks {
+        out.extend_from_slice(&id.to_be_bytes());
+        out.extend_from_slice(&offset.to_be_bytes());
+        offset += chunk.len() as u64;
+    }
+    out.extend_from_slice(&0u32.to_be_bytes());
+    out.extend_from_slice(&offset.to_be_bytes());
+    for (_, chunk) in chunks {
+        out.extend_from_slice(chunk);
+    }
+    let checksum = hash.digest(&out)?;
+    out.extend_from_slice(&checksum);
+    Ok(out)
+}
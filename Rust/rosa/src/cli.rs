@@ -0,0 +1,98 @@
+//! Command line entry point.
+
+use std::ffi::OsString;
+use std::io;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+use crate::commands::*;
+use crate::error::{Error, Result};
+
+/// A Git implementation for learning
+#[derive(Debug, Parser)]
+#[command(name = "mygit")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    Add(add::Args),
+    CatFile(cat_file::Args),
+    Checkout(checkout::Args),
+    CommitGraph(commit_graph::Args),
+    Fsck(fsck::Args),
+    Gc(gc::Args),
+    HashObject(hash_object::Args),
+    Init(init::Args),
+    Log(log::Args),
+    LsTree(ls_tree::Args),
+    MergeBase(merge_base::Args),
+    MultiPackIndex(multi_pack_index::Args),
+    PackObjects(pack_objects::Args),
+    RevList(rev_list::Args),
+    RevParse(rev_parse::Args),
+    ShowRef(show_ref::Args),
+    Tag(tag::Args),
+}
+
+fn dispatch(command: Command) -> Result<()> {
+    match command {
+        Command::Add(args) => add::run(args),
+        Command::CatFile(args) => cat_file::run(args),
+        Command::Checkout(args) => checkout::run(args),
+        Command::CommitGraph(args) => commit_graph::run(args),
+        Command::Fsck(args) => fsck::run(args),
+        Command::Gc(args) => gc::run(args),
+        Command::HashObject(args) => hash_object::run(args),
+        Command::Init(args) => init::run(args),
+        Command::Log(args) => log::run(args),
+        Command::LsTree(args) => ls_tree::run(args),
+        Command::MergeBase(args) => merge_base::run(args),
+        Command::MultiPackIndex(args) => multi_pack_index::run(args),
+        Command::PackObjects(args) => pack_objects::run(args),
+        Command::RevList(args) => rev_list::run(args),
+        Command::RevParse(args) => rev_parse::run(args),
+        Command::ShowRef(args) => show_ref::run(args),
+        Command::Tag(args) => tag::run(args),
+    }
+}
+
+pub fn main() -> ExitCode {
+    let cli = Cli::parse_from(max_count_shorthand(std::env::args_os().collect()));
+    match dispatch(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        // The reader went away (e.g. `mygit log | head`); nothing to report.
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Turns the `-<n>` of `log` and `rev-list` into `--max-count=<n>`, which
+/// clap would otherwise take for an unknown flag. Paths after `--` are left
+/// alone.
+fn max_count_shorthand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let walks = args
+        .get(1)
+        .is_some_and(|command| command == "log" || command == "rev-list");
+    if !walks {
+        return args;
+    }
+    for arg in args.iter_mut().skip(2) {
+        if arg == "--" {
+            break;
+        }
+        let count = arg.to_str().and_then(|arg| arg.strip_prefix('-'));
+        if let Some(count) =
+            count.filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        {
+            *arg = format!("--max-count={count}").into();
+        }
+    }
+    args
+}
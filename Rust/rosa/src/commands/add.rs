@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::ignore::IgnoreRules;
+use crate::index::{Index, IndexEntry};
+use crate::object::{ObjectId, ObjectKind};
+use crate::path;
+use crate::repository::Repository;
+
+/// Add file contents to the index
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Files to add; directories are added recursively
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let mut index = Index::read(&repo)?;
+    let rules = IgnoreRules::read(&repo)?;
+
+    // Worktree-relative name to absolute path of everything to stage.
+    let mut to_add = BTreeMap::new();
+    for path in &args.paths {
+        let absolute = path::absolute(path)?;
+        let meta = fs::symlink_metadata(&absolute)
+            .map_err(|_| Error::Usage(format!("path does not exist: {}", path.display())))?;
+        let relative = path::relative(repo.worktree(), &absolute)
+            .ok_or_else(|| Error::Usage(format!("path outside worktree: {}", path.display())))?;
+        if absolute.starts_with(repo.gitdir()) {
+            continue;
+        }
+        if meta.is_dir() {
+            collect(&repo, &rules, &absolute, &mut to_add)?;
+        } else if !rules.is_ignored(&relative, false) {
+            to_add.insert(relative, absolute);
+        }
+    }
+
+    let existing: HashMap<Vec<u8>, IndexEntry> = index
+        .entries
+        .drain(..)
+        .map(|e| (e.name.clone(), e))
+        .collect();
+    let mut entries: Vec<IndexEntry> = existing
+        .values()
+        .filter(|e| !to_add.contains_key(&e.name))
+        .cloned()
+        .collect();
+
+    for (name, path) in to_add {
+        match stage(&repo, &path) {
+            Ok((id, meta)) => {
+                if let Some(old) = existing.get(&name).filter(|old| old.id == id) {
+                    entries.push(old.clone());
+                    continue;
+                }
+                println!("add '{}'", path::quote(&name));
+                entries.push(IndexEntry::from_metadata(name, id, &meta));
+            }
+            Err(e) => eprintln!("warning: could not add {}: {e}", path::quote(&name)),
+        }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    index.entries = entries;
+    index.write(&repo)
+}
+
+/// Stores the content of a worktree file as a blob, streaming it so that
+/// files of any size can be added.
+fn stage(repo: &Repository, path: &Path) -> Result<(ObjectId, fs::Metadata)> {
+    let meta = fs::symlink_metadata(path)?;
+    let id = if meta.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        let target = path::from_os_str(target.as_os_str()).ok_or_else(|| {
+            Error::Usage(format!("symlink target is not Unicode: {}", path.display()))
+        })?;
+        repo.odb().write_raw(ObjectKind::Blob, &target)?
+    } else {
+        let file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        repo.odb().write_stream(ObjectKind::Blob, size, file)?
+    };
+    Ok((id, meta))
+}
+
+/// Gathers the files below `dir`, leaving out `.git` and ignored paths.
+fn collect(
+    repo: &Repository,
+    rules: &IgnoreRules,
+    dir: &Path,
+    out: &mut BTreeMap<Vec<u8>, PathBuf>,
+) -> Result<()> {
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path == repo.gitdir() {
+                continue;
+            }
+            let Some(relative) = path::relative(repo.worktree(), &path) else {
+                continue;
+            };
+            let file_type = fs::symlink_metadata(&path)?.file_type();
+            if rules.is_ignored(&relative, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() || file_type.is_symlink() {
+                out.insert(relative, path);
+            }
+        }
+    }
+    Ok(())
+}
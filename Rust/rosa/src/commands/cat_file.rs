@@ -0,0 +1,289 @@
+use std::io::{self, BufRead, BufWriter, Write};
+
+use crate::commands::find_object;
+use crate::error::{Error, Result};
+use crate::object::{ObjectId, ObjectKind};
+use crate::path;
+use crate::repository::Repository;
+
+/// What `--batch` and `--batch-check` print when given no format.
+const DEFAULT_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+
+/// Provide content or type and size information for repository objects
+///
+/// With `<type> <object>`, prints the raw content of the object, peeled
+/// to that type. The `--batch` modes read object names from standard
+/// input, one per line, and answer each until end of input; their output
+/// is laid out by `%(objectname)`, `%(objecttype)`, `%(objectsize)` and
+/// `%(rest)` (what follows the name on the input line).
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Show the object type
+    #[arg(short = 't', group = "mode")]
+    show_type: bool,
+    /// Show the object size
+    #[arg(short = 's', group = "mode")]
+    show_size: bool,
+    /// Exit with zero status if the object exists, silently
+    #[arg(short = 'e', group = "mode")]
+    exists: bool,
+    /// Pretty-print the object's content
+    #[arg(short = 'p', group = "mode")]
+    pretty: bool,
+    /// Print information and content of each object named on stdin
+    #[arg(long, value_name = "format", num_args = 0..=1, require_equals = true,
+          default_missing_value = DEFAULT_FORMAT, group = "mode")]
+    batch: Option<String>,
+    /// Print information about each object named on stdin
+    #[arg(long, value_name = "format", num_args = 0..=1, require_equals = true,
+          default_missing_value = DEFAULT_FORMAT, group = "mode")]
+    batch_check: Option<String>,
+    /// Read `contents <object>`, `info <object>` and `flush` commands from
+    /// stdin
+    #[arg(long, value_name = "format", num_args = 0..=1, require_equals = true,
+          default_missing_value = DEFAULT_FORMAT, group = "mode")]
+    batch_command: Option<String>,
+    /// Only write batch output when stdin ends or on `flush`, instead of
+    /// after every object
+    #[arg(long)]
+    buffer: bool,
+    /// `<type> <object>` without an option, `<object>` with one
+    #[arg(value_name = "object", num_args = 0..=2)]
+    names: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+
+    let batch = match (&args.batch, &args.batch_check, &args.batch_command) {
+        (Some(format), _, _) => Some((BatchMode::Contents, format)),
+        (_, Some(format), _) => Some((BatchMode::Info, format)),
+        (_, _, Some(format)) => Some((BatchMode::Commands, format)),
+        _ => None,
+    };
+    if let Some((mode, format)) = batch {
+        if !args.names.is_empty() {
+            return Err(Error::Usage("batch modes take no arguments".into()));
+        }
+        let mut batch = Batch {
+            repo: &repo,
+            format: Format::parse(format)?,
+            buffer: args.buffer,
+            out: BufWriter::new(io::stdout().lock()),
+        };
+        return batch.run(mode);
+    }
+    if args.buffer {
+        return Err(Error::Usage("--buffer is only for batch modes".into()));
+    }
+
+    let single = args.show_type || args.show_size || args.exists || args.pretty;
+    let (kind, name) = match (single, args.names.as_slice()) {
+        (true, [name]) => (None, name),
+        (false, [kind, name]) => (Some(kind.parse::<ObjectKind>()?), name),
+        _ => {
+            return Err(Error::Usage(
+                "expected <type> <object>, or one of -t, -s, -e and -p with <object>".into(),
+            ))
+        }
+    };
+
+    if args.exists {
+        match find_object(&repo, name, None, false) {
+            Ok(id) if repo.odb().contains(&id) => return Ok(()),
+            Ok(_) | Err(Error::NoSuchRef(_) | Error::NoSuchPath { .. }) => std::process::exit(1),
+            Err(e) => return Err(e),
+        }
+    }
+    let id = find_object(&repo, name, kind, true)?;
+    let mut out = io::stdout().lock();
+    if args.show_type || args.show_size {
+        let (kind, size) = repo.odb().read_header(&id)?;
+        match args.show_type {
+            true => writeln!(out, "{kind}")?,
+            false => writeln!(out, "{size}")?,
+        }
+    } else if args.pretty && repo.odb().read_header(&id)?.0 == ObjectKind::Tree {
+        pretty_tree(&repo, id, &mut out)?;
+    } else {
+        // Everything but trees pretty-prints as itself.
+        io::copy(&mut repo.odb().open_stream(&id)?, &mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Lists a tree as `ls-tree` does.
+fn pretty_tree(repo: &Repository, id: ObjectId, out: &mut impl Write) -> Result<()> {
+    let tree = repo.odb().read(&id)?.into_tree(id)?;
+    for entry in &tree.entries {
+        let kind = entry.kind().ok_or_else(|| {
+            Error::parse("tree", format!("weird tree leaf mode {:o}", entry.mode))
+        })?;
+        writeln!(
+            out,
+            "{:06o} {kind} {}\t{}",
+            entry.mode,
+            entry.id,
+            path::quote(&entry.name)
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BatchMode {
+    /// `--batch`: the formatted line, then the content.
+    Contents,
+    /// `--batch-check`: the formatted line alone.
+    Info,
+    /// `--batch-command`: either, as each input line asks.
+    Commands,
+}
+
+/// A parsed `--batch` format.
+#[derive(Clone, Debug)]
+struct Format {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Name,
+    Type,
+    Size,
+    Rest,
+}
+
+impl Format {
+    fn parse(format: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = format;
+        while let Some(percent) = rest.find('%') {
+            literal.push_str(&rest[..percent]);
+            rest = &rest[percent + 1..];
+            if let Some(after) = rest.strip_prefix('%') {
+                literal.push('%');
+                rest = after;
+                continue;
+            }
+            let Some(atom) = rest.strip_prefix('(').and_then(|atom| atom.split_once(')')) else {
+                // Anything else is kept as it is, as in git.
+                literal.push('%');
+                continue;
+            };
+            let part = match atom.0 {
+                "objectname" => Part::Name,
+                "objecttype" => Part::Type,
+                "objectsize" => Part::Size,
+                "rest" => Part::Rest,
+                other => return Err(Error::Usage(format!("unknown format element: {other}"))),
+            };
+            parts.push(Part::Literal(std::mem::take(&mut literal)));
+            parts.push(part);
+            rest = atom.1;
+        }
+        literal.push_str(rest);
+        parts.push(Part::Literal(literal));
+        Ok(Format { parts })
+    }
+
+    /// Whether input lines are a name followed by text for `%(rest)`,
+    /// rather than a name alone.
+    fn splits_input(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, Part::Rest))
+    }
+
+    fn expand(&self, id: &ObjectId, kind: ObjectKind, size: u64, rest: &str) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Name => out.push_str(&id.to_hex()),
+                Part::Type => out.push_str(kind.as_str()),
+                Part::Size => out.push_str(&size.to_string()),
+                Part::Rest => out.push_str(rest),
+            }
+        }
+        out
+    }
+}
+
+struct Batch<'r, W: Write> {
+    repo: &'r Repository,
+    format: Format,
+    buffer: bool,
+    out: W,
+}
+
+impl<W: Write> Batch<'_, W> {
+    fn run(&mut self, mode: BatchMode) -> Result<()> {
+        for line in io::stdin().lock().split(b'\n') {
+            let line = line?;
+            let line = String::from_utf8_lossy(&line);
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            match mode {
+                BatchMode::Contents | BatchMode::Info => {
+                    self.object(line, mode == BatchMode::Contents)?;
+                }
+                BatchMode::Commands => self.command(line)?,
+            }
+            if !self.buffer && mode != BatchMode::Commands {
+                self.out.flush()?;
+            }
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn command(&mut self, line: &str) -> Result<()> {
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "contents" | "info" if !argument.is_empty() => {
+                self.object(argument, command == "contents")?;
+                if !self.buffer {
+                    self.out.flush()?;
+                }
+            }
+            "flush" if argument.is_empty() => match self.buffer {
+                true => self.out.flush()?,
+                false => return Err(Error::Usage("flush is only for --buffer mode".into())),
+            },
+            "" => return Err(Error::Usage("empty command in input".into())),
+            _ => return Err(Error::Usage(format!("unknown command: '{line}'"))),
+        }
+        Ok(())
+    }
+
+    /// Answers for the object named at the start of `input`.
+    fn object(&mut self, input: &str, contents: bool) -> Result<()> {
+        let (name, rest) = match self.format.splits_input() {
+            true => {
+                let (name, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+                (name, rest.trim_start())
+            }
+            false => (input, ""),
+        };
+        let id = match find_object(self.repo, name, None, false) {
+            Ok(id) => id,
+            Err(Error::NoSuchRef(_) | Error::NoSuchPath { .. }) => {
+                return Ok(writeln!(self.out, "{name} missing")?)
+            }
+            Err(Error::AmbiguousId { .. }) => return Ok(writeln!(self.out, "{name} ambiguous")?),
+            Err(e) => return Err(e),
+        };
+        let (kind, size) = match self.repo.odb().read_header(&id) {
+            Ok(header) => header,
+            Err(Error::ObjectNotFound(_)) => return Ok(writeln!(self.out, "{name} missing")?),
+            Err(e) => return Err(e),
+        };
+        writeln!(self.out, "{}", self.format.expand(&id, kind, size, rest))?;
+        if contents {
+            io::copy(&mut self.repo.odb().open_stream(&id)?, &mut self.out)?;
+            writeln!(self.out)?;
+        }
+        Ok(())
+    }
+}
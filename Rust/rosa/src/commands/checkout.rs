@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::commands::find_object;
+use crate::error::{Error, Result};
+use crate::fsck::{self, DotgitProtection};
+use crate::object::{ObjectId, ObjectKind, MODE_BLOB_EXECUTABLE, MODE_SYMLINK};
+use crate::path;
+use crate::repository::Repository;
+
+/// Checkout a commit inside of a directory
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// The commit or tree to checkout
+    commit: String,
+    /// The EMPTY directory to checkout on
+    path: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let id = find_object(&repo, &args.commit, Some(ObjectKind::Tree), true)?;
+
+    if args.path.exists() {
+        if !args.path.is_dir() {
+            return Err(Error::Usage(format!(
+                "not a directory {}",
+                args.path.display()
+            )));
+        }
+        if fs::read_dir(&args.path)?.next().is_some() {
+            return Err(Error::NotEmpty(args.path));
+        }
+    } else {
+        fs::create_dir_all(&args.path)?;
+    }
+
+    tree_checkout(&repo, id, &args.path)
+}
+
+/// Writes the contents of a tree below `dest`, directories first.
+///
+/// Trees may come from anyone, so names that could land outside `dest` or
+/// in a repository are refused, and nothing already on disk is written
+/// through: a symlink from the tree itself cannot redirect later entries.
+fn tree_checkout(repo: &Repository, tree: ObjectId, dest: &Path) -> Result<()> {
+    let protection = DotgitProtection::from_config(repo.config())?;
+    let symlinks = repo.config().get_bool("core.symlinks")?.unwrap_or(true);
+    let mut pending = vec![(tree, dest.to_path_buf())];
+    while let Some((id, dir)) = pending.pop() {
+        let tree = repo.odb().read(&id)?.into_tree(id)?;
+        for entry in tree.entries {
+            if !fsck::is_safe_name(&entry.name, protection) {
+                return Err(Error::MalformedObject {
+                    id,
+                    reason: format!("invalid path {}", path::quote(&entry.name)),
+                });
+            }
+            let path = dir.join(path::to_os_str(&entry.name)?);
+            // Creating rather than opening fails on anything already there,
+            // symlinks included, instead of following it.
+            let exists = |e: io::Error| match e.kind() {
+                io::ErrorKind::AlreadyExists => Error::Usage(format!(
+                    "refusing to overwrite {}, checked out earlier",
+                    path.display()
+                )),
+                _ => e.into(),
+            };
+            match entry.kind() {
+                Some(ObjectKind::Tree) => {
+                    fs::create_dir(&path).map_err(exists)?;
+                    pending.push((entry.id, path));
+                }
+                Some(ObjectKind::Blob) => {
+                    // Inflated straight to disk, so blobs of any size fit.
+                    let mut blob = repo.odb().open_stream(&entry.id)?;
+                    if blob.kind() != ObjectKind::Blob {
+                        return Err(Error::UnexpectedObjectType {
+                            id: entry.id,
+                            expected: ObjectKind::Blob.as_str(),
+                            actual: blob.kind().as_str(),
+                        });
+                    }
+                    if entry.mode == MODE_SYMLINK && symlinks {
+                        let mut target = Vec::new();
+                        blob.read_to_end(&mut target)?;
+                        create_symlink(&target, &path).map_err(exists)?;
+                        continue;
+                    }
+                    let file =
+                        new_file(&path, entry.mode == MODE_BLOB_EXECUTABLE).map_err(exists)?;
+                    let mut file = io::BufWriter::new(file);
+                    io::copy(&mut blob, &mut file)?;
+                    file.flush()?;
+                }
+                // Submodules have nothing to check out.
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Creates a file that must not exist yet, executable if asked. As in git,
+/// executable files get every execute bit the umask lets through.
+#[cfg(unix)]
+fn new_file(path: &Path, executable: bool) -> io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(if executable { 0o777 } else { 0o666 })
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn new_file(path: &Path, _executable: bool) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &[u8], path: &Path) -> io::Result<()> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    std::os::unix::fs::symlink(OsStr::from_bytes(target), path)
+}
+
+/// Without symlinks the target is written as a plain file, as git does.
+#[cfg(not(unix))]
+fn create_symlink(target: &[u8], path: &Path) -> io::Result<()> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?
+        .write_all(target)
+}
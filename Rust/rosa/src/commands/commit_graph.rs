@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+
+use crate::commands::find_object;
+use crate::commit_graph::{self, CommitGraph, Split, WriteOptions};
+use crate::error::{Error, Result};
+use crate::object::{ObjectId, ObjectKind};
+use crate::repository::Repository;
+
+/// Write and verify commit-graph files
+///
+/// The commit-graph records the parents, dates and generation numbers of
+/// commits so that history walks need not read the commits themselves.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Action {
+    /// Write a commit-graph of the commits in packs, or of those given
+    Write {
+        /// Start from every ref instead of the packed commits
+        #[arg(long, conflicts_with = "stdin_commits")]
+        reachable: bool,
+        /// Start from the commits listed on standard input
+        #[arg(long)]
+        stdin_commits: bool,
+        /// Add a layer to a split graph instead of writing a single file;
+        /// `no-merge` never merges layers, `replace` merges them all
+        #[arg(long, value_name = "strategy", num_args = 0..=1, require_equals = true,
+              default_missing_value = "merge", value_parser = ["merge", "no-merge", "replace"])]
+        split: Option<String>,
+        /// Merge a layer into the new one when it has at most this many
+        /// times as many commits
+        #[arg(long, value_name = "n", default_value_t = WriteOptions::default().size_multiple)]
+        size_multiple: usize,
+        /// Keep merging layers while the new one has more commits than this
+        #[arg(long, value_name = "n")]
+        max_commits: Option<usize>,
+        /// Write changed-path Bloom filters, which speed up `log -- <path>`
+        #[arg(long, overrides_with = "no_changed_paths")]
+        changed_paths: bool,
+        /// Do not write changed-path Bloom filters, even if the existing
+        /// graph has them
+        #[arg(long, overrides_with = "changed_paths")]
+        no_changed_paths: bool,
+        /// Compute at most this many new Bloom filters, leaving the rest to
+        /// later writes (default: commitGraph.maxNewFilters, or no limit)
+        #[arg(long, value_name = "n")]
+        max_new_filters: Option<usize>,
+    },
+    /// Check the commit-graph against the commits it describes
+    Verify,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let odb = repo.odb();
+
+    match args.action {
+        Action::Write {
+            reachable,
+            stdin_commits,
+            split,
+            size_multiple,
+            max_commits,
+            changed_paths,
+            no_changed_paths,
+            max_new_filters,
+        } => {
+            let tips = if reachable {
+                commit_graph::ref_tips(&repo)?
+            } else if stdin_commits {
+                let mut tips = Vec::new();
+                for line in io::stdin().lock().lines() {
+                    let line = line?;
+                    let name = line.trim();
+                    if !name.is_empty() {
+                        tips.push(find_object(&repo, name, Some(ObjectKind::Commit), true)?);
+                    }
+                }
+                tips
+            } else {
+                packed_commits(&repo)?
+            };
+            let options = WriteOptions {
+                split: match split.as_deref() {
+                    None => Split::No,
+                    Some("no-merge") => Split::NoMerge,
+                    Some("replace") => Split::Replace,
+                    Some(_) => Split::Merge,
+                },
+                size_multiple,
+                max_commits,
+                changed_paths: match (changed_paths, no_changed_paths) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                max_new_filters: match max_new_filters {
+                    Some(n) => Some(n),
+                    None => repo
+                        .config()
+                        .get_int("commitGraph.maxNewFilters")?
+                        .and_then(|n| usize::try_from(n).ok()),
+                },
+            };
+            if let Some(report) = commit_graph::write(odb, &tips, &options)? {
+                eprintln!(
+                    "Wrote {} commits to the commit-graph ({} layer(s)), computed {} Bloom filters",
+                    report.commits, report.layers, report.computed_filters
+                );
+            }
+            Ok(())
+        }
+        Action::Verify => {
+            // Nothing to verify is not a failure, as in git.
+            let Some(graph) = CommitGraph::open(odb.dir(), odb.hash())? else {
+                return Ok(());
+            };
+            let problems = graph.verify(odb);
+            for problem in &problems {
+                eprintln!("error: {problem}");
+            }
+            match problems.len() {
+                0 => Ok(()),
+                n => Err(Error::Usage(format!(
+                    "commit-graph verification found {n} problem(s)"
+                ))),
+            }
+        }
+    }
+}
+
+/// Every commit stored in a pack of the repository.
+fn packed_commits(repo: &Repository) -> Result<Vec<ObjectId>> {
+    let mut commits = HashSet::new();
+    for pack in repo.odb().packs() {
+        let index = pack.index();
+        for n in 0..index.len() {
+            if pack.read_header_at(index.offset_at(n))?.0 == ObjectKind::Commit {
+                commits.insert(index.id_at(n));
+            }
+        }
+    }
+    Ok(commits.into_iter().collect())
+}
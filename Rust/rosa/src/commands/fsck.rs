@@ -0,0 +1,77 @@
+use std::io::{self, Write};
+
+use crate::error::{Error, Result};
+use crate::fsck::{self, FsckOptions};
+use crate::repository::Repository;
+
+/// Verifies the connectivity and validity of the objects in the database
+///
+/// Problems in objects are reported with git's message ids, and can be
+/// made errors, warnings or ignored with `fsck.<msg-id>`.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Report every unreachable object, not just dangling ones
+    #[arg(long)]
+    unreachable: bool,
+    /// Do not report dangling objects
+    #[arg(long)]
+    no_dangling: bool,
+    /// Report root commits
+    #[arg(long)]
+    root: bool,
+    /// Treat warnings as errors
+    #[arg(long)]
+    strict: bool,
+    /// Only check that reachable objects are present
+    #[arg(long)]
+    connectivity_only: bool,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let options = FsckOptions {
+        strict: args.strict,
+        connectivity_only: args.connectivity_only,
+        ..FsckOptions::from_config(repo.config())?
+    };
+    let outcome = fsck::fsck(&repo, &options)?;
+
+    for problem in &outcome.problems {
+        eprintln!("{problem}");
+    }
+    let mut out = io::stdout().lock();
+    for link in &outcome.broken_links {
+        writeln!(
+            out,
+            "broken link from {:>7} {}",
+            link.from.0.as_str(),
+            link.from.1
+        )?;
+        writeln!(
+            out,
+            "              to {:>7} {}",
+            link.to.0.as_str(),
+            link.to.1
+        )?;
+    }
+    for (kind, id) in &outcome.missing {
+        writeln!(out, "missing {kind} {id}")?;
+    }
+    for (kind, id, dangling) in &outcome.unreachable {
+        if args.unreachable {
+            writeln!(out, "unreachable {kind} {id}")?;
+        } else if *dangling && !args.no_dangling {
+            writeln!(out, "dangling {kind} {id}")?;
+        }
+    }
+    if args.root {
+        for id in &outcome.roots {
+            writeln!(out, "root {id}")?;
+        }
+    }
+
+    match outcome.error_count() {
+        0 => Ok(()),
+        n => Err(Error::Usage(format!("fsck found {n} problem(s)"))),
+    }
+}
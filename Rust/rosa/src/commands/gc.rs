@@ -0,0 +1,71 @@
+use std::time::SystemTime;
+
+use crate::error::Result;
+use crate::gc::{self, GcOptions, DEFAULT_PRUNE_EXPIRE};
+use crate::pack::PackOptions;
+use crate::repository::Repository;
+
+/// Cleanup unnecessary files and optimize the local repository
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Prune unreachable loose objects older than this date
+    /// (default: gc.pruneExpire, or 2.weeks.ago)
+    #[arg(long, value_name = "date")]
+    prune: Option<String>,
+    /// Do not prune any unreachable objects
+    #[arg(long, conflicts_with = "prune")]
+    no_prune: bool,
+    /// Spend more time looking for good deltas
+    #[arg(long)]
+    aggressive: bool,
+    /// Do not report what was done
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+
+    let expire = match (&args.prune, args.no_prune) {
+        (_, true) => "never",
+        (Some(date), _) => date.as_str(),
+        (None, _) => repo
+            .config()
+            .get("gc.pruneExpire")
+            .unwrap_or(DEFAULT_PRUNE_EXPIRE),
+    };
+    let mut pack = PackOptions::from_config(repo.config())?;
+    if args.aggressive {
+        pack.window = 250;
+    }
+    // As with git, bare repositories (which are served from) get bitmaps
+    // unless told otherwise.
+    let bare = repo.config().get_bool("core.bare")?.unwrap_or(false);
+    pack.write_bitmap = repo
+        .config()
+        .get_bool("repack.writeBitmaps")?
+        .unwrap_or(bare);
+    let options = GcOptions {
+        prune_expire: gc::parse_expiry(expire, SystemTime::now())?,
+        pack,
+        write_commit_graph: repo
+            .config()
+            .get_bool("gc.writeCommitGraph")?
+            .unwrap_or(true),
+    };
+
+    let report = gc::gc(&repo, &options)?;
+    if !args.quiet {
+        if let Some(name) = &report.pack {
+            eprintln!("Packed {} objects into pack-{name}", report.packed_objects);
+        }
+        eprintln!(
+            "Removed {} loose objects and {} old packs, pruned {} unreachable objects, packed {} refs",
+            report.removed_loose, report.removed_packs, report.pruned, report.packed_refs
+        );
+        if report.graph_commits > 0 {
+            eprintln!("Wrote {} commits to the commit-graph", report.graph_commits);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::Result;
+use crate::fsck;
+use crate::object::{HashAlgorithm, ObjectId, ObjectKind};
+use crate::repository::Repository;
+
+/// Compute object ID and optionally create an object from a file
+///
+/// Trees, commits and tags are held to the checks of `fsck --strict`
+/// first, unless `--literally` is given.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Specify the type
+    #[arg(short = 't', value_name = "type", default_value = "blob",
+          value_parser = ["blob", "commit", "tag", "tree"])]
+    kind: String,
+    /// Actually write the object into the database
+    #[arg(short = 'w')]
+    write: bool,
+    /// Skip checking that the object is well formed
+    #[arg(long)]
+    literally: bool,
+    /// Read object from <file>
+    path: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let kind: ObjectKind = args.kind.parse()?;
+    // Outside a repository ids are SHA-1, as with git.
+    let repo = match Repository::discover(".") {
+        Ok(repo) => Some(repo),
+        Err(e) if args.write => return Err(e),
+        Err(_) => None,
+    };
+    let hash = repo
+        .as_ref()
+        .map_or(HashAlgorithm::Sha1, |r| r.odb().hash());
+    let odb = repo.as_ref().filter(|_| args.write).map(|r| r.odb());
+
+    let id = if kind == ObjectKind::Blob {
+        // Blobs need no validation, so they are streamed however large.
+        let file = fs::File::open(&args.path)?;
+        let size = file.metadata()?.len();
+        match odb {
+            Some(odb) => odb.write_stream(kind, size, file)?,
+            None => ObjectId::hash_reader(hash, kind, size, file)?,
+        }
+    } else {
+        let data = fs::read(&args.path)?;
+        match odb {
+            Some(odb) if args.literally => odb.write_literally(kind, &data)?,
+            // Checked on the way in.
+            Some(odb) => odb.write_raw(kind, &data)?,
+            None => {
+                if !args.literally {
+                    fsck::check_before_writing(kind, &data, hash)?;
+                }
+                ObjectId::hash_object(hash, kind, &data)?
+            }
+        }
+    };
+    println!("{id}");
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use crate::error::Result;
+use crate::object::HashAlgorithm;
+use crate::repository::Repository;
+
+/// Initialize a new repository
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Where to create the repository
+    #[arg(default_value = ".")]
+    directory: PathBuf,
+    /// Hash algorithm naming the objects (sha1 or sha256; default:
+    /// $GIT_DEFAULT_HASH, or sha1)
+    #[arg(long, value_name = "format")]
+    object_format: Option<String>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let format = args
+        .object_format
+        .or_else(|| std::env::var("GIT_DEFAULT_HASH").ok());
+    let hash = match format {
+        Some(name) => name.parse()?,
+        None => HashAlgorithm::Sha1,
+    };
+    let repo = Repository::init(&args.directory, hash)?;
+    println!(
+        "Initialized empty Git repository in {}",
+        repo.gitdir().display()
+    );
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use std::ffi::OsString;
+use std::io::{self, IsTerminal, Write};
+use std::time::SystemTime;
+
+use super::rev_list::{Revisions, WalkFlags};
+use crate::date::DateMode;
+use crate::error::{Error, Result};
+use crate::graph::Graph;
+use crate::mailmap::Mailmap;
+use crate::notes::Notes;
+use crate::path;
+use crate::pretty::{Decorations, Format, Printer};
+use crate::repository::Repository;
+use crate::revision;
+use crate::revwalk::{Order, RevWalk};
+
+/// Display history of a given commit
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// How to show each commit: oneline, short, medium, full, fuller,
+    /// `format:<template>` or `tformat:<template>`
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "medium",
+        require_equals = true,
+        overrides_with = "format"
+    )]
+    pretty: Option<String>,
+    /// Same as `--pretty=<FORMAT>`
+    #[arg(long, value_name = "FORMAT", overrides_with = "pretty")]
+    format: Option<String>,
+    /// Shorthand for `--pretty=oneline --abbrev-commit`
+    #[arg(long)]
+    oneline: bool,
+    /// Show abbreviated commit ids
+    #[arg(long)]
+    abbrev_commit: bool,
+    /// How to show dates: default, iso, iso-strict, rfc, short, raw, unix
+    /// or relative
+    #[arg(long, value_name = "MODE")]
+    date: Option<String>,
+    /// Show the ref names of commits: short, full, auto or no
+    #[arg(
+        long,
+        value_name = "STYLE",
+        num_args = 0..=1,
+        default_missing_value = "short",
+        require_equals = true
+    )]
+    decorate: Option<String>,
+    /// Do not show ref names
+    #[arg(long, overrides_with = "decorate")]
+    no_decorate: bool,
+    #[command(flatten)]
+    walk: WalkFlags,
+    /// Draw the history in text next to the commits
+    #[arg(long, conflicts_with_all = ["reverse", "graphviz"])]
+    graph: bool,
+    /// Emit the history as a Graphviz digraph
+    #[arg(long)]
+    graphviz: bool,
+    #[command(flatten)]
+    revisions: Revisions,
+    /// Only show commits that change these paths
+    #[arg(last = true)]
+    paths: Vec<OsString>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let paths = args
+        .paths
+        .iter()
+        .map(path::from_arg)
+        .collect::<Result<Vec<_>>>()?;
+    // The graph needs children before their parents.
+    let order = match args.graph {
+        true => Order::Topo,
+        false => Order::Chronological,
+    };
+    let mut walk = RevWalk::new(&repo, args.walk.options(order, false, paths))?;
+    match args.revisions.0.is_empty() {
+        true => walk.add_args(&["HEAD".to_owned()])?,
+        false => walk.add_args(&args.revisions.0)?,
+    }
+    super::warn(&walk.take_warnings());
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    if args.graphviz {
+        writeln!(out, "digraph mygitlog{{")?;
+        writeln!(out, "  node[shape=rect]")?;
+        log_graphviz(&repo, &mut walk, &mut out)?;
+        writeln!(out, "}}")?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    let format = match (args.pretty.as_ref().or(args.format.as_ref()), args.oneline) {
+        (Some(spec), _) => Format::parse(spec)?,
+        (None, true) => Format::Oneline,
+        (None, false) => Format::Medium,
+    };
+    let oneline = args.oneline && args.pretty.is_none() && args.format.is_none();
+    let dates = match &args.date {
+        Some(name) => DateMode::from_name(name)
+            .ok_or_else(|| Error::Usage(format!("unknown date format {name}")))?,
+        None => DateMode::Default,
+    };
+    let style = match (args.no_decorate, &args.decorate) {
+        (true, _) => "no".to_owned(),
+        (false, Some(style)) => style.clone(),
+        (false, None) => repo
+            .config()
+            .get("log.decorate")
+            .unwrap_or("auto")
+            .to_ascii_lowercase(),
+    };
+    let (decorate, full) = match style.as_str() {
+        "short" | "true" | "yes" | "on" | "1" => (true, false),
+        "full" => (true, true),
+        "auto" => (io::stdout().is_terminal(), false),
+        "no" | "false" | "off" | "0" => (false, false),
+        _ => return Err(Error::Usage(format!("invalid --decorate option: {style}"))),
+    };
+    let decorations = match decorate || format.uses_decorations() {
+        true => Decorations::load(&repo, full)?,
+        false => Decorations::default(),
+    };
+    let mailmap = match format.uses_mailmap() {
+        true => Mailmap::load(&repo)?,
+        false => Mailmap::default(),
+    };
+    let notes = match format.uses_notes() {
+        true => Notes::load(&repo)?,
+        false => Notes::default(),
+    };
+    let printer = Printer {
+        repo: &repo,
+        format,
+        dates,
+        abbrev: revision::abbrev_len(&repo)?,
+        abbrev_commit: args.abbrev_commit || oneline,
+        decorate,
+        decorations,
+        mailmap,
+        notes,
+        now: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64),
+    };
+
+    let terminated = printer.format.terminated();
+    let mut graph = args.graph.then(Graph::new);
+    let mut first = true;
+    // Whether the last entry ended without a line break of its own.
+    let mut missing_newline = false;
+    while let Some(id) = walk.next() {
+        let id = id?;
+        let mut commit = repo.odb().read(&id)?.into_commit(id)?;
+        if let Some(graph) = &mut graph {
+            // As in git, the graph shows commits with their parents
+            // rewritten, and draws lines to those shown.
+            commit.parents = walk.parents(id)?;
+            let mut shown = Vec::new();
+            for &parent in &commit.parents {
+                if walk.shown(parent)? {
+                    shown.push(parent);
+                }
+            }
+            graph.update(id, shown);
+        }
+        if !terminated && !first {
+            if let (Some(graph), false) = (&mut graph, missing_newline) {
+                graph.show_padding(&mut out)?;
+            }
+            writeln!(out)?;
+        }
+        first = false;
+        let entry = printer.entry(id, &commit)?;
+        missing_newline = !entry.ends_with(b"\n");
+        match &mut graph {
+            Some(graph) => {
+                graph.show_commit(&mut out)?;
+                graph.show_text(&mut out, &entry)?;
+            }
+            None => out.write_all(&entry)?,
+        }
+        if terminated && !printer.format.is_empty() {
+            if let (Some(graph), false) = (&mut graph, missing_newline) {
+                graph.show_padding(&mut out)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Emits one node per commit walked and one edge per parent link; with
+/// paths, the links go to the nearest ancestors changing them.
+fn log_graphviz(repo: &Repository, walk: &mut RevWalk, out: &mut impl Write) -> Result<()> {
+    let abbrev = revision::abbrev_len(repo)?;
+    while let Some(id) = walk.next() {
+        let id = id?;
+        let commit = repo.odb().read(&id)?.into_commit(id)?;
+
+        let message = String::from_utf8_lossy(commit.message.as_deref().unwrap_or_default());
+        let subject = message.trim().lines().next().unwrap_or_default();
+        let subject = subject.replace('\\', "\\\\").replace('"', "\\\"");
+        let hex = id.to_hex();
+        let short = revision::abbreviate(repo, &id, abbrev)?;
+        writeln!(out, "  c_{hex} [label=\"{short}: {subject}\"]")?;
+
+        for parent in walk.parents(id)? {
+            writeln!(out, "  c_{hex} -> c_{parent};")?;
+        }
+    }
+    Ok(())
+}
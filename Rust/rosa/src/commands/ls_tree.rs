@@ -0,0 +1,52 @@
+use std::io::{self, Write};
+
+use crate::commands::find_object;
+use crate::error::{Error, Result};
+use crate::object::{ObjectId, ObjectKind};
+use crate::path;
+use crate::repository::Repository;
+
+/// Pretty-print a tree object
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Recurse into sub-trees
+    #[arg(short = 'r')]
+    recursive: bool,
+    /// A tree-ish object
+    tree: String,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let id = find_object(&repo, &args.tree, Some(ObjectKind::Tree), true)?;
+    ls_tree(&repo, id, args.recursive, b"", &mut io::stdout().lock())
+}
+
+fn ls_tree(
+    repo: &Repository,
+    id: ObjectId,
+    recursive: bool,
+    prefix: &[u8],
+    out: &mut impl Write,
+) -> Result<()> {
+    let tree = repo.odb().read(&id)?.into_tree(id)?;
+    for entry in &tree.entries {
+        let kind = entry.kind().ok_or_else(|| {
+            Error::parse("tree", format!("weird tree leaf mode {:o}", entry.mode))
+        })?;
+        let path = path::join(prefix, &entry.name);
+
+        if recursive && kind == ObjectKind::Tree {
+            ls_tree(repo, entry.id, recursive, &path, out)?;
+        } else {
+            writeln!(
+                out,
+                "{:06o} {kind} {}\t{}",
+                entry.mode,
+                entry.id,
+                path::quote(&path)
+            )?;
+        }
+    }
+    Ok(())
+}
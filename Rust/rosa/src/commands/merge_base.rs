@@ -0,0 +1,54 @@
+use crate::commands::find_object;
+use crate::error::{Error, Result};
+use crate::history::{self, CommitCache};
+use crate::object::ObjectKind;
+use crate::repository::Repository;
+
+/// Find as good common ancestors as possible for a merge
+///
+/// With more than two commits, finds the common ancestors of the first
+/// and a hypothetical merge of the others.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Output all merge bases instead of just one
+    #[arg(short, long)]
+    all: bool,
+    /// Exit with status 0 if the first commit is an ancestor of the
+    /// second, 1 otherwise
+    #[arg(long, conflicts_with = "all")]
+    is_ancestor: bool,
+    #[arg(required = true, num_args = 2..)]
+    commits: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let commits = args
+        .commits
+        .iter()
+        .map(|name| find_object(&repo, name, Some(ObjectKind::Commit), true))
+        .collect::<Result<Vec<_>>>()?;
+    let cache = CommitCache::new(&repo)?;
+
+    if args.is_ancestor {
+        if commits.len() != 2 {
+            return Err(Error::Usage(
+                "--is-ancestor takes exactly two commits".into(),
+            ));
+        }
+        if !history::is_ancestor(&cache, commits[0], commits[1])? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let bases = history::merge_bases(&cache, commits[0], &commits[1..])?;
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+    let shown = if args.all { &bases[..] } else { &bases[..1] };
+    for id in shown {
+        println!("{id}");
+    }
+    Ok(())
+}
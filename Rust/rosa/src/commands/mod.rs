@@ -0,0 +1,44 @@
+//! One module per subcommand, each exposing its clap `Args` and a `run`.
+
+pub mod add;
+pub mod cat_file;
+pub mod checkout;
+pub mod commit_graph;
+pub mod fsck;
+pub mod gc;
+pub mod hash_object;
+pub mod init;
+pub mod log;
+pub mod ls_tree;
+pub mod merge_base;
+pub mod multi_pack_index;
+pub mod pack_objects;
+pub mod rev_list;
+pub mod rev_parse;
+pub mod show_ref;
+pub mod tag;
+
+use crate::error::Result;
+use crate::object::{ObjectId, ObjectKind};
+use crate::repository::Repository;
+use crate::revision;
+
+/// [`revision::resolve`] for a name given on the command line, showing
+/// what resolving it warned about.
+pub(crate) fn find_object(
+    repo: &Repository,
+    name: &str,
+    kind: Option<ObjectKind>,
+    follow: bool,
+) -> Result<ObjectId> {
+    let resolved = revision::resolve(repo, name, kind, follow)?;
+    warn(&resolved.warnings);
+    Ok(resolved.id)
+}
+
+/// Shows warnings on stderr, as git does.
+pub(crate) fn warn(warnings: &[String]) {
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+}
@@ -0,0 +1,77 @@
+use std::rc::Rc;
+
+use crate::error::{Error, Result};
+use crate::pack::{self, MultiPackIndex, Pack, MIDX_FILE_NAME};
+use crate::repository::Repository;
+
+/// Write and verify multi-pack-indexes
+///
+/// A multi-pack-index lists every object of the packs in objects/pack in a
+/// single sorted table, so finding an object takes one lookup however many
+/// packs there are.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Action {
+    /// Write a multi-pack-index covering every pack in objects/pack
+    Write {
+        /// Attribute objects found in several packs to this pack (the name
+        /// of its .idx or .pack file)
+        #[arg(long, value_name = "pack")]
+        preferred_pack: Option<String>,
+    },
+    /// Check the multi-pack-index against the packs it covers
+    Verify,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let odb = repo.odb();
+    let path = odb.dir().join("pack").join(MIDX_FILE_NAME);
+
+    match args.action {
+        Action::Write { preferred_pack } => {
+            let packs = odb.packs();
+            let packs: Vec<&Pack> = packs.iter().map(Rc::as_ref).collect();
+            let preferred = preferred_pack.map(|name| match name.strip_suffix(".pack") {
+                Some(stem) => format!("{stem}.idx"),
+                None => name,
+            });
+            pack::midx::write(&path, &packs, preferred.as_deref(), odb.hash())?;
+            odb.refresh_packs()
+        }
+        Action::Verify => {
+            // Nothing to verify is not a failure, as in git.
+            if !path.exists() {
+                return Ok(());
+            }
+            let midx = MultiPackIndex::open(&path, odb.hash())?;
+            let all = odb.packs();
+            let mut packs = Vec::new();
+            let mut problems = Vec::new();
+            for name in midx.pack_names() {
+                let idx = odb.dir().join("pack").join(name);
+                match all.iter().find(|p| p.path().with_extension("idx") == idx) {
+                    Some(pack) => packs.push(pack.as_ref()),
+                    None => problems.push(format!("failed to load pack '{name}'")),
+                }
+            }
+            if problems.is_empty() {
+                problems = midx.verify(&packs);
+            }
+            for problem in &problems {
+                eprintln!("error: {problem}");
+            }
+            match problems.len() {
+                0 => Ok(()),
+                n => Err(Error::Usage(format!(
+                    "multi-pack-index verification found {n} problem(s)"
+                ))),
+            }
+        }
+    }
+}
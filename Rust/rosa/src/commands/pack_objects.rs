@@ -0,0 +1,112 @@
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use crate::commands::find_object;
+use crate::error::Result;
+use crate::pack::{self, PackObject, PackOptions};
+use crate::reachable;
+use crate::repository::Repository;
+
+/// Create a packed archive of objects
+///
+/// Reads object ids (optionally followed by the path they were found at)
+/// from standard input, or revisions with --revs, and writes
+/// <base-name>-<checksum>.pack and .idx.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Read revisions instead of object ids and pack everything reachable
+    /// from them; `^rev` excludes what is reachable from rev
+    #[arg(long)]
+    revs: bool,
+    /// Number of objects tried as delta bases for each object
+    #[arg(long, default_value_t = PackOptions::default().window)]
+    window: usize,
+    /// Maximum delta chain length
+    #[arg(long, default_value_t = PackOptions::default().depth)]
+    depth: usize,
+    /// Write the pack to standard output instead of files
+    #[arg(long, conflicts_with = "base_name")]
+    stdout: bool,
+    /// Also write a reachability bitmap index for the pack
+    #[arg(long, conflicts_with = "stdout")]
+    write_bitmap_index: bool,
+    /// Prefix of the pack and index files to write
+    #[arg(required_unless_present = "stdout")]
+    base_name: Option<PathBuf>,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    // Paths are bytes, in whatever encoding they were committed.
+    let lines: Vec<Vec<u8>> = io::stdin().lock().split(b'\n').collect::<io::Result<_>>()?;
+
+    let objects = if args.revs {
+        let lines: Vec<String> = lines
+            .iter()
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect();
+        revs_to_objects(&repo, &lines)?
+    } else {
+        lines
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (id, path) = match line.iter().position(|&b| b == b' ') {
+                    Some(space) => (&line[..space], &line[space + 1..]),
+                    None => (&line[..], &[][..]),
+                };
+                Ok(PackObject {
+                    id: String::from_utf8_lossy(id).parse()?,
+                    name_hash: pack::name_hash(path),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let options = PackOptions {
+        window: args.window,
+        depth: args.depth,
+        write_bitmap: args.write_bitmap_index,
+        ..PackOptions::from_config(repo.config())?
+    };
+
+    if args.stdout {
+        pack::write_pack(repo.odb(), &objects, &options, io::stdout().lock())?;
+        return Ok(());
+    }
+
+    let base_name = args.base_name.expect("clap requires a base name");
+    let written = pack::write_pack_files(repo.odb(), &objects, &options, &base_name)?;
+    if options.write_bitmap && written.bitmap_path.is_none() {
+        eprintln!("warning: disabling bitmap writing, as some objects are not being packed");
+    }
+    writeln!(io::stdout().lock(), "{}", written.name())?;
+    Ok(())
+}
+
+/// Lists the objects reachable from the given revisions.
+fn revs_to_objects(repo: &Repository, lines: &[String]) -> Result<Vec<PackObject>> {
+    let mut tips = Vec::new();
+    let mut exclude = Vec::new();
+    let mut negate = false;
+    for line in lines.iter().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        if line == "--not" {
+            negate = !negate;
+            continue;
+        }
+        match line.strip_prefix('^') {
+            Some(rev) => (if negate { &mut tips } else { &mut exclude })
+                .push(find_object(repo, rev, None, true)?),
+            None => (if negate { &mut exclude } else { &mut tips })
+                .push(find_object(repo, line, None, true)?),
+        }
+    }
+
+    Ok(reachable::objects(repo, &tips, &exclude)?
+        .into_iter()
+        .map(|r| PackObject {
+            id: r.id,
+            name_hash: r.name_hash,
+        })
+        .collect())
+}
@@ -0,0 +1,187 @@
+use std::ffi::OsString;
+use std::io::{self, Write};
+
+use crate::error::{Error, Result};
+use crate::path;
+use crate::repository::Repository;
+use crate::revwalk::{Order, RevWalk, WalkOptions};
+
+/// Lists commit objects in reverse chronological order
+///
+/// Lists the commits reachable from the given revisions, leaving out those
+/// reachable from any revision given with a leading `^`.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    #[command(flatten)]
+    walk: WalkFlags,
+    /// Also list the trees and blobs used by the commits, with their paths
+    #[arg(long)]
+    objects: bool,
+    /// Print only how many objects would have been listed
+    #[arg(long)]
+    count: bool,
+    #[command(flatten)]
+    revisions: Revisions,
+    /// Only list commits that change these paths
+    #[arg(last = true)]
+    paths: Vec<OsString>,
+}
+
+/// The ordering and limiting flags `rev-list` and `log` share.
+#[derive(Debug, clap::Args)]
+pub struct WalkFlags {
+    /// Show no parents before all of their children, in commit date order
+    #[arg(long, conflicts_with = "topo_order")]
+    date_order: bool,
+    /// Show no parents before all of their children, keeping lines of
+    /// history together
+    #[arg(long)]
+    topo_order: bool,
+    /// Output the commits chosen in reverse order
+    #[arg(long)]
+    reverse: bool,
+    /// Follow only the first parent of merge commits
+    #[arg(long)]
+    first_parent: bool,
+    /// Limit the number of commits to output; `-<NUMBER>` does the same
+    #[arg(short = 'n', long, value_name = "NUMBER")]
+    max_count: Option<usize>,
+    /// Only show commits that descend from an excluded commit
+    #[arg(long)]
+    ancestry_path: bool,
+}
+
+impl WalkFlags {
+    /// The walk these flags ask for, in `order` unless they name one.
+    pub fn options(&self, order: Order, objects: bool, paths: Vec<Vec<u8>>) -> WalkOptions {
+        let order = match (self.topo_order, self.date_order) {
+            (true, _) => Order::Topo,
+            (_, true) => Order::Date,
+            _ => order,
+        };
+        WalkOptions {
+            order,
+            reverse: self.reverse,
+            first_parent: self.first_parent,
+            max_count: self.max_count,
+            ancestry_path: self.ancestry_path,
+            objects,
+            paths,
+        }
+    }
+}
+
+/// Revisions to walk from, in command line order with the `--not`,
+/// `--all` and `--branches` among them, which clap would otherwise
+/// collect apart (see [`RevWalk::add_args`]).
+#[derive(Clone, Debug, Default)]
+pub struct Revisions(pub Vec<String>);
+
+const FLAGS: [(&str, &str); 3] = [
+    ("all", "Walk from HEAD and every ref"),
+    ("branches", "Walk from every branch"),
+    (
+        "not",
+        "Exclude the revisions that follow, up to the next --not",
+    ),
+];
+
+impl clap::FromArgMatches for Revisions {
+    fn from_arg_matches(matches: &clap::ArgMatches) -> std::result::Result<Self, clap::Error> {
+        let mut found: Vec<(usize, String)> = Vec::new();
+        if let (Some(indices), Some(values)) = (
+            matches.indices_of("revisions"),
+            matches.get_many::<String>("revisions"),
+        ) {
+            found.extend(indices.zip(values.cloned()));
+        }
+        for (flag, _) in FLAGS {
+            if let Some(indices) = matches.indices_of(flag) {
+                found.extend(indices.map(|at| (at, format!("--{flag}"))));
+            }
+        }
+        found.sort_by_key(|&(at, _)| at);
+        Ok(Revisions(found.into_iter().map(|(_, arg)| arg).collect()))
+    }
+
+    fn update_from_arg_matches(
+        &mut self,
+        matches: &clap::ArgMatches,
+    ) -> std::result::Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+        Ok(())
+    }
+}
+
+impl clap::Args for Revisions {
+    fn augment_args(mut cmd: clap::Command) -> clap::Command {
+        cmd = cmd.arg(
+            clap::Arg::new("revisions")
+                .value_name("REVISION")
+                .num_args(1..)
+                .action(clap::ArgAction::Append)
+                .help(
+                    "Revisions to walk from: `<rev>`, `^<rev>` to exclude one, \
+                     `<a>..<b>` or `<a>...<b>`",
+                ),
+        );
+        for (flag, help) in FLAGS {
+            cmd = cmd.arg(
+                clap::Arg::new(flag)
+                    .long(flag)
+                    .num_args(0)
+                    .default_missing_value("")
+                    .action(clap::ArgAction::Append)
+                    .help(help),
+            );
+        }
+        cmd
+    }
+
+    fn augment_args_for_update(cmd: clap::Command) -> clap::Command {
+        Self::augment_args(cmd)
+    }
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let paths = args
+        .paths
+        .iter()
+        .map(path::from_arg)
+        .collect::<Result<Vec<_>>>()?;
+    let options = args.walk.options(Order::Chronological, args.objects, paths);
+    let mut walk = RevWalk::new(&repo, options)?;
+    if args.revisions.0.is_empty() {
+        return Err(Error::Usage("no revisions given".into()));
+    }
+    walk.add_args(&args.revisions.0)?;
+    super::warn(&walk.take_warnings());
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    let mut count = 0;
+    for id in walk.by_ref() {
+        let id = id?;
+        count += 1;
+        if !args.count {
+            writeln!(out, "{id}")?;
+        }
+    }
+    if args.objects {
+        for (id, name) in walk.objects()? {
+            count += 1;
+            if !args.count {
+                write!(out, "{id} ")?;
+                // As git, up to the first newline.
+                let end = name.iter().position(|&b| b == b'\n').unwrap_or(name.len());
+                out.write_all(&name[..end])?;
+                writeln!(out)?;
+            }
+        }
+    }
+    if args.count {
+        writeln!(out, "{count}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use crate::commands::find_object;
+use crate::error::Result;
+use crate::object::ObjectKind;
+use crate::repository::Repository;
+use crate::revision;
+
+/// Parse revision (or other objects) identifiers
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Specify the expected type
+    #[arg(long = "mygit-type", value_name = "type",
+          value_parser = ["blob", "commit", "tag", "tree"])]
+    kind: Option<String>,
+    /// Print the shortest unique abbreviation of the id, of at least
+    /// `core.abbrev` or the given number of hex digits
+    #[arg(long, value_name = "length", num_args = 0..=1, require_equals = true)]
+    short: Option<Option<usize>>,
+    /// The name to parse
+    name: String,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let kind = args
+        .kind
+        .as_deref()
+        .map(str::parse::<ObjectKind>)
+        .transpose()?;
+    let id = find_object(&repo, &args.name, kind, true)?;
+    match args.short {
+        None => println!("{id}"),
+        Some(len) => {
+            let len = match len {
+                Some(len) => len.clamp(revision::MIN_ABBREV, repo.odb().hash().hex_len()),
+                None => revision::abbrev_len(&repo)?,
+            };
+            println!("{}", revision::abbreviate(&repo, &id, len)?);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use std::io::{self, Write};
+
+use crate::error::Result;
+use crate::refs;
+use crate::repository::Repository;
+
+/// List references
+#[derive(Debug, clap::Args)]
+pub struct Args {}
+
+pub fn run(_args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let mut out = io::stdout().lock();
+    for (name, id) in refs::list(&repo)? {
+        writeln!(out, "{id} {name}")?;
+    }
+    Ok(())
+}
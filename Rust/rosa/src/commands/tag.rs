@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::commands::find_object;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::object::{Object, Offset, Signature, Tag};
+use crate::refs;
+use crate::repository::Repository;
+
+/// List and create tags
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Whether to create a tag object
+    #[arg(short = 'a')]
+    annotate: bool,
+    /// Message for an annotated tag
+    #[arg(short = 'm', value_name = "message", requires = "annotate")]
+    message: Option<String>,
+    /// The new tag's name
+    name: Option<String>,
+    /// The object the new tag will point to
+    #[arg(default_value = "HEAD")]
+    object: String,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let repo = Repository::discover(".")?;
+
+    let Some(name) = args.name else {
+        let mut out = io::stdout().lock();
+        for (name, _) in refs::list(&repo)? {
+            if let Some(tag) = name.strip_prefix("refs/tags/") {
+                writeln!(out, "{tag}")?;
+            }
+        }
+        return Ok(());
+    };
+
+    refs::check_ref_format(&name)?;
+    let id = find_object(&repo, &args.object, None, true)?;
+    let target = if args.annotate {
+        let kind = repo.odb().read_raw(&id)?.0;
+        let message = args
+            .message
+            .unwrap_or_else(|| "A tag generated by mygit!".into());
+        let tag = Tag {
+            object: id,
+            target_kind: kind,
+            name: name.clone().into_bytes(),
+            tagger: Some(identity(&repo)?),
+            extra_headers: Vec::new(),
+            message: Some(format!("{}\n", message.trim_end()).into_bytes()),
+        };
+        // A name or identity with a newline or stray angle brackets would
+        // make a tag that cannot be read back: writing refuses it.
+        repo.odb().write(&Object::Tag(tag))?
+    } else {
+        id
+    };
+    refs::update(&repo, &format!("refs/tags/{name}"), &target)
+}
+
+/// The configured user as of now, in UTC, preferring the repository's own
+/// settings over the user-wide ones.
+fn identity(repo: &Repository) -> Result<Signature> {
+    let global = Config::load_global()?;
+    let get = |name| repo.config().get(name).or_else(|| global.get(name));
+    let (Some(name), Some(email)) = (get("user.name"), get("user.email")) else {
+        return Err(Error::Config(
+            "no user configured; set user.name and user.email".into(),
+        ));
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok(Signature {
+        name: name.as_bytes().to_vec(),
+        email: email.as_bytes().to_vec(),
+        time: i64::try_from(now).unwrap_or(i64::MAX),
+        offset: Offset::from_minutes(0),
+        raw: None,
+    })
+}
@@ -0,0 +1,197 @@
+//! Changed-path Bloom filters: for each commit, a small bit set built from
+//! the paths that differ from its first parent, so that a path-limited
+//! walk can tell without diffing that a commit did not touch a path.
+//!
+//! The BIDX chunk holds, per commit, where its filter ends in the BDAT
+//! chunk. BDAT starts with the settings the filters were built with
+//! (hash version, number of hashes, bits per entry) and holds the filters
+//! back to back. An empty filter means it was never computed; a single
+//! all-ones byte means too many paths changed to bother.
+
+use std::collections::BTreeSet;
+
+use crate::diff;
+use crate::error::Result;
+use crate::object::ObjectId;
+use crate::odb::ObjectDatabase;
+
+/// Commits changing more paths than this (directories included) get a
+/// filter that matches everything.
+pub const MAX_CHANGED_PATHS: usize = 512;
+
+const SEED0: u32 = 0x293a_e76f;
+const SEED1: u32 = 0x7e64_6e2c;
+
+/// How filters are built, as recorded at the start of the BDAT chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BloomSettings {
+    pub hash_version: u32,
+    pub num_hashes: u32,
+    pub bits_per_entry: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        BloomSettings {
+            hash_version: 1,
+            num_hashes: 7,
+            bits_per_entry: 10,
+        }
+    }
+}
+
+impl BloomSettings {
+    pub(super) const LEN: usize = 12;
+
+    pub(super) fn parse(data: &[u8]) -> Self {
+        let word =
+            |n: usize| u32::from_be_bytes(data[n * 4..n * 4 + 4].try_into().expect("4-byte slice"));
+        BloomSettings {
+            hash_version: word(0),
+            num_hashes: word(1),
+            bits_per_entry: word(2),
+        }
+    }
+
+    pub(super) fn serialize(&self) -> [u8; Self::LEN] {
+        let mut out = [0; Self::LEN];
+        out[..4].copy_from_slice(&self.hash_version.to_be_bytes());
+        out[4..8].copy_from_slice(&self.num_hashes.to_be_bytes());
+        out[8..].copy_from_slice(&self.bits_per_entry.to_be_bytes());
+        out
+    }
+}
+
+/// The bit positions one path sets in a filter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BloomKey {
+    hashes: Vec<u32>,
+}
+
+impl BloomKey {
+    pub fn new(path: &[u8], settings: &BloomSettings) -> Self {
+        let hash0 = murmur3_seeded(SEED0, path);
+        let hash1 = murmur3_seeded(SEED1, path);
+        BloomKey {
+            hashes: (0..settings.num_hashes)
+                .map(|i| hash0.wrapping_add(i.wrapping_mul(hash1)))
+                .collect(),
+        }
+    }
+
+    /// The keys a walk limited to `path` checks: the path and each of its
+    /// leading directories, all of which a touching commit records.
+    pub fn for_pathspec(mut path: &[u8], settings: &BloomSettings) -> Vec<Self> {
+        while let Some(rest) = path.strip_suffix(b"/") {
+            path = rest;
+        }
+        let mut keys = vec![BloomKey::new(path, settings)];
+        for (at, _) in path.iter().enumerate().filter(|(_, &b)| b == b'/') {
+            keys.push(BloomKey::new(&path[..at], settings));
+        }
+        keys
+    }
+
+    fn positions(&self, filter_len: usize) -> impl Iterator<Item = (usize, u8)> + '_ {
+        let bits = filter_len as u64 * 8;
+        self.hashes.iter().map(move |&hash| {
+            let bit = u64::from(hash) % bits;
+            ((bit / 8) as usize, 1 << (bit % 8))
+        })
+    }
+}
+
+/// Whether `filter` may contain `key`. Filters never give false negatives.
+pub fn filter_contains(filter: &[u8], key: &BloomKey) -> bool {
+    filter.is_empty()
+        || key
+            .positions(filter.len())
+            .all(|(byte, mask)| filter[byte] & mask != 0)
+}
+
+/// Builds the filter recording `paths`.
+pub fn build_filter(paths: &BTreeSet<Vec<u8>>, settings: &BloomSettings) -> Vec<u8> {
+    if paths.len() > MAX_CHANGED_PATHS {
+        return vec![0xff];
+    }
+    let bits = paths.len() * settings.bits_per_entry as usize;
+    // A commit changing nothing still gets a (zero) byte, so that its
+    // filter does not read as "not computed".
+    let mut filter = vec![0; bits.div_ceil(8).max(1)];
+    for path in paths {
+        let key = BloomKey::new(path, settings);
+        for (byte, mask) in key.positions(filter.len()) {
+            filter[byte] |= mask;
+        }
+    }
+    filter
+}
+
+/// Computes the filter of a commit with root tree `tree` from what
+/// changed since its first parent's tree (`None` for root commits).
+pub fn compute_filter(
+    odb: &ObjectDatabase,
+    parent_tree: Option<ObjectId>,
+    tree: ObjectId,
+    settings: &BloomSettings,
+) -> Result<Vec<u8>> {
+    let changed = diff::changed_paths(odb, parent_tree, Some(tree), Some(MAX_CHANGED_PATHS))?;
+    let Some(changed) = changed else {
+        return Ok(vec![0xff]);
+    };
+    let mut paths = BTreeSet::new();
+    for path in changed {
+        let mut end = path.len();
+        paths.insert(path.clone());
+        while let Some(slash) = path[..end].iter().rposition(|&b| b == b'/') {
+            paths.insert(path[..slash].to_vec());
+            end = slash;
+        }
+    }
+    Ok(build_filter(&paths, settings))
+}
+
+/// Git's version 1 of seeded murmur3. It reads bytes as C `char`s, which
+/// are signed on the platforms git is mostly built for, so bytes of 0x80
+/// and up are sign-extended; the filters on disk depend on that.
+pub fn murmur3_seeded(mut seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let signed = |b: u8| b as i8 as i32 as u32;
+
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = signed(chunk[0])
+            | signed(chunk[1]) << 8
+            | signed(chunk[2]) << 16
+            | signed(chunk[3]) << 24;
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        seed ^= k;
+        seed = seed
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        if tail.len() >= 3 {
+            k ^= signed(tail[2]) << 16;
+        }
+        if tail.len() >= 2 {
+            k ^= signed(tail[1]) << 8;
+        }
+        k ^= signed(tail[0]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        seed ^= k;
+    }
+
+    seed ^= data.len() as u32;
+    seed ^= seed >> 16;
+    seed = seed.wrapping_mul(0x85eb_ca6b);
+    seed ^= seed >> 13;
+    seed = seed.wrapping_mul(0xc2b2_ae35);
+    seed ^= seed >> 16;
+    seed
+}
@@ -0,0 +1,566 @@
+//! The commit-graph: the root tree, parents, date and generation number of
+//! each commit in a compact sorted table, so history walks need not
+//! inflate commit objects.
+//!
+//! The graph is either one file, `objects/info/commit-graph`, or a chain
+//! of layers in `objects/info/commit-graphs`, listed base first by
+//! `commit-graph-chain` and each named after its checksum. A layer only
+//! holds commits missing from the layers below it, and refers to commits
+//! by their position in the whole chain.
+//!
+//! Each file has an 8-byte header (`CGPH`, version, hash version, chunk
+//! count, number of base layers) followed by the chunks: a fan-out table
+//! (OIDF), the sorted ids (OIDL), the commit data (CDAT), corrected commit
+//! date offsets (GDA2, with GDO2 for those that do not fit in 31 bits),
+//! the extra parents of octopus merges (EDGE), changed-path Bloom filters
+//! (BIDX and BDAT, see [`bloom`]) and, in layers above the base, the
+//! checksums of the layers below (BASE).
+
+pub mod bloom;
+mod write;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::chunk::{ChunkTable, FANOUT_LEN};
+use crate::error::{Error, Result};
+use crate::object::{hex, HashAlgorithm, ObjectId, ObjectKind};
+use crate::odb::ObjectDatabase;
+use crate::pack::{be32, be64};
+use crate::refs;
+use crate::repository::Repository;
+
+pub use bloom::{BloomKey, BloomSettings};
+pub use write::{write, Split, WriteOptions, WriteReport};
+
+/// Generation of commits the graph does not cover: larger than any real
+/// one, so that walks never stop early on their account.
+pub const GENERATION_INFINITY: u64 = u64::MAX;
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8;
+
+const CHUNK_FANOUT: u32 = u32::from_be_bytes(*b"OIDF");
+const CHUNK_LOOKUP: u32 = u32::from_be_bytes(*b"OIDL");
+const CHUNK_DATA: u32 = u32::from_be_bytes(*b"CDAT");
+const CHUNK_GENERATION_DATA: u32 = u32::from_be_bytes(*b"GDA2");
+const CHUNK_GENERATION_OVERFLOW: u32 = u32::from_be_bytes(*b"GDO2");
+const CHUNK_EXTRA_EDGES: u32 = u32::from_be_bytes(*b"EDGE");
+const CHUNK_BLOOM_INDEX: u32 = u32::from_be_bytes(*b"BIDX");
+const CHUNK_BLOOM_DATA: u32 = u32::from_be_bytes(*b"BDAT");
+const CHUNK_BASE: u32 = u32::from_be_bytes(*b"BASE");
+
+/// Parent slot value for "no parent".
+const PARENT_NONE: u32 = 0x7000_0000;
+/// Set on the second parent slot when it points into the EDGE chunk, and
+/// on the last entry of each list there.
+const EDGE_FLAG: u32 = 0x8000_0000;
+/// Set on a GDA2 entry when the offset lives in the GDO2 chunk.
+const OFFSET_OVERFLOW_FLAG: u32 = 0x8000_0000;
+/// Largest topological level the commit data can hold.
+const MAX_LEVEL: u32 = 0x3fff_ffff;
+
+/// The path of the single-file graph in `objects_dir`.
+pub fn graph_path(objects_dir: &Path) -> PathBuf {
+    objects_dir.join("info").join("commit-graph")
+}
+
+/// The directory holding split graph layers in `objects_dir`.
+pub fn chain_dir(objects_dir: &Path) -> PathBuf {
+    objects_dir.join("info").join("commit-graphs")
+}
+
+fn chain_path(objects_dir: &Path) -> PathBuf {
+    chain_dir(objects_dir).join("commit-graph-chain")
+}
+
+fn layer_path(objects_dir: &Path, checksum: &str) -> PathBuf {
+    chain_dir(objects_dir).join(format!("graph-{checksum}.graph"))
+}
+
+/// The commits HEAD and the refs point to, tags peeled: where a graph of
+/// everything reachable starts.
+pub fn ref_tips(repo: &Repository) -> Result<Vec<ObjectId>> {
+    let mut ids: Vec<ObjectId> = refs::list(repo)?.into_iter().map(|(_, id)| id).collect();
+    ids.extend(refs::resolve(repo, "HEAD")?);
+    let mut tips = Vec::new();
+    for mut id in ids {
+        let mut kind = repo.odb().read_header(&id)?.0;
+        while kind == ObjectKind::Tag {
+            id = repo.odb().read(&id)?.into_tag(id)?.object;
+            kind = repo.odb().read_header(&id)?.0;
+        }
+        if kind == ObjectKind::Commit {
+            tips.push(id);
+        }
+    }
+    tips.sort();
+    tips.dedup();
+    Ok(tips)
+}
+
+/// What the graph records about one commit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphCommit {
+    pub id: ObjectId,
+    pub tree: ObjectId,
+    /// Positions of the parents in the graph.
+    pub parents: Vec<usize>,
+    /// Committer timestamp (34 bits are stored).
+    pub commit_time: u64,
+    /// Topological level: 1 for root commits, one more than the highest
+    /// parent otherwise.
+    pub level: u32,
+    /// Corrected commit date when every layer has one, the topological
+    /// level otherwise. Either way it is larger than any parent's.
+    pub generation: u64,
+}
+
+#[derive(Debug)]
+pub struct CommitGraph {
+    hash: HashAlgorithm,
+    /// Base first.
+    layers: Vec<Layer>,
+    /// Whether every layer has corrected commit dates; mixing them with
+    /// topological levels would break the ordering walks rely on.
+    corrected_dates: bool,
+}
+
+/// One graph file.
+#[derive(Debug)]
+struct Layer {
+    data: Vec<u8>,
+    /// Number of commits in the layers below, so the position of this
+    /// layer's `n`th commit is `base + n`.
+    base: usize,
+    count: usize,
+    fanout: usize,
+    lookup: usize,
+    commit_data: usize,
+    generation_data: Option<usize>,
+    generation_overflow: Option<(usize, usize)>,
+    extra_edges: Option<(usize, usize)>,
+    bloom: Option<LayerBloom>,
+}
+
+/// Where a layer keeps its Bloom filters.
+#[derive(Debug)]
+struct LayerBloom {
+    settings: BloomSettings,
+    index: usize,
+    /// Start and length of the filters, past the settings.
+    data: (usize, usize),
+}
+
+impl CommitGraph {
+    /// Opens the graph of the store at `objects_dir`: the single file if
+    /// there is one, the chain of layers otherwise. A chain whose upper
+    /// layers are missing or damaged is cut short at the last good layer,
+    /// as git does.
+    pub fn open(objects_dir: &Path, hash: HashAlgorithm) -> Result<Option<Self>> {
+        match CommitGraph::open_single(objects_dir, hash)? {
+            Some(graph) => Ok(Some(graph)),
+            None => CommitGraph::open_chain(objects_dir, hash),
+        }
+    }
+
+    fn open_single(objects_dir: &Path, hash: HashAlgorithm) -> Result<Option<Self>> {
+        let path = graph_path(objects_dir);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let layer = Layer::parse(data, hash, &[], 0).map_err(|reason| {
+            Error::parse("commit-graph", format!("{}: {reason}", path.display()))
+        })?;
+        Ok(Some(CommitGraph::from_layers(hash, vec![layer])))
+    }
+
+    fn open_chain(objects_dir: &Path, hash: HashAlgorithm) -> Result<Option<Self>> {
+        let text = match fs::read_to_string(chain_path(objects_dir)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut layers: Vec<Layer> = Vec::new();
+        let mut checksums: Vec<String> = Vec::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let Ok(data) = fs::read(layer_path(objects_dir, line)) else {
+                break;
+            };
+            let base = layers.last().map_or(0, |l| l.base + l.count);
+            match Layer::parse(data, hash, &checksums, base) {
+                Ok(layer) if hex(layer.checksum(hash)) == line => {
+                    checksums.push(line.to_owned());
+                    layers.push(layer);
+                }
+                _ => break,
+            }
+        }
+        if layers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(CommitGraph::from_layers(hash, layers)))
+    }
+
+    fn from_layers(hash: HashAlgorithm, layers: Vec<Layer>) -> Self {
+        let corrected_dates = layers.iter().all(|l| l.generation_data.is_some());
+        CommitGraph {
+            hash,
+            layers,
+            corrected_dates,
+        }
+    }
+
+    /// Number of commits in all layers.
+    pub fn len(&self) -> usize {
+        self.layers.last().map_or(0, |l| l.base + l.count)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of files the graph is made of.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Whether generations are corrected commit dates rather than
+    /// topological levels.
+    pub fn has_corrected_dates(&self) -> bool {
+        self.corrected_dates
+    }
+
+    /// The settings of the Bloom filters, if the top layer has any.
+    pub fn bloom_settings(&self) -> Option<BloomSettings> {
+        self.layers.last()?.bloom.as_ref().map(|b| b.settings)
+    }
+
+    /// The changed-path Bloom filter of the commit at `pos`, if one was
+    /// computed with `settings`.
+    pub fn bloom_filter(&self, pos: usize, settings: &BloomSettings) -> Option<&[u8]> {
+        let (layer, n) = self.locate(pos);
+        let bloom = layer.bloom.as_ref().filter(|b| b.settings == *settings)?;
+        let end = be32(&layer.data, bloom.index + n * 4) as usize;
+        let start = match n {
+            0 => 0,
+            _ => be32(&layer.data, bloom.index + (n - 1) * 4) as usize,
+        };
+        let (data, len) = bloom.data;
+        if start >= end || end > len {
+            return None;
+        }
+        Some(&layer.data[data + start..data + end])
+    }
+
+    /// Position of `id` in the graph, if it is covered.
+    pub fn position(&self, id: &ObjectId) -> Option<usize> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| layer.position(id, self.hash).map(|n| layer.base + n))
+    }
+
+    pub fn id_at(&self, pos: usize) -> ObjectId {
+        let (layer, n) = self.locate(pos);
+        layer.id_at(n, self.hash)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        (0..self.len()).map(|pos| self.id_at(pos))
+    }
+
+    /// What the graph records about the commit at `pos`.
+    pub fn commit_at(&self, pos: usize) -> Result<GraphCommit> {
+        let (layer, n) = self.locate(pos);
+        let raw_len = self.hash.raw_len();
+        let row = layer.commit_data + n * (raw_len + 16);
+        let data = &layer.data;
+        let tree = ObjectId::from_bytes(&data[row..row + raw_len])?;
+
+        let mut parents = Vec::new();
+        let first = be32(data, row + raw_len);
+        let second = be32(data, row + raw_len + 4);
+        if first != PARENT_NONE {
+            parents.push(first as usize);
+        }
+        if second & EDGE_FLAG != 0 {
+            let (start, len) = layer
+                .extra_edges
+                .ok_or_else(|| Error::parse("commit-graph", "octopus merge without EDGE chunk"))?;
+            let mut at = start + (second & !EDGE_FLAG) as usize * 4;
+            loop {
+                if at + 4 > start + len {
+                    return Err(Error::parse("commit-graph", "EDGE list runs off the chunk"));
+                }
+                let edge = be32(data, at);
+                parents.push((edge & !EDGE_FLAG) as usize);
+                if edge & EDGE_FLAG != 0 {
+                    break;
+                }
+                at += 4;
+            }
+        } else if second != PARENT_NONE {
+            parents.push(second as usize);
+        }
+        if parents.iter().any(|&p| p >= self.len()) {
+            return Err(Error::parse("commit-graph", "parent position out of range"));
+        }
+
+        let high = be32(data, row + raw_len + 8);
+        let low = be32(data, row + raw_len + 12);
+        let commit_time = (u64::from(high & 3) << 32) | u64::from(low);
+        let level = high >> 2;
+        let generation = match layer.generation_data.filter(|_| self.corrected_dates) {
+            Some(start) => {
+                let offset = be32(data, start + n * 4);
+                let offset = if offset & OFFSET_OVERFLOW_FLAG == 0 {
+                    u64::from(offset)
+                } else {
+                    let slot = (offset & !OFFSET_OVERFLOW_FLAG) as usize;
+                    match layer.generation_overflow {
+                        Some((start, len)) if (slot + 1) * 8 <= len => be64(data, start + slot * 8),
+                        _ => return Err(Error::parse("commit-graph", "bad generation offset")),
+                    }
+                };
+                commit_time + offset
+            }
+            None => u64::from(level),
+        };
+
+        Ok(GraphCommit {
+            id: layer.id_at(n, self.hash),
+            tree,
+            parents,
+            commit_time,
+            level,
+            generation,
+        })
+    }
+
+    fn locate(&self, pos: usize) -> (&Layer, usize) {
+        let layer = self
+            .layers
+            .iter()
+            .rev()
+            .find(|l| l.base <= pos)
+            .expect("positions start at zero");
+        (layer, pos - layer.base)
+    }
+
+    /// Checks the graph against the commits it describes, returning every
+    /// problem found: bad checksums, unsorted ids, and trees, parents,
+    /// dates or generations that do not match the commit objects.
+    pub fn verify(&self, odb: &ObjectDatabase) -> Vec<String> {
+        let mut problems = Vec::new();
+        for layer in &self.layers {
+            let raw_len = self.hash.raw_len();
+            let body = &layer.data[..layer.data.len() - raw_len];
+            match self.hash.digest(body) {
+                Ok(sum) if sum == layer.checksum(self.hash) => {}
+                Ok(_) => problems.push(
+                    "the commit-graph file has incorrect checksum and is likely corrupt".to_owned(),
+                ),
+                Err(e) => problems.push(e.to_string()),
+            }
+            for n in 1..layer.count {
+                let (prev, next) = (layer.id_at(n - 1, self.hash), layer.id_at(n, self.hash));
+                if prev >= next {
+                    problems.push(format!(
+                        "commit-graph has incorrect OID order: {prev} then {next}"
+                    ));
+                }
+            }
+        }
+
+        for pos in 0..self.len() {
+            let entry = match self.commit_at(pos) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    problems.push(format!("{}: {e}", self.id_at(pos)));
+                    continue;
+                }
+            };
+            let id = entry.id;
+            let commit = match odb.read(&id).and_then(|o| o.into_commit(id)) {
+                Ok(commit) => commit,
+                Err(e) => {
+                    problems.push(format!(
+                        "failed to parse commit {id} from object database for commit-graph: {e}"
+                    ));
+                    continue;
+                }
+            };
+            if commit.tree != entry.tree {
+                problems.push(format!(
+                    "root tree OID for commit {id} in commit-graph is {} != {}",
+                    entry.tree, commit.tree
+                ));
+            }
+            let graph_parents: Vec<ObjectId> =
+                entry.parents.iter().map(|&p| self.id_at(p)).collect();
+            if commit.parents != graph_parents {
+                problems.push(format!(
+                    "commit-graph parent list for commit {id} does not match"
+                ));
+            }
+            let time = u64::try_from(commit.committer.time).unwrap_or(0) & ((1 << 34) - 1);
+            if time != entry.commit_time {
+                problems.push(format!(
+                    "commit date for commit {id} in commit-graph is {} != {time}",
+                    entry.commit_time
+                ));
+            }
+            let mut max_level = 0;
+            for &parent in &entry.parents {
+                let Ok(parent) = self.commit_at(parent) else {
+                    continue;
+                };
+                max_level = max_level.max(parent.level);
+                if parent.generation >= entry.generation {
+                    problems.push(format!(
+                        "commit-graph generation for commit {id} is {} <= {} of its parent {}",
+                        entry.generation, parent.generation, parent.id
+                    ));
+                }
+            }
+            let expected = (max_level + 1).min(MAX_LEVEL);
+            if entry.level != expected {
+                problems.push(format!(
+                    "commit-graph topological level for commit {id} is {} != {expected}",
+                    entry.level
+                ));
+            }
+        }
+        problems
+    }
+}
+
+impl Layer {
+    /// Parses one graph file sitting on top of the layers whose checksums
+    /// are `bases` and which hold `base` commits between them.
+    fn parse(
+        data: Vec<u8>,
+        hash: HashAlgorithm,
+        bases: &[String],
+        base: usize,
+    ) -> std::result::Result<Self, String> {
+        if data.len() < HEADER_LEN || &data[..4] != SIGNATURE {
+            return Err("not a commit-graph".into());
+        }
+        if data[4] != VERSION {
+            return Err(format!("unsupported version {}", data[4]));
+        }
+        if data[5] != hash_version(hash) {
+            return Err(format!(
+                "hash version {} does not match the repository",
+                data[5]
+            ));
+        }
+        let chunks = ChunkTable::parse(&data, HEADER_LEN, usize::from(data[6]), hash)?;
+        if usize::from(data[7]) != bases.len() {
+            return Err(format!(
+                "built on {} layers but sits on {}",
+                data[7],
+                bases.len()
+            ));
+        }
+        if !bases.is_empty() {
+            let (start, len) = chunks.require(CHUNK_BASE)?;
+            let listed: Vec<String> = data[start..start + len]
+                .chunks(hash.raw_len())
+                .map(hex)
+                .collect();
+            if listed != bases {
+                return Err("BASE chunk does not match the chain".into());
+            }
+        }
+
+        let (fanout, fanout_len) = chunks.require(CHUNK_FANOUT)?;
+        if fanout_len != FANOUT_LEN {
+            return Err("OID fanout chunk has the wrong size".into());
+        }
+        let count = be32(&data, fanout + 255 * 4) as usize;
+        let (lookup, lookup_len) = chunks.require(CHUNK_LOOKUP)?;
+        if lookup_len != count * hash.raw_len() {
+            return Err("OID lookup chunk has the wrong size".into());
+        }
+        let (commit_data, data_len) = chunks.require(CHUNK_DATA)?;
+        if data_len != count * (hash.raw_len() + 16) {
+            return Err("commit data chunk has the wrong size".into());
+        }
+        let generation_data = match chunks.get(CHUNK_GENERATION_DATA) {
+            Some((start, len)) if len == count * 4 => Some(start),
+            Some(_) => return Err("generation data chunk has the wrong size".into()),
+            None => None,
+        };
+
+        let bloom = match (chunks.get(CHUNK_BLOOM_INDEX), chunks.get(CHUNK_BLOOM_DATA)) {
+            (Some((index, index_len)), Some((start, len)))
+                if index_len == count * 4 && len >= BloomSettings::LEN =>
+            {
+                Some(LayerBloom {
+                    settings: BloomSettings::parse(&data[start..]),
+                    index,
+                    data: (start + BloomSettings::LEN, len - BloomSettings::LEN),
+                })
+            }
+            _ => None,
+        };
+
+        Ok(Layer {
+            bloom,
+            base,
+            count,
+            fanout,
+            lookup,
+            commit_data,
+            generation_data,
+            generation_overflow: chunks.get(CHUNK_GENERATION_OVERFLOW),
+            extra_edges: chunks.get(CHUNK_EXTRA_EDGES),
+            data,
+        })
+    }
+
+    fn checksum(&self, hash: HashAlgorithm) -> &[u8] {
+        &self.data[self.data.len() - hash.raw_len()..]
+    }
+
+    fn id_at(&self, n: usize, hash: HashAlgorithm) -> ObjectId {
+        let start = self.lookup + n * hash.raw_len();
+        ObjectId::from_bytes(&self.data[start..start + hash.raw_len()])
+            .expect("fixed-size id slice")
+    }
+
+    fn position(&self, id: &ObjectId, hash: HashAlgorithm) -> Option<usize> {
+        let first = usize::from(id.as_bytes()[0]);
+        let mut hi = (be32(&self.data, self.fanout + first * 4) as usize).min(self.count);
+        let mut lo = match first {
+            0 => 0,
+            _ => be32(&self.data, self.fanout + (first - 1) * 4) as usize,
+        };
+        let raw_len = hash.raw_len();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let start = self.lookup + mid * raw_len;
+            match self.data[start..start + raw_len].cmp(id.as_bytes()) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+}
+
+/// Git's number for each object format in the header.
+fn hash_version(hash: HashAlgorithm) -> u8 {
+    match hash {
+        HashAlgorithm::Sha1 => 1,
+        HashAlgorithm::Sha256 => 2,
+    }
+}
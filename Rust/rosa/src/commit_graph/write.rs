@@ -0,0 +1,487 @@
+//! Writing commit-graph files, whole or as a new layer of a split chain.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use super::bloom::{self, BloomSettings};
+use super::{
+    chain_dir, chain_path, graph_path, hash_version, layer_path, CommitGraph, CHUNK_BASE,
+    CHUNK_BLOOM_DATA, CHUNK_BLOOM_INDEX, CHUNK_DATA, CHUNK_EXTRA_EDGES, CHUNK_FANOUT,
+    CHUNK_GENERATION_DATA, CHUNK_GENERATION_OVERFLOW, CHUNK_LOOKUP, EDGE_FLAG, HEADER_LEN,
+    MAX_LEVEL, OFFSET_OVERFLOW_FLAG, PARENT_NONE, SIGNATURE, VERSION,
+};
+use crate::chunk;
+use crate::error::{Error, Result};
+use crate::object::{hex, ObjectId};
+use crate::odb::{write_atomically, ObjectDatabase};
+
+/// How [`write`] lays the graph out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Split {
+    /// One file holding every commit, replacing any chain.
+    #[default]
+    No,
+    /// A new layer with the commits the chain lacks, merged with the
+    /// layers below while they are not much bigger than it.
+    Merge,
+    /// A new layer on top of the chain, leaving the other layers alone.
+    NoMerge,
+    /// A chain of one layer holding every commit.
+    Replace,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WriteOptions {
+    pub split: Split,
+    /// With [`Split::Merge`], a layer is merged into the new one when it
+    /// holds at most this many times as many commits.
+    pub size_multiple: usize,
+    /// With [`Split::Merge`], layers are merged regardless of size while the
+    /// new one would hold more commits than this.
+    pub max_commits: Option<usize>,
+    /// Whether to write changed-path Bloom filters; by default they are
+    /// written if the existing graph has them.
+    pub changed_paths: Option<bool>,
+    /// Compute at most this many filters that the existing graph lacks,
+    /// leaving the rest to later writes.
+    pub max_new_filters: Option<usize>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            split: Split::No,
+            size_multiple: 2,
+            max_commits: None,
+            changed_paths: None,
+            max_new_filters: None,
+        }
+    }
+}
+
+/// What [`write`] did.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteReport {
+    /// Commits in the file written.
+    pub commits: usize,
+    /// Files the graph is now made of.
+    pub layers: usize,
+    /// Bloom filters computed rather than carried over.
+    pub computed_filters: usize,
+}
+
+/// A commit about to be written.
+struct Entry {
+    tree: ObjectId,
+    parents: Vec<ObjectId>,
+    time: u64,
+    /// The changed-path filter, when carried over from an existing graph.
+    filter: Option<Vec<u8>>,
+}
+
+/// Writes a graph of every commit reachable from `tips`. Returns `None`
+/// when a split write finds nothing to add.
+pub fn write(
+    odb: &ObjectDatabase,
+    tips: &[ObjectId],
+    options: &WriteOptions,
+) -> Result<Option<WriteReport>> {
+    let hash = odb.hash();
+    let dir = odb.dir();
+
+    // Commits already in a graph are read from it rather than inflated.
+    // A damaged graph is simply not used.
+    let (known, mut base) = match options.split {
+        Split::No => (CommitGraph::open(dir, hash).ok().flatten(), None),
+        _ => (None, CommitGraph::open_chain(dir, hash).ok().flatten()),
+    };
+    let base_len = base.as_ref().map_or(0, CommitGraph::len);
+    let settings = BloomSettings::default();
+    let write_filters = options.changed_paths.unwrap_or_else(|| {
+        known
+            .as_ref()
+            .or(base.as_ref())
+            .is_some_and(|g| g.bloom_settings().is_some())
+    });
+    let mut entries = collect(
+        odb,
+        known.as_ref().or(base.as_ref()),
+        base_len,
+        tips,
+        &settings,
+    )?;
+
+    if let Some(chain) = &mut base {
+        let mut new_commits = entries.len();
+        let mut keep = chain.layers.len();
+        while keep > 0 {
+            let below = chain.layers[keep - 1].count;
+            let merge = match options.split {
+                Split::Replace => true,
+                Split::Merge => {
+                    below <= options.size_multiple.saturating_mul(new_commits)
+                        || options.max_commits.is_some_and(|max| new_commits > max)
+                }
+                _ => false,
+            };
+            if !merge {
+                break;
+            }
+            new_commits += below;
+            keep -= 1;
+        }
+        if entries.is_empty() && keep == chain.layers.len() {
+            return Ok(None);
+        }
+        let kept_len = chain.layers.get(keep).map_or(chain.len(), |l| l.base);
+        for pos in kept_len..chain.len() {
+            let commit = chain.commit_at(pos)?;
+            entries.insert(
+                commit.id,
+                Entry {
+                    tree: commit.tree,
+                    parents: commit.parents.iter().map(|&p| chain.id_at(p)).collect(),
+                    time: commit.commit_time,
+                    filter: chain.bloom_filter(pos, &settings).map(<[u8]>::to_vec),
+                },
+            );
+        }
+        chain.layers.truncate(keep);
+        *chain = CommitGraph::from_layers(hash, std::mem::take(&mut chain.layers));
+    }
+    let base = base.filter(|chain| !chain.is_empty());
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    // Corrected dates are only worth writing if the layers below have them.
+    let write_generation_data = base.as_ref().is_none_or(|b| b.has_corrected_dates());
+    let generations = generations(&entries, base.as_ref())?;
+
+    let mut ids: Vec<ObjectId> = entries.keys().copied().collect();
+    ids.sort();
+    let base_len = base.as_ref().map_or(0, CommitGraph::len);
+    let positions: HashMap<ObjectId, usize> = ids
+        .iter()
+        .enumerate()
+        .map(|(n, id)| (*id, base_len + n))
+        .collect();
+    let computed_filters = match write_filters {
+        true => compute_filters(
+            odb,
+            &mut entries,
+            &generations,
+            base.as_ref(),
+            &settings,
+            options.max_new_filters,
+        )?,
+        false => 0,
+    };
+    let position = |id: &ObjectId| -> Result<u32> {
+        positions
+            .get(id)
+            .copied()
+            .or_else(|| base.as_ref().and_then(|b| b.position(id)))
+            .map(|p| p as u32)
+            .ok_or(Error::ObjectNotFound(*id))
+    };
+
+    let mut lookup = Vec::with_capacity(ids.len() * hash.raw_len());
+    let mut data = Vec::with_capacity(ids.len() * (hash.raw_len() + 16));
+    let mut generation_data = Vec::with_capacity(ids.len() * 4);
+    let mut overflow = Vec::new();
+    let mut edges: Vec<u32> = Vec::new();
+    for id in &ids {
+        let entry = &entries[id];
+        let (level, corrected) = generations[id];
+        lookup.extend_from_slice(id.as_bytes());
+
+        data.extend_from_slice(entry.tree.as_bytes());
+        let first = match entry.parents.first() {
+            Some(parent) => position(parent)?,
+            None => PARENT_NONE,
+        };
+        let second = match entry.parents.as_slice() {
+            [] | [_] => PARENT_NONE,
+            [_, second] => position(second)?,
+            [_, rest @ ..] => {
+                let start = edges.len() as u32 | EDGE_FLAG;
+                for parent in rest {
+                    edges.push(position(parent)?);
+                }
+                *edges.last_mut().expect("octopus has extra parents") |= EDGE_FLAG;
+                start
+            }
+        };
+        data.extend_from_slice(&first.to_be_bytes());
+        data.extend_from_slice(&second.to_be_bytes());
+        let high = (level.min(MAX_LEVEL) << 2) | ((entry.time >> 32) & 3) as u32;
+        data.extend_from_slice(&high.to_be_bytes());
+        data.extend_from_slice(&(entry.time as u32).to_be_bytes());
+
+        let offset = corrected - entry.time;
+        let stored = match u32::try_from(offset) {
+            Ok(offset) if offset & OFFSET_OVERFLOW_FLAG == 0 => offset,
+            _ => {
+                overflow.extend_from_slice(&offset.to_be_bytes());
+                OFFSET_OVERFLOW_FLAG | (overflow.len() / 8 - 1) as u32
+            }
+        };
+        generation_data.extend_from_slice(&stored.to_be_bytes());
+    }
+
+    let mut chunks = vec![
+        (CHUNK_FANOUT, chunk::fanout(&ids)),
+        (CHUNK_LOOKUP, lookup),
+        (CHUNK_DATA, data),
+    ];
+    if write_generation_data {
+        chunks.push((CHUNK_GENERATION_DATA, generation_data));
+        if !overflow.is_empty() {
+            chunks.push((CHUNK_GENERATION_OVERFLOW, overflow));
+        }
+    }
+    if !edges.is_empty() {
+        let edges = edges.iter().flat_map(|e| e.to_be_bytes()).collect();
+        chunks.push((CHUNK_EXTRA_EDGES, edges));
+    }
+    if write_filters {
+        let mut index = Vec::with_capacity(ids.len() * 4);
+        let mut data = settings.serialize().to_vec();
+        for id in &ids {
+            // Filters left for later are empty.
+            if let Some(filter) = &entries[id].filter {
+                data.extend_from_slice(filter);
+            }
+            let end = (data.len() - BloomSettings::LEN) as u32;
+            index.extend_from_slice(&end.to_be_bytes());
+        }
+        chunks.push((CHUNK_BLOOM_INDEX, index));
+        chunks.push((CHUNK_BLOOM_DATA, data));
+    }
+    let base_checksums: Vec<String> = base
+        .iter()
+        .flat_map(|b| &b.layers)
+        .map(|l| hex(l.checksum(hash)))
+        .collect();
+    if let Some(base) = &base {
+        let listed = base
+            .layers
+            .iter()
+            .flat_map(|l| l.checksum(hash).to_vec())
+            .collect();
+        chunks.push((CHUNK_BASE, listed));
+    }
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(SIGNATURE);
+    header.extend_from_slice(&[
+        VERSION,
+        hash_version(hash),
+        chunks.len() as u8,
+        base_checksums.len() as u8,
+    ]);
+    let out = chunk::assemble(&header, &chunks, hash)?;
+
+    if options.split == Split::No {
+        // Repositories need not have an `info` directory yet.
+        fs::create_dir_all(dir.join("info"))?;
+        write_atomically(&graph_path(dir), &out)?;
+        remove_stale_layers(odb, &[])?;
+        return Ok(Some(WriteReport {
+            commits: ids.len(),
+            layers: 1,
+            computed_filters,
+        }));
+    }
+
+    let checksum = hex(&out[out.len() - hash.raw_len()..]);
+    fs::create_dir_all(chain_dir(dir))?;
+    write_atomically(&layer_path(dir, &checksum), &out)?;
+    let mut chain = base_checksums;
+    chain.push(checksum);
+    let text: String = chain.iter().map(|c| format!("{c}\n")).collect();
+    write_atomically(&chain_path(dir), text.as_bytes())?;
+    // The single file would take precedence over the chain.
+    remove_if_present(&graph_path(dir))?;
+    remove_stale_layers(odb, &chain)?;
+    Ok(Some(WriteReport {
+        commits: ids.len(),
+        layers: chain.len(),
+        computed_filters,
+    }))
+}
+
+/// Walks from `tips` to every commit outside the first `stop` positions of
+/// `known`, reading commits from `known` where it has them.
+fn collect(
+    odb: &ObjectDatabase,
+    known: Option<&CommitGraph>,
+    stop: usize,
+    tips: &[ObjectId],
+    settings: &BloomSettings,
+) -> Result<HashMap<ObjectId, Entry>> {
+    let mut entries = HashMap::new();
+    let mut pending: Vec<ObjectId> = tips.to_vec();
+    while let Some(id) = pending.pop() {
+        if entries.contains_key(&id) {
+            continue;
+        }
+        let graph_entry = known.and_then(|g| Some((g, g.position(&id)?)));
+        if graph_entry.is_some_and(|(_, pos)| pos < stop) {
+            continue;
+        }
+        let graph_entry = graph_entry.and_then(|(g, pos)| Some((g, pos, g.commit_at(pos).ok()?)));
+        let entry = match graph_entry {
+            Some((graph, pos, commit)) => Entry {
+                tree: commit.tree,
+                parents: commit.parents.iter().map(|&p| graph.id_at(p)).collect(),
+                time: commit.commit_time,
+                filter: graph.bloom_filter(pos, settings).map(<[u8]>::to_vec),
+            },
+            None => {
+                let commit = odb.read(&id)?.into_commit(id)?;
+                Entry {
+                    tree: commit.tree,
+                    parents: commit.parents,
+                    time: u64::try_from(commit.committer.time).unwrap_or(0) & ((1 << 34) - 1),
+                    filter: None,
+                }
+            }
+        };
+        pending.extend(entry.parents.iter().copied());
+        entries.insert(id, entry);
+    }
+    Ok(entries)
+}
+
+/// Computes the Bloom filters `entries` lack, oldest commits first and at
+/// most `max_new` of them, returning how many were computed.
+fn compute_filters(
+    odb: &ObjectDatabase,
+    entries: &mut HashMap<ObjectId, Entry>,
+    generations: &HashMap<ObjectId, (u32, u64)>,
+    base: Option<&CommitGraph>,
+    settings: &BloomSettings,
+    max_new: Option<usize>,
+) -> Result<usize> {
+    let mut missing: Vec<(u64, u64, ObjectId)> = entries
+        .iter()
+        .filter(|(_, e)| e.filter.is_none())
+        .map(|(id, e)| (generations[id].1, e.time, *id))
+        .collect();
+    missing.sort();
+    missing.truncate(max_new.unwrap_or(usize::MAX));
+
+    for (_, _, id) in &missing {
+        let entry = &entries[id];
+        let parent_tree = match entry.parents.first() {
+            Some(parent) => Some(match entries.get(parent) {
+                Some(parent) => parent.tree,
+                None => {
+                    let base = base.ok_or(Error::ObjectNotFound(*parent))?;
+                    let pos = base
+                        .position(parent)
+                        .ok_or(Error::ObjectNotFound(*parent))?;
+                    base.commit_at(pos)?.tree
+                }
+            }),
+            None => None,
+        };
+        let filter = bloom::compute_filter(odb, parent_tree, entry.tree, settings)?;
+        entries.get_mut(id).expect("listed from entries").filter = Some(filter);
+    }
+    Ok(missing.len())
+}
+
+/// The topological level and corrected commit date of each entry, parents
+/// first. Parents outside `entries` are looked up in `base`.
+fn generations(
+    entries: &HashMap<ObjectId, Entry>,
+    base: Option<&CommitGraph>,
+) -> Result<HashMap<ObjectId, (u32, u64)>> {
+    let mut done: HashMap<ObjectId, (u32, u64)> = HashMap::with_capacity(entries.len());
+    let from_base = |id: &ObjectId| -> Result<(u32, u64)> {
+        let base = base.ok_or(Error::ObjectNotFound(*id))?;
+        let pos = base.position(id).ok_or(Error::ObjectNotFound(*id))?;
+        let commit = base.commit_at(pos)?;
+        Ok((commit.level, commit.generation))
+    };
+
+    for &start in entries.keys() {
+        let mut stack = vec![start];
+        while let Some(&id) = stack.last() {
+            if done.contains_key(&id) {
+                stack.pop();
+                continue;
+            }
+            let entry = &entries[&id];
+            let mut level = 0;
+            let mut corrected = 0;
+            let mut ready = true;
+            for parent in &entry.parents {
+                let (parent_level, parent_corrected) = match done.get(parent) {
+                    Some(&generation) => generation,
+                    None if entries.contains_key(parent) => {
+                        stack.push(*parent);
+                        ready = false;
+                        continue;
+                    }
+                    None => from_base(parent)?,
+                };
+                level = level.max(parent_level);
+                corrected = corrected.max(parent_corrected);
+            }
+            if ready {
+                let corrected = entry.time.max(if entry.parents.is_empty() {
+                    0
+                } else {
+                    corrected + 1
+                });
+                done.insert(id, ((level + 1).min(MAX_LEVEL), corrected));
+                stack.pop();
+            }
+        }
+    }
+    Ok(done)
+}
+
+/// Deletes the chain and layer files that are no longer part of the graph:
+/// all of them when the graph is a single file (`keep` is empty).
+fn remove_stale_layers(odb: &ObjectDatabase, keep: &[String]) -> Result<()> {
+    let dir = chain_dir(odb.dir());
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let stale = match name
+            .strip_prefix("graph-")
+            .and_then(|n| n.strip_suffix(".graph"))
+        {
+            Some(checksum) => !keep.iter().any(|k| k == checksum),
+            None => keep.is_empty() && name == "commit-graph-chain",
+        };
+        if stale {
+            fs::remove_file(&path)?;
+        }
+    }
+    if keep.is_empty() {
+        // Only succeeds once the directory is empty, which is all we want.
+        let _ = fs::remove_dir(&dir);
+    }
+    Ok(())
+}
+
+fn remove_if_present(path: &std::path::Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
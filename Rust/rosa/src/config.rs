@@ -0,0 +1,332 @@
+//! Git configuration files.
+//!
+//! Only the subset of the syntax git itself writes is supported: sections
+//! with optional quoted subsections, `key = value` lines, comments and
+//! double-quoted values with backslash escapes.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    section: String,
+    subsection: Option<String>,
+    key: String,
+    value: Option<String>,
+}
+
+impl Entry {
+    fn matches(&self, section: &str, subsection: Option<&str>, key: &str) -> bool {
+        self.section == section && self.subsection.as_deref() == subsection && self.key == key
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    entries: Vec<Entry>,
+}
+
+impl Config {
+    /// Loads a config file, treating a missing file as empty.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Loads the user-wide configuration (`$XDG_CONFIG_HOME/git/config`
+    /// and `~/.gitconfig`), later files overriding earlier ones.
+    pub fn load_global() -> Result<Self> {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let xdg = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|h| h.join(".config")));
+
+        let mut config = Config::default();
+        for path in [
+            xdg.map(|x| x.join("git").join("config")),
+            home.map(|h| h.join(".gitconfig")),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            config.entries.extend(Config::load(&path)?.entries);
+        }
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut section: Option<(String, Option<String>)> = None;
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let bad = |what: &str| Error::Config(format!("line {}: {what}", lineno + 1));
+
+            if let Some(rest) = line.strip_prefix('[') {
+                let end = rest.find(']').ok_or_else(|| bad("unterminated section"))?;
+                let header = &rest[..end];
+                section = Some(match header.find(|c: char| c.is_whitespace()) {
+                    Some(split) => {
+                        let sub = header[split..].trim();
+                        let sub = sub
+                            .strip_prefix('"')
+                            .and_then(|s| s.strip_suffix('"'))
+                            .ok_or_else(|| bad("subsection must be quoted"))?;
+                        (header[..split].to_ascii_lowercase(), Some(unescape(sub)))
+                    }
+                    // Legacy `[section.subsection]` syntax.
+                    None => match header.split_once('.') {
+                        Some((s, sub)) => (s.to_ascii_lowercase(), Some(sub.to_ascii_lowercase())),
+                        None => (header.to_ascii_lowercase(), None),
+                    },
+                });
+                continue;
+            }
+
+            let (section, subsection) =
+                section.clone().ok_or_else(|| bad("key outside section"))?;
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), Some(parse_value(v))),
+                None => (strip_comment(line).trim(), None),
+            };
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(bad("invalid key"));
+            }
+            entries.push(Entry {
+                section,
+                subsection,
+                key: key.to_ascii_lowercase(),
+                value,
+            });
+        }
+
+        Ok(Config { entries })
+    }
+
+    /// Looks up a dotted name such as `core.bare` or `remote.origin.url`.
+    /// The last occurrence wins, as in git.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).last().map(|v| v.unwrap_or("true"))
+    }
+
+    /// Every value set for a dotted name, in file order. Keys given without
+    /// `=` yield `None`.
+    pub fn get_all(&self, name: &str) -> Vec<Option<&str>> {
+        let Some((section, subsection, key)) = split_name(name) else {
+            return Vec::new();
+        };
+        self.entries
+            .iter()
+            .filter(|e| e.matches(&section, subsection, &key))
+            .map(|e| e.value.as_deref())
+            .collect()
+    }
+
+    /// The distinct keys set directly in `section` (no subsection),
+    /// lowercased, in file order.
+    pub fn keys(&self, section: &str) -> Vec<&str> {
+        let section = section.to_ascii_lowercase();
+        let mut keys: Vec<&str> = Vec::new();
+        for e in &self.entries {
+            if e.section == section && e.subsection.is_none() && !keys.contains(&e.key.as_str()) {
+                keys.push(&e.key);
+            }
+        }
+        keys
+    }
+
+    pub fn get_bool(&self, name: &str) -> Result<Option<bool>> {
+        self.get(name)
+            .map(|v| match v.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok(true),
+                "false" | "no" | "off" | "0" | "" => Ok(false),
+                _ => Err(Error::Config(format!("bad boolean value '{v}' for {name}"))),
+            })
+            .transpose()
+    }
+
+    /// Reads an integer, honouring git's `k`, `m` and `g` suffixes.
+    pub fn get_int(&self, name: &str) -> Result<Option<i64>> {
+        self.get(name)
+            .map(|v| {
+                let bad = || Error::Config(format!("bad numeric value '{v}' for {name}"));
+                let (digits, scale) = match v.chars().last().map(|c| c.to_ascii_lowercase()) {
+                    Some('k') => (&v[..v.len() - 1], 1 << 10),
+                    Some('m') => (&v[..v.len() - 1], 1 << 20),
+                    Some('g') => (&v[..v.len() - 1], 1 << 30),
+                    _ => (v, 1),
+                };
+                digits
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|n| n.checked_mul(scale))
+                    .ok_or_else(bad)
+            })
+            .transpose()
+    }
+
+    /// Sets a dotted name, replacing any existing values. As in git, the
+    /// section and key may only hold letters, digits and `-`, and the key
+    /// must start with a letter; the subsection may not hold a newline.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let (section, subsection, key) = split_name(name)
+            .ok_or_else(|| Error::Config(format!("key does not contain a section: {name}")))?;
+        let valid = |s: &str| s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+        if section.is_empty()
+            || !valid(&section)
+            || !key.starts_with(|c: char| c.is_ascii_alphabetic())
+            || !valid(&key)
+            || subsection.is_some_and(|sub| sub.contains('\n'))
+        {
+            return Err(Error::Config(format!("invalid key: {name}")));
+        }
+        let entry = Entry {
+            subsection: subsection.map(str::to_owned),
+            section,
+            key,
+            value: Some(value.to_owned()),
+        };
+        let same = |e: &Entry| e.matches(&entry.section, entry.subsection.as_deref(), &entry.key);
+        match self.entries.iter().position(same) {
+            Some(pos) => {
+                self.entries.retain(|e| !same(e));
+                self.entries.insert(pos, entry);
+            }
+            None => {
+                // Keep entries of the same section together.
+                let pos = self
+                    .entries
+                    .iter()
+                    .rposition(|e| e.section == entry.section && e.subsection == entry.subsection)
+                    .map_or(self.entries.len(), |p| p + 1);
+                self.entries.insert(pos, entry);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = String::new();
+        let mut current: Option<(&str, Option<&str>)> = None;
+        for e in &self.entries {
+            let this = (e.section.as_str(), e.subsection.as_deref());
+            if current != Some(this) {
+                match e.subsection {
+                    Some(ref sub) => writeln!(out, "[{} \"{}\"]", e.section, escape(sub))?,
+                    None => writeln!(out, "[{}]", e.section)?,
+                }
+                current = Some(this);
+            }
+            match e.value {
+                Some(ref v) => writeln!(out, "\t{} = {}", e.key, quote(v))?,
+                None => writeln!(out, "\t{}", e.key)?,
+            }
+        }
+        f.write_str(&out)
+    }
+}
+
+/// Splits `section[.subsection].key`; section and key are case-insensitive.
+fn split_name(name: &str) -> Option<(String, Option<&str>, String)> {
+    let (section, rest) = name.split_once('.')?;
+    let (subsection, key) = match rest.rsplit_once('.') {
+        Some((sub, key)) => (Some(sub), key),
+        None => (None, rest),
+    };
+    Some((
+        section.to_ascii_lowercase(),
+        subsection,
+        key.to_ascii_lowercase(),
+    ))
+}
+
+fn strip_comment(s: &str) -> &str {
+    s.find(['#', ';']).map_or(s, |i| &s[..i])
+}
+
+fn parse_value(raw: &str) -> String {
+    let mut out = String::new();
+    let mut in_quotes = false;
+    let mut chars = raw.trim().chars();
+    // Trailing whitespace outside quotes is dropped, inside it is kept.
+    let mut pending_space = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push_str(&pending_space);
+                pending_space.clear();
+                in_quotes = !in_quotes;
+            }
+            '\\' => {
+                out.push_str(&pending_space);
+                pending_space.clear();
+                match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('b') => {
+                        out.pop();
+                    }
+                    Some(other) => out.push(other),
+                    None => {}
+                }
+            }
+            '#' | ';' if !in_quotes => break,
+            c if c.is_whitespace() && !in_quotes => pending_space.push(c),
+            c => {
+                out.push_str(&pending_space);
+                pending_space.clear();
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn quote(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    let escaped = escape(value).replace('\n', "\\n").replace('\t', "\\t");
+    if needs_quotes {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
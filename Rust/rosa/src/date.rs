@@ -0,0 +1,288 @@
+//! Dates: read as people write them, for `@{<date>}`, and shown in the
+//! formats of `--date=<format>`.
+//!
+//! Only UTC is known here: dates without an explicit offset are taken to
+//! be UTC, where git would use the local time zone.
+
+use crate::object::Offset;
+
+const DAY: i64 = 86_400;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// How a date is shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DateMode {
+    /// `Thu Nov 16 22:13:20 2023 +0100`
+    #[default]
+    Default,
+    /// `2023-11-16 22:13:20 +0100`
+    Iso,
+    /// `2023-11-16T22:13:20+01:00`
+    IsoStrict,
+    /// `Thu, 16 Nov 2023 22:13:20 +0100`
+    Rfc,
+    /// `2023-11-16`
+    Short,
+    /// `1700169200 +0100`
+    Raw,
+    /// `1700169200`
+    Unix,
+    /// `3 weeks ago`
+    Relative,
+}
+
+impl DateMode {
+    /// The mode `--date=<name>` asks for.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "default" => DateMode::Default,
+            "iso" | "iso8601" => DateMode::Iso,
+            "iso-strict" | "iso8601-strict" => DateMode::IsoStrict,
+            "rfc" | "rfc2822" => DateMode::Rfc,
+            "short" => DateMode::Short,
+            "raw" => DateMode::Raw,
+            "unix" => DateMode::Unix,
+            "relative" => DateMode::Relative,
+            _ => return None,
+        })
+    }
+}
+
+/// Shows `time`, in the zone `offset` east of UTC, the way `mode` says;
+/// relative dates count back from `now`.
+pub fn format(time: i64, offset: Offset, mode: DateMode, now: i64) -> String {
+    // An unknown zone shows as UTC, `+0000`, as in git.
+    let offset = Offset::from_minutes(offset.minutes);
+    let local = time + i64::from(offset.minutes) * 60;
+    let days = local.div_euclid(DAY);
+    let (year, month, day) = civil_from_days(days);
+    let month_name = MONTHS[month as usize - 1];
+    let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+    let seconds = local.rem_euclid(DAY);
+    let clock = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    match mode {
+        DateMode::Default => {
+            format!("{weekday} {month_name} {day} {clock} {year} {offset}")
+        }
+        DateMode::Iso => format!("{year:04}-{month:02}-{day:02} {clock} {offset}"),
+        DateMode::IsoStrict => {
+            let zone = offset.to_string();
+            let (hours, minutes) = zone.split_at(3);
+            format!("{year:04}-{month:02}-{day:02}T{clock}{hours}:{minutes}")
+        }
+        DateMode::Rfc => {
+            format!("{weekday}, {day} {month_name} {year} {clock} {offset}")
+        }
+        DateMode::Short => short(time, offset.minutes),
+        DateMode::Raw => format!("{time} {offset}"),
+        DateMode::Unix => time.to_string(),
+        DateMode::Relative => relative(time, now),
+    }
+}
+
+/// How long before `now` `time` was, rounded as git rounds it.
+fn relative(time: i64, now: i64) -> String {
+    if now < time {
+        return "in the future".into();
+    }
+    let ago = |count: i64, unit: &str| {
+        let plural = if count == 1 { "" } else { "s" };
+        format!("{count} {unit}{plural} ago")
+    };
+    let seconds = now - time;
+    if seconds < 90 {
+        return ago(seconds, "second");
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return ago(minutes, "minute");
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return ago(hours, "hour");
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return ago(days, "day");
+    }
+    if days < 70 {
+        return ago((days + 3) / 7, "week");
+    }
+    if days < 365 {
+        return ago((days + 15) / 30, "month");
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months == 0 {
+            return ago(years, "year");
+        }
+        let plural = if years == 1 { "" } else { "s" };
+        return format!("{years} year{plural}, {}", ago(months, "month"));
+    }
+    ago((days + 183) / 365, "year")
+}
+
+/// The seconds since the epoch `text` stands for, relative to `now` where
+/// it is relative. Understands what `git rev-parse` users mostly type:
+///
+/// - `now`, `yesterday`
+/// - `@<seconds>`, or a bare number of seconds of at least nine digits
+/// - `YYYY-MM-DD`, optionally followed by `[T ]HH:MM[:SS]` and an offset
+///   (`Z`, `+hhmm` or `+hh:mm`); a date alone keeps the time of day of
+///   `now`, as in git
+/// - counts of units going back from `now`, such as `2.weeks.ago` or
+///   `1 day 3 hours ago`
+pub fn parse(text: &str, now: i64) -> Option<i64> {
+    let text = text.trim();
+    match text.to_ascii_lowercase().as_str() {
+        "now" => return Some(now),
+        "yesterday" => return Some(now - DAY),
+        _ => {}
+    }
+    if let Some(seconds) = text.strip_prefix('@') {
+        return seconds.parse().ok();
+    }
+    if text.len() >= 9 && text.bytes().all(|b| b.is_ascii_digit()) {
+        return text.parse().ok();
+    }
+    parse_absolute(text, now).or_else(|| parse_relative(text, now))
+}
+
+fn parse_absolute(text: &str, now: i64) -> Option<i64> {
+    let (date, rest) = text.split_at(text.find([' ', 'T']).unwrap_or(text.len()));
+    let mut fields = date.splitn(3, '-');
+    let year = fields.next()?.parse().ok()?;
+    let month = fields.next()?.parse().ok()?;
+    let day = fields.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+
+    let rest = rest.trim_start_matches([' ', 'T']);
+    if rest.is_empty() {
+        return Some(days * DAY + now.rem_euclid(DAY));
+    }
+    let zone_at = rest.find(['Z', '+', '-', ' ']).unwrap_or(rest.len());
+    let (time, zone) = rest.split_at(zone_at);
+    let mut fields = time.split(':');
+    let hour: i64 = fields.next()?.parse().ok()?;
+    let minute: i64 = fields.next()?.parse().ok()?;
+    let second: i64 = fields.next().map_or(Some(0), |s| s.parse().ok())?;
+    if fields.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let offset = match zone.trim() {
+        "" | "Z" => 0,
+        zone => parse_offset(zone)?,
+    };
+    Some(days * DAY + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// `+hhmm` or `+hh:mm` as seconds east of UTC.
+fn parse_offset(zone: &str) -> Option<i64> {
+    let sign = match zone.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = zone[1..].replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes): (i64, i64) = (digits[..2].parse().ok()?, digits[2..].parse().ok()?);
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn parse_relative(text: &str, now: i64) -> Option<i64> {
+    let mut words = text
+        .split([' ', '.'])
+        .filter(|w| !w.is_empty())
+        .map(str::to_ascii_lowercase)
+        .peekable();
+    let mut time = now;
+    let mut any = false;
+    while let Some(word) = words.next() {
+        if word == "ago" && words.peek().is_none() && any {
+            break;
+        }
+        let count: i64 = word.parse().ok()?;
+        let unit = words.next()?;
+        let unit = unit.strip_suffix('s').unwrap_or(&unit);
+        time = match unit {
+            "second" => time - count,
+            "minute" => time - count * 60,
+            "hour" => time - count * 3600,
+            "day" => time - count * DAY,
+            "week" => time - count * 7 * DAY,
+            "month" => add_months(time, -count),
+            "year" => add_months(time, -12 * count),
+            _ => return None,
+        };
+        any = true;
+    }
+    any.then_some(time)
+}
+
+/// `time` moved by whole calendar months, the day clamped to the length
+/// of the month reached.
+fn add_months(time: i64, months: i64) -> i64 {
+    let (year, month, day) = civil_from_days(time.div_euclid(DAY));
+    let index = year * 12 + i64::from(month) - 1 + months;
+    let (year, month) = (index.div_euclid(12), (index.rem_euclid(12) + 1) as u32);
+    let day = day.min(days_in_month(year, month));
+    days_from_civil(year, month, day) * DAY + time.rem_euclid(DAY)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// `YYYY-MM-DD` of `time` in the zone `offset_minutes` east of UTC.
+pub fn short(time: i64, offset_minutes: i32) -> String {
+    let local = time + i64::from(offset_minutes) * 60;
+    let (year, month, day) = civil_from_days(local.div_euclid(DAY));
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day of a number of days since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,111 @@
+//! Comparing trees.
+
+use std::collections::BTreeMap;
+
+use crate::error::Result;
+use crate::object::{ObjectId, Tree, MODE_TREE};
+use crate::odb::ObjectDatabase;
+
+/// The paths of the files that differ between `old` and `new` (either
+/// absent for an empty tree), recursing into subtrees. Stops and returns
+/// `None` once more than `limit` paths are found.
+pub fn changed_paths(
+    odb: &ObjectDatabase,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    limit: Option<usize>,
+) -> Result<Option<Vec<Vec<u8>>>> {
+    let mut out = Vec::new();
+    let mut pending = vec![(Vec::new(), old, new)];
+    while let Some((prefix, old, new)) = pending.pop() {
+        if old == new {
+            continue;
+        }
+        let old = entries(odb, old)?;
+        let new = entries(odb, new)?;
+        let mut names: Vec<&Vec<u8>> = old.keys().chain(new.keys()).collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let (a, b) = (old.get(name), new.get(name));
+            if a == b {
+                continue;
+            }
+            let mut path = prefix.clone();
+            if !path.is_empty() {
+                path.push(b'/');
+            }
+            path.extend_from_slice(name);
+
+            let tree = |side: Option<&(u32, ObjectId)>| {
+                side.filter(|(m, _)| *m == MODE_TREE).map(|(_, id)| *id)
+            };
+            let (old_tree, new_tree) = (tree(a), tree(b));
+            // A file on either side is a change of its own; the files of a
+            // tree on either side are compared one by one.
+            let file_changed =
+                a.is_some_and(|(m, _)| *m != MODE_TREE) || b.is_some_and(|(m, _)| *m != MODE_TREE);
+            if file_changed {
+                out.push(path.clone());
+            }
+            if old_tree.is_some() || new_tree.is_some() {
+                pending.push((path, old_tree, new_tree));
+            }
+            if limit.is_some_and(|limit| out.len() > limit) {
+                return Ok(None);
+            }
+        }
+    }
+    out.sort();
+    Ok(Some(out))
+}
+
+/// Whether anything at or below one of `paths` differs between `old` and
+/// `new`.
+pub fn differs_under(
+    odb: &ObjectDatabase,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    paths: &[Vec<u8>],
+) -> Result<bool> {
+    if old == new {
+        return Ok(false);
+    }
+    for path in paths {
+        if entry_at(odb, old, path)? != entry_at(odb, new, path)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The mode and id found at `path` (`/`-separated) inside `tree`.
+pub fn entry_at(
+    odb: &ObjectDatabase,
+    tree: Option<ObjectId>,
+    path: &[u8],
+) -> Result<Option<(u32, ObjectId)>> {
+    let mut found = tree.map(|id| (MODE_TREE, id));
+    for part in path.split(|&b| b == b'/').filter(|p| !p.is_empty()) {
+        found = match found {
+            Some((MODE_TREE, id)) => entries(odb, Some(id))?.remove(part),
+            _ => return Ok(None),
+        };
+    }
+    Ok(found)
+}
+
+fn entries(
+    odb: &ObjectDatabase,
+    tree: Option<ObjectId>,
+) -> Result<BTreeMap<Vec<u8>, (u32, ObjectId)>> {
+    let Some(id) = tree else {
+        return Ok(BTreeMap::new());
+    };
+    let (_, data) = odb.read_raw(&id)?;
+    Ok(Tree::parse(&data, odb.hash())?
+        .entries
+        .into_iter()
+        .map(|e| (e.name, (e.mode, e.id)))
+        .collect())
+}
@@ -0,0 +1,91 @@
+//! Error type shared by the whole crate.
+
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::object::ObjectId;
+
+/// Everything that can go wrong while talking to a repository.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("not a git repository: {}", .0.display())]
+    NotARepository(PathBuf),
+
+    #[error("{} is not empty", .0.display())]
+    NotEmpty(PathBuf),
+
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    #[error("malformed object {id}: {reason}")]
+    MalformedObject { id: ObjectId, reason: String },
+
+    #[error("unknown object type {0}")]
+    UnknownObjectType(String),
+
+    #[error("object {0} not found")]
+    ObjectNotFound(ObjectId),
+
+    #[error("object {id} is a {actual}, not a {expected}")]
+    UnexpectedObjectType {
+        id: ObjectId,
+        expected: &'static str,
+        actual: &'static str,
+    },
+
+    /// Hashing met data crafted to produce a SHA-1 collision (as in the
+    /// SHAttered attack); the digest is the one the attack aimed for.
+    #[error("SHA-1 appears to be part of a collision attack: {0}")]
+    Sha1Collision(String),
+
+    #[error("invalid object id {0}")]
+    InvalidObjectId(String),
+
+    #[error("failed to parse {kind}: {reason}")]
+    Parse { kind: &'static str, reason: String },
+
+    #[error("no such reference {0}")]
+    NoSuchRef(String),
+
+    #[error("'{0}' is not a valid ref name")]
+    InvalidRefName(String),
+
+    /// The `.lock` file next to a file being rewritten already exists:
+    /// another process is updating it, or crashed while doing so.
+    #[error("unable to create '{}': file exists", .0.display())]
+    Locked(PathBuf),
+
+    /// A `<rev>:<path>` or `:<path>` whose path is not there; `within`
+    /// says where it was looked for.
+    #[error("path '{path}' does not exist in {within}")]
+    NoSuchPath { path: String, within: String },
+
+    /// An id prefix several objects share, with a description of each.
+    #[error(
+        "short object ID {prefix} is ambiguous\nhint: The candidates are:{}",
+        .candidates.iter().map(|c| format!("\nhint:   {c}")).collect::<String>()
+    )]
+    AmbiguousId {
+        prefix: String,
+        candidates: Vec<String>,
+    },
+
+    #[error("{0}")]
+    Usage(String),
+}
+
+impl Error {
+    pub(crate) fn parse(kind: &'static str, reason: impl Into<String>) -> Self {
+        Error::Parse {
+            kind,
+            reason: reason.into(),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,1020 @@
+//! Checking a repository: that every object is well formed and stored
+//! under its own hash, and that everything refs, HEAD, the index and the
+//! reflogs lead to is there.
+//!
+//! Objects are checked from their raw bytes rather than through the typed
+//! parsers, which give up at the first oddity; each kind of problem has the
+//! name git gives it (`badTreeSha1`, `missingEmail`, ...), and its severity
+//! can be changed with `fsck.<name>` as in git.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::index::{Index, MODE_TYPE_GITLINK};
+use crate::object::{is_lower_hex, HashAlgorithm, ObjectId, ObjectKind};
+use crate::path;
+use crate::reflog;
+use crate::refs;
+use crate::repository::Repository;
+
+/// How much a problem matters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Reported as a warning, but never turned into an error by `--strict`.
+    Info,
+    Ignore,
+}
+
+macro_rules! message_ids {
+    ($($variant:ident $name:literal $severity:ident,)*) => {
+        /// The problems found in objects, named as git names them.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum MessageId {
+            $($variant,)*
+        }
+
+        impl MessageId {
+            pub const ALL: &'static [MessageId] = &[$(MessageId::$variant,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(MessageId::$variant => $name,)*
+                }
+            }
+
+            pub fn default_severity(self) -> Severity {
+                match self {
+                    $(MessageId::$variant => Severity::$severity,)*
+                }
+            }
+        }
+    };
+}
+
+message_ids! {
+    BadDate "badDate" Error,
+    BadDateOverflow "badDateOverflow" Error,
+    BadEmail "badEmail" Error,
+    BadFilemode "badFilemode" Info,
+    BadName "badName" Error,
+    BadObjectSha1 "badObjectSha1" Error,
+    BadParentSha1 "badParentSha1" Error,
+    BadTagName "badTagName" Info,
+    BadTimezone "badTimezone" Error,
+    BadTree "badTree" Error,
+    BadTreeSha1 "badTreeSha1" Error,
+    BadType "badType" Error,
+    DuplicateEntries "duplicateEntries" Error,
+    EmptyName "emptyName" Warning,
+    FullPathname "fullPathname" Warning,
+    HasDot "hasDot" Warning,
+    HasDotdot "hasDotdot" Warning,
+    HasDotgit "hasDotgit" Warning,
+    MissingAuthor "missingAuthor" Error,
+    MissingCommitter "missingCommitter" Error,
+    MissingEmail "missingEmail" Error,
+    MissingNameBeforeEmail "missingNameBeforeEmail" Error,
+    MissingObject "missingObject" Error,
+    MissingSpaceBeforeDate "missingSpaceBeforeDate" Error,
+    MissingSpaceBeforeEmail "missingSpaceBeforeEmail" Error,
+    MissingTagEntry "missingTagEntry" Error,
+    MissingTaggerEntry "missingTaggerEntry" Info,
+    MissingTree "missingTree" Error,
+    MissingTypeEntry "missingTypeEntry" Error,
+    MultipleAuthors "multipleAuthors" Error,
+    NulInCommit "nulInCommit" Warning,
+    NulInHeader "nulInHeader" Error,
+    NullSha1 "nullSha1" Warning,
+    TreeNotSorted "treeNotSorted" Error,
+    UnterminatedHeader "unterminatedHeader" Error,
+    ZeroPaddedDate "zeroPaddedDate" Error,
+    ZeroPaddedFilemode "zeroPaddedFilemode" Warning,
+}
+
+impl MessageId {
+    /// Looks a name up regardless of case, as config keys are.
+    pub fn from_name(name: &str) -> Option<Self> {
+        MessageId::ALL
+            .iter()
+            .copied()
+            .find(|id| id.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// One problem found in an object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub id: MessageId,
+    pub message: String,
+}
+
+impl Report {
+    fn new(id: MessageId, message: impl Into<String>) -> Self {
+        Report {
+            id,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.id.name(), self.message)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FsckOptions {
+    /// Turn warnings into errors, and refuse group-writable file modes.
+    pub strict: bool,
+    /// Only check that objects are there, not what is in them.
+    pub connectivity_only: bool,
+    /// Severities changed from git's defaults.
+    pub severities: HashMap<MessageId, Severity>,
+}
+
+impl FsckOptions {
+    /// Reads `fsck.<name>` settings (`error`, `warn` or `ignore`).
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut options = FsckOptions::default();
+        for id in MessageId::ALL {
+            let Some(value) = config.get(&format!("fsck.{}", id.name())) else {
+                continue;
+            };
+            let severity = match value.to_ascii_lowercase().as_str() {
+                "error" => Severity::Error,
+                "warn" => Severity::Warning,
+                "ignore" => Severity::Ignore,
+                _ => {
+                    return Err(Error::Config(format!(
+                        "fsck.{}: unknown severity '{value}'",
+                        id.name()
+                    )))
+                }
+            };
+            options.severities.insert(*id, severity);
+        }
+        Ok(options)
+    }
+
+    pub fn severity(&self, id: MessageId) -> Severity {
+        match self.severities.get(&id) {
+            Some(&severity) => severity,
+            None => match id.default_severity() {
+                Severity::Warning if self.strict => Severity::Error,
+                severity => severity,
+            },
+        }
+    }
+}
+
+/// Checks the content of an object of `kind`. Checks stop at the first
+/// problem that makes the rest of the object unreadable.
+pub fn check_object(
+    kind: ObjectKind,
+    data: &[u8],
+    hash: HashAlgorithm,
+    strict: bool,
+) -> Vec<Report> {
+    let mut out = Vec::new();
+    match kind {
+        ObjectKind::Blob => {}
+        ObjectKind::Tree => check_tree(data, hash, strict, &mut out),
+        ObjectKind::Commit => check_commit(data, hash, &mut out),
+        ObjectKind::Tag => check_tag(data, hash, &mut out),
+    }
+    out
+}
+
+/// Refuses an object about to be written that `fsck --strict` would
+/// complain about, so that nothing malformed is ever stored.
+pub fn check_before_writing(kind: ObjectKind, data: &[u8], hash: HashAlgorithm) -> Result<()> {
+    let reports = check_object(kind, data, hash, true);
+    if reports.is_empty() {
+        return Ok(());
+    }
+    Err(Error::MalformedObject {
+        id: ObjectId::hash_object(hash, kind, data)?,
+        reason: reports
+            .iter()
+            .map(Report::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
+    })
+}
+
+fn check_tree(data: &[u8], hash: HashAlgorithm, strict: bool, out: &mut Vec<Report>) {
+    let Some(entries) = tree_entries(data, hash) else {
+        out.push(Report::new(
+            MessageId::BadTree,
+            "cannot be parsed as a tree",
+        ));
+        return;
+    };
+
+    let mut found = HashSet::new();
+    let mut names = HashSet::new();
+    let mut previous: Option<&RawEntry> = None;
+    for entry in &entries {
+        let name = entry.name;
+        if entry.id.iter().all(|&b| b == 0) {
+            found.insert(MessageId::NullSha1);
+        }
+        if name.contains(&b'/') {
+            found.insert(MessageId::FullPathname);
+        }
+        match name {
+            b"" => found.insert(MessageId::EmptyName),
+            b"." => found.insert(MessageId::HasDot),
+            b".." => found.insert(MessageId::HasDotdot),
+            _ if is_dotgit(name, DotgitProtection::ALL) => found.insert(MessageId::HasDotgit),
+            _ => false,
+        };
+        if entry.mode_text.first() == Some(&b'0') {
+            found.insert(MessageId::ZeroPaddedFilemode);
+        }
+        let valid_mode = match entry.mode {
+            0o100755 | 0o100644 | 0o120000 | 0o040000 | 0o160000 => true,
+            // Old git wrote group-writable files; only strict checks mind.
+            0o100664 => !strict,
+            _ => false,
+        };
+        if !valid_mode {
+            found.insert(MessageId::BadFilemode);
+        }
+        if !names.insert(name) {
+            found.insert(MessageId::DuplicateEntries);
+        }
+        if previous.is_some_and(|previous| sort_key(previous) > sort_key(entry)) {
+            found.insert(MessageId::TreeNotSorted);
+        }
+        previous = Some(entry);
+    }
+
+    let message = |id: MessageId| match id {
+        MessageId::NullSha1 => "contains entries pointing to null sha1",
+        MessageId::FullPathname => "contains full pathnames",
+        MessageId::EmptyName => "contains empty pathname",
+        MessageId::HasDot => "contains '.'",
+        MessageId::HasDotdot => "contains '..'",
+        MessageId::HasDotgit => "contains '.git'",
+        MessageId::ZeroPaddedFilemode => "contains zero-padded file modes",
+        MessageId::BadFilemode => "contains bad file modes",
+        MessageId::DuplicateEntries => "contains duplicate file entries",
+        MessageId::TreeNotSorted => "not properly sorted",
+        _ => unreachable!("not a tree check"),
+    };
+    // In the order git reports them.
+    let found = [
+        MessageId::NullSha1,
+        MessageId::FullPathname,
+        MessageId::EmptyName,
+        MessageId::HasDot,
+        MessageId::HasDotdot,
+        MessageId::HasDotgit,
+        MessageId::ZeroPaddedFilemode,
+        MessageId::BadFilemode,
+        MessageId::DuplicateEntries,
+        MessageId::TreeNotSorted,
+    ]
+    .into_iter()
+    .filter(|id| found.contains(id));
+    out.extend(found.map(|id| Report::new(id, message(id))));
+}
+
+/// Which filesystems' other spellings of `.git` to watch for, besides
+/// case differences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DotgitProtection {
+    /// HFS+ ignores some invisible Unicode characters in names.
+    pub hfs: bool,
+    /// NTFS ignores trailing dots and spaces, has 8.3 short names and
+    /// alternate data streams, and takes backslashes as separators.
+    pub ntfs: bool,
+}
+
+impl DotgitProtection {
+    /// Guarding against every spelling, as fsck does.
+    pub const ALL: DotgitProtection = DotgitProtection {
+        hfs: true,
+        ntfs: true,
+    };
+
+    /// Reads `core.protectHFS` (on by default on macOS only) and
+    /// `core.protectNTFS` (on by default).
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(DotgitProtection {
+            hfs: config
+                .get_bool("core.protectHFS")?
+                .unwrap_or(cfg!(target_os = "macos")),
+            ntfs: config.get_bool("core.protectNTFS")?.unwrap_or(true),
+        })
+    }
+}
+
+/// Whether a tree entry name would be taken for the repository itself.
+pub fn is_dotgit(name: &[u8], protection: DotgitProtection) -> bool {
+    name.eq_ignore_ascii_case(b".git")
+        || (protection.hfs && is_hfs_dotgit(name))
+        || (protection.ntfs && is_ntfs_dotgit(name))
+}
+
+/// Whether a tree entry name can be written below a directory without
+/// escaping it or reaching into the repository: no empty, `.`, `..` or
+/// `.git` component, and no separator.
+pub fn is_safe_name(name: &[u8], protection: DotgitProtection) -> bool {
+    if name.contains(&b'/') || name.contains(&0) {
+        return false;
+    }
+    let mut components = name.split(|&b| protection.ntfs && b == b'\\');
+    components.all(|component| {
+        !matches!(component, b"" | b"." | b"..") && !is_dotgit(component, protection)
+    })
+}
+
+/// HFS+ drops zero-width joiners, direction marks and the like when
+/// comparing names, so `.g\u{200c}it` is `.git` there.
+fn is_hfs_dotgit(name: &[u8]) -> bool {
+    let Ok(name) = std::str::from_utf8(name) else {
+        return false;
+    };
+    let mut chars = name.chars().filter(|&c| {
+        !matches!(c,
+            '\u{200c}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{206a}'..='\u{206f}' | '\u{feff}')
+    });
+    let lowered = chars.by_ref().take(4).map(|c| c.to_ascii_lowercase());
+    lowered.eq(".git".chars()) && chars.next().is_none()
+}
+
+/// NTFS takes `.git. .`, `.git::$INDEX_ALLOCATION` and the short name
+/// `git~1` for `.git`.
+fn is_ntfs_dotgit(name: &[u8]) -> bool {
+    let rest = if name.len() >= 4 && name[..4].eq_ignore_ascii_case(b".git") {
+        &name[4..]
+    } else if name.len() >= 5 && name[..5].eq_ignore_ascii_case(b"git~1") {
+        &name[5..]
+    } else {
+        return false;
+    };
+    for &b in rest {
+        match b {
+            b'\\' | b':' => return true,
+            b'.' | b' ' => {}
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// A tree entry as stored, before any interpretation.
+struct RawEntry<'a> {
+    mode_text: &'a [u8],
+    mode: u32,
+    name: &'a [u8],
+    id: &'a [u8],
+}
+
+fn tree_entries(data: &[u8], hash: HashAlgorithm) -> Option<Vec<RawEntry<'_>>> {
+    let mut entries = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let mode_text = &rest[..space];
+        if mode_text.is_empty() || mode_text.len() > 7 {
+            return None;
+        }
+        let mode = mode_text.iter().try_fold(0u32, |mode, &c| match c {
+            b'0'..=b'7' => Some(mode << 3 | u32::from(c - b'0')),
+            _ => None,
+        })?;
+        let nul = space + 1 + rest[space + 1..].iter().position(|&b| b == 0)?;
+        let end = nul + 1 + hash.raw_len();
+        let id = rest.get(nul + 1..end)?;
+        entries.push(RawEntry {
+            mode_text,
+            mode,
+            name: &rest[space + 1..nul],
+            id,
+        });
+        rest = &rest[end..];
+    }
+    Some(entries)
+}
+
+/// Git's tree order: directories sort as if their name ended with `/`.
+fn sort_key(entry: &RawEntry) -> Vec<u8> {
+    let mut key = entry.name.to_vec();
+    if entry.mode & 0o170000 == 0o040000 {
+        key.push(b'/');
+    }
+    key
+}
+
+fn check_commit(data: &[u8], hash: HashAlgorithm, out: &mut Vec<Report>) {
+    if let Err(report) = verify_headers(data) {
+        return out.push(report);
+    }
+    let mut lines = HeaderLines::new(data);
+    match lines.take(b"tree ") {
+        None => {
+            return out.push(Report::new(
+                MessageId::MissingTree,
+                "invalid format - expected 'tree' line",
+            ))
+        }
+        Some(value) if !is_hex_id(value, hash) => {
+            return out.push(Report::new(
+                MessageId::BadTreeSha1,
+                "invalid 'tree' line format - bad sha1",
+            ))
+        }
+        Some(_) => {}
+    }
+    while let Some(value) = lines.take(b"parent ") {
+        if !is_hex_id(value, hash) {
+            return out.push(Report::new(
+                MessageId::BadParentSha1,
+                "invalid 'parent' line format - bad sha1",
+            ));
+        }
+    }
+
+    let mut authors = 0;
+    while let Some(value) = lines.take(b"author ") {
+        authors += 1;
+        if let Err(report) = check_ident(value) {
+            return out.push(report);
+        }
+    }
+    match authors {
+        0 => {
+            return out.push(Report::new(
+                MessageId::MissingAuthor,
+                "invalid format - expected 'author' line",
+            ))
+        }
+        1 => {}
+        _ => out.push(Report::new(
+            MessageId::MultipleAuthors,
+            "invalid format - multiple 'author' lines",
+        )),
+    }
+    let Some(committer) = lines.take(b"committer ") else {
+        return out.push(Report::new(
+            MessageId::MissingCommitter,
+            "invalid format - expected 'committer' line",
+        ));
+    };
+    if let Err(report) = check_ident(committer) {
+        return out.push(report);
+    }
+
+    if data.contains(&0) {
+        out.push(Report::new(
+            MessageId::NulInCommit,
+            "NUL byte in the commit object body",
+        ));
+    }
+}
+
+fn check_tag(data: &[u8], hash: HashAlgorithm, out: &mut Vec<Report>) {
+    if let Err(report) = verify_headers(data) {
+        return out.push(report);
+    }
+    let mut lines = HeaderLines::new(data);
+    match lines.take(b"object ") {
+        None => {
+            return out.push(Report::new(
+                MessageId::MissingObject,
+                "invalid format - expected 'object' line",
+            ))
+        }
+        Some(value) if !is_hex_id(value, hash) => {
+            return out.push(Report::new(
+                MessageId::BadObjectSha1,
+                "invalid 'object' line format - bad sha1",
+            ))
+        }
+        Some(_) => {}
+    }
+    match lines.take(b"type ") {
+        None => {
+            return out.push(Report::new(
+                MessageId::MissingTypeEntry,
+                "invalid format - expected 'type' line",
+            ))
+        }
+        Some(value) if ObjectKind::from_bytes(value).is_err() => {
+            return out.push(Report::new(MessageId::BadType, "invalid 'type' value"))
+        }
+        Some(_) => {}
+    }
+    match lines.take(b"tag ") {
+        None => {
+            return out.push(Report::new(
+                MessageId::MissingTagEntry,
+                "invalid format - expected 'tag' line",
+            ))
+        }
+        Some(name) if !is_valid_tag_name(name) => out.push(Report::new(
+            MessageId::BadTagName,
+            format!("invalid 'tag' name: {}", String::from_utf8_lossy(name)),
+        )),
+        Some(_) => {}
+    }
+    match lines.take(b"tagger ") {
+        None => out.push(Report::new(
+            MessageId::MissingTaggerEntry,
+            "invalid format - expected 'tagger' line",
+        )),
+        Some(value) => {
+            if let Err(report) = check_ident(value) {
+                out.push(report);
+            }
+        }
+    }
+}
+
+/// The header lines of a commit or tag, taken in the order git expects.
+struct HeaderLines<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> HeaderLines<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        HeaderLines { rest: data }
+    }
+
+    /// The value of the next line if it starts with `prefix`.
+    fn take(&mut self, prefix: &[u8]) -> Option<&'a [u8]> {
+        let line_end = self.rest.iter().position(|&b| b == b'\n')?;
+        let value = self.rest[..line_end].strip_prefix(prefix)?;
+        self.rest = &self.rest[line_end + 1..];
+        Some(value)
+    }
+}
+
+/// Headers must end with a blank line (or the object with a newline) and
+/// hold no NUL.
+fn verify_headers(data: &[u8]) -> std::result::Result<(), Report> {
+    for (n, &b) in data.iter().enumerate() {
+        match b {
+            0 => {
+                return Err(Report::new(
+                    MessageId::NulInHeader,
+                    format!("unterminated header: NUL at offset {n}"),
+                ))
+            }
+            b'\n' if data.get(n + 1) == Some(&b'\n') => return Ok(()),
+            _ => {}
+        }
+    }
+    // Headers without a message are fine.
+    match data.last() {
+        Some(b'\n') => Ok(()),
+        _ => Err(Report::new(
+            MessageId::UnterminatedHeader,
+            "unterminated header",
+        )),
+    }
+}
+
+fn is_hex_id(value: &[u8], hash: HashAlgorithm) -> bool {
+    value.len() == hash.hex_len() && is_lower_hex(value)
+}
+
+/// Checks a `Name <email> 1234567890 +0100` identity.
+fn check_ident(value: &[u8]) -> std::result::Result<(), Report> {
+    let bad = |id, what: &str| {
+        Err(Report::new(
+            id,
+            format!("invalid author/committer line - {what}"),
+        ))
+    };
+    if value.first() == Some(&b'<') {
+        return bad(
+            MessageId::MissingNameBeforeEmail,
+            "missing space before email",
+        );
+    }
+    let Some(open) = value.iter().position(|&b| b == b'<' || b == b'>') else {
+        return bad(MessageId::MissingEmail, "missing email");
+    };
+    if value[open] == b'>' {
+        return bad(MessageId::BadName, "bad name");
+    }
+    if value[open - 1] != b' ' {
+        return bad(
+            MessageId::MissingSpaceBeforeEmail,
+            "missing space before email",
+        );
+    }
+    let rest = &value[open + 1..];
+    let Some(close) = rest.iter().position(|&b| b == b'<' || b == b'>') else {
+        return bad(MessageId::BadEmail, "bad email");
+    };
+    if rest[close] != b'>' {
+        return bad(MessageId::BadEmail, "bad email");
+    }
+    let Some(date) = rest[close + 1..].strip_prefix(b" ") else {
+        return bad(
+            MessageId::MissingSpaceBeforeDate,
+            "missing space before date",
+        );
+    };
+    if date.first() == Some(&b'0') && date.get(1) != Some(&b' ') {
+        return bad(MessageId::ZeroPaddedDate, "zero-padded date");
+    }
+    let digits = date.iter().take_while(|b| b.is_ascii_digit()).count();
+    let parsed = std::str::from_utf8(&date[..digits])
+        .ok()
+        .and_then(|d| d.parse::<u64>().ok());
+    if digits > 0 && parsed.is_none_or(|d| d > MAX_TIMESTAMP) {
+        return bad(MessageId::BadDateOverflow, "date causes integer overflow");
+    }
+    let Some(zone) = date[digits..].strip_prefix(b" ").filter(|_| digits > 0) else {
+        return bad(MessageId::BadDate, "bad date");
+    };
+    let valid_zone = zone.len() == 5
+        && matches!(zone[0], b'+' | b'-')
+        && zone[1..].iter().all(u8::is_ascii_digit);
+    if !valid_zone {
+        return bad(MessageId::BadTimezone, "bad time zone");
+    }
+    Ok(())
+}
+
+/// The largest timestamp git accepts.
+const MAX_TIMESTAMP: u64 = 0x7fff_ffff_ffff_ffff;
+
+/// Whether `refs/tags/<name>` is a well-formed ref name.
+fn is_valid_tag_name(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name.split(|&b| b == b'/').all(|part| {
+            !part.is_empty()
+                && !part.starts_with(b".")
+                && !part.ends_with(b".lock")
+                && !part.windows(2).any(|w| w == b"..")
+        })
+        && !name.ends_with(b".")
+        && !name.windows(2).any(|w| w == b"@{")
+        && name != b"@"
+        && !name
+            .iter()
+            .any(|&b| b < 0x20 || b == 0x7f || b" ~^:?*[\\".contains(&b))
+}
+
+/// What the objects of an object points to, with the kind it expects.
+fn links(kind: ObjectKind, data: &[u8], hash: HashAlgorithm) -> Vec<(ObjectId, ObjectKind)> {
+    let parse = |hex: &[u8]| {
+        std::str::from_utf8(hex)
+            .ok()
+            .filter(|hex| hex.len() == hash.hex_len())
+            .and_then(|hex| ObjectId::from_hex(hex).ok())
+    };
+    let mut out = Vec::new();
+    match kind {
+        ObjectKind::Blob => {}
+        ObjectKind::Tree => {
+            for entry in tree_entries(data, hash).unwrap_or_default() {
+                let kind = match entry.mode & 0o170000 {
+                    0o040000 => ObjectKind::Tree,
+                    // Submodule commits live in another repository.
+                    0o160000 => continue,
+                    _ => ObjectKind::Blob,
+                };
+                if let Ok(id) = ObjectId::from_bytes(entry.id) {
+                    out.push((id, kind));
+                }
+            }
+        }
+        ObjectKind::Commit => {
+            let mut lines = HeaderLines::new(data);
+            if let Some(id) = lines.take(b"tree ").and_then(parse) {
+                out.push((id, ObjectKind::Tree));
+            }
+            while let Some(value) = lines.take(b"parent ") {
+                out.extend(parse(value).map(|id| (id, ObjectKind::Commit)));
+            }
+        }
+        ObjectKind::Tag => {
+            let mut lines = HeaderLines::new(data);
+            let id = lines.take(b"object ").and_then(parse);
+            let kind = lines
+                .take(b"type ")
+                .and_then(|t| ObjectKind::from_bytes(t).ok());
+            if let (Some(id), Some(kind)) = (id, kind) {
+                out.push((id, kind));
+            }
+        }
+    }
+    out
+}
+
+/// Something wrong in the repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// A problem with the content of an object.
+    Object {
+        kind: ObjectKind,
+        id: ObjectId,
+        severity: Severity,
+        report: Report,
+    },
+    /// Anything else: unreadable or misnamed objects, refs and reflogs
+    /// pointing nowhere, links to objects of the wrong kind.
+    Other(String),
+}
+
+impl Problem {
+    pub fn is_error(&self) -> bool {
+        match self {
+            Problem::Object { severity, .. } => *severity == Severity::Error,
+            Problem::Other(_) => true,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Object {
+                kind,
+                id,
+                severity,
+                report,
+            } => {
+                let level = match severity {
+                    Severity::Error => "error",
+                    _ => "warning",
+                };
+                write!(f, "{level} in {kind} {id}: {report}")
+            }
+            Problem::Other(message) => write!(f, "error: {message}"),
+        }
+    }
+}
+
+/// A reachable object pointing to one that is missing, or that is not
+/// of the kind the link says.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BrokenLink {
+    pub from: (ObjectKind, ObjectId),
+    pub to: (ObjectKind, ObjectId),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FsckOutcome {
+    pub problems: Vec<Problem>,
+    pub broken_links: Vec<BrokenLink>,
+    /// Reachable objects that are nowhere to be found, by id.
+    pub missing: Vec<(ObjectKind, ObjectId)>,
+    /// Objects nothing reachable leads to, by id, with whether nothing at
+    /// all points to them ("dangling").
+    pub unreachable: Vec<(ObjectKind, ObjectId, bool)>,
+    /// Commits without parents that passed their checks, by id.
+    pub roots: Vec<ObjectId>,
+}
+
+impl FsckOutcome {
+    /// Problems that make the check fail; warnings and unreachable objects
+    /// do not.
+    pub fn error_count(&self) -> usize {
+        self.problems.iter().filter(|p| p.is_error()).count() + self.missing.len()
+    }
+}
+
+/// An object, by kind and id.
+type Linked = (ObjectKind, ObjectId);
+
+/// An object of this store, as far as connectivity is concerned.
+struct Stored {
+    kind: ObjectKind,
+    links: Vec<(ObjectId, ObjectKind)>,
+    /// Whether its checks found no error.
+    sound: bool,
+}
+
+/// The objects read so far and what was wrong with them.
+struct Scan<'o> {
+    options: &'o FsckOptions,
+    hash: HashAlgorithm,
+    objects: HashMap<ObjectId, Stored>,
+    /// Present but unreadable, so neither missing nor walkable.
+    corrupt: HashSet<ObjectId>,
+    outcome: FsckOutcome,
+}
+
+impl Scan<'_> {
+    /// Checks the object `id` given what reading it gave; `mismatch` makes
+    /// the complaint when it cannot be read, or hashes to what it is given.
+    fn examine(
+        &mut self,
+        id: ObjectId,
+        read: Result<(ObjectKind, Vec<u8>)>,
+        mismatch: impl FnOnce(Option<ObjectId>) -> String,
+    ) -> Result<()> {
+        let Ok((kind, data)) = read else {
+            self.outcome.problems.push(Problem::Other(mismatch(None)));
+            self.corrupt.insert(id);
+            return Ok(());
+        };
+        let mut sound = true;
+        if !self.options.connectivity_only {
+            let actual = ObjectId::hash_object(self.hash, kind, &data)?;
+            if actual != id {
+                self.outcome
+                    .problems
+                    .push(Problem::Other(mismatch(Some(actual))));
+                self.corrupt.insert(id);
+                return Ok(());
+            }
+            for report in check_object(kind, &data, self.hash, self.options.strict) {
+                let severity = self.options.severity(report.id);
+                sound &= severity != Severity::Error;
+                if severity != Severity::Ignore {
+                    self.outcome.problems.push(Problem::Object {
+                        kind,
+                        id,
+                        severity,
+                        report,
+                    });
+                }
+            }
+        }
+        let links = links(kind, &data, self.hash);
+        self.objects.insert(id, Stored { kind, links, sound });
+        Ok(())
+    }
+}
+
+/// Checks every object stored in the repository (not in its alternates),
+/// then walks from refs, HEAD, the index and reflogs to find what is
+/// missing and what is unreachable.
+pub fn fsck(repo: &Repository, options: &FsckOptions) -> Result<FsckOutcome> {
+    let odb = repo.odb();
+    let hash = odb.hash();
+    let mut scan = Scan {
+        options,
+        hash,
+        objects: HashMap::new(),
+        corrupt: HashSet::new(),
+        outcome: FsckOutcome::default(),
+    };
+    let mut loose = odb.loose().list()?;
+    loose.sort();
+    for id in loose {
+        let path = odb.loose().path(&id);
+        let read = odb
+            .loose()
+            .read(&id)
+            .and_then(|found| found.ok_or(Error::ObjectNotFound(id)));
+        let mismatch = |actual: Option<ObjectId>| match actual {
+            Some(actual) => format!("{actual}: hash-path mismatch, found at: {}", path.display()),
+            None => format!("{id}: object corrupt or missing: {}", path.display()),
+        };
+        scan.examine(id, read, mismatch)?;
+    }
+    for pack in odb.packs() {
+        let index = pack.index();
+        for n in 0..index.len() {
+            let id = index.id_at(n);
+            if scan.objects.contains_key(&id) {
+                continue;
+            }
+            let mismatch = |_| format!("packed {id} from {} is corrupt", pack.path().display());
+            scan.examine(id, pack.read_at(index.offset_at(n)), mismatch)?;
+        }
+    }
+    let Scan {
+        objects,
+        corrupt,
+        mut outcome,
+        ..
+    } = scan;
+
+    // What links to the object, the object, and the kind it should be.
+    let mut pending: Vec<(Option<Linked>, ObjectId, Option<ObjectKind>)> = Vec::new();
+    let present =
+        |id: &ObjectId| objects.contains_key(id) || corrupt.contains(id) || odb.contains(id);
+
+    for (name, id) in refs::list(repo)?
+        .into_iter()
+        .chain(refs::resolve(repo, "HEAD")?.map(|id| ("HEAD".to_owned(), id)))
+    {
+        match present(&id) {
+            true => pending.push((None, id, None)),
+            false => outcome
+                .problems
+                .push(Problem::Other(format!("{name}: invalid sha1 pointer {id}"))),
+        }
+    }
+    for entry in Index::read(repo)?.entries {
+        if entry.mode_type == MODE_TYPE_GITLINK {
+            continue;
+        }
+        match present(&entry.id) {
+            true => pending.push((None, entry.id, Some(ObjectKind::Blob))),
+            false => outcome.problems.push(Problem::Other(format!(
+                "{}: invalid sha1 pointer in index",
+                path::quote(&entry.name)
+            ))),
+        }
+    }
+    for name in reflog::list(repo)? {
+        for entry in reflog::read(repo, &name)? {
+            for id in [entry.old, entry.new] {
+                if id.is_null() {
+                    continue;
+                }
+                match present(&id) {
+                    true => pending.push((None, id, None)),
+                    false => outcome
+                        .problems
+                        .push(Problem::Other(format!("{name}: invalid reflog entry {id}"))),
+                }
+            }
+        }
+    }
+
+    // Objects borrowed from alternates are walked through, not checked.
+    let mut borrowed: HashMap<ObjectId, Stored> = HashMap::new();
+    let mut reachable = HashSet::new();
+    let mut missing = HashMap::new();
+    pending.reverse();
+    while let Some((from, id, expected)) = pending.pop() {
+        if corrupt.contains(&id) {
+            continue;
+        }
+        if !objects.contains_key(&id) && !borrowed.contains_key(&id) {
+            match odb.read_raw(&id) {
+                Ok((kind, data)) => {
+                    let links = links(kind, &data, hash);
+                    let sound = true;
+                    borrowed.insert(id, Stored { kind, links, sound });
+                }
+                Err(_) => {
+                    let kind = expected.unwrap_or(ObjectKind::Blob);
+                    if let Some(from) = from {
+                        outcome.broken_links.push(BrokenLink {
+                            from,
+                            to: (kind, id),
+                        });
+                    }
+                    missing.insert(id, kind);
+                    continue;
+                }
+            }
+        }
+        let stored = objects
+            .get(&id)
+            .or_else(|| borrowed.get(&id))
+            .expect("just looked up");
+        // Every link is checked, not only the first one to reach the
+        // object: a tree entry saying 040000 for a blob is broken even if
+        // the blob was already reached as one.
+        if let Some(expected) = expected.filter(|&k| k != stored.kind) {
+            outcome.problems.push(Problem::Other(format!(
+                "object {id} is a {}, not a {expected}",
+                stored.kind
+            )));
+            if let Some(from) = from {
+                outcome.broken_links.push(BrokenLink {
+                    from,
+                    to: (expected, id),
+                });
+            }
+        }
+        if !reachable.insert(id) {
+            continue;
+        }
+        for &(link, kind) in stored.links.iter().rev() {
+            pending.push((Some((stored.kind, id)), link, Some(kind)));
+        }
+    }
+
+    let referenced: HashSet<ObjectId> = objects
+        .values()
+        .flat_map(|stored| stored.links.iter().map(|(id, _)| *id))
+        .collect();
+    outcome.unreachable = objects
+        .iter()
+        .filter(|(id, _)| !reachable.contains(*id))
+        .map(|(id, stored)| (stored.kind, *id, !referenced.contains(id)))
+        .collect();
+    outcome.unreachable.sort_by_key(|&(_, id, _)| id);
+    outcome.missing = missing.into_iter().map(|(id, kind)| (kind, id)).collect();
+    outcome.missing.sort_by_key(|&(_, id)| id);
+    outcome.roots = objects
+        .iter()
+        .filter(|(_, stored)| stored.kind == ObjectKind::Commit && stored.sound)
+        .filter(|(_, stored)| !stored.links.iter().any(|(_, k)| *k == ObjectKind::Commit))
+        .map(|(id, _)| *id)
+        .collect();
+    outcome.roots.sort();
+    Ok(outcome)
+}
@@ -0,0 +1,235 @@
+//! Housekeeping: packing reachable objects, pruning unreachable ones and
+//! packing refs.
+
+use std::collections::HashSet;
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use crate::commit_graph::{self, WriteOptions};
+use crate::error::{Error, Result};
+use crate::index::{Index, MODE_TYPE_GITLINK};
+use crate::object::ObjectId;
+use crate::pack::{self, PackObject, PackOptions, MIDX_FILE_NAME};
+use crate::reachable;
+use crate::reflog;
+use crate::refs;
+use crate::repository::Repository;
+
+/// Git's default for `gc.pruneExpire`.
+pub const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+#[derive(Clone, Debug)]
+pub struct GcOptions {
+    /// Unreachable loose objects older than this are deleted; `None` keeps
+    /// them all.
+    pub prune_expire: Option<SystemTime>,
+    pub pack: PackOptions,
+    /// Rewrite the commit-graph afterwards (`gc.writeCommitGraph`).
+    pub write_commit_graph: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GcReport {
+    /// Name of the pack holding every reachable object, if there were any.
+    pub pack: Option<String>,
+    pub packed_objects: usize,
+    pub packed_refs: usize,
+    /// Loose objects deleted because the new pack holds them.
+    pub removed_loose: usize,
+    /// Unreachable objects deleted for being older than the grace period.
+    pub pruned: usize,
+    /// Older packs replaced by the new one.
+    pub removed_packs: usize,
+    /// Packs left alone because a `.keep` file marks them.
+    pub kept_packs: usize,
+    /// Commits in the rewritten commit-graph.
+    pub graph_commits: usize,
+}
+
+/// Every object a repository must keep: what refs, HEAD, the index and
+/// the reflogs point to.
+pub fn roots(repo: &Repository) -> Result<Vec<ObjectId>> {
+    let mut roots: Vec<ObjectId> = refs::list(repo)?.into_iter().map(|(_, id)| id).collect();
+    roots.extend(refs::resolve(repo, "HEAD")?);
+
+    for entry in Index::read(repo)?.entries {
+        // Submodule commits live in another repository.
+        if entry.mode_type != MODE_TYPE_GITLINK {
+            roots.push(entry.id);
+        }
+    }
+
+    for name in reflog::list(repo)? {
+        for entry in reflog::read(repo, &name)? {
+            // Reflogs may mention objects that are long gone, and the null
+            // id stands for "did not exist".
+            for id in [entry.old, entry.new] {
+                if repo.odb().contains(&id) {
+                    roots.push(id);
+                }
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    roots.retain(|id| seen.insert(*id));
+    Ok(roots)
+}
+
+pub fn gc(repo: &Repository, options: &GcOptions) -> Result<GcReport> {
+    let mut report = GcReport {
+        packed_refs: refs::pack(repo)?,
+        ..GcReport::default()
+    };
+
+    let reachable = reachable::objects(repo, &roots(repo)?, &[])?;
+    let keep: HashSet<ObjectId> = reachable.iter().map(|r| r.id).collect();
+    let odb = repo.odb();
+    // Packs with a `.keep` file are neither repacked nor deleted, and their
+    // objects stay out of the new pack, as with `repack -a -d`.
+    let (kept_packs, old_packs): (Vec<_>, Vec<_>) = odb
+        .packs()
+        .into_iter()
+        .partition(|p| p.path().with_extension("keep").exists());
+    report.kept_packs = kept_packs.len();
+    // Objects borrowed from alternates stay there, as with `repack -l`.
+    let objects: Vec<PackObject> = reachable
+        .iter()
+        .filter(|r| odb.contains_local(&r.id))
+        .filter(|r| !kept_packs.iter().any(|p| p.contains(&r.id)))
+        .map(|r| PackObject {
+            id: r.id,
+            name_hash: r.name_hash,
+        })
+        .collect();
+
+    let new_pack = if objects.is_empty() {
+        None
+    } else {
+        let base_name = odb.dir().join("pack").join("pack");
+        Some(pack::write_pack_files(
+            odb,
+            &objects,
+            &options.pack,
+            &base_name,
+        )?)
+    };
+    report.packed_objects = objects.len();
+    report.pack = new_pack.as_ref().map(|p| p.name());
+
+    // Unreachable objects that only live in old packs are loosened when
+    // still within the grace period, so that pruning below can age them
+    // out like any other loose object.
+    for old in &old_packs {
+        if new_pack.as_ref().is_some_and(|p| p.pack_path == old.path()) {
+            continue;
+        }
+        let mtime = fs::metadata(old.path())?.modified()?;
+        let recent = options.prune_expire.is_none_or(|expire| mtime > expire);
+        if recent {
+            for id in old.index().ids() {
+                let elsewhere =
+                    odb.loose().contains(&id) || kept_packs.iter().any(|p| p.contains(&id));
+                if !keep.contains(&id) && !elsewhere {
+                    let (kind, data) = old.read(&id)?.ok_or(Error::ObjectNotFound(id))?;
+                    odb.loose().write(&id, kind, &data)?;
+                    fs::File::options()
+                        .write(true)
+                        .open(odb.loose().path(&id))?
+                        .set_modified(mtime)?;
+                }
+            }
+        }
+    }
+    for old in old_packs {
+        if new_pack.as_ref().is_some_and(|p| p.pack_path == old.path()) {
+            continue;
+        }
+        let path = old.path().to_path_buf();
+        drop(old);
+        let bitmap = pack::bitmap::bitmap_path(&path);
+        if bitmap.exists() {
+            fs::remove_file(bitmap)?;
+        }
+        fs::remove_file(path.with_extension("idx"))?;
+        fs::remove_file(&path)?;
+        report.removed_packs += 1;
+    }
+    // A multi-pack index naming the packs just deleted is of no more use.
+    let midx = odb.dir().join("pack").join(MIDX_FILE_NAME);
+    if report.removed_packs > 0 && midx.exists() {
+        fs::remove_file(midx)?;
+    }
+    odb.refresh_packs()?;
+    update_info_packs(repo)?;
+
+    for id in odb.loose().list()? {
+        if keep.contains(&id) {
+            odb.loose().remove(&id)?;
+            report.removed_loose += 1;
+        } else if let Some(expire) = options.prune_expire {
+            let mtime = fs::metadata(odb.loose().path(&id))?.modified()?;
+            if mtime <= expire {
+                odb.loose().remove(&id)?;
+                report.pruned += 1;
+            }
+        }
+    }
+
+    if options.write_commit_graph {
+        let tips = commit_graph::ref_tips(repo)?;
+        let written = commit_graph::write(odb, &tips, &WriteOptions::default())?;
+        report.graph_commits = written.map_or(0, |w| w.commits);
+    }
+
+    Ok(report)
+}
+
+/// Keeps `objects/info/packs`, which dumb transports read to find packs,
+/// in sync with the packs now on disk if the file is in use.
+fn update_info_packs(repo: &Repository) -> Result<()> {
+    let path = repo.odb().dir().join("info").join("packs");
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut out = String::new();
+    for pack in repo.odb().packs() {
+        if let Some(name) = pack.path().file_name().and_then(|n| n.to_str()) {
+            out.push_str(&format!("P {name}\n"));
+        }
+    }
+    out.push('\n');
+    crate::odb::write_atomically(&path, out.as_bytes())
+}
+
+/// Parses an expiry such as `now`, `never`, `2.weeks.ago` or a Unix
+/// timestamp into the cut-off time (`None` for `never`).
+pub fn parse_expiry(spec: &str, now: SystemTime) -> Result<Option<SystemTime>> {
+    let bad = || Error::Usage(format!("malformed expiration date '{spec}'"));
+    match spec {
+        "never" | "false" => return Ok(None),
+        "now" | "all" => return Ok(Some(now)),
+        _ => {}
+    }
+    if let Ok(seconds) = spec.trim_start_matches('@').parse::<u64>() {
+        return Ok(Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)));
+    }
+
+    let words: Vec<&str> = spec.split(['.', ' ']).filter(|w| !w.is_empty()).collect();
+    let [count, unit, "ago"] = words.as_slice() else {
+        return Err(bad());
+    };
+    let count: u64 = count.parse().map_err(|_| bad())?;
+    let unit = match unit.trim_end_matches('s') {
+        "second" | "sec" => 1,
+        "minute" | "min" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return Err(bad()),
+    };
+    let ago = Duration::from_secs(count.checked_mul(unit).ok_or_else(bad)?);
+    Ok(Some(now.checked_sub(ago).unwrap_or(SystemTime::UNIX_EPOCH)))
+}
@@ -0,0 +1,562 @@
+//! History drawn in text next to `log` output, as `git log --graph` draws
+//! it: a `|` for each line of descent, `*` for the commit shown, and `\`,
+//! `/` and `_` where lines branch off at merges and join again.
+//!
+//! A port of git's graph.c, without colors. Each commit is drawn in
+//! states: lines making room for an octopus merge's parents
+//! (`PreCommit`), the commit line, the edges of a merge
+//! (`PostMerge`), and lines moving to the left until every line
+//! is in its own column again (`Collapsing`).
+
+use std::io::Write;
+
+use crate::error::Result;
+use crate::object::ObjectId;
+
+/// What a merge's parent edges look like, by where the first one goes:
+/// down and left, straight down, or down and right.
+const MERGE_CHARS: [u8; 3] = [b'/', b'|', b'\\'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Done with the commit: lines go straight down.
+    Padding,
+    /// Lines were left unfinished by the previous commit: `...`.
+    Skip,
+    PreCommit,
+    Commit,
+    PostMerge,
+    Collapsing,
+}
+
+/// The drawing, updated with each commit shown.
+#[derive(Debug)]
+pub struct Graph {
+    /// The commit being drawn.
+    commit: Option<ObjectId>,
+    /// Its parents that are shown as well.
+    parents: Vec<ObjectId>,
+    /// The commit each line goes down to, left to right, before the
+    /// commit's line and after it.
+    columns: Vec<ObjectId>,
+    new_columns: Vec<ObjectId>,
+    /// For each character of a line, the entry of `new_columns` the edge
+    /// there heads for.
+    mapping: Vec<Option<usize>>,
+    /// The mapping before the last collapsing line.
+    old_mapping: Vec<Option<usize>>,
+    /// How much of `mapping` is in use.
+    mapping_size: usize,
+    /// Characters the graph takes up, so that the text after it lines up.
+    width: usize,
+    /// How many pre-commit lines were drawn for an octopus merge.
+    expansion_row: usize,
+    state: State,
+    prev_state: State,
+    /// The column the commit is drawn in.
+    commit_index: usize,
+    prev_commit_index: usize,
+    /// For merges, the index into [`MERGE_CHARS`] of the first parent's
+    /// edge.
+    merge_layout: Option<usize>,
+    /// How many more columns there are below the commit than above it;
+    /// -1 when a merge's edge joins the column next to it.
+    edges_added: isize,
+    prev_edges_added: isize,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Graph {
+            commit: None,
+            parents: Vec::new(),
+            columns: Vec::new(),
+            new_columns: Vec::new(),
+            mapping: Vec::new(),
+            old_mapping: Vec::new(),
+            mapping_size: 0,
+            width: 0,
+            expansion_row: 0,
+            state: State::Padding,
+            prev_state: State::Padding,
+            commit_index: 0,
+            prev_commit_index: 0,
+            merge_layout: Some(0),
+            edges_added: 0,
+            prev_edges_added: 0,
+        }
+    }
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Graph::default()
+    }
+
+    /// Moves on to the next commit shown, whose `parents` are those that
+    /// are shown as well.
+    pub fn update(&mut self, commit: ObjectId, parents: Vec<ObjectId>) {
+        self.commit = Some(commit);
+        self.parents = parents;
+        self.prev_commit_index = self.commit_index;
+        self.update_columns(commit);
+        self.expansion_row = 0;
+        self.state = if self.state != State::Padding {
+            State::Skip
+        } else if self.needs_pre_commit_line() {
+            State::PreCommit
+        } else {
+            State::Commit
+        };
+    }
+
+    /// Writes the lines down to the commit's own, which is left
+    /// unfinished for the commit's first line of text.
+    pub fn show_commit(&mut self, out: &mut impl Write) -> Result<()> {
+        let mut shown_commit_line = false;
+        while !shown_commit_line && !self.is_finished() {
+            let mut line = Vec::new();
+            shown_commit_line = self.next_line(&mut line);
+            out.write_all(&line)?;
+            if !shown_commit_line {
+                out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `text`, shown for the commit, with the graph before each of
+    /// its lines but the first, then what is left of the commit's drawing.
+    pub fn show_text(&mut self, out: &mut impl Write, text: &[u8]) -> Result<()> {
+        let mut lines = text.split_inclusive(|&b| b == b'\n').peekable();
+        while let Some(line) = lines.next() {
+            out.write_all(line)?;
+            if line.ends_with(b"\n") && lines.peek().is_some() {
+                let mut graph = Vec::new();
+                self.next_line(&mut graph);
+                out.write_all(&graph)?;
+            }
+        }
+        if self.is_finished() {
+            return Ok(());
+        }
+        let newline_terminated = text.ends_with(b"\n");
+        if !newline_terminated {
+            out.write_all(b"\n")?;
+        }
+        loop {
+            let mut line = Vec::new();
+            self.next_line(&mut line);
+            out.write_all(&line)?;
+            if self.is_finished() {
+                break;
+            }
+            out.write_all(b"\n")?;
+        }
+        if newline_terminated {
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the graph for a line of its own between commits.
+    pub fn show_padding(&mut self, out: &mut impl Write) -> Result<()> {
+        let mut line = Vec::new();
+        if self.state != State::Commit {
+            self.next_line(&mut line);
+            out.write_all(&line)?;
+            return Ok(());
+        }
+        // Above the commit line: the lines as they come into it, with room
+        // for an octopus merge's dashes.
+        let commit = self.commit;
+        for &column in &self.columns {
+            line.push(b'|');
+            match self.parents.len() {
+                n if Some(column) == commit && n > 2 => {
+                    line.resize(line.len() + (n - 2) * 2, b' ');
+                }
+                _ => line.push(b' '),
+            }
+        }
+        self.pad(&mut line);
+        out.write_all(&line)?;
+        self.prev_state = State::Padding;
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.state == State::Padding
+    }
+
+    /// Draws the next line into `line`, returning whether it is the one
+    /// with the commit.
+    fn next_line(&mut self, line: &mut Vec<u8>) -> bool {
+        let Some(commit) = self.commit else {
+            return false;
+        };
+        let commit_line = self.state == State::Commit;
+        match self.state {
+            State::Padding => self.padding_line(line),
+            State::Skip => self.skip_line(line),
+            State::PreCommit => self.pre_commit_line(line, commit),
+            State::Commit => self.commit_line(line, commit),
+            State::PostMerge => self.post_merge_line(line, commit),
+            State::Collapsing => self.collapsing_line(line),
+        }
+        self.pad(line);
+        commit_line
+    }
+
+    fn update_state(&mut self, state: State) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+
+    fn pad(&self, line: &mut Vec<u8>) {
+        if line.len() < self.width {
+            line.resize(self.width, b' ');
+        }
+    }
+
+    fn layout(&self) -> usize {
+        self.merge_layout.unwrap_or(0)
+    }
+
+    /// Lays out the columns below the commit: those above it, the commit
+    /// replaced by its parents.
+    fn update_columns(&mut self, commit: ObjectId) {
+        std::mem::swap(&mut self.columns, &mut self.new_columns);
+        self.new_columns.clear();
+
+        let max_new_columns = self.columns.len() + self.parents.len();
+        self.mapping_size = 2 * max_new_columns;
+        if self.mapping.len() < self.mapping_size {
+            self.mapping.resize(self.mapping_size, None);
+            self.old_mapping.resize(self.mapping_size, None);
+        }
+        self.mapping[..self.mapping_size].fill(None);
+
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        // The commit goes at the end when no line leads to it.
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column = match self.columns.get(i) {
+                Some(&column) => column,
+                None if seen_this => break,
+                None => commit,
+            };
+            if column == commit {
+                seen_this = true;
+                self.commit_index = i;
+                self.merge_layout = None;
+                for parent in self.parents.clone() {
+                    self.insert_into_new_columns(parent, Some(i));
+                }
+                // The commit takes up room even without parents.
+                if self.parents.is_empty() {
+                    self.width += 2;
+                }
+            } else {
+                self.insert_into_new_columns(column, None);
+            }
+        }
+
+        while self.mapping_size > 1 && self.mapping[self.mapping_size - 1].is_none() {
+            self.mapping_size -= 1;
+        }
+    }
+
+    /// Gives `commit` a column below, if it has none yet, and maps the
+    /// edge to it: from the commit's column `index` for parents.
+    fn insert_into_new_columns(&mut self, commit: ObjectId, index: Option<usize>) {
+        let i = match self.new_columns.iter().position(|&c| c == commit) {
+            Some(i) => i,
+            None => {
+                self.new_columns.push(commit);
+                self.new_columns.len() - 1
+            }
+        };
+
+        let mapping_index = match index {
+            Some(index) if self.parents.len() > 1 && self.merge_layout.is_none() => {
+                // A merge's first parent: its edge goes left if the parent
+                // is in a column to the left, and the others follow.
+                let distance = index as isize - i as isize;
+                let shift = if distance > 1 { 2 * distance - 3 } else { 1 };
+                let layout = usize::from(distance <= 0);
+                self.merge_layout = Some(layout);
+                self.edges_added = (self.parents.len() + layout) as isize - 2;
+                let mapping_index = self.width as isize + (layout as isize - 1) * shift;
+                self.width += 2 * layout;
+                mapping_index as usize
+            }
+            // The edge of a merge reaching the last column there already
+            // joins it right away:
+            //
+            //     * |        * |
+            //     |\ \   =>  |\|
+            //     | |/       | *
+            //     | *
+            _ if self.edges_added > 0
+                && self.width >= 2
+                && self.mapping[self.width - 2] == Some(i) =>
+            {
+                self.edges_added = -1;
+                self.width - 2
+            }
+            _ => {
+                self.width += 2;
+                self.width - 2
+            }
+        };
+        self.mapping[mapping_index] = Some(i);
+    }
+
+    /// Whether an octopus merge needs lines to spread the columns to its
+    /// right apart before its own: two for each dashed parent.
+    fn needs_pre_commit_line(&self) -> bool {
+        self.parents.len() >= 3
+            && self.commit_index + 1 < self.columns.len()
+            && self.expansion_row < self.num_dashed_parents() * 2
+    }
+
+    /// How many parents are joined to an octopus merge by dashes.
+    fn num_dashed_parents(&self) -> usize {
+        (self.parents.len() + self.layout()).saturating_sub(3)
+    }
+
+    /// Whether every line is in its own column.
+    fn is_mapping_correct(&self) -> bool {
+        self.mapping[..self.mapping_size]
+            .iter()
+            .enumerate()
+            .all(|(i, target)| target.is_none_or(|target| target == i / 2))
+    }
+
+    fn padding_line(&mut self, line: &mut Vec<u8>) {
+        for _ in &self.new_columns {
+            line.extend_from_slice(b"| ");
+        }
+    }
+
+    fn skip_line(&mut self, line: &mut Vec<u8>) {
+        line.extend_from_slice(b"...");
+        match self.needs_pre_commit_line() {
+            true => self.update_state(State::PreCommit),
+            false => self.update_state(State::Commit),
+        }
+    }
+
+    fn pre_commit_line(&mut self, line: &mut Vec<u8>, commit: ObjectId) {
+        let mut seen_this = false;
+        for (i, &column) in self.columns.iter().enumerate() {
+            if column == commit {
+                seen_this = true;
+                line.push(b'|');
+                line.resize(line.len() + self.expansion_row, b' ');
+            } else if seen_this && self.expansion_row == 0 {
+                // Lines a merge just above left as `\` stay that way.
+                match self.prev_state == State::PostMerge && self.prev_commit_index < i {
+                    true => line.push(b'\\'),
+                    false => line.push(b'|'),
+                }
+            } else if seen_this {
+                line.push(b'\\');
+            } else {
+                line.push(b'|');
+            }
+            line.push(b' ');
+        }
+
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.update_state(State::Commit);
+        }
+    }
+
+    fn commit_line(&mut self, line: &mut Vec<u8>, commit: ObjectId) {
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column = match self.columns.get(i) {
+                Some(&column) => column,
+                None if seen_this => break,
+                None => commit,
+            };
+            if column == commit {
+                seen_this = true;
+                line.push(b'*');
+                if self.parents.len() > 2 {
+                    self.draw_octopus_merge(line);
+                }
+            } else if seen_this && self.edges_added > 1 {
+                line.push(b'\\');
+            } else if seen_this && self.edges_added == 1 {
+                // A merge with no pre-commit lines: lines a merge just
+                // above left as `\` stay that way.
+                match self.prev_state == State::PostMerge
+                    && self.prev_edges_added > 0
+                    && self.prev_commit_index < i
+                {
+                    true => line.push(b'\\'),
+                    false => line.push(b'|'),
+                }
+            } else if self.prev_state == State::Collapsing
+                && self.old_mapping[2 * i + 1] == Some(i)
+                && self.mapping[2 * i].is_none_or(|target| target < i)
+            {
+                line.push(b'/');
+            } else {
+                line.push(b'|');
+            }
+            line.push(b' ');
+        }
+
+        if self.parents.len() > 1 {
+            self.update_state(State::PostMerge);
+        } else if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        } else {
+            self.update_state(State::Collapsing);
+        }
+    }
+
+    /// `-.`, `---.` and so on after an octopus merge's `*`.
+    fn draw_octopus_merge(&self, line: &mut Vec<u8>) {
+        let dashed = self.num_dashed_parents();
+        for i in 0..dashed {
+            line.push(b'-');
+            line.push(if i + 1 == dashed { b'.' } else { b'-' });
+        }
+    }
+
+    fn post_merge_line(&mut self, line: &mut Vec<u8>, commit: ObjectId) {
+        let first_parent = self.parents.first().copied();
+        let mut seen_this = false;
+        let mut seen_parent = false;
+        for i in 0..=self.columns.len() {
+            let column = match self.columns.get(i) {
+                Some(&column) => column,
+                None if seen_this => break,
+                None => commit,
+            };
+            if column == commit {
+                seen_this = true;
+                let mut layout = self.layout();
+                for j in 0..self.parents.len() {
+                    line.push(MERGE_CHARS[layout]);
+                    if layout < 2 {
+                        layout += 1;
+                    } else if self.edges_added > 0 || j + 1 < self.parents.len() {
+                        line.push(b' ');
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(b' ');
+                }
+            } else if seen_this {
+                match self.edges_added > 0 {
+                    true => line.push(b'\\'),
+                    false => line.push(b'|'),
+                }
+                line.push(b' ');
+            } else {
+                line.push(b'|');
+                if self.merge_layout != Some(0) || i + 1 != self.commit_index {
+                    // An edge to the first parent runs along here.
+                    line.push(if seen_parent { b'_' } else { b' ' });
+                }
+            }
+            if Some(column) == first_parent {
+                seen_parent = true;
+            }
+        }
+
+        match self.is_mapping_correct() {
+            true => self.update_state(State::Padding),
+            false => self.update_state(State::Collapsing),
+        }
+    }
+
+    /// Moves each line that is not in its column yet one step to the
+    /// left, or, for one line at a time, along a `_` run to its column.
+    fn collapsing_line(&mut self, line: &mut Vec<u8>) {
+        std::mem::swap(&mut self.mapping, &mut self.old_mapping);
+        self.mapping[..self.mapping_size].fill(None);
+
+        let mut horizontal_edge = None;
+        let mut horizontal_edge_target = None;
+        for i in 0..self.mapping_size {
+            let Some(target) = self.old_mapping[i] else {
+                continue;
+            };
+            // Lines only ever move left, so that crossing lines go
+            // different ways.
+            if target * 2 == i {
+                self.mapping[i] = Some(target);
+            } else if self.mapping[i - 1].is_none() {
+                self.mapping[i - 1] = Some(target);
+                if horizontal_edge.is_none() {
+                    horizontal_edge = Some(i);
+                    horizontal_edge_target = Some(target);
+                    let mut j = target * 2 + 3;
+                    while j + 2 < i {
+                        self.mapping[j] = Some(target);
+                        j += 2;
+                    }
+                }
+            } else if self.mapping[i - 1] == Some(target) {
+                // Joins the line to its left, which goes the same way.
+            } else {
+                // Crosses the line to its left.
+                self.mapping[i - 2] = Some(target);
+                if horizontal_edge.is_none() {
+                    horizontal_edge = Some(i - 1);
+                    horizontal_edge_target = Some(target);
+                    let mut j = target * 2 + 3;
+                    while j + 2 < i {
+                        self.mapping[j] = Some(target);
+                        j += 2;
+                    }
+                }
+            }
+        }
+
+        let size = self.mapping_size;
+        self.old_mapping[..size].copy_from_slice(&self.mapping[..size]);
+        if size > 0 && self.mapping[size - 1].is_none() {
+            self.mapping_size -= 1;
+        }
+
+        let mut used_horizontal = false;
+        for i in 0..self.mapping_size {
+            match self.mapping[i] {
+                None => line.push(b' '),
+                Some(target) if target * 2 == i => line.push(b'|'),
+                Some(target)
+                    if Some(target) == horizontal_edge_target && horizontal_edge != Some(i + 1) =>
+                {
+                    // Only the first `_` carries on to the next line.
+                    if i != target * 2 + 3 {
+                        self.mapping[i] = None;
+                    }
+                    used_horizontal = true;
+                    line.push(b'_');
+                }
+                Some(_) => {
+                    if used_horizontal && horizontal_edge.is_some_and(|edge| i < edge) {
+                        self.mapping[i] = None;
+                    }
+                    line.push(b'/');
+                }
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Commit ancestry: parents, dates and generation numbers, taken from the
+//! commit-graph for the commits it covers and from the commit objects
+//! otherwise, and the merge-base, ancestry and path-limited queries built
+//! on them.
+
+use std::cell::RefCell;
+use std::collections::{BinaryHeap, HashMap};
+use std::rc::Rc;
+
+use crate::commit_graph::{bloom, BloomKey, BloomSettings, CommitGraph, GENERATION_INFINITY};
+use crate::diff;
+use crate::error::Result;
+use crate::object::ObjectId;
+use crate::repository::Repository;
+
+/// What history walks need to know about a commit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitInfo {
+    pub id: ObjectId,
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    /// Committer timestamp.
+    pub time: i64,
+    /// Larger than every ancestor's; [`GENERATION_INFINITY`] for commits
+    /// the commit-graph does not cover.
+    pub generation: u64,
+}
+
+/// Looks commits up once and remembers them.
+pub struct CommitCache<'r> {
+    repo: &'r Repository,
+    graph: Option<CommitGraph>,
+    /// Whether the graph's Bloom filters may be used.
+    read_changed_paths: bool,
+    commits: RefCell<HashMap<ObjectId, Rc<CommitInfo>>>,
+}
+
+impl<'r> CommitCache<'r> {
+    /// Uses the repository's commit-graph unless `core.commitGraph` is
+    /// off. A graph that cannot be read is ignored: everything it would
+    /// have answered can be found in the commits themselves.
+    pub fn new(repo: &'r Repository) -> Result<Self> {
+        let enabled = repo.config().get_bool("core.commitGraph")?.unwrap_or(true);
+        let graph = match enabled {
+            true => CommitGraph::open(repo.odb().dir(), repo.odb().hash())
+                .ok()
+                .flatten(),
+            false => None,
+        };
+        let read_changed_paths = repo
+            .config()
+            .get_bool("commitGraph.readChangedPaths")?
+            .unwrap_or(true);
+        Ok(CommitCache {
+            repo,
+            graph,
+            read_changed_paths,
+            commits: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn graph(&self) -> Option<&CommitGraph> {
+        self.graph.as_ref()
+    }
+
+    pub fn get(&self, id: &ObjectId) -> Result<Rc<CommitInfo>> {
+        if let Some(info) = self.commits.borrow().get(id) {
+            return Ok(info.clone());
+        }
+        let info = Rc::new(match self.read_graph(id) {
+            Some(info) => info,
+            None => {
+                // Not covered (written since the graph was) or unreadable.
+                let commit = self.repo.odb().read(id)?.into_commit(*id)?;
+                CommitInfo {
+                    id: *id,
+                    tree: commit.tree,
+                    parents: commit.parents,
+                    time: commit.committer.time,
+                    generation: GENERATION_INFINITY,
+                }
+            }
+        });
+        self.commits.borrow_mut().insert(*id, info.clone());
+        Ok(info)
+    }
+
+    fn read_graph(&self, id: &ObjectId) -> Option<CommitInfo> {
+        let graph = self.graph.as_ref()?;
+        let commit = graph.commit_at(graph.position(id)?).ok()?;
+        Some(CommitInfo {
+            id: *id,
+            tree: commit.tree,
+            parents: commit.parents.iter().map(|&p| graph.id_at(p)).collect(),
+            time: commit.commit_time as i64,
+            generation: commit.generation,
+        })
+    }
+
+    /// Prepares a walk limited to `paths`, looking up their Bloom keys if
+    /// the graph has filters.
+    pub fn path_limit(&self, paths: &[Vec<u8>]) -> PathLimit {
+        let settings = self
+            .graph
+            .as_ref()
+            .filter(|_| self.read_changed_paths)
+            .and_then(CommitGraph::bloom_settings);
+        PathLimit {
+            paths: paths.to_vec(),
+            keys: settings.map(|settings| {
+                let keys = paths
+                    .iter()
+                    .map(|p| BloomKey::for_pathspec(p, &settings))
+                    .collect();
+                (settings, keys)
+            }),
+        }
+    }
+
+    /// How a walk limited by `limit` treats `id`, following git's default
+    /// history simplification: a commit that leaves the paths as one of
+    /// its parents had them is hidden and only that parent is followed;
+    /// any other commit is shown and all its parents are followed. With
+    /// `first_parent`, merges are only compared with their first parent,
+    /// so that the walk is not led off down a side branch.
+    pub fn simplify(
+        &self,
+        id: &ObjectId,
+        limit: &PathLimit,
+        first_parent: bool,
+    ) -> Result<Simplified> {
+        let info = self.get(id)?;
+        if limit.paths.is_empty() {
+            return Ok(Simplified {
+                shown: true,
+                parents: info.parents.clone(),
+            });
+        }
+        let odb = self.repo.odb();
+        if info.parents.is_empty() {
+            return Ok(Simplified {
+                shown: diff::differs_under(odb, None, Some(info.tree), &limit.paths)?,
+                parents: Vec::new(),
+            });
+        }
+        let compared = match first_parent {
+            true => &info.parents[..1],
+            false => &info.parents[..],
+        };
+        for (n, parent) in compared.iter().enumerate() {
+            // Filters record what changed since the first parent only.
+            let same = (n == 0 && self.rules_out(id, limit))
+                || !diff::differs_under(
+                    odb,
+                    Some(self.get(parent)?.tree),
+                    Some(info.tree),
+                    &limit.paths,
+                )?;
+            if same {
+                return Ok(Simplified {
+                    shown: false,
+                    parents: vec![*parent],
+                });
+            }
+        }
+        Ok(Simplified {
+            shown: true,
+            parents: info.parents.clone(),
+        })
+    }
+
+    /// Whether the Bloom filter of `id` proves that none of the paths
+    /// changed since its first parent.
+    fn rules_out(&self, id: &ObjectId, limit: &PathLimit) -> bool {
+        let (Some(graph), Some((settings, keys))) = (&self.graph, &limit.keys) else {
+            return false;
+        };
+        let Some(filter) = graph
+            .position(id)
+            .and_then(|pos| graph.bloom_filter(pos, settings))
+        else {
+            return false;
+        };
+        // A path changed only if it and every directory above it did.
+        !keys.iter().any(|path_keys| {
+            path_keys
+                .iter()
+                .all(|key| bloom::filter_contains(filter, key))
+        })
+    }
+}
+
+/// Paths a walk is limited to (none for an unlimited walk).
+#[derive(Clone, Debug)]
+pub struct PathLimit {
+    paths: Vec<Vec<u8>>,
+    keys: Option<(BloomSettings, Vec<Vec<BloomKey>>)>,
+}
+
+/// The verdict of [`CommitCache::simplify`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Simplified {
+    pub shown: bool,
+    /// The parents the walk goes on to.
+    pub parents: Vec<ObjectId>,
+}
+
+const PARENT1: u8 = 1 << 0;
+const PARENT2: u8 = 1 << 1;
+const STALE: u8 = 1 << 2;
+const RESULT: u8 = 1 << 3;
+
+/// The best common ancestors of `one` and any of `others`: those that are
+/// not themselves ancestors of another, most recent first.
+pub fn merge_bases(
+    cache: &CommitCache,
+    one: ObjectId,
+    others: &[ObjectId],
+) -> Result<Vec<ObjectId>> {
+    if others.contains(&one) {
+        return Ok(vec![one]);
+    }
+    let (candidates, _) = paint_down_to_common(cache, one, others, 0)?;
+    remove_redundant(cache, candidates)
+}
+
+/// Whether `ancestor` is reachable from `descendant` (or is it).
+pub fn is_ancestor(cache: &CommitCache, ancestor: ObjectId, descendant: ObjectId) -> Result<bool> {
+    is_ancestor_of_any(cache, ancestor, &[descendant])
+}
+
+fn is_ancestor_of_any(cache: &CommitCache, ancestor: ObjectId, of: &[ObjectId]) -> Result<bool> {
+    if of.contains(&ancestor) {
+        return Ok(true);
+    }
+    let generation = cache.get(&ancestor)?.generation;
+    let mut highest = 0;
+    for id in of {
+        highest = highest.max(cache.get(id)?.generation);
+    }
+    if generation > highest {
+        return Ok(false);
+    }
+    let (_, flags) = paint_down_to_common(cache, ancestor, of, generation)?;
+    Ok(flags.get(&ancestor).is_some_and(|f| f & PARENT2 != 0))
+}
+
+/// Drops the candidates reachable from another candidate, keeping the most
+/// recent first.
+fn remove_redundant(cache: &CommitCache, candidates: Vec<ObjectId>) -> Result<Vec<ObjectId>> {
+    let mut kept = Vec::with_capacity(candidates.len());
+    for (n, &candidate) in candidates.iter().enumerate() {
+        let others: Vec<ObjectId> = candidates
+            .iter()
+            .enumerate()
+            .filter(|&(m, _)| m != n)
+            .map(|(_, id)| *id)
+            .collect();
+        if !is_ancestor_of_any(cache, candidate, &others)? {
+            kept.push(candidate);
+        }
+    }
+    let mut dated = Vec::with_capacity(kept.len());
+    for id in kept {
+        dated.push((cache.get(&id)?.time, id));
+    }
+    dated.sort_by_key(|&(time, _)| std::cmp::Reverse(time));
+    Ok(dated.into_iter().map(|(_, id)| id).collect())
+}
+
+/// Walks down from `one` and `twos` at once, highest generation (then most
+/// recent) first, marking what each side reaches. Commits reached from
+/// both are the merge base candidates; their ancestors are marked stale,
+/// and the walk ends once only stale commits are left, or once it gets
+/// below `min_generation`, under which nothing can reach back up.
+fn paint_down_to_common(
+    cache: &CommitCache,
+    one: ObjectId,
+    twos: &[ObjectId],
+    min_generation: u64,
+) -> Result<(Vec<ObjectId>, HashMap<ObjectId, u8>)> {
+    let mut queue = PaintQueue::default();
+    queue.mark(one, PARENT1);
+    queue.push(cache, one)?;
+    for &two in twos {
+        queue.mark(two, PARENT2);
+        queue.push(cache, two)?;
+    }
+
+    let mut results = Vec::new();
+    while queue.has_nonstale() {
+        let Some((generation, _, id)) = queue.pop() else {
+            break;
+        };
+        if min_generation > 0 && generation < min_generation {
+            break;
+        }
+        let mut flags = queue.flags(id) & (PARENT1 | PARENT2 | STALE);
+        if flags == PARENT1 | PARENT2 {
+            if queue.flags(id) & RESULT == 0 {
+                queue.mark(id, RESULT);
+                results.push(id);
+            }
+            // Everything below a common ancestor is a worse one.
+            flags |= STALE;
+        }
+        for parent in cache.get(&id)?.parents.iter().copied() {
+            if queue.flags(parent) & flags == flags {
+                continue;
+            }
+            queue.mark(parent, flags);
+            queue.push(cache, parent)?;
+        }
+    }
+    Ok((
+        results
+            .into_iter()
+            .filter(|id| queue.flags(*id) & STALE == 0)
+            .collect(),
+        queue.flags,
+    ))
+}
+
+/// The frontier of [`paint_down_to_common`], keeping count of the queued
+/// commits that are not stale yet so the walk knows when to stop.
+#[derive(Default)]
+struct PaintQueue {
+    heap: BinaryHeap<(u64, i64, ObjectId)>,
+    flags: HashMap<ObjectId, u8>,
+    queued: HashMap<ObjectId, usize>,
+    nonstale: usize,
+}
+
+impl PaintQueue {
+    fn flags(&self, id: ObjectId) -> u8 {
+        self.flags.get(&id).copied().unwrap_or(0)
+    }
+
+    fn mark(&mut self, id: ObjectId, flags: u8) {
+        let old = self.flags(id);
+        if old & STALE == 0 && flags & STALE != 0 {
+            self.nonstale -= self.queued.get(&id).copied().unwrap_or(0);
+        }
+        self.flags.insert(id, old | flags);
+    }
+
+    fn push(&mut self, cache: &CommitCache, id: ObjectId) -> Result<()> {
+        let info = cache.get(&id)?;
+        self.heap.push((info.generation, info.time, id));
+        *self.queued.entry(id).or_default() += 1;
+        if self.flags(id) & STALE == 0 {
+            self.nonstale += 1;
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<(u64, i64, ObjectId)> {
+        let entry = self.heap.pop()?;
+        let id = entry.2;
+        if let Some(count) = self.queued.get_mut(&id) {
+            *count -= 1;
+        }
+        if self.flags(id) & STALE == 0 {
+            self.nonstale -= 1;
+        }
+        Some(entry)
+    }
+
+    fn has_nonstale(&self) -> bool {
+        self.nonstale > 0
+    }
+}
@@ -0,0 +1,312 @@
+//! Ignore rules: `.gitignore` files, `.git/info/exclude` and the user's
+//! `$XDG_CONFIG_HOME/git/ignore`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::index::Index;
+use crate::path;
+use crate::repository::Repository;
+
+/// A pattern and whether matching paths are ignored (`false` for `!`
+/// patterns, which re-include them).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    /// Matched byte by byte, as paths are in no particular encoding.
+    pub pattern: Vec<u8>,
+    pub ignore: bool,
+}
+
+/// Parses the lines of an ignore file, skipping blanks and comments.
+pub fn parse(text: &[u8]) -> Vec<Rule> {
+    text.split(|&b| b == b'\n')
+        .filter_map(|line| {
+            let line = line.trim_ascii();
+            let (pattern, ignore) = match line.first()? {
+                b'#' => return None,
+                b'!' => (&line[1..], false),
+                b'\\' => (&line[1..], true),
+                _ => (line, true),
+            };
+            Some(Rule {
+                pattern: pattern.to_vec(),
+                ignore,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules {
+    /// Rules that apply to the whole worktree, in priority order.
+    absolute: Vec<Vec<Rule>>,
+    /// `.gitignore` rules keyed by the directory holding them (empty for
+    /// the top level).
+    scoped: HashMap<Vec<u8>, Vec<Rule>>,
+}
+
+impl IgnoreRules {
+    /// Collects every rule that applies to the repository. `.gitignore`
+    /// files in the worktree take precedence over the staged versions.
+    pub fn read(repo: &Repository) -> Result<Self> {
+        let mut rules = IgnoreRules::default();
+
+        if let Some(text) = read_optional(&repo.path("info").join("exclude"))? {
+            rules.absolute.push(parse(&text));
+        }
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")));
+        if let Some(home) = config_home {
+            if let Some(text) = read_optional(&home.join("git").join("ignore"))? {
+                rules.absolute.push(parse(&text));
+            }
+        }
+
+        for entry in Index::read(repo)?.entries {
+            let (dir, name) = split_last(&entry.name);
+            if name != b".gitignore" {
+                continue;
+            }
+            let (_, data) = repo.odb().read_raw(&entry.id)?;
+            rules.scoped.insert(dir.to_vec(), parse(&data));
+        }
+
+        let mut pending = vec![(repo.worktree().to_path_buf(), Vec::new())];
+        while let Some((dir, relative)) = pending.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                let file_name = entry.file_name();
+                let Some(name) = path::from_os_str(&file_name) else {
+                    continue;
+                };
+                if entry.file_type()?.is_dir() {
+                    if path != repo.gitdir() {
+                        pending.push((path, path::join(&relative, &name)));
+                    }
+                } else if *name == *b".gitignore" {
+                    // An unreadable file is as good as none.
+                    if let Ok(data) = fs::read(&path) {
+                        rules.scoped.insert(relative.clone(), parse(&data));
+                    }
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Whether `path` (relative to the worktree, `/`-separated) is ignored,
+    /// `is_dir` saying whether it names a directory. As in git, nothing
+    /// below an ignored directory can be re-included.
+    pub fn is_ignored(&self, path: &[u8], is_dir: bool) -> bool {
+        let slashes = path.iter().enumerate().filter(|&(_, &b)| b == b'/');
+        for (at, _) in slashes {
+            if self.verdict(&path[..at], true) {
+                return true;
+            }
+        }
+        self.verdict(path, is_dir)
+    }
+
+    /// Whether `path` itself is ignored: the nearest `.gitignore` with an
+    /// opinion wins, then the repository-wide rules are consulted.
+    fn verdict(&self, path: &[u8], is_dir: bool) -> bool {
+        let mut dir = split_last(path).0;
+        loop {
+            if let Some(rules) = self.scoped.get(dir) {
+                let relative = match dir {
+                    b"" => path,
+                    _ => &path[dir.len() + 1..],
+                };
+                if let Some(ignore) = check(rules, relative, is_dir) {
+                    return ignore;
+                }
+            }
+            if dir.is_empty() {
+                break;
+            }
+            dir = split_last(dir).0;
+        }
+
+        self.absolute
+            .iter()
+            .find_map(|rules| check(rules, path, is_dir))
+            .unwrap_or(false)
+    }
+}
+
+/// The verdict of the last rule in `rules` matching `path`, if any does.
+/// A trailing `/` limits a pattern to directories; any other `/` anchors
+/// it to the directory of the file it came from, where otherwise it
+/// matches the last component at any depth.
+fn check(rules: &[Rule], path: &[u8], is_dir: bool) -> Option<bool> {
+    let mut result = None;
+    for rule in rules {
+        let mut pattern = rule.pattern.as_slice();
+        if let Some(dir) = pattern.strip_suffix(b"/") {
+            if !is_dir {
+                continue;
+            }
+            pattern = trim_end_slashes(dir);
+        }
+        let matched = if pattern.contains(&b'/') {
+            let anchored = pattern.strip_prefix(b"/").unwrap_or(pattern);
+            wildmatch(anchored, path)
+        } else {
+            wildmatch(pattern, split_last(path).1)
+        };
+        if matched {
+            result = Some(rule.ignore);
+        }
+    }
+    result
+}
+
+/// [`fnmatch`] with git's rules for paths: `*` and `?` stop at slashes,
+/// while `**` between slashes (or at either end) matches any number of
+/// whole directories, none included.
+fn wildmatch(pattern: &[u8], name: &[u8]) -> bool {
+    if let Some(rest) = pattern.strip_prefix(b"**/") {
+        // Zero directories, or one more and try again.
+        return wildmatch(rest, name)
+            || name
+                .iter()
+                .position(|&b| b == b'/')
+                .is_some_and(|slash| wildmatch(pattern, &name[slash + 1..]));
+    }
+    if pattern == b"**" {
+        return true;
+    }
+    if let Some(rest) = pattern.strip_prefix(b"/**") {
+        if rest.is_empty() || rest.starts_with(b"/") {
+            // `a/**` needs something below `a`; `a/**/b` may have nothing
+            // between them.
+            let rest = rest.strip_prefix(b"/").map(|r| [b"**/", r].concat());
+            return match name.first() {
+                Some(b'/') => match rest {
+                    Some(rest) => wildmatch(&rest, &name[1..]),
+                    None => name.len() > 1,
+                },
+                _ => false,
+            };
+        }
+    }
+
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            let run = name.iter().position(|&b| b == b'/').unwrap_or(name.len());
+            (0..=run).any(|skip| wildmatch(rest, &name[skip..]))
+        }
+        Some(b'?') => {
+            name.first().is_some_and(|&c| c != b'/') && wildmatch(&pattern[1..], &name[1..])
+        }
+        Some(b'[') => match name.first() {
+            Some(&c) if c != b'/' => match match_class(pattern, 0, c) {
+                Some((matched, next)) => matched && wildmatch(&pattern[next..], &name[1..]),
+                None => c == b'[' && wildmatch(&pattern[1..], &name[1..]),
+            },
+            _ => false,
+        },
+        Some(&c) => name.first() == Some(&c) && wildmatch(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Shell-style matching of `name` against `pattern`, byte by byte as in
+/// git: `*` matches any run of bytes (slashes included), `?` any one byte
+/// and `[...]` a byte class, negated with a leading `!`.
+pub fn fnmatch(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the most recent `*` if the rest fails to match.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match match_class(pattern, p, name[n]) {
+                Some((true, next)) => Some(next),
+                Some((false, _)) => None,
+                // An unterminated class is a literal `[`.
+                None => (name[n] == b'[').then_some(p + 1),
+            },
+            Some(&c) => (c == name[n]).then_some(p + 1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                p = star_p;
+                n = star_n + 1;
+                backtrack = Some((star_p, star_n + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Matches `c` against the class opening at `pattern[start]`, returning
+/// whether it matched and where the pattern continues, or `None` if the
+/// class is never closed.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(pattern.get(i), Some(b'!'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let lo = *pattern.get(i)?;
+        // A `]` right after the opening bracket is a literal.
+        if lo == b']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&b'-') && pattern.get(i + 2).is_some_and(|&hi| hi != b']') {
+            let hi = pattern[i + 2];
+            matched |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+}
+
+/// The directory part of `path` (empty at the top level) and its name.
+fn split_last(path: &[u8]) -> (&[u8], &[u8]) {
+    match path.iter().rposition(|&b| b == b'/') {
+        Some(slash) => (&path[..slash], &path[slash + 1..]),
+        None => (b"", path),
+    }
+}
+
+fn trim_end_slashes(mut path: &[u8]) -> &[u8] {
+    while let Some(rest) = path.strip_suffix(b"/") {
+        path = rest;
+    }
+    path
+}
+
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,339 @@
+//! The index (staging area). Versions 2 to 4 are read; it is written as
+//! version 2, or 3 when entries carry extended flags.
+
+use std::fs;
+use std::io;
+use std::time::SystemTime;
+
+use crate::error::{Error, Result};
+use crate::object::{HashAlgorithm, ObjectId};
+use crate::repository::Repository;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const HEADER_LEN: usize = 12;
+/// Fixed-size part of an entry before the object id.
+const ENTRY_STAT_LEN: usize = 40;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const NAME_MASK: u16 = 0x0fff;
+const EXT_FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const EXT_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// Mode type bits of a regular file.
+pub const MODE_TYPE_REGULAR: u32 = 0b1000;
+/// Mode type bits of a symbolic link.
+pub const MODE_TYPE_SYMLINK: u32 = 0b1010;
+/// Mode type bits of a submodule.
+pub const MODE_TYPE_GITLINK: u32 = 0b1110;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// When the file's metadata last changed, as seconds and nanoseconds.
+    pub ctime: (u32, u32),
+    /// When the file's data last changed.
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+    /// One of the `MODE_TYPE_*` constants.
+    pub mode_type: u32,
+    pub mode_perms: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub id: ObjectId,
+    pub assume_valid: bool,
+    pub stage: u16,
+    /// Set by sparse checkout for paths left out of the worktree.
+    pub skip_worktree: bool,
+    /// Recorded by `add -N`: the path is tracked but has no content yet.
+    pub intent_to_add: bool,
+    /// Path relative to the worktree, with `/` separators, as bytes in no
+    /// particular encoding.
+    pub name: Vec<u8>,
+}
+
+impl IndexEntry {
+    /// An entry for the worktree file `name` with the given metadata (as
+    /// from `symlink_metadata`), whose content is stored as `id`.
+    pub fn from_metadata(name: Vec<u8>, id: ObjectId, meta: &fs::Metadata) -> Self {
+        let (mode_type, mode_perms) = if meta.file_type().is_symlink() {
+            (MODE_TYPE_SYMLINK, 0)
+        } else if is_executable(meta) {
+            (MODE_TYPE_REGULAR, 0o755)
+        } else {
+            (MODE_TYPE_REGULAR, 0o644)
+        };
+        let time = |t: io::Result<SystemTime>| {
+            let since = t
+                .ok()
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            (since.as_secs() as u32, since.subsec_nanos())
+        };
+        // The index only has room for the low 32 bits of these.
+        #[cfg(unix)]
+        let (ctime, dev, ino, uid, gid) = {
+            use std::os::unix::fs::MetadataExt;
+            (
+                (meta.ctime() as u32, meta.ctime_nsec() as u32),
+                meta.dev() as u32,
+                meta.ino() as u32,
+                meta.uid(),
+                meta.gid(),
+            )
+        };
+        #[cfg(not(unix))]
+        let (ctime, dev, ino, uid, gid) = (time(meta.created()), 0, 0, 0, 0);
+
+        IndexEntry {
+            ctime,
+            mtime: time(meta.modified()),
+            dev,
+            ino,
+            mode_type,
+            mode_perms,
+            uid,
+            gid,
+            size: meta.len() as u32,
+            id,
+            assume_valid: false,
+            stage: 0,
+            skip_worktree: false,
+            intent_to_add: false,
+            name,
+        }
+    }
+
+    /// Whether the entry needs the extended flags of version 3.
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+
+    /// The mode as it appears in trees.
+    pub fn mode(&self) -> u32 {
+        self.mode_type << 12 | self.mode_perms
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Index {
+    pub version: u32,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index {
+            version: 2,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl Index {
+    /// Reads `.git/index`, treating a missing file as an empty index.
+    pub fn read(repo: &Repository) -> Result<Self> {
+        match fs::read(repo.path("index")) {
+            Ok(raw) => Index::parse(&raw, repo.odb().hash()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Index::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parses an index whose ids and checksum use `hash`.
+    pub fn parse(raw: &[u8], hash: HashAlgorithm) -> Result<Self> {
+        let bad = |reason: &str| Error::parse("index", reason.to_owned());
+        if raw.len() < HEADER_LEN || &raw[..4] != SIGNATURE {
+            return Err(bad("invalid index signature"));
+        }
+        let version = be32(raw, 4);
+        if !(2..=4).contains(&version) {
+            return Err(bad(&format!("unsupported index file version {version}")));
+        }
+        let count = be32(raw, 8) as usize;
+        // Fixed-size part of an entry, up to and including the flags.
+        let fixed_len = ENTRY_STAT_LEN + hash.raw_len() + 2;
+
+        // The count is only checked against the entries actually there.
+        let mut entries: Vec<IndexEntry> = Vec::with_capacity(count.min(raw.len() / fixed_len));
+        let mut pos = HEADER_LEN;
+        for _ in 0..count {
+            let fixed = raw
+                .get(pos..pos + fixed_len)
+                .ok_or_else(|| bad("truncated entry"))?;
+            if be16(fixed, 24) != 0 {
+                return Err(bad("invalid index entry"));
+            }
+            let mode = u32::from(be16(fixed, 26));
+            let mode_type = mode >> 12;
+            if ![MODE_TYPE_REGULAR, MODE_TYPE_SYMLINK, MODE_TYPE_GITLINK].contains(&mode_type) {
+                return Err(bad(&format!("invalid mode type {mode_type:#b}")));
+            }
+            let flags = be16(fixed, ENTRY_STAT_LEN + hash.raw_len());
+            let mut name_start = pos + fixed_len;
+            let ext_flags = match flags & FLAG_EXTENDED {
+                0 => 0,
+                _ if version == 2 => return Err(bad("extended flags in a version 2 index")),
+                _ => {
+                    let ext = raw
+                        .get(name_start..name_start + 2)
+                        .ok_or_else(|| bad("truncated entry"))?;
+                    name_start += 2;
+                    be16(ext, 0)
+                }
+            };
+
+            let (name, entry_end) = if version == 4 {
+                // The name drops a number of bytes from the end of the
+                // previous one, then appends a NUL-terminated suffix.
+                let (strip, len) =
+                    read_offset(&raw[name_start..]).ok_or_else(|| bad("bad name prefix"))?;
+                let prev = entries.last().map_or(&[][..], |e: &IndexEntry| &e.name);
+                let keep = prev
+                    .len()
+                    .checked_sub(strip)
+                    .ok_or_else(|| bad("bad name prefix"))?;
+                let suffix_start = name_start + len;
+                let suffix_len = raw[suffix_start..]
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| bad("name not NUL-terminated"))?;
+                let mut name = prev[..keep].to_vec();
+                name.extend_from_slice(&raw[suffix_start..suffix_start + suffix_len]);
+                // Version 4 entries are not padded.
+                (name, suffix_start + suffix_len + 1)
+            } else {
+                // Names of 0xfff bytes or more are only NUL-terminated.
+                let name_len = match flags & NAME_MASK {
+                    NAME_MASK => raw[name_start..]
+                        .iter()
+                        .position(|&b| b == 0)
+                        .ok_or_else(|| bad("name not NUL-terminated"))?,
+                    len => usize::from(len),
+                };
+                if raw.get(name_start + name_len) != Some(&0) {
+                    return Err(bad("invalid name termination"));
+                }
+                // Entries are NUL-padded to a multiple of eight bytes.
+                let entry_len = name_start - pos + name_len + 1;
+                (
+                    raw[name_start..name_start + name_len].to_vec(),
+                    pos + entry_len.next_multiple_of(8),
+                )
+            };
+
+            entries.push(IndexEntry {
+                ctime: (be32(fixed, 0), be32(fixed, 4)),
+                mtime: (be32(fixed, 8), be32(fixed, 12)),
+                dev: be32(fixed, 16),
+                ino: be32(fixed, 20),
+                mode_type,
+                mode_perms: mode & 0o777,
+                uid: be32(fixed, 28),
+                gid: be32(fixed, 32),
+                size: be32(fixed, 36),
+                id: ObjectId::from_bytes(&fixed[ENTRY_STAT_LEN..ENTRY_STAT_LEN + hash.raw_len()])?,
+                assume_valid: flags & FLAG_ASSUME_VALID != 0,
+                stage: (flags & FLAG_STAGE_MASK) >> 12,
+                skip_worktree: ext_flags & EXT_FLAG_SKIP_WORKTREE != 0,
+                intent_to_add: ext_flags & EXT_FLAG_INTENT_TO_ADD != 0,
+                name,
+            });
+            pos = entry_end;
+        }
+
+        Ok(Index { version, entries })
+    }
+
+    /// Serializes the index with a trailing checksum computed with `hash`,
+    /// which must be the algorithm of the entries' ids.
+    pub fn serialize(&self, hash: HashAlgorithm) -> Result<Vec<u8>> {
+        let extended = self.entries.iter().any(IndexEntry::is_extended);
+        let version = match extended {
+            true => 3,
+            false => self.version.clamp(2, 3),
+        };
+        let mut out = Vec::new();
+        out.extend_from_slice(SIGNATURE);
+        out.extend_from_slice(&version.to_be_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        for e in &self.entries {
+            let start = out.len();
+            for field in [e.ctime.0, e.ctime.1, e.mtime.0, e.mtime.1, e.dev, e.ino] {
+                out.extend_from_slice(&field.to_be_bytes());
+            }
+            out.extend_from_slice(&e.mode().to_be_bytes());
+            for field in [e.uid, e.gid, e.size] {
+                out.extend_from_slice(&field.to_be_bytes());
+            }
+            out.extend_from_slice(e.id.as_bytes());
+
+            let name_len = e.name.len().min(usize::from(NAME_MASK)) as u16;
+            let assume_valid = if e.assume_valid { FLAG_ASSUME_VALID } else { 0 };
+            let extended = if e.is_extended() { FLAG_EXTENDED } else { 0 };
+            let flags = assume_valid | extended | (e.stage << 12) & FLAG_STAGE_MASK | name_len;
+            out.extend_from_slice(&flags.to_be_bytes());
+            if e.is_extended() {
+                let skip = if e.skip_worktree {
+                    EXT_FLAG_SKIP_WORKTREE
+                } else {
+                    0
+                };
+                let ita = if e.intent_to_add {
+                    EXT_FLAG_INTENT_TO_ADD
+                } else {
+                    0
+                };
+                out.extend_from_slice(&(skip | ita).to_be_bytes());
+            }
+            out.extend_from_slice(&e.name);
+
+            let padded = (out.len() - start + 1).next_multiple_of(8);
+            out.resize(start + padded, 0);
+        }
+
+        let checksum = hash.digest(&out)?;
+        out.extend_from_slice(&checksum);
+        Ok(out)
+    }
+
+    pub fn write(&self, repo: &Repository) -> Result<()> {
+        crate::odb::write_atomically(&repo.path("index"), &self.serialize(repo.odb().hash())?)
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_meta: &fs::Metadata) -> bool {
+    false
+}
+
+/// Reads the offset encoding of version 4 name prefixes (the one packs
+/// use for delta base offsets), returning the value and its length.
+fn read_offset(data: &[u8]) -> Option<(usize, usize)> {
+    let mut byte = *data.first()?;
+    let mut value = usize::from(byte & 0x7f);
+    let mut len = 1;
+    while byte & 0x80 != 0 {
+        byte = *data.get(len)?;
+        len += 1;
+        value = value.checked_add(1)?.checked_mul(1 << 7)? | usize::from(byte & 0x7f);
+    }
+    Some((value, len))
+}
+
+fn be16(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([data[at], data[at + 1]])
+}
+
+fn be32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().expect("4-byte slice"))
+}
@@ -0,0 +1,34 @@
+//! A Git implementation for learning purposes, ported from the Python `rosa`
+//! package.
+
+pub mod chunk;
+pub mod cli;
+pub mod commands;
+pub mod commit_graph;
+pub mod config;
+pub mod date;
+pub mod diff;
+pub mod error;
+pub mod fsck;
+pub mod gc;
+pub mod graph;
+pub mod history;
+pub mod ignore;
+pub mod index;
+pub mod mailmap;
+pub mod notes;
+pub mod object;
+pub mod odb;
+pub mod pack;
+pub mod path;
+pub mod pretty;
+pub mod reachable;
+pub mod reflog;
+pub mod refs;
+pub mod repository;
+pub mod revision;
+pub mod revwalk;
+
+pub use error::{Error, Result};
+pub use object::{Object, ObjectId, ObjectKind};
+pub use repository::Repository;
@@ -0,0 +1,139 @@
+//! Mailmaps: the names and emails people should be shown under, read from
+//! `.mailmap` at the top of the worktree, the blob `mailmap.blob` names and
+//! the file `mailmap.file` names, later entries winning.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::object::ObjectKind;
+use crate::repository::Repository;
+use crate::revision;
+
+/// What an email, or a name with an email, is replaced by.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Replacement {
+    name: Option<Vec<u8>>,
+    email: Option<Vec<u8>>,
+}
+
+/// The entries for one email, which is matched ignoring case as the names
+/// are.
+#[derive(Clone, Debug, Default)]
+struct Entry {
+    /// For any name not listed in `by_name`.
+    any: Replacement,
+    /// Keyed by lowercased name.
+    by_name: HashMap<Vec<u8>, Replacement>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mailmap {
+    /// Keyed by lowercased email.
+    entries: HashMap<Vec<u8>, Entry>,
+}
+
+impl Mailmap {
+    /// Reads every mailmap of the repository; missing ones are skipped.
+    pub fn load(repo: &Repository) -> Result<Self> {
+        let mut mailmap = Mailmap::default();
+        if let Some(text) = read_optional(&repo.worktree().join(".mailmap"))? {
+            mailmap.add(&text);
+        }
+        if let Some(spec) = repo.config().get("mailmap.blob") {
+            match revision::find(repo, spec, Some(ObjectKind::Blob), true) {
+                Ok(id) => mailmap.add(&repo.odb().read_raw(&id)?.1),
+                Err(Error::NoSuchRef(_) | Error::NoSuchPath { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(path) = repo.config().get("mailmap.file") {
+            if let Some(text) = read_optional(Path::new(path))? {
+                mailmap.add(&text);
+            }
+        }
+        Ok(mailmap)
+    }
+
+    /// Parses the lines of a mailmap into this one, skipping those that
+    /// hold no email. Each line is one of
+    ///
+    /// ```text
+    /// Proper Name <commit@email>
+    /// <proper@email> <commit@email>
+    /// Proper Name <proper@email> <commit@email>
+    /// Proper Name <proper@email> Commit Name <commit@email>
+    /// ```
+    pub fn add(&mut self, text: &[u8]) {
+        for line in text.split(|&b| b == b'\n') {
+            if line.first() == Some(&b'#') {
+                continue;
+            }
+            let Some((name, email, rest)) = name_and_email(line) else {
+                continue;
+            };
+            if email.is_empty() {
+                continue;
+            }
+            let (name, email, old_name, old_email) = match name_and_email(rest) {
+                Some((old_name, old_email, _)) => (name, Some(email), old_name, old_email),
+                // The one email is the one matched.
+                None => (name, None, &b""[..], email),
+            };
+            let name = (!name.is_empty()).then(|| name.to_vec());
+            let entry = self
+                .entries
+                .entry(old_email.to_ascii_lowercase())
+                .or_default();
+            let email = email.map(<[u8]>::to_vec);
+            if old_name.is_empty() {
+                if name.is_some() {
+                    entry.any.name = name;
+                }
+                if email.is_some() {
+                    entry.any.email = email;
+                }
+            } else {
+                let replacement = Replacement { name, email };
+                entry
+                    .by_name
+                    .insert(old_name.to_ascii_lowercase(), replacement);
+            }
+        }
+    }
+
+    /// The name and email to show for `name` and `email`, which are given
+    /// back when no entry matches.
+    pub fn map<'a>(&'a self, name: &'a [u8], email: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        let Some(entry) = self.entries.get(&email.to_ascii_lowercase()) else {
+            return (name, email);
+        };
+        let replacement = entry
+            .by_name
+            .get(&name.to_ascii_lowercase())
+            .unwrap_or(&entry.any);
+        (
+            replacement.name.as_deref().unwrap_or(name),
+            replacement.email.as_deref().unwrap_or(email),
+        )
+    }
+}
+
+/// Reads `Name <email>` from the start of `text`, and returns the name,
+/// empty if blank, the email and what follows.
+fn name_and_email(text: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let left = text.iter().position(|&b| b == b'<')?;
+    let right = left + 1 + text[left + 1..].iter().position(|&b| b == b'>')?;
+    let name = text[..left].trim_ascii();
+    Some((name, &text[left + 1..right], &text[right + 1..]))
+}
+
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,80 @@
+//! Notes: text attached to objects without changing their ids, kept as
+//! blobs named by the annotated object's id in the tree of a notes ref.
+
+use std::collections::HashMap;
+
+use crate::error::Result;
+use crate::object::ObjectId;
+use crate::refs;
+use crate::repository::Repository;
+
+/// The ref notes are read from unless configured otherwise.
+pub const DEFAULT_REF: &str = "refs/notes/commits";
+
+/// The notes of one notes ref, by the id of the object they annotate.
+#[derive(Clone, Debug, Default)]
+pub struct Notes {
+    blobs: HashMap<ObjectId, ObjectId>,
+}
+
+impl Notes {
+    /// Reads the notes of `$GIT_NOTES_REF`, `core.notesRef` or
+    /// [`DEFAULT_REF`]; there are none if the ref does not exist.
+    pub fn load(repo: &Repository) -> Result<Self> {
+        let name = std::env::var("GIT_NOTES_REF")
+            .ok()
+            .or_else(|| repo.config().get("core.notesRef").map(str::to_owned))
+            .map_or_else(|| DEFAULT_REF.to_owned(), |name| expand_ref(&name));
+        let mut notes = Notes::default();
+        let Some(id) = refs::resolve(repo, &name)? else {
+            return Ok(notes);
+        };
+        let tree = repo.odb().read(&id)?.into_commit(id)?.tree;
+        notes.read_tree(repo, tree, String::new())?;
+        Ok(notes)
+    }
+
+    /// Collects the notes under `tree`, whose path spells `prefix`. Big
+    /// notes trees fan out into directories named by the first hex digits
+    /// of the ids; entries that spell no id are not notes.
+    fn read_tree(&mut self, repo: &Repository, tree: ObjectId, prefix: String) -> Result<()> {
+        let hex_len = repo.odb().hash().hex_len();
+        for entry in repo.odb().read(&tree)?.into_tree(tree)?.entries {
+            let Ok(name) = std::str::from_utf8(&entry.name) else {
+                continue;
+            };
+            if !name.bytes().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+            let path = prefix.clone() + name;
+            if entry.is_tree() && name.len() == 2 && path.len() < hex_len {
+                self.read_tree(repo, entry.id, path)?;
+            } else if !entry.is_tree() && path.len() == hex_len {
+                if let Ok(annotated) = path.parse() {
+                    self.blobs.insert(annotated, entry.id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The note attached to `id`, as stored.
+    pub fn get(&self, repo: &Repository, id: &ObjectId) -> Result<Option<Vec<u8>>> {
+        match self.blobs.get(id) {
+            Some(blob) => Ok(Some(repo.odb().read_raw(blob)?.1)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The full name of a notes ref given as `commits`, `notes/commits` or
+/// `refs/notes/commits`.
+fn expand_ref(name: &str) -> String {
+    if name.starts_with("refs/notes/") {
+        name.to_owned()
+    } else if name.starts_with("notes/") {
+        format!("refs/{name}")
+    } else {
+        format!("refs/notes/{name}")
+    }
+}
@@ -0,0 +1,11 @@
+/// File contents, stored verbatim.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Blob {
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    pub fn new(data: Vec<u8>) -> Self {
+        Blob { data }
+    }
+}
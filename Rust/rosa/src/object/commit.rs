@@ -0,0 +1,148 @@
+use super::{is_lower_hex, Header, Kvlm, ObjectId, Signature};
+use crate::error::{Error, Result};
+
+/// A snapshot of the tree plus its history and metadata.
+///
+/// Headers after the committer and encoding (`gpgsig`, `mergetag` and
+/// whatever else) are kept in order, so that a parsed commit serializes
+/// back to the same bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    pub author: Signature,
+    pub committer: Signature,
+    pub encoding: Option<Vec<u8>>,
+    /// The remaining headers, continuation lines unfolded.
+    pub extra_headers: Vec<Header>,
+    /// `None` when the commit ends with its headers.
+    pub message: Option<Vec<u8>>,
+}
+
+impl Commit {
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let kvlm = Kvlm::parse(raw)?;
+        let mut headers = Headers::new("commit", kvlm.headers);
+        let tree = parse_id("commit", &headers.take(b"tree")?)?;
+        let mut parents = Vec::new();
+        while let Some(parent) = headers.take_optional(b"parent")? {
+            parents.push(parse_id("commit", &parent)?);
+        }
+        let author = Signature::parse(&headers.take(b"author")?)?;
+        let committer = Signature::parse(&headers.take(b"committer")?)?;
+        let encoding = headers.take_optional(b"encoding")?;
+        Ok(Commit {
+            tree,
+            parents,
+            author,
+            committer,
+            encoding,
+            extra_headers: headers.rest(),
+            message: kvlm.message,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut kvlm = Kvlm::default();
+        kvlm.push(b"tree", self.tree.to_hex());
+        for parent in &self.parents {
+            kvlm.push(b"parent", parent.to_hex());
+        }
+        kvlm.push(b"author", self.author.to_bytes());
+        kvlm.push(b"committer", self.committer.to_bytes());
+        if let Some(encoding) = &self.encoding {
+            kvlm.push(b"encoding", encoding.as_slice());
+        }
+        kvlm.headers.extend(self.extra_headers.iter().cloned());
+        kvlm.message = self.message.clone();
+        kvlm.serialize()
+    }
+
+    /// The first paragraph of the message, its lines joined by spaces, as
+    /// git's `%s` shows it.
+    pub fn subject(&self) -> Vec<u8> {
+        let message = self.message.as_deref().unwrap_or_default();
+        let mut subject = Vec::new();
+        let lines = message
+            .split(|&b| b == b'\n')
+            .map(|line| line.trim_ascii_end());
+        for line in lines.skip_while(|line| line.is_empty()) {
+            if line.is_empty() {
+                break;
+            }
+            if !subject.is_empty() {
+                subject.push(b' ');
+            }
+            subject.extend_from_slice(line);
+        }
+        subject
+    }
+
+    /// The first value of an extra header, such as `gpgsig`.
+    pub fn extra_header(&self, key: &[u8]) -> Option<&[u8]> {
+        self.extra_headers
+            .iter()
+            .find(|h| h.key == key)
+            .map(|h| h.value.as_slice())
+    }
+}
+
+/// Headers of a commit or tag, taken in the order they must come in.
+pub(super) struct Headers {
+    kind: &'static str,
+    headers: std::iter::Peekable<std::vec::IntoIter<Header>>,
+}
+
+impl Headers {
+    pub(super) fn new(kind: &'static str, headers: Vec<Header>) -> Self {
+        Headers {
+            kind,
+            headers: headers.into_iter().peekable(),
+        }
+    }
+
+    /// The value of the next header, which must be `key`.
+    pub(super) fn take(&mut self, key: &[u8]) -> Result<Vec<u8>> {
+        self.take_optional(key)?.ok_or_else(|| {
+            Error::parse(
+                self.kind,
+                format!("missing {} header", String::from_utf8_lossy(key)),
+            )
+        })
+    }
+
+    /// The value of the next header if it is `key`. The headers taken this
+    /// way are written back from their values, so one that could only be
+    /// kept as the bytes it was read from is refused.
+    pub(super) fn take_optional(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(header) = self.headers.next_if(|h| h.key == key) else {
+            return Ok(None);
+        };
+        match header.raw {
+            None => Ok(Some(header.value)),
+            Some(raw) => Err(Error::parse(
+                self.kind,
+                format!("malformed header {:?}", String::from_utf8_lossy(&raw)),
+            )),
+        }
+    }
+
+    pub(super) fn rest(self) -> Vec<Header> {
+        self.headers.collect()
+    }
+}
+
+/// Parses a lowercase hex id, the only spelling that serializes back the
+/// same.
+pub(super) fn parse_id(kind: &'static str, raw: &[u8]) -> Result<ObjectId> {
+    std::str::from_utf8(raw)
+        .ok()
+        .filter(|s| is_lower_hex(s.as_bytes()))
+        .and_then(|s| ObjectId::from_hex(s).ok())
+        .ok_or_else(|| {
+            Error::parse(
+                kind,
+                format!("bad object id {}", String::from_utf8_lossy(raw)),
+            )
+        })
+}
@@ -0,0 +1,121 @@
+//! The hash functions object ids can be computed with.
+
+use std::fmt;
+use std::str::FromStr;
+
+use sha1_checked::{CollisionResult, Sha1};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+/// Length in bytes of the longest object id, a SHA-256 one.
+pub const MAX_HASH_LEN: usize = 32;
+
+/// The object format of a repository (`extensions.objectFormat`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Length in bytes of the ids this algorithm produces.
+    pub const fn raw_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha256 => 32,
+        }
+    }
+
+    /// Length of the ids in hex digits.
+    pub const fn hex_len(self) -> usize {
+        self.raw_len() * 2
+    }
+
+    /// The name used in configuration and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// The algorithm producing ids of `len` bytes.
+    pub fn from_len(len: usize) -> Option<Self> {
+        match len {
+            20 => Some(HashAlgorithm::Sha1),
+            32 => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            // Like git's sha1dc: report collisions rather than quietly
+            // returning a "safe" hash that differs from real SHA-1.
+            HashAlgorithm::Sha1 => Hasher::Sha1(Box::new(Sha1::builder().safe_hash(false).build())),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// Hashes `data` in one go, as used for file checksums.
+    pub fn digest(self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(Error::Config(format!("unknown object format '{s}'"))),
+        }
+    }
+}
+
+/// A running hash of either algorithm. SHA-1 runs with collision
+/// detection, so finishing it can fail.
+#[derive(Clone)]
+pub enum Hasher {
+    /// Boxed: collision detection keeps a large state.
+    Sha1(Box<Sha1>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            Hasher::Sha1(_) => HashAlgorithm::Sha1,
+            Hasher::Sha256(_) => HashAlgorithm::Sha256,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> Result<Vec<u8>> {
+        match self {
+            Hasher::Sha1(h) => match h.try_finalize() {
+                CollisionResult::Ok(digest) => Ok(digest.to_vec()),
+                collision => Err(Error::Sha1Collision(crate::object::hex(collision.hash()))),
+            },
+            Hasher::Sha256(h) => Ok(h.finalize().to_vec()),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! The "key-value list with message" format shared by commits and tags.
+//!
+//! Object ids hash the exact bytes, so anything parsed here must serialize
+//! back to what it was read from: headers keep their order, repeated keys
+//! stay interleaved as they were, and a header that would be written back
+//! differently keeps the bytes it was read from.
+
+use crate::error::Result;
+
+/// One header, continuation lines unfolded. A header without a value is
+/// written as its bare key, as git does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    /// The bytes the header was read from, written back in place of the
+    /// key and value when those would not give them again: a bare key
+    /// followed by continuation lines, a bare key with a trailing space,
+    /// a last header missing its newline. Once the key or value no longer
+    /// reads the same from them, they are ignored.
+    pub raw: Option<Vec<u8>>,
+}
+
+impl Header {
+    pub fn new(key: &[u8], value: impl Into<Vec<u8>>) -> Self {
+        Header {
+            key: key.to_vec(),
+            value: value.into(),
+            raw: None,
+        }
+    }
+}
+
+/// Ordered headers followed by a free-form message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Kvlm {
+    pub headers: Vec<Header>,
+    /// `None` when the object ends right after its headers, without the
+    /// blank line that starts a message.
+    pub message: Option<Vec<u8>>,
+}
+
+impl Kvlm {
+    /// Parses the headers and message of a commit or tag. Nothing is
+    /// refused: whatever the bytes, serializing gives them back.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let mut kvlm = Kvlm::default();
+        let mut rest = raw;
+
+        loop {
+            match rest.first() {
+                None => return Ok(kvlm),
+                Some(b'\n') => {
+                    kvlm.message = Some(rest[1..].to_vec());
+                    return Ok(kvlm);
+                }
+                Some(_) => {}
+            }
+            // The end of a line, or of the object if it is unterminated.
+            let line_end = |from: usize| {
+                rest[from..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(rest.len(), |n| from + n)
+            };
+            let mut end = line_end(0);
+            let first_line = &rest[..end];
+            // Continuation lines start with a space.
+            while end < rest.len() && rest.get(end + 1) == Some(&b' ') {
+                end = line_end(end + 1);
+            }
+
+            let (key, value) = match first_line.iter().position(|&b| b == b' ') {
+                Some(space) => (&rest[..space], unfold(&rest[space + 1..end])),
+                None => (first_line, unfold(&rest[first_line.len()..end])),
+            };
+            let source = &rest[..(end + 1).min(rest.len())];
+            let mut header = Header::new(key, value);
+            let mut written = Vec::with_capacity(source.len());
+            write_header(&mut written, &header);
+            if written != source {
+                header.raw = Some(source.to_vec());
+            }
+            kvlm.headers.push(header);
+            rest = &rest[source.len()..];
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for header in &self.headers {
+            write_header(&mut out, header);
+        }
+        if let Some(message) = &self.message {
+            out.push(b'\n');
+            out.extend_from_slice(message);
+        }
+        out
+    }
+
+    /// The first value stored under `key`.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.get_all(key).next()
+    }
+
+    /// Every value stored under `key`, in file order.
+    pub fn get_all<'a, 'k>(&'a self, key: &'k [u8]) -> impl Iterator<Item = &'a [u8]> + 'k
+    where
+        'a: 'k,
+    {
+        self.headers
+            .iter()
+            .filter(move |h| h.key == key)
+            .map(|h| h.value.as_slice())
+    }
+
+    pub fn push(&mut self, key: &[u8], value: impl Into<Vec<u8>>) {
+        self.headers.push(Header::new(key, value));
+    }
+}
+
+/// Writes `key value`, folding every further line of the value onto a
+/// continuation line, or the bytes the header was read from if it has not
+/// been changed since.
+fn write_header(out: &mut Vec<u8>, header: &Header) {
+    if let Some(raw) = &header.raw {
+        let unchanged = match Kvlm::parse(raw) {
+            Ok(Kvlm {
+                headers,
+                message: None,
+            }) => {
+                matches!(&headers[..], [read] if read.key == header.key && read.value == header.value)
+            }
+            _ => false,
+        };
+        if unchanged {
+            out.extend_from_slice(raw);
+            return;
+        }
+    }
+    let (key, value) = (&header.key, &header.value);
+    out.extend_from_slice(key);
+    if !value.is_empty() {
+        out.push(b' ');
+    }
+    for &b in value.iter() {
+        out.push(b);
+        if b == b'\n' {
+            out.push(b' ');
+        }
+    }
+    out.push(b'\n');
+}
+
+/// Drops the leading space of every continuation line.
+fn unfold(value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len());
+    let mut iter = value.iter().peekable();
+    while let Some(&b) = iter.next() {
+        out.push(b);
+        if b == b'\n' && iter.peek() == Some(&&b' ') {
+            iter.next();
+        }
+    }
+    out
+}
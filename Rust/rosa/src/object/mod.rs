@@ -0,0 +1,357 @@
+//! Typed git objects and the ids that name them.
+
+mod blob;
+mod commit;
+mod hash;
+pub(crate) mod kvlm;
+mod signature;
+mod tag;
+mod tree;
+
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+pub use blob::Blob;
+pub use commit::Commit;
+pub use hash::{HashAlgorithm, Hasher, MAX_HASH_LEN};
+pub use kvlm::{Header, Kvlm};
+pub use signature::{Offset, Signature};
+pub use tag::Tag;
+pub use tree::{
+    Tree, TreeEntry, MODE_BLOB, MODE_BLOB_EXECUTABLE, MODE_GITLINK, MODE_SYMLINK, MODE_TREE,
+};
+
+/// An object id, under either hash algorithm. SHA-1 ids only use the
+/// first twenty bytes; the rest stay zero so ordering is unaffected.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId {
+    raw: [u8; MAX_HASH_LEN],
+    hash: HashAlgorithm,
+}
+
+impl ObjectId {
+    /// Builds an id from its raw bytes, telling the algorithm by length.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let hash = HashAlgorithm::from_len(bytes.len())
+            .ok_or_else(|| Error::InvalidObjectId(hex(bytes)))?;
+        let mut raw = [0u8; MAX_HASH_LEN];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        Ok(ObjectId { raw, hash })
+    }
+
+    /// Parses a full-length hex id of either algorithm.
+    pub fn from_hex(s: &str) -> Result<Self> {
+        let bytes = s.as_bytes();
+        let invalid = || Error::InvalidObjectId(s.to_owned());
+        let hash = match bytes.len() % 2 {
+            0 => HashAlgorithm::from_len(bytes.len() / 2).ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+        let mut raw = [0u8; MAX_HASH_LEN];
+        for (i, pair) in bytes.chunks(2).enumerate() {
+            match (hex_val(pair[0]), hex_val(pair[1])) {
+                (Some(hi), Some(lo)) => raw[i] = hi << 4 | lo,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(ObjectId { raw, hash })
+    }
+
+    /// The all-zero id, which stands for "no object" in reflogs and the
+    /// like.
+    pub fn null(hash: HashAlgorithm) -> Self {
+        ObjectId {
+            raw: [0; MAX_HASH_LEN],
+            hash,
+        }
+    }
+
+    /// Hashes `data` as an object of the given kind, header included.
+    pub fn hash_object(hash: HashAlgorithm, kind: ObjectKind, data: &[u8]) -> Result<Self> {
+        let mut hasher = ObjectHasher::new(hash, kind, data.len() as u64);
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    /// Hashes the `size` bytes `reader` yields as an object of the given
+    /// kind, a chunk at a time.
+    pub fn hash_reader(
+        hash: HashAlgorithm,
+        kind: ObjectKind,
+        size: u64,
+        reader: impl Read,
+    ) -> Result<Self> {
+        let mut hasher = ObjectHasher::new(hash, kind, size);
+        copy_exact(reader, size, |chunk| {
+            hasher.update(chunk);
+            Ok(())
+        })?;
+        hasher.finish()
+    }
+
+    /// The algorithm that produced this id.
+    pub fn hash(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.raw == [0; MAX_HASH_LEN]
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.raw[..self.hash.raw_len()]
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex(self.as_bytes())
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({self})")
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ObjectId::from_hex(s)
+    }
+}
+
+fn hex_val(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Whether `raw` is hex digits in the lowercase form git writes ids in,
+/// the only form objects may name other objects by.
+pub(crate) fn is_lower_hex(raw: &[u8]) -> bool {
+    raw.iter().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push(DIGITS[(b >> 4) as usize] as char);
+        s.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+    s
+}
+
+/// Computes an object id from a payload fed in pieces, for objects too
+/// large to hold in memory.
+pub struct ObjectHasher(Hasher);
+
+impl ObjectHasher {
+    /// Starts hashing an object of `size` bytes; exactly that many must be
+    /// fed through [`update`](Self::update) for the id to be meaningful.
+    pub fn new(hash: HashAlgorithm, kind: ObjectKind, size: u64) -> Self {
+        let mut hasher = hash.hasher();
+        hasher.update(&header(kind, size));
+        ObjectHasher(hasher)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    /// The id, or [`Error::Sha1Collision`] if the payload looks like one
+    /// half of a SHA-1 collision.
+    pub fn finish(self) -> Result<ObjectId> {
+        ObjectId::from_bytes(&self.0.finalize()?)
+    }
+}
+
+/// Size of the chunks streamed objects are read in.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Feeds exactly `size` bytes of `reader` to `sink` in chunks, failing if
+/// the reader ends early or has more to give (a file that changed while
+/// it was being read).
+pub(crate) fn copy_exact(
+    mut reader: impl Read,
+    size: u64,
+    mut sink: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut remaining = size;
+    loop {
+        let want = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX).max(1));
+        let n = match reader.read(&mut buf[..want]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            break;
+        }
+        if n as u64 > remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected {size} bytes, got more"),
+            )
+            .into());
+        }
+        remaining -= n as u64;
+        sink(&buf[..n])?;
+    }
+    if remaining != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("expected {size} bytes, got {}", size - remaining),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// The four kinds of object git stores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+impl ObjectKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ObjectKind::Blob => "blob",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tag => "tag",
+        }
+    }
+
+    pub fn from_bytes(name: &[u8]) -> Result<Self> {
+        match name {
+            b"blob" => Ok(ObjectKind::Blob),
+            b"tree" => Ok(ObjectKind::Tree),
+            b"commit" => Ok(ObjectKind::Commit),
+            b"tag" => Ok(ObjectKind::Tag),
+            _ => Err(Error::UnknownObjectType(
+                String::from_utf8_lossy(name).into_owned(),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ObjectKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ObjectKind::from_bytes(s.as_bytes())
+    }
+}
+
+/// The `<type> <size>\0` header that prefixes every object before hashing.
+pub(crate) fn header(kind: ObjectKind, size: u64) -> Vec<u8> {
+    format!("{kind} {size}\0").into_bytes()
+}
+
+/// A parsed object of any kind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Object {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl Object {
+    /// Parses the payload of an object (header already stripped). Trees
+    /// hold raw ids, so their length depends on the repository's hash.
+    pub fn parse(kind: ObjectKind, data: &[u8], hash: HashAlgorithm) -> Result<Self> {
+        Ok(match kind {
+            ObjectKind::Blob => Object::Blob(Blob::new(data.to_vec())),
+            ObjectKind::Tree => Object::Tree(Tree::parse(data, hash)?),
+            ObjectKind::Commit => Object::Commit(Commit::parse(data)?),
+            ObjectKind::Tag => Object::Tag(Tag::parse(data)?),
+        })
+    }
+
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            Object::Blob(_) => ObjectKind::Blob,
+            Object::Tree(_) => ObjectKind::Tree,
+            Object::Commit(_) => ObjectKind::Commit,
+            Object::Tag(_) => ObjectKind::Tag,
+        }
+    }
+
+    /// Serializes the payload, without the header.
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            Object::Blob(b) => b.data.clone(),
+            Object::Tree(t) => t.serialize(),
+            Object::Commit(c) => c.serialize(),
+            Object::Tag(t) => t.serialize(),
+        }
+    }
+
+    pub fn id(&self, hash: HashAlgorithm) -> Result<ObjectId> {
+        ObjectId::hash_object(hash, self.kind(), &self.serialize())
+    }
+
+    pub fn into_blob(self, id: ObjectId) -> Result<Blob> {
+        match self {
+            Object::Blob(b) => Ok(b),
+            other => Err(other.type_mismatch(id, ObjectKind::Blob)),
+        }
+    }
+
+    pub fn into_tree(self, id: ObjectId) -> Result<Tree> {
+        match self {
+            Object::Tree(t) => Ok(t),
+            other => Err(other.type_mismatch(id, ObjectKind::Tree)),
+        }
+    }
+
+    pub fn into_commit(self, id: ObjectId) -> Result<Commit> {
+        match self {
+            Object::Commit(c) => Ok(c),
+            other => Err(other.type_mismatch(id, ObjectKind::Commit)),
+        }
+    }
+
+    pub fn into_tag(self, id: ObjectId) -> Result<Tag> {
+        match self {
+            Object::Tag(t) => Ok(t),
+            other => Err(other.type_mismatch(id, ObjectKind::Tag)),
+        }
+    }
+
+    fn type_mismatch(&self, id: ObjectId, expected: ObjectKind) -> Error {
+        Error::UnexpectedObjectType {
+            id,
+            expected: expected.as_str(),
+            actual: self.kind().as_str(),
+        }
+    }
+}
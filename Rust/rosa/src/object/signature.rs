@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+/// Who did something and when: `Name <email> 1234567890 +0100`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Signature {
+    pub name: Vec<u8>,
+    pub email: Vec<u8>,
+    /// Seconds since the epoch.
+    pub time: i64,
+    pub offset: Offset,
+    /// The bytes the signature was parsed from, when they differ from
+    /// what the fields would be written as (a zero-padded time, a zone
+    /// like `+05030`, no space before the email). They are written back
+    /// as they were, so that the object keeps its id, for as long as the
+    /// fields still read the same from them; editing a field drops them.
+    pub raw: Option<Vec<u8>>,
+}
+
+/// The UTC offset written after a signature's time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Offset {
+    /// Minutes east of UTC.
+    pub minutes: i32,
+    /// Written `-0000`, as some tools do for an unknown zone.
+    pub unknown: bool,
+}
+
+impl Offset {
+    pub fn from_minutes(minutes: i32) -> Self {
+        Offset {
+            minutes,
+            unknown: false,
+        }
+    }
+
+    /// Parses `+hhmm` or `-hhmm`.
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let [sign @ (b'+' | b'-'), digits @ ..] = raw else {
+            return None;
+        };
+        if digits.len() != 4 || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        let value = |d: &[u8]| i32::from((d[0] - b'0') * 10 + (d[1] - b'0'));
+        let minutes = value(&digits[..2]) * 60 + value(&digits[2..]);
+        Some(match sign {
+            b'-' if minutes == 0 => Offset {
+                minutes: 0,
+                unknown: true,
+            },
+            b'-' => Offset::from_minutes(-minutes),
+            _ => Offset::from_minutes(minutes),
+        })
+    }
+}
+
+impl Offset {
+    /// Reads a zone the way git does when it is not four digits: the
+    /// digits as a number `hhmm`, however many there are; anything else
+    /// is UTC.
+    fn parse_lenient(raw: &[u8]) -> Self {
+        let (sign, digits) = match raw {
+            [b'-', digits @ ..] => (-1, digits),
+            [b'+', digits @ ..] => (1, digits),
+            _ => return Offset::default(),
+        };
+        let len = digits.iter().take_while(|b| b.is_ascii_digit()).count();
+        let value: i32 = std::str::from_utf8(&digits[..len])
+            .ok()
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(0);
+        Offset::from_minutes(sign * (value / 100 * 60 + value % 100))
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.minutes < 0 || self.unknown {
+            '-'
+        } else {
+            '+'
+        };
+        let minutes = self.minutes.unsigned_abs();
+        write!(f, "{sign}{:02}{:02}", minutes / 60, minutes % 60)
+    }
+}
+
+impl Signature {
+    /// Parses a signature as leniently as git reads one: all it needs is
+    /// an email in angle brackets. Whatever follows the last `>` is read as
+    /// a time and a zone if it can be, and as the epoch in UTC otherwise;
+    /// fsck is the place to complain about such signatures.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let mut signature = Signature::read(raw)?;
+        if signature.to_bytes() != raw {
+            signature.raw = Some(raw.to_vec());
+        }
+        Ok(signature)
+    }
+
+    /// The fields of the signature in `raw`, without keeping the bytes.
+    fn read(raw: &[u8]) -> Result<Self> {
+        let bad = || {
+            Error::parse(
+                "signature",
+                format!("malformed signature {}", String::from_utf8_lossy(raw)),
+            )
+        };
+        let open = raw.iter().position(|&b| b == b'<').ok_or_else(bad)?;
+        let close = open
+            + raw[open..]
+                .iter()
+                .position(|&b| b == b'>')
+                .ok_or_else(bad)?;
+        let last = raw.iter().rposition(|&b| b == b'>').expect("found one");
+        let name = &raw[..open];
+        let name_len = name.len() - name.iter().rev().take_while(|&&b| b == b' ').count();
+
+        let rest = raw[last + 1..].trim_ascii_start();
+        let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+        let time = std::str::from_utf8(&rest[..digits])
+            .ok()
+            .and_then(|time| time.parse().ok())
+            .unwrap_or(0);
+        let zone = rest[digits..].trim_ascii_start();
+        let offset = Offset::parse(zone).unwrap_or_else(|| Offset::parse_lenient(zone));
+
+        Ok(Signature {
+            name: name[..name_len].to_vec(),
+            email: raw[open + 1..close].to_vec(),
+            time,
+            offset,
+            raw: None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if let Some(raw) = &self.raw {
+            let unchanged = Signature::read(raw).is_ok_and(|read| {
+                (&read.name, &read.email, read.time, read.offset)
+                    == (&self.name, &self.email, self.time, self.offset)
+            });
+            if unchanged {
+                return raw.clone();
+            }
+        }
+        let mut out = self.name.clone();
+        out.extend_from_slice(b" <");
+        out.extend_from_slice(&self.email);
+        out.extend_from_slice(format!("> {} {}", self.time, self.offset).as_bytes());
+        out
+    }
+}
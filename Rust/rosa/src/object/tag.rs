@@ -0,0 +1,55 @@
+use super::commit::{parse_id, Headers};
+use super::{Header, Kvlm, ObjectId, ObjectKind, Signature};
+use crate::error::Result;
+
+/// An annotated tag pointing at another object.
+///
+/// As with commits, headers after the tagger are kept in order so that a
+/// parsed tag serializes back to the same bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub object: ObjectId,
+    pub target_kind: ObjectKind,
+    pub name: Vec<u8>,
+    /// Missing from tags made by very old versions of git.
+    pub tagger: Option<Signature>,
+    /// The remaining headers, continuation lines unfolded.
+    pub extra_headers: Vec<Header>,
+    /// `None` when the tag ends with its headers.
+    pub message: Option<Vec<u8>>,
+}
+
+impl Tag {
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let kvlm = Kvlm::parse(raw)?;
+        let mut headers = Headers::new("tag", kvlm.headers);
+        let object = parse_id("tag", &headers.take(b"object")?)?;
+        let target_kind = ObjectKind::from_bytes(&headers.take(b"type")?)?;
+        let name = headers.take(b"tag")?;
+        let tagger = headers
+            .take_optional(b"tagger")?
+            .map(|tagger| Signature::parse(&tagger))
+            .transpose()?;
+        Ok(Tag {
+            object,
+            target_kind,
+            name,
+            tagger,
+            extra_headers: headers.rest(),
+            message: kvlm.message,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut kvlm = Kvlm::default();
+        kvlm.push(b"object", self.object.to_hex());
+        kvlm.push(b"type", self.target_kind.as_str());
+        kvlm.push(b"tag", self.name.as_slice());
+        if let Some(tagger) = &self.tagger {
+            kvlm.push(b"tagger", tagger.to_bytes());
+        }
+        kvlm.headers.extend(self.extra_headers.iter().cloned());
+        kvlm.message = self.message.clone();
+        kvlm.serialize()
+    }
+}
@@ -0,0 +1,121 @@
+use std::cmp::Ordering;
+
+use super::{HashAlgorithm, ObjectId, ObjectKind};
+use crate::error::{Error, Result};
+
+/// Mode of a directory entry.
+pub const MODE_TREE: u32 = 0o040000;
+/// Mode of a regular, non-executable file.
+pub const MODE_BLOB: u32 = 0o100644;
+/// Mode of an executable file.
+pub const MODE_BLOB_EXECUTABLE: u32 = 0o100755;
+/// Mode of a symbolic link.
+pub const MODE_SYMLINK: u32 = 0o120000;
+/// Mode of a submodule commit.
+pub const MODE_GITLINK: u32 = 0o160000;
+
+/// One line of a tree: a mode, a name and the id it points at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: u32,
+    /// Any bytes but `/` and NUL, in no particular encoding.
+    pub name: Vec<u8>,
+    pub id: ObjectId,
+}
+
+impl TreeEntry {
+    pub fn new(mode: u32, name: impl Into<Vec<u8>>, id: ObjectId) -> Self {
+        TreeEntry {
+            mode,
+            name: name.into(),
+            id,
+        }
+    }
+
+    /// The kind of object this entry points to, derived from its mode.
+    pub fn kind(&self) -> Option<ObjectKind> {
+        match self.mode >> 12 {
+            0o04 => Some(ObjectKind::Tree),
+            0o10 | 0o12 => Some(ObjectKind::Blob),
+            0o16 => Some(ObjectKind::Commit),
+            _ => None,
+        }
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.mode == MODE_TREE
+    }
+
+    /// Git orders entries by name, comparing directories as if their name
+    /// ended with a slash.
+    pub fn cmp_git(&self, other: &TreeEntry) -> Ordering {
+        let a = self.name.iter().chain(self.is_tree().then_some(&b'/'));
+        let b = other.name.iter().chain(other.is_tree().then_some(&b'/'));
+        a.cmp(b)
+    }
+}
+
+/// A directory listing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    pub fn parse(raw: &[u8], hash: HashAlgorithm) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut pos = 0;
+
+        while pos < raw.len() {
+            let rest = &raw[pos..];
+            let space = rest
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| Error::parse("tree", "missing space after mode"))?;
+            let mode = parse_mode(&rest[..space])?;
+
+            let nul = rest[space..]
+                .iter()
+                .position(|&b| b == 0)
+                .map(|n| space + n)
+                .ok_or_else(|| Error::parse("tree", "missing NUL after name"))?;
+            let name = &rest[space + 1..nul];
+
+            let id_end = nul + 1 + hash.raw_len();
+            if id_end > rest.len() {
+                return Err(Error::parse("tree", "truncated object id"));
+            }
+            let id = ObjectId::from_bytes(&rest[nul + 1..id_end])?;
+
+            entries.push(TreeEntry::new(mode, name, id));
+            pos += id_end;
+        }
+
+        Ok(Tree { entries })
+    }
+
+    /// Serializes the tree, emitting entries in git's canonical order.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut entries: Vec<&TreeEntry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.cmp_git(b));
+
+        let mut out = Vec::new();
+        for entry in entries {
+            out.extend_from_slice(format!("{:o} ", entry.mode).as_bytes());
+            out.extend_from_slice(&entry.name);
+            out.push(0);
+            out.extend_from_slice(entry.id.as_bytes());
+        }
+        out
+    }
+}
+
+fn parse_mode(raw: &[u8]) -> Result<u32> {
+    if raw.is_empty() || raw.len() > 6 {
+        return Err(Error::parse("tree", "bad mode length"));
+    }
+    raw.iter().try_fold(0u32, |mode, &c| match c {
+        b'0'..=b'7' => Ok(mode << 3 | u32::from(c - b'0')),
+        _ => Err(Error::parse("tree", "mode is not octal")),
+    })
+}
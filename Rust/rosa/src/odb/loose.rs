@@ -0,0 +1,255 @@
+//! Loose objects: one zlib-compressed file per object under `objects/xx/`.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use super::RawStream;
+use crate::error::{Error, Result};
+use crate::object::{copy_exact, header, HashAlgorithm, ObjectHasher, ObjectId, ObjectKind};
+
+#[derive(Debug)]
+pub struct LooseStore {
+    dir: PathBuf,
+    hash: HashAlgorithm,
+}
+
+impl LooseStore {
+    pub fn new(objects_dir: impl Into<PathBuf>, hash: HashAlgorithm) -> Self {
+        LooseStore {
+            dir: objects_dir.into(),
+            hash,
+        }
+    }
+
+    pub fn path(&self, id: &ObjectId) -> PathBuf {
+        let hex = id.to_hex();
+        self.dir.join(&hex[..2]).join(&hex[2..])
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.path(id).is_file()
+    }
+
+    /// Reads and inflates a loose object, returning `None` if it is absent.
+    pub fn read(&self, id: &ObjectId) -> Result<Option<(ObjectKind, Vec<u8>)>> {
+        let compressed = match fs::read(self.path(id)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut raw)
+            .map_err(|e| malformed(id, format!("corrupt zlib stream: {e}")))?;
+
+        let (kind, size, body_start) =
+            parse_header(&raw).map_err(|reason| malformed(id, reason))?;
+        if size != (raw.len() - body_start) as u64 {
+            return Err(malformed(id, "bad length".into()));
+        }
+        raw.drain(..body_start);
+        Ok(Some((kind, raw)))
+    }
+
+    /// Reads only the kind and size of a loose object.
+    pub fn read_header(&self, id: &ObjectId) -> Result<Option<(ObjectKind, u64)>> {
+        let file = match fs::File::open(self.path(id)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // "commit 18446744073709551615\0" is the longest possible header.
+        let mut raw = Vec::with_capacity(32);
+        ZlibDecoder::new(file)
+            .take(32)
+            .read_to_end(&mut raw)
+            .map_err(|e| malformed(id, format!("corrupt zlib stream: {e}")))?;
+        let (kind, size, _) = parse_header(&raw).map_err(|reason| malformed(id, reason))?;
+        Ok(Some((kind, size)))
+    }
+
+    /// Writes an object unless it already exists.
+    pub fn write(&self, id: &ObjectId, kind: ObjectKind, data: &[u8]) -> Result<()> {
+        let path = self.path(id);
+        if path.exists() {
+            return Ok(());
+        }
+        let dir = path
+            .parent()
+            .expect("loose object path has a fan-out directory");
+        fs::create_dir_all(dir)?;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&header(kind, data.len() as u64))?;
+        encoder.write_all(data)?;
+        write_atomically(&path, &encoder.finish()?)
+    }
+
+    /// Hashes and deflates the `size` bytes `reader` yields a chunk at a
+    /// time into a temporary file, then moves it into place, so objects of
+    /// any size can be stored without holding them in memory. The id is
+    /// only known at the end: if `stored` says the object is already kept
+    /// elsewhere (in a pack, say), the temporary file is dropped instead.
+    pub fn write_stream(
+        &self,
+        kind: ObjectKind,
+        size: u64,
+        reader: impl Read,
+        stored: impl Fn(&ObjectId) -> bool,
+    ) -> Result<ObjectId> {
+        fs::create_dir_all(&self.dir)?;
+        let tmp = self
+            .dir
+            .join(format!("tmp_obj_{}_stream", std::process::id()));
+        let written = (|| {
+            let mut encoder = ZlibEncoder::new(
+                io::BufWriter::new(fs::File::create(&tmp)?),
+                Compression::default(),
+            );
+            encoder.write_all(&header(kind, size))?;
+            let mut hasher = ObjectHasher::new(self.hash, kind, size);
+            copy_exact(reader, size, |chunk| {
+                hasher.update(chunk);
+                Ok(encoder.write_all(chunk)?)
+            })?;
+            let file = encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+
+            let id = hasher.finish()?;
+            let path = self.path(&id);
+            if path.exists() || stored(&id) {
+                fs::remove_file(&tmp)?;
+            } else {
+                fs::create_dir_all(path.parent().expect("fan-out directory"))?;
+                fs::rename(&tmp, &path)?;
+            }
+            Ok(id)
+        })();
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        written
+    }
+
+    /// Opens a loose object for reading without inflating it all, returning
+    /// its kind, size and a reader positioned at the start of its payload.
+    pub fn open(&self, id: &ObjectId) -> Result<Option<RawStream>> {
+        let file = match fs::File::open(self.path(id)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut decoder = ZlibDecoder::new(io::BufReader::new(file));
+
+        // Read byte by byte so the payload is left unconsumed.
+        let mut raw = Vec::with_capacity(32);
+        let mut byte = [0u8; 1];
+        while raw.last() != Some(&0) && raw.len() < 32 {
+            match decoder.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) => raw.push(byte[0]),
+                Err(e) => return Err(malformed(id, format!("corrupt zlib stream: {e}"))),
+            }
+        }
+        let (kind, size, _) = parse_header(&raw).map_err(|reason| malformed(id, reason))?;
+        Ok(Some((kind, size, Box::new(decoder))))
+    }
+
+    /// Every loose object in the store.
+    pub fn list(&self) -> Result<Vec<ObjectId>> {
+        let mut found = Vec::new();
+        for fanout in 0..=255u8 {
+            found.extend(self.find_prefix(&format!("{fanout:02x}"))?);
+        }
+        Ok(found)
+    }
+
+    /// Deletes a loose object, and its fan-out directory once empty.
+    pub fn remove(&self, id: &ObjectId) -> Result<()> {
+        let path = self.path(id);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    /// Every loose object whose hex id starts with `prefix`.
+    pub fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>> {
+        let mut found = Vec::new();
+        if prefix.len() < 2 {
+            return Ok(found);
+        }
+        let (fanout, rest) = prefix.split_at(2);
+        let entries = match fs::read_dir(self.dir.join(fanout)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(found),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let name = entry?.file_name();
+            let Some(name) = name.to_str() else { continue };
+            if name.starts_with(rest) {
+                if let Ok(id) = ObjectId::from_hex(&format!("{fanout}{name}")) {
+                    found.push(id);
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+fn parse_header(raw: &[u8]) -> std::result::Result<(ObjectKind, u64, usize), String> {
+    let space = raw
+        .iter()
+        .position(|&b| b == b' ')
+        .ok_or("missing object type")?;
+    let nul = raw
+        .iter()
+        .position(|&b| b == 0)
+        .filter(|&nul| nul > space)
+        .ok_or("missing header terminator")?;
+    let kind = ObjectKind::from_bytes(&raw[..space]).map_err(|e| e.to_string())?;
+    let size = std::str::from_utf8(&raw[space + 1..nul])
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or("bad object size")?;
+    Ok((kind, size, nul + 1))
+}
+
+fn malformed(id: &ObjectId, reason: String) -> Error {
+    Error::MalformedObject { id: *id, reason }
+}
+
+/// Writes `data` to a temporary file next to `path` and renames it into
+/// place, so readers never observe a half-written object.
+pub fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let tmp = dir.join(format!(
+        "tmp_obj_{}_{}",
+        std::process::id(),
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("object")
+    ));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    Ok(result?)
+}
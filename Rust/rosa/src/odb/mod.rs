@@ -0,0 +1,508 @@
+//! The object database: where object ids are turned back into objects.
+
+mod loose;
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use crate::error::{Error, Result};
+use crate::fsck;
+use crate::object::{copy_exact, HashAlgorithm, Object, ObjectId, ObjectKind};
+use crate::pack::{MultiPackIndex, Pack, DEFAULT_CACHE_LIMIT, MIDX_FILE_NAME};
+
+pub use loose::{write_atomically, LooseStore};
+
+#[derive(Debug)]
+pub struct ObjectDatabase {
+    dir: PathBuf,
+    hash: HashAlgorithm,
+    loose: LooseStore,
+    packs: RefCell<PackSet>,
+    delta_base_cache_limit: Cell<usize>,
+    /// Other stores objects are borrowed from, in lookup order. Their own
+    /// alternates are flattened into this list.
+    alternates: Vec<ObjectDatabase>,
+}
+
+impl ObjectDatabase {
+    /// Opens the object store at `objects_dir`, whose objects are named by
+    /// `hash`, along with the stores listed in `$GIT_ALTERNATE_OBJECT_DIRECTORIES`
+    /// and, recursively, in `info/alternates`.
+    pub fn open(objects_dir: impl Into<PathBuf>, hash: HashAlgorithm) -> Result<Self> {
+        let mut odb = ObjectDatabase::open_local(objects_dir.into(), hash)?;
+
+        let mut seen = HashSet::new();
+        seen.insert(fs::canonicalize(&odb.dir).unwrap_or_else(|_| odb.dir.clone()));
+        let mut alternates = Vec::new();
+        let from_env: Vec<PathBuf> = std::env::var_os(ALTERNATES_ENV)
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        link_alternates(from_env, 0, hash, &mut seen, &mut alternates)?;
+        let from_file = read_alternates(&odb.dir)?;
+        link_alternates(from_file, 0, hash, &mut seen, &mut alternates)?;
+
+        odb.alternates = alternates;
+        Ok(odb)
+    }
+
+    /// Opens just the store at `dir`, ignoring its alternates.
+    fn open_local(dir: PathBuf, hash: HashAlgorithm) -> Result<Self> {
+        let packs = PackSet::load(&dir.join("pack"), hash, &[])?;
+        Ok(ObjectDatabase {
+            hash,
+            loose: LooseStore::new(&dir, hash),
+            packs: RefCell::new(packs),
+            delta_base_cache_limit: Cell::new(DEFAULT_CACHE_LIMIT),
+            alternates: Vec::new(),
+            dir,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The algorithm object ids are computed with.
+    pub fn hash(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    pub fn loose(&self) -> &LooseStore {
+        &self.loose
+    }
+
+    /// The stores objects are borrowed from, in lookup order.
+    pub fn alternates(&self) -> &[ObjectDatabase] {
+        &self.alternates
+    }
+
+    /// The packs of this store (not its alternates) currently known, most
+    /// recently modified first.
+    pub fn packs(&self) -> Vec<Rc<Pack>> {
+        self.packs.borrow().all.clone()
+    }
+
+    /// The multi-pack index of this store, if it has a usable one.
+    pub fn multi_pack_index(&self) -> Option<Rc<MultiPackIndex>> {
+        self.packs
+            .borrow()
+            .midx
+            .as_ref()
+            .map(|(midx, _)| midx.clone())
+    }
+
+    /// Rescans `objects/pack`, picking up packs written since we opened.
+    /// Packs already open are kept rather than having their index read
+    /// again.
+    pub fn refresh_packs(&self) -> Result<()> {
+        let known = self.packs();
+        let packs = PackSet::load(&self.dir.join("pack"), self.hash, &known)?;
+        for pack in &packs.all {
+            pack.set_cache_limit(self.delta_base_cache_limit.get());
+        }
+        *self.packs.borrow_mut() = packs;
+        Ok(())
+    }
+
+    /// Rescans `objects/pack` if it changed since it was last scanned, as
+    /// git's `reprepare_packed_git` does, returning whether it did.
+    fn reprepare(&self) -> Result<bool> {
+        if dir_mtime(&self.dir.join("pack")) == self.packs.borrow().scanned {
+            return Ok(false);
+        }
+        self.refresh_packs()?;
+        Ok(true)
+    }
+
+    /// Bounds the memory each pack spends caching delta bases
+    /// (`core.deltaBaseCacheLimit`).
+    pub fn set_delta_base_cache_limit(&self, limit: usize) {
+        self.delta_base_cache_limit.set(limit);
+        for pack in &self.packs.borrow().all {
+            pack.set_cache_limit(limit);
+        }
+        for alternate in &self.alternates {
+            alternate.set_delta_base_cache_limit(limit);
+        }
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.contains_local(id) || self.alternates.iter().any(|a| a.contains_local(id))
+    }
+
+    /// Whether this store itself, rather than one of its alternates, has
+    /// the object.
+    pub fn contains_local(&self, id: &ObjectId) -> bool {
+        self.loose.contains(id)
+            || self
+                .packs
+                .borrow()
+                .find(id)
+                .is_ok_and(|found| found.is_some())
+    }
+
+    /// Reads the kind and payload of an object without parsing it.
+    pub fn read_raw(&self, id: &ObjectId) -> Result<(ObjectKind, Vec<u8>)> {
+        self.lookup(id, |pack, offset| pack.read_at(offset), LooseStore::read)
+    }
+
+    /// The kind and size of an object, reading as little of it as possible.
+    pub fn read_header(&self, id: &ObjectId) -> Result<(ObjectKind, u64)> {
+        self.lookup(
+            id,
+            |pack, offset| pack.read_header_at(offset),
+            LooseStore::read_header,
+        )
+    }
+
+    /// Opens an object for reading a chunk at a time, so that large blobs
+    /// can be copied somewhere without being held in memory.
+    pub fn open_stream(&self, id: &ObjectId) -> Result<ObjectStream> {
+        let (kind, size, inner) =
+            self.lookup(id, |pack, offset| pack.open_at(offset), LooseStore::open)?;
+        Ok(ObjectStream {
+            kind,
+            size,
+            remaining: size,
+            inner,
+        })
+    }
+
+    pub fn read(&self, id: &ObjectId) -> Result<Object> {
+        let (kind, data) = self.read_raw(id)?;
+        Object::parse(kind, &data, self.hash)
+    }
+
+    pub fn write(&self, object: &Object) -> Result<ObjectId> {
+        self.write_raw(object.kind(), &object.serialize())
+    }
+
+    /// Stores an object, refusing a tree, commit or tag that `fsck
+    /// --strict` would complain about so that nothing malformed gets in.
+    pub fn write_raw(&self, kind: ObjectKind, data: &[u8]) -> Result<ObjectId> {
+        if kind != ObjectKind::Blob {
+            fsck::check_before_writing(kind, data, self.hash)?;
+        }
+        self.write_literally(kind, data)
+    }
+
+    /// Stores an object as it is, unchecked: for `hash-object --literally`
+    /// and for making broken objects on purpose.
+    pub fn write_literally(&self, kind: ObjectKind, data: &[u8]) -> Result<ObjectId> {
+        let id = ObjectId::hash_object(self.hash, kind, data)?;
+        if !self.contains(&id) {
+            self.loose.write(&id, kind, data)?;
+        }
+        Ok(id)
+    }
+
+    /// Stores the `size` bytes `reader` yields as an object. Blobs are
+    /// hashed and compressed as they arrive; anything else is read whole
+    /// first, to be checked as [`write_raw`](Self::write_raw) does.
+    pub fn write_stream(&self, kind: ObjectKind, size: u64, reader: impl Read) -> Result<ObjectId> {
+        if kind != ObjectKind::Blob {
+            let mut data = Vec::new();
+            copy_exact(reader, size, |chunk| {
+                data.extend_from_slice(chunk);
+                Ok(())
+            })?;
+            return self.write_raw(kind, &data);
+        }
+        self.loose
+            .write_stream(kind, size, reader, |id| self.contains(id))
+    }
+
+    /// Every object whose hex id starts with `prefix` (lowercase).
+    pub fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>> {
+        let mut found = Vec::new();
+        for odb in std::iter::once(self).chain(&self.alternates) {
+            found.extend(odb.loose.find_prefix(prefix)?);
+            for pack in &odb.packs.borrow().all {
+                found.extend(pack.index().find_prefix(prefix));
+            }
+        }
+        found.sort();
+        found.dedup();
+        Ok(found)
+    }
+
+    /// How many hex digits, `min_len` or more, tell `id` apart from every
+    /// other object here.
+    pub fn unique_abbrev_len(&self, id: &ObjectId, min_len: usize) -> Result<usize> {
+        let hex = id.to_hex();
+        let min_len = min_len.min(hex.len());
+        let longest_shared = self
+            .find_prefix(&hex[..min_len])?
+            .iter()
+            .filter(|other| *other != id)
+            .map(|other| {
+                let other = other.to_hex();
+                hex.bytes()
+                    .zip(other.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            })
+            .max();
+        Ok(longest_shared.map_or(min_len, |shared| (shared + 1).clamp(min_len, hex.len())))
+    }
+
+    /// The number of packed objects, which is all git goes by when sizing
+    /// abbreviations: counting loose objects would mean listing them.
+    pub fn approximate_count(&self) -> usize {
+        std::iter::once(self)
+            .chain(&self.alternates)
+            .flat_map(|odb| odb.packs.borrow().all.clone())
+            .map(|pack| pack.index().len())
+            .sum()
+    }
+
+    /// Finds an object here or in an alternate, getting at it with `packed`
+    /// when it is in a pack and `loose` otherwise.
+    fn lookup<T>(
+        &self,
+        id: &ObjectId,
+        packed: impl Fn(&Pack, u64) -> Result<T>,
+        loose: impl Fn(&LooseStore, &ObjectId) -> Result<Option<T>>,
+    ) -> Result<T> {
+        let stores = || std::iter::once(self).chain(&self.alternates);
+        for odb in stores() {
+            if let Some(found) = odb.lookup_local(id, &packed, &loose)? {
+                return Ok(found);
+            }
+        }
+        // Someone may have repacked the object away since we looked: try
+        // again if any pack directory changed.
+        let mut changed = false;
+        for odb in stores() {
+            changed |= odb.reprepare()?;
+        }
+        if changed {
+            for odb in stores() {
+                if let Some(found) = odb.lookup_local(id, &packed, &loose)? {
+                    return Ok(found);
+                }
+            }
+        }
+        Err(Error::ObjectNotFound(*id))
+    }
+
+    fn lookup_local<T>(
+        &self,
+        id: &ObjectId,
+        packed: impl Fn(&Pack, u64) -> Result<T>,
+        loose: impl Fn(&LooseStore, &ObjectId) -> Result<Option<T>>,
+    ) -> Result<Option<T>> {
+        let found = self.packs.borrow().find(id)?;
+        match found {
+            Some((pack, offset)) => packed(&pack, offset).map(Some),
+            None => loose(&self.loose, id),
+        }
+    }
+}
+
+/// Colon-separated object stores to borrow from, on top of those listed in
+/// `info/alternates`.
+pub const ALTERNATES_ENV: &str = "GIT_ALTERNATE_OBJECT_DIRECTORIES";
+
+/// How deep alternates of alternates are followed, as in git.
+const MAX_ALTERNATE_DEPTH: usize = 5;
+
+/// The stores listed in `objects_dir/info/alternates`: one path per line,
+/// relative ones being relative to `objects_dir`.
+fn read_alternates(objects_dir: &Path) -> Result<Vec<PathBuf>> {
+    let text = match fs::read_to_string(objects_dir.join("info").join("alternates")) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| objects_dir.join(line))
+        .collect())
+}
+
+/// Opens each store in `paths` and, depth first, the stores it borrows
+/// from. Stores already in `seen` are skipped, which also breaks cycles;
+/// missing ones are skipped too, as git only warns about them.
+fn link_alternates(
+    paths: Vec<PathBuf>,
+    depth: usize,
+    hash: HashAlgorithm,
+    seen: &mut HashSet<PathBuf>,
+    out: &mut Vec<ObjectDatabase>,
+) -> Result<()> {
+    for path in paths {
+        let Ok(dir) = fs::canonicalize(&path) else {
+            continue;
+        };
+        if !dir.is_dir() || !seen.insert(dir.clone()) {
+            continue;
+        }
+        out.push(ObjectDatabase::open_local(dir.clone(), hash)?);
+        if depth < MAX_ALTERNATE_DEPTH {
+            link_alternates(read_alternates(&dir)?, depth + 1, hash, seen, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// The kind, size and payload reader of an object opened for streaming.
+pub type RawStream = (ObjectKind, u64, Box<dyn Read>);
+
+/// An object opened by [`ObjectDatabase::open_stream`]. Reading yields its
+/// payload, and fails rather than stopping short if the stored data ends
+/// before the size its header announced.
+pub struct ObjectStream {
+    kind: ObjectKind,
+    size: u64,
+    remaining: u64,
+    inner: Box<dyn Read>,
+}
+
+impl ObjectStream {
+    pub fn kind(&self) -> ObjectKind {
+        self.kind
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl Read for ObjectStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let want = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..want])?;
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "object data ends before its announced size",
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// The packs of one store and how to search them.
+#[derive(Debug, Default)]
+struct PackSet {
+    /// Every pack, most recently modified first.
+    all: Vec<Rc<Pack>>,
+    /// The multi-pack index and the packs it covers, by pack id.
+    midx: Option<(Rc<MultiPackIndex>, Vec<Rc<Pack>>)>,
+    /// Packs written since the multi-pack index, searched one by one.
+    uncovered: Vec<Rc<Pack>>,
+    /// When the directory was last modified as of the scan (`None` if it
+    /// did not exist), to tell whether a rescan could find anything new.
+    scanned: Option<SystemTime>,
+}
+
+impl PackSet {
+    /// Scans `dir`, reusing the packs in `known` that are still there.
+    fn load(dir: &Path, hash: HashAlgorithm, known: &[Rc<Pack>]) -> Result<Self> {
+        // Taken first, so that changes made during the scan show later.
+        let scanned = dir_mtime(dir);
+        let all = load_packs(dir, hash, known)?;
+        let midx = load_midx(dir, hash, &all);
+        let uncovered = match &midx {
+            Some((_, covered)) => all
+                .iter()
+                .filter(|pack| !covered.iter().any(|c| Rc::ptr_eq(c, pack)))
+                .cloned()
+                .collect(),
+            None => all.clone(),
+        };
+        Ok(PackSet {
+            all,
+            midx,
+            uncovered,
+            scanned,
+        })
+    }
+
+    /// The pack holding `id` and its offset there.
+    fn find(&self, id: &ObjectId) -> Result<Option<(Rc<Pack>, u64)>> {
+        if let Some((midx, packs)) = &self.midx {
+            if let Some((pack, offset)) = midx.lookup(id)? {
+                return Ok(Some((packs[pack].clone(), offset)));
+            }
+        }
+        Ok(self
+            .uncovered
+            .iter()
+            .find_map(|pack| Some((pack.clone(), pack.index().lookup(id)?))))
+    }
+}
+
+/// Opens `dir/multi-pack-index` and matches its pack names up with `packs`.
+/// Like git, we carry on without an index that is unreadable, or stale
+/// because a pack it names has gone.
+fn load_midx(
+    dir: &Path,
+    hash: HashAlgorithm,
+    packs: &[Rc<Pack>],
+) -> Option<(Rc<MultiPackIndex>, Vec<Rc<Pack>>)> {
+    let path = dir.join(MIDX_FILE_NAME);
+    if !path.is_file() {
+        return None;
+    }
+    let midx = MultiPackIndex::open(&path, hash).ok()?;
+    let covered = midx
+        .pack_names()
+        .iter()
+        .map(|name| {
+            packs
+                .iter()
+                .find(|pack| pack.path().with_extension("idx") == dir.join(name))
+                .cloned()
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((Rc::new(midx), covered))
+}
+
+fn dir_mtime(dir: &Path) -> Option<SystemTime> {
+    fs::metadata(dir).and_then(|m| m.modified()).ok()
+}
+
+/// Opens every `*.idx` with a matching `*.pack` in `dir`, or takes it from
+/// `known` if it is open already.
+fn load_packs(dir: &Path, hash: HashAlgorithm, known: &[Rc<Pack>]) -> Result<Vec<Rc<Pack>>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut packs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "idx") && path.with_extension("pack").is_file() {
+            let mtime = fs::metadata(path.with_extension("pack"))?
+                .modified()
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let pack_path = path.with_extension("pack");
+            let pack = match known.iter().find(|p| p.path() == pack_path) {
+                Some(pack) => Rc::clone(pack),
+                None => Rc::new(Pack::open(&path, hash)?),
+            };
+            packs.push((mtime, pack));
+        }
+    }
+    // Recent packs are the likeliest to hold what we are looking for.
+    packs.sort_by_key(|(mtime, _)| Reverse(*mtime));
+    Ok(packs.into_iter().map(|(_, pack)| pack).collect())
+}
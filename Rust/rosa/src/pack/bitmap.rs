@@ -0,0 +1,347 @@
+//! Reachability bitmaps (`.bitmap`, version 1): for a selection of the
+//! commits in a pack, the set of every object reachable from them, so that
+//! counting what to send or keep takes a few bit operations instead of a
+//! walk through every tree.
+//!
+//! Bit `n` of every bitmap stands for the `n`-th object of the pack in
+//! offset order. The file holds a header (`BITM`, version, option flags,
+//! number of commit bitmaps and the checksum of the pack), one bitmap per
+//! object kind (commits, trees, blobs, tags), then for each selected commit
+//! its position in the `.idx`, an XOR offset, a flags byte and its bitmap.
+//! A bitmap with a non-zero XOR offset is stored XORed with the one that
+//! many entries earlier. The name hashes of the objects may follow, in
+//! `.idx` order, and a checksum ends the file.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::ewah::Bitmap;
+use super::index::IndexEntry;
+use super::{be32, Pack};
+use crate::error::{Error, Result};
+use crate::object::{HashAlgorithm, Object, ObjectId, ObjectKind};
+use crate::odb::ObjectDatabase;
+
+const MAGIC: &[u8; 4] = b"BITM";
+const VERSION: u16 = 1;
+/// Every bitmap covers the full closure of its commit; git requires it.
+const OPT_FULL_DAG: u16 = 0x1;
+const OPT_HASH_CACHE: u16 = 0x4;
+const HEADER_LEN: usize = 12;
+const MAX_XOR_OFFSET: usize = 160;
+/// Besides the tips, every this many commits (newest first) get a bitmap.
+const SELECT_EVERY: usize = 100;
+
+/// The kinds in the order of their bitmaps.
+const KINDS: [ObjectKind; 4] = [
+    ObjectKind::Commit,
+    ObjectKind::Tree,
+    ObjectKind::Blob,
+    ObjectKind::Tag,
+];
+
+/// The path of the bitmap belonging to the pack at `pack_path`.
+pub fn bitmap_path(pack_path: &Path) -> PathBuf {
+    pack_path.with_extension("bitmap")
+}
+
+#[derive(Debug)]
+pub struct PackBitmap {
+    pack: Rc<Pack>,
+    /// Position in the `.idx` of the object at each bit.
+    objects: Vec<u32>,
+    /// Bit of the object at each `.idx` position.
+    bits: Vec<u32>,
+    kinds: [Bitmap; 4],
+    commits: HashMap<ObjectId, Bitmap>,
+    /// By `.idx` position.
+    name_hashes: Option<Vec<u32>>,
+}
+
+impl PackBitmap {
+    /// Opens the bitmap of `pack`, if it has one.
+    pub fn open(pack: Rc<Pack>) -> Result<Option<Self>> {
+        let path = bitmap_path(pack.path());
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        PackBitmap::parse(&data, pack)
+            .map(Some)
+            .map_err(|reason| Error::parse("bitmap", format!("{}: {reason}", path.display())))
+    }
+
+    fn parse(data: &[u8], pack: Rc<Pack>) -> std::result::Result<Self, String> {
+        let index = pack.index();
+        let hash_len = index.hash().raw_len();
+        if data.len() < HEADER_LEN + 2 * hash_len || &data[..4] != MAGIC {
+            return Err("not a bitmap file".into());
+        }
+        let version = u16::from_be_bytes([data[4], data[5]]);
+        if version != VERSION {
+            return Err(format!("unsupported bitmap version {version}"));
+        }
+        let options = u16::from_be_bytes([data[6], data[7]]);
+        if options & OPT_FULL_DAG == 0 {
+            return Err("bitmaps do not cover full closures".into());
+        }
+        let count = be32(data, 8) as usize;
+        if &data[HEADER_LEN..HEADER_LEN + hash_len] != index.pack_checksum() {
+            return Err("written for another pack".into());
+        }
+
+        let mut order: Vec<(u64, u32)> = (0..index.len())
+            .map(|n| (index.offset_at(n), n as u32))
+            .collect();
+        order.sort_unstable();
+        let objects: Vec<u32> = order.into_iter().map(|(_, n)| n).collect();
+        let mut bits = vec![0; objects.len()];
+        for (bit, &n) in objects.iter().enumerate() {
+            bits[n as usize] = bit as u32;
+        }
+
+        // The name hashes sit right before the trailing checksum.
+        let mut end = data.len() - hash_len;
+        let name_hashes = match options & OPT_HASH_CACHE {
+            0 => None,
+            _ => {
+                end = end
+                    .checked_sub(objects.len() * 4)
+                    .filter(|&start| start >= HEADER_LEN + hash_len)
+                    .ok_or("too short to hold its name hashes")?;
+                Some(
+                    (0..objects.len())
+                        .map(|n| be32(data, end + n * 4))
+                        .collect(),
+                )
+            }
+        };
+        let data = &data[..end];
+
+        let mut pos = HEADER_LEN + hash_len;
+        let mut kinds: [Bitmap; 4] = Default::default();
+        for kind in &mut kinds {
+            *kind = Bitmap::parse(data, &mut pos, objects.len())?;
+        }
+
+        let mut entries: Vec<(ObjectId, Bitmap)> = Vec::with_capacity(count.min(objects.len()));
+        for n in 0..count {
+            if data.len() < pos + 6 {
+                return Err("truncated commit bitmap".into());
+            }
+            let at = be32(data, pos) as usize;
+            let xor_offset = usize::from(data[pos + 4]);
+            pos += 6;
+            if at >= index.len() {
+                return Err(format!("commit bitmap for object {at} out of range"));
+            }
+            if xor_offset > MAX_XOR_OFFSET || xor_offset > n {
+                return Err(format!("bad XOR offset {xor_offset}"));
+            }
+            let mut bitmap = Bitmap::parse(data, &mut pos, objects.len())?;
+            if xor_offset > 0 {
+                bitmap.xor(&entries[n - xor_offset].1);
+            }
+            entries.push((index.id_at(at), bitmap));
+        }
+
+        Ok(PackBitmap {
+            objects,
+            bits,
+            kinds,
+            commits: entries.into_iter().collect(),
+            name_hashes,
+            pack,
+        })
+    }
+
+    pub fn pack(&self) -> &Rc<Pack> {
+        &self.pack
+    }
+
+    /// Number of objects (bits) the bitmaps cover.
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// The bit standing for `id`, if the pack has it.
+    pub fn position(&self, id: &ObjectId) -> Option<usize> {
+        let n = self.pack.index().position(id)?;
+        Some(self.bits[n] as usize)
+    }
+
+    pub fn id_at(&self, bit: usize) -> ObjectId {
+        self.pack.index().id_at(self.objects[bit] as usize)
+    }
+
+    pub fn kind_at(&self, bit: usize) -> Option<ObjectKind> {
+        (0..KINDS.len())
+            .find(|&k| self.kinds[k].get(bit))
+            .map(|k| KINDS[k])
+    }
+
+    /// The name hash recorded for the object at `bit`, 0 if none was.
+    pub fn name_hash_at(&self, bit: usize) -> u32 {
+        self.name_hashes
+            .as_ref()
+            .map_or(0, |hashes| hashes[self.objects[bit] as usize])
+    }
+
+    /// Everything reachable from `id`, if it is one of the selected commits.
+    pub fn commit_bitmap(&self, id: &ObjectId) -> Option<&Bitmap> {
+        self.commits.get(id)
+    }
+
+    /// Number of commits with a bitmap.
+    pub fn commit_count(&self) -> usize {
+        self.commits.len()
+    }
+}
+
+/// Writes the bitmap of a freshly written pack whose index `entries` are
+/// sorted by id, recording each object's `name_hashes`. Returns `false`
+/// without writing anything if some object reachable from a packed commit
+/// is not in the pack, since every bitmap must cover all of its closure.
+pub fn write(
+    odb: &ObjectDatabase,
+    path: &Path,
+    entries: &[IndexEntry],
+    name_hashes: &HashMap<ObjectId, u32>,
+    pack_checksum: &[u8],
+    hash: HashAlgorithm,
+) -> Result<bool> {
+    let mut order: Vec<(u64, usize)> = entries
+        .iter()
+        .enumerate()
+        .map(|(n, e)| (e.offset, n))
+        .collect();
+    order.sort_unstable();
+    let bits: HashMap<ObjectId, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(bit, &(_, n))| (entries[n].id, bit))
+        .collect();
+
+    let mut kinds: [Bitmap; 4] = Default::default();
+    let mut commits = Vec::new();
+    let mut parents_of_packed = std::collections::HashSet::new();
+    for (bit, &(_, n)) in order.iter().enumerate() {
+        let id = entries[n].id;
+        let (kind, _) = odb.read_header(&id)?;
+        kinds[KINDS
+            .iter()
+            .position(|&k| k == kind)
+            .expect("every kind has a bitmap")]
+        .set(bit);
+        if kind == ObjectKind::Commit {
+            let commit = odb.read(&id)?.into_commit(id)?;
+            parents_of_packed.extend(commit.parents);
+            commits.push((commit.committer.time, id));
+        }
+    }
+
+    // The tips, and a sample of the rest so that walks starting anywhere
+    // soon meet a commit with a bitmap.
+    commits.sort_by(|a, b| b.cmp(a));
+    let mut selected: Vec<(i64, ObjectId)> = commits
+        .iter()
+        .enumerate()
+        .filter(|(n, (_, id))| !parents_of_packed.contains(id) || n % SELECT_EVERY == 0)
+        .map(|(_, commit)| *commit)
+        .collect();
+
+    // Oldest first, so that later walks stop at the bitmaps already made.
+    selected.reverse();
+    let mut done: HashMap<ObjectId, Bitmap> = HashMap::new();
+    let mut written = Vec::with_capacity(selected.len());
+    for (_, id) in selected {
+        let Some(bitmap) = reach(odb, id, &bits, &done)? else {
+            return Ok(false);
+        };
+        done.insert(id, bitmap);
+        written.push(id);
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_be_bytes());
+    out.extend_from_slice(&(OPT_FULL_DAG | OPT_HASH_CACHE).to_be_bytes());
+    out.extend_from_slice(&(written.len() as u32).to_be_bytes());
+    out.extend_from_slice(pack_checksum);
+    for kind in &kinds {
+        kind.serialize(&mut out);
+    }
+    for id in &written {
+        let at = entries
+            .binary_search_by_key(id, |e| e.id)
+            .expect("selected commits are packed");
+        out.extend_from_slice(&(at as u32).to_be_bytes());
+        // Neither XOR-compressed nor marked for reuse.
+        out.extend_from_slice(&[0, 0]);
+        done[id].serialize(&mut out);
+    }
+    for entry in entries {
+        let name_hash = name_hashes.get(&entry.id).copied().unwrap_or(0);
+        out.extend_from_slice(&name_hash.to_be_bytes());
+    }
+    let checksum = hash.digest(&out)?;
+    out.extend_from_slice(&checksum);
+
+    crate::odb::write_atomically(path, &out)?;
+    Ok(true)
+}
+
+/// The bitmap of everything reachable from `start`, reusing the bitmaps in
+/// `done`; `None` once something reachable turns out not to be packed.
+fn reach(
+    odb: &ObjectDatabase,
+    start: ObjectId,
+    bits: &HashMap<ObjectId, usize>,
+    done: &HashMap<ObjectId, Bitmap>,
+) -> Result<Option<Bitmap>> {
+    let mut out = Bitmap::new();
+    let mut pending = vec![start];
+    while let Some(id) = pending.pop() {
+        let Some(&bit) = bits.get(&id) else {
+            return Ok(None);
+        };
+        if out.get(bit) {
+            continue;
+        }
+        if let Some(bitmap) = done.get(&id) {
+            out.or(bitmap);
+            continue;
+        }
+        out.set(bit);
+        match odb.read(&id)? {
+            Object::Commit(commit) => {
+                pending.push(commit.tree);
+                pending.extend(commit.parents);
+            }
+            Object::Tree(tree) => {
+                for entry in tree.entries {
+                    match entry.kind() {
+                        // Submodule commits live in another repository.
+                        Some(ObjectKind::Commit) => {}
+                        // Blobs lead nowhere, so they need not be read.
+                        Some(ObjectKind::Blob) => match bits.get(&entry.id) {
+                            Some(&bit) => out.set(bit),
+                            None => return Ok(None),
+                        },
+                        _ => pending.push(entry.id),
+                    }
+                }
+            }
+            Object::Tag(tag) => pending.push(tag.object),
+            Object::Blob(_) => {}
+        }
+    }
+    Ok(Some(out))
+}
@@ -0,0 +1,89 @@
+//! A byte-bounded LRU of reconstructed delta bases, so walking a long
+//! history does not re-inflate the same bases over and over.
+
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use crate::object::ObjectKind;
+
+/// Same default as git's `core.deltaBaseCacheLimit`.
+pub const DEFAULT_CACHE_LIMIT: usize = 96 * 1024 * 1024;
+
+#[derive(Debug)]
+struct Slot {
+    kind: ObjectKind,
+    data: Rc<[u8]>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+pub struct DeltaBaseCache {
+    slots: HashMap<u64, Slot>,
+    /// Pack offsets ordered by last use, oldest first.
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+    size: usize,
+    limit: usize,
+}
+
+impl Default for DeltaBaseCache {
+    fn default() -> Self {
+        DeltaBaseCache::new(DEFAULT_CACHE_LIMIT)
+    }
+}
+
+impl DeltaBaseCache {
+    pub fn new(limit: usize) -> Self {
+        DeltaBaseCache {
+            slots: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            size: 0,
+            limit,
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.evict();
+    }
+
+    pub fn get(&mut self, offset: u64) -> Option<(ObjectKind, Rc<[u8]>)> {
+        self.tick += 1;
+        let slot = self.slots.get_mut(&offset)?;
+        self.lru.remove(&slot.tick);
+        slot.tick = self.tick;
+        self.lru.insert(slot.tick, offset);
+        Some((slot.kind, Rc::clone(&slot.data)))
+    }
+
+    pub fn insert(&mut self, offset: u64, kind: ObjectKind, data: Rc<[u8]>) {
+        if data.len() > self.limit {
+            return;
+        }
+        self.tick += 1;
+        self.size += data.len();
+        let slot = Slot {
+            kind,
+            data,
+            tick: self.tick,
+        };
+        if let Some(old) = self.slots.insert(offset, slot) {
+            self.lru.remove(&old.tick);
+            self.size -= old.data.len();
+        }
+        self.lru.insert(self.tick, offset);
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.limit {
+            let Some((_, offset)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(slot) = self.slots.remove(&offset) {
+                self.size -= slot.data.len();
+            }
+        }
+    }
+}
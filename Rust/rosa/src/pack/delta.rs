@@ -0,0 +1,247 @@
+//! Git's delta format: a base size, a result size, then a stream of
+//! "copy from base" and "insert literal" instructions.
+
+use crate::error::{Error, Result};
+
+/// Rebuilds an object from its delta base and a delta.
+pub fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let bad = |reason: &str| Error::parse("delta", reason.to_owned());
+
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos).ok_or_else(|| bad("truncated header"))?;
+    let result_size = read_size(delta, &mut pos).ok_or_else(|| bad("truncated header"))?;
+    if base_size != base.len() as u64 {
+        return Err(bad("base size mismatch"));
+    }
+    let result_size = usize::try_from(result_size).map_err(|_| bad("result too large"))?;
+
+    let mut out = Vec::with_capacity(result_size.min(super::MAX_RESERVE));
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+
+        if op & 0x80 != 0 {
+            // Copy: bits 0-3 select offset bytes, bits 4-6 size bytes.
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    let byte = *delta.get(pos).ok_or_else(|| bad("truncated copy"))?;
+                    offset |= usize::from(byte) << (8 * i);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    let byte = *delta.get(pos).ok_or_else(|| bad("truncated copy"))?;
+                    size |= usize::from(byte) << (8 * i);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let chunk = offset
+                .checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .ok_or_else(|| bad("copy outside of base"))?;
+            out.extend_from_slice(chunk);
+        } else if op != 0 {
+            // Insert: the next `op` bytes are literal data.
+            let chunk = delta
+                .get(pos..pos + usize::from(op))
+                .ok_or_else(|| bad("truncated insert"))?;
+            out.extend_from_slice(chunk);
+            pos += usize::from(op);
+        } else {
+            return Err(bad("reserved opcode 0"));
+        }
+
+        if out.len() > result_size {
+            return Err(bad("result larger than announced"));
+        }
+    }
+
+    if out.len() != result_size {
+        return Err(bad("result size mismatch"));
+    }
+    Ok(out)
+}
+
+/// Reads the little-endian base-128 sizes at the start of a delta.
+pub(crate) fn read_size(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut size = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        if shift > 63 {
+            return None;
+        }
+        size |= u64::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+    }
+}
+
+/// Width of the blocks of the base that matches are anchored on.
+const BLOCK: usize = 16;
+/// Cap on how many base offsets are remembered per block hash, so repetitive
+/// content cannot make matching quadratic.
+const MAX_BUCKET: usize = 64;
+const HASH_MUL: u64 = 0x100_0000_01b3;
+/// Largest size a single copy instruction can carry.
+const MAX_COPY: usize = 0xff_ffff;
+/// Largest literal a single insert instruction can carry.
+const MAX_INSERT: usize = 0x7f;
+
+/// Computes a delta turning `base` into `target`, or `None` if it would be
+/// larger than `max_size` bytes.
+pub fn create(base: &[u8], target: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    write_size(&mut out, base.len() as u64);
+    write_size(&mut out, target.len() as u64);
+
+    let index = BlockIndex::new(base);
+    let mut literal: Vec<u8> = Vec::new();
+    let mut pos = 0;
+    let mut hash = (target.len() >= BLOCK).then(|| block_hash(&target[..BLOCK]));
+
+    while pos < target.len() {
+        let found = hash.and_then(|h| index.longest_match(h, base, &target[pos..]));
+        match found {
+            Some((mut offset, forward)) => {
+                // Grow the match backwards into bytes we were about to insert.
+                let mut len = forward;
+                while offset > 0 && literal.last() == Some(&base[offset - 1]) {
+                    literal.pop();
+                    offset -= 1;
+                    len += 1;
+                }
+                flush_literal(&mut out, &mut literal);
+                emit_copy(&mut out, offset, len);
+                pos += forward;
+                hash = (pos + BLOCK <= target.len()).then(|| block_hash(&target[pos..pos + BLOCK]));
+            }
+            None => {
+                literal.push(target[pos]);
+                if literal.len() == MAX_INSERT {
+                    flush_literal(&mut out, &mut literal);
+                }
+                hash = match hash {
+                    Some(h) if pos + BLOCK < target.len() => {
+                        Some(roll(h, target[pos], target[pos + BLOCK]))
+                    }
+                    _ => None,
+                };
+                pos += 1;
+            }
+        }
+        if out.len() + literal.len() > max_size {
+            return None;
+        }
+    }
+    flush_literal(&mut out, &mut literal);
+
+    (out.len() <= max_size).then_some(out)
+}
+
+/// Hashes of the non-overlapping `BLOCK`-sized chunks of a delta base.
+struct BlockIndex {
+    buckets: std::collections::HashMap<u64, Vec<usize>>,
+}
+
+impl BlockIndex {
+    fn new(base: &[u8]) -> Self {
+        let mut buckets: std::collections::HashMap<u64, Vec<usize>> = Default::default();
+        let mut offset = 0;
+        while offset + BLOCK <= base.len() {
+            let bucket = buckets
+                .entry(block_hash(&base[offset..offset + BLOCK]))
+                .or_default();
+            if bucket.len() < MAX_BUCKET {
+                bucket.push(offset);
+            }
+            offset += BLOCK;
+        }
+        BlockIndex { buckets }
+    }
+
+    /// The longest run of `target` found verbatim in `base` starting at one
+    /// of the offsets hashing to `hash`, if it spans at least one block.
+    fn longest_match(&self, hash: u64, base: &[u8], target: &[u8]) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        for &offset in self.buckets.get(&hash)? {
+            let len = base[offset..]
+                .iter()
+                .zip(target)
+                .take(MAX_COPY)
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= BLOCK && best.is_none_or(|(_, l)| len > l) {
+                best = Some((offset, len));
+            }
+        }
+        best
+    }
+}
+
+fn block_hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |h, &b| {
+        h.wrapping_mul(HASH_MUL).wrapping_add(u64::from(b))
+    })
+}
+
+/// Slides the hash window one byte forward.
+fn roll(hash: u64, out: u8, inc: u8) -> u64 {
+    let top = HASH_MUL.wrapping_pow(BLOCK as u32 - 1);
+    hash.wrapping_sub(u64::from(out).wrapping_mul(top))
+        .wrapping_mul(HASH_MUL)
+        .wrapping_add(u64::from(inc))
+}
+
+fn flush_literal(out: &mut Vec<u8>, literal: &mut Vec<u8>) {
+    for chunk in literal.chunks(MAX_INSERT) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+    literal.clear();
+}
+
+fn emit_copy(out: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY);
+        let op_pos = out.len();
+        out.push(0x80);
+        for i in 0..4 {
+            let byte = (offset >> (8 * i)) as u8;
+            if byte != 0 {
+                out[op_pos] |= 1 << i;
+                out.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = (size >> (8 * i)) as u8;
+            if byte != 0 {
+                out[op_pos] |= 0x10 << i;
+                out.push(byte);
+            }
+        }
+        offset += size;
+        len -= size;
+    }
+}
+
+fn write_size(out: &mut Vec<u8>, mut size: u64) {
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
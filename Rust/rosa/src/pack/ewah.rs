@@ -0,0 +1,162 @@
+//! Bit sets, and the EWAH compression they are stored with in `.bitmap`
+//! files.
+//!
+//! A compressed bitmap is a sequence of 64-bit words: a marker word says
+//! how many all-zero or all-one words are skipped (bit 0 tells which, bits
+//! 1 to 32 how many) and how many literal words follow it (bits 33 to 63).
+//! On disk it is preceded by its size in bits and its word count, and
+//! followed by the position of the last marker word, all big-endian.
+
+use super::{be32, be64};
+
+const RUNNING_LEN_BITS: u32 = 32;
+const MAX_RUNNING_LEN: u64 = (1 << RUNNING_LEN_BITS) - 1;
+const MAX_LITERAL_WORDS: u64 = (1 << 31) - 1;
+
+/// An uncompressed bit set, bit `n` being bit `n % 64` of word `n / 64`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    pub fn new() -> Self {
+        Bitmap::default()
+    }
+
+    pub fn get(&self, bit: usize) -> bool {
+        self.words
+            .get(bit / 64)
+            .is_some_and(|word| word & (1 << (bit % 64)) != 0)
+    }
+
+    pub fn set(&mut self, bit: usize) {
+        if self.words.len() <= bit / 64 {
+            self.words.resize(bit / 64 + 1, 0);
+        }
+        self.words[bit / 64] |= 1 << (bit % 64);
+    }
+
+    /// Adds every bit of `other`.
+    pub fn or(&mut self, other: &Bitmap) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    /// Clears every bit of `other`.
+    pub fn and_not(&mut self, other: &Bitmap) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word &= !other;
+        }
+    }
+
+    /// Flips every bit of `other`.
+    pub fn xor(&mut self, other: &Bitmap) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word ^= other;
+        }
+    }
+
+    /// The set bits, in increasing order.
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(n, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| n * 64 + bit)
+        })
+    }
+
+    /// Decodes the compressed bitmap at `*pos`, moving past it. One that
+    /// would decode to more words than its size in bits needs, or than
+    /// `max_bits` (the objects it can be about) do, is refused before its
+    /// runs are expanded.
+    pub fn parse(data: &[u8], pos: &mut usize, max_bits: usize) -> Result<Self, String> {
+        let truncated = || "truncated bitmap".to_string();
+        if data.len() < *pos + 8 {
+            return Err(truncated());
+        }
+        let bits = be32(data, *pos) as usize;
+        let count = be32(data, *pos + 4) as usize;
+        let start = *pos + 8;
+        let end = count
+            .checked_mul(8)
+            .and_then(|len| len.checked_add(start))
+            .filter(|&end| end + 4 <= data.len())
+            .ok_or_else(truncated)?;
+        let compressed: Vec<u64> = (0..count).map(|n| be64(data, start + n * 8)).collect();
+        *pos = end + 4;
+
+        let max_words = bits.min(max_bits).div_ceil(64);
+        let too_long = || format!("bitmap runs past {} bits", bits.min(max_bits));
+        let mut words = Vec::new();
+        let mut n = 0;
+        while n < compressed.len() {
+            let marker = compressed[n];
+            let run = ((marker >> 1) & MAX_RUNNING_LEN) as usize;
+            let literals = (marker >> (1 + RUNNING_LEN_BITS)) as usize;
+            let fill = if marker & 1 != 0 { u64::MAX } else { 0 };
+            let literal = compressed
+                .get(n + 1..n + 1 + literals)
+                .ok_or_else(|| "bitmap literal words run past its end".to_string())?;
+            if words.len() + run + literals > max_words {
+                return Err(too_long());
+            }
+            words.extend(std::iter::repeat_n(fill, run));
+            words.extend_from_slice(literal);
+            n += 1 + literals;
+        }
+        Ok(Bitmap { words })
+    }
+
+    /// Appends the compressed form to `out`.
+    pub fn serialize(&self, out: &mut Vec<u8>) {
+        // Trailing empty words are implied, as git leaves them out too.
+        let len = self
+            .words
+            .iter()
+            .rposition(|&w| w != 0)
+            .map_or(1, |n| n + 1);
+        let words = &self.words[..len.min(self.words.len())];
+
+        let mut compressed = Vec::new();
+        let mut last_marker;
+        let mut n = 0;
+        loop {
+            let fill = words.get(n).copied().filter(|&w| w == 0 || w == u64::MAX);
+            let mut run = 0;
+            while run < MAX_RUNNING_LEN && words.get(n).copied() == fill && fill.is_some() {
+                run += 1;
+                n += 1;
+            }
+            let literal_start = n;
+            while ((n - literal_start) as u64) < MAX_LITERAL_WORDS
+                && words.get(n).is_some_and(|&w| w != 0 && w != u64::MAX)
+            {
+                n += 1;
+            }
+            let literals = (n - literal_start) as u64;
+            last_marker = compressed.len();
+            compressed.push(
+                u64::from(fill == Some(u64::MAX)) | run << 1 | literals << (1 + RUNNING_LEN_BITS),
+            );
+            compressed.extend_from_slice(&words[literal_start..n]);
+            if n >= words.len() {
+                break;
+            }
+        }
+
+        out.extend_from_slice(&((len * 64) as u32).to_be_bytes());
+        out.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        for word in &compressed {
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        out.extend_from_slice(&(last_marker as u32).to_be_bytes());
+    }
+}
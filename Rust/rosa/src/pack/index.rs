@@ -0,0 +1,248 @@
+//! Pack index files (`.idx`), version 2.
+//!
+//! Layout: a 4-byte magic and version, a 256-entry fan-out table of
+//! cumulative counts by first id byte, the sorted object ids, their CRC32s,
+//! 31-bit offsets (with the high bit redirecting into a table of 64-bit
+//! offsets for packs larger than 2 GiB) and finally two trailing checksums.
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::object::{HashAlgorithm, ObjectId};
+
+const MAGIC: &[u8; 4] = b"\xfftOc";
+const FANOUT_LEN: usize = 256 * 4;
+const HEADER_LEN: usize = 8;
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+#[derive(Debug)]
+pub struct PackIndex {
+    data: Vec<u8>,
+    count: usize,
+    /// Nothing in the file says which hash it uses; the repository does.
+    hash: HashAlgorithm,
+}
+
+impl PackIndex {
+    pub fn open(path: &Path, hash: HashAlgorithm) -> Result<Self> {
+        PackIndex::parse(fs::read(path)?, hash)
+            .map_err(|reason| Error::parse("pack index", format!("{}: {reason}", path.display())))
+    }
+
+    fn parse(data: Vec<u8>, hash: HashAlgorithm) -> std::result::Result<Self, String> {
+        if data.len() < HEADER_LEN + FANOUT_LEN || &data[..4] != MAGIC {
+            return Err("not a version 2 pack index".into());
+        }
+        let version = be32(&data, 4);
+        if version != 2 {
+            return Err(format!("unsupported index version {version}"));
+        }
+
+        let mut previous = 0;
+        for n in 0..256 {
+            let total = be32(&data, HEADER_LEN + n * 4);
+            if total < previous {
+                return Err("fan-out table is not monotonic".into());
+            }
+            previous = total;
+        }
+        let count = previous as usize;
+        let min_len =
+            HEADER_LEN + FANOUT_LEN + count * (hash.raw_len() + 4 + 4) + 2 * hash.raw_len();
+        if data.len() < min_len {
+            return Err("index file is truncated".into());
+        }
+        let index = PackIndex { data, count, hash };
+
+        let large = index.large_offset_count();
+        if index.data.len() != min_len + large * 8 {
+            return Err("index file has the wrong size".into());
+        }
+        let slot_beyond_table = (0..index.count).any(|n| {
+            let small = be32(&index.data, index.offsets_start() + n * 4);
+            small & LARGE_OFFSET_FLAG != 0 && (small & !LARGE_OFFSET_FLAG) as usize >= large
+        });
+        if slot_beyond_table {
+            return Err("large offset slot is outside its table".into());
+        }
+        Ok(index)
+    }
+
+    pub fn hash(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Checksum of the pack this index describes.
+    pub fn pack_checksum(&self) -> &[u8] {
+        let end = self.data.len() - self.hash.raw_len();
+        &self.data[end - self.hash.raw_len()..end]
+    }
+
+    pub fn id_at(&self, n: usize) -> ObjectId {
+        ObjectId::from_bytes(self.id_bytes(n)).expect("fixed-size id slice")
+    }
+
+    pub fn crc32_at(&self, n: usize) -> u32 {
+        be32(&self.data, self.crc_start() + n * 4)
+    }
+
+    pub fn offset_at(&self, n: usize) -> u64 {
+        let small = be32(&self.data, self.offsets_start() + n * 4);
+        if small & LARGE_OFFSET_FLAG == 0 {
+            return u64::from(small);
+        }
+        let slot = (small & !LARGE_OFFSET_FLAG) as usize;
+        be64(&self.data, self.large_offsets_start() + slot * 8)
+    }
+
+    /// Position of `id` in the sorted id table.
+    pub fn position(&self, id: &ObjectId) -> Option<usize> {
+        let (mut lo, mut hi) = self.fanout_range(id.as_bytes()[0]);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.id_bytes(mid).cmp(id.as_bytes()) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// Pack offset of `id`, if this index contains it.
+    pub fn lookup(&self, id: &ObjectId) -> Option<u64> {
+        self.position(id).map(|n| self.offset_at(n))
+    }
+
+    /// Every id whose hex form starts with `prefix` (lowercase, at least two
+    /// digits).
+    pub fn find_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        let Ok(first) = u8::from_str_radix(&prefix[..2.min(prefix.len())], 16) else {
+            return Vec::new();
+        };
+        let (lo, hi) = self.fanout_range(first);
+        (lo..hi)
+            .map(|n| self.id_at(n))
+            .filter(|id| id.to_hex().starts_with(prefix))
+            .collect()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        (0..self.count).map(|n| self.id_at(n))
+    }
+
+    fn id_bytes(&self, n: usize) -> &[u8] {
+        let start = self.ids_start() + n * self.hash.raw_len();
+        &self.data[start..start + self.hash.raw_len()]
+    }
+
+    fn fanout_range(&self, first: u8) -> (usize, usize) {
+        let hi = be32(&self.data, HEADER_LEN + usize::from(first) * 4) as usize;
+        let lo = match first {
+            0 => 0,
+            _ => be32(&self.data, HEADER_LEN + (usize::from(first) - 1) * 4) as usize,
+        };
+        (lo, hi)
+    }
+
+    fn large_offset_count(&self) -> usize {
+        (0..self.count)
+            .filter(|&n| be32(&self.data, self.offsets_start() + n * 4) & LARGE_OFFSET_FLAG != 0)
+            .count()
+    }
+
+    fn ids_start(&self) -> usize {
+        HEADER_LEN + FANOUT_LEN
+    }
+
+    fn crc_start(&self) -> usize {
+        self.ids_start() + self.count * self.hash.raw_len()
+    }
+
+    fn offsets_start(&self) -> usize {
+        self.crc_start() + self.count * 4
+    }
+
+    fn large_offsets_start(&self) -> usize {
+        self.offsets_start() + self.count * 4
+    }
+}
+
+pub(crate) fn be32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(data[at..at + 4].try_into().expect("4-byte slice"))
+}
+
+pub(crate) fn be64(data: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(data[at..at + 8].try_into().expect("8-byte slice"))
+}
+
+/// What the index records about one packed object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub id: ObjectId,
+    pub crc32: u32,
+    pub offset: u64,
+}
+
+/// Writes a version 2 index for a pack whose trailing checksum is
+/// `pack_checksum`, checksummed with `hash`. Entries are sorted in place.
+pub fn write(
+    path: &Path,
+    entries: &mut [IndexEntry],
+    pack_checksum: &[u8],
+    hash: HashAlgorithm,
+) -> Result<()> {
+    entries.sort_by_key(|e| e.id);
+
+    let mut out = Vec::with_capacity(
+        HEADER_LEN + FANOUT_LEN + entries.len() * (hash.raw_len() + 8) + 2 * hash.raw_len(),
+    );
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&2u32.to_be_bytes());
+
+    let mut fanout = [0u32; 256];
+    for entry in entries.iter() {
+        fanout[usize::from(entry.id.as_bytes()[0])] += 1;
+    }
+    let mut total = 0;
+    for count in fanout {
+        total += count;
+        out.extend_from_slice(&total.to_be_bytes());
+    }
+
+    for entry in entries.iter() {
+        out.extend_from_slice(entry.id.as_bytes());
+    }
+    for entry in entries.iter() {
+        out.extend_from_slice(&entry.crc32.to_be_bytes());
+    }
+    let mut large = Vec::new();
+    for entry in entries.iter() {
+        let small = match u32::try_from(entry.offset) {
+            Ok(offset) if offset & LARGE_OFFSET_FLAG == 0 => offset,
+            _ => {
+                large.push(entry.offset);
+                LARGE_OFFSET_FLAG | (large.len() as u32 - 1)
+            }
+        };
+        out.extend_from_slice(&small.to_be_bytes());
+    }
+    for offset in large {
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+
+    out.extend_from_slice(pack_checksum);
+    let checksum = hash.digest(&out)?;
+    out.extend_from_slice(&checksum);
+
+    crate::odb::write_atomically(path, &out)
+}
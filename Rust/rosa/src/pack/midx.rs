@@ -0,0 +1,365 @@
+//! Multi-pack indexes (`objects/pack/multi-pack-index`): one sorted table
+//! of every object in a set of packs, so a lookup costs a single binary
+//! search however many packs there are.
+//!
+//! Layout: a 12-byte header (`MIDX`, version, hash version, chunk count,
+//! base file count, pack count), a table of chunk ids and offsets ended by
+//! a zero id, the chunks themselves and a trailing checksum. The chunks are
+//! the pack index names (PNAM), a fan-out table (OIDF), the sorted ids
+//! (OIDL), each object's pack and offset (OOFF) and, for offsets that do
+//! not fit in 31 bits, a table of 64-bit offsets (LOFF).
+
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::index::{be32, be64};
+use super::Pack;
+use crate::chunk::{self, ChunkTable, FANOUT_LEN};
+use crate::error::{Error, Result};
+use crate::object::{HashAlgorithm, ObjectId};
+
+/// File name of the multi-pack index inside `objects/pack`.
+pub const MIDX_FILE_NAME: &str = "multi-pack-index";
+
+const SIGNATURE: &[u8; 4] = b"MIDX";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 12;
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+const CHUNK_PACK_NAMES: u32 = u32::from_be_bytes(*b"PNAM");
+const CHUNK_FANOUT: u32 = u32::from_be_bytes(*b"OIDF");
+const CHUNK_LOOKUP: u32 = u32::from_be_bytes(*b"OIDL");
+const CHUNK_OFFSETS: u32 = u32::from_be_bytes(*b"OOFF");
+const CHUNK_LARGE_OFFSETS: u32 = u32::from_be_bytes(*b"LOFF");
+
+#[derive(Debug)]
+pub struct MultiPackIndex {
+    data: Vec<u8>,
+    hash: HashAlgorithm,
+    /// Names of the `.idx` files covered, in pack-id order.
+    pack_names: Vec<String>,
+    count: usize,
+    fanout: usize,
+    lookup: usize,
+    offsets: usize,
+    large_offsets: Option<(usize, usize)>,
+}
+
+impl MultiPackIndex {
+    pub fn open(path: &Path, hash: HashAlgorithm) -> Result<Self> {
+        MultiPackIndex::parse(fs::read(path)?, hash).map_err(|reason| {
+            Error::parse("multi-pack-index", format!("{}: {reason}", path.display()))
+        })
+    }
+
+    fn parse(data: Vec<u8>, hash: HashAlgorithm) -> std::result::Result<Self, String> {
+        if data.len() < HEADER_LEN || &data[..4] != SIGNATURE {
+            return Err("not a multi-pack-index".into());
+        }
+        if data[4] != VERSION {
+            return Err(format!("unsupported version {}", data[4]));
+        }
+        if data[5] != hash_version(hash) {
+            return Err(format!(
+                "hash version {} does not match the repository",
+                data[5]
+            ));
+        }
+        let chunk_count = usize::from(data[6]);
+        if data[7] != 0 {
+            return Err("incremental multi-pack-indexes are not supported".into());
+        }
+        let pack_count = be32(&data, 8) as usize;
+
+        let chunks = ChunkTable::parse(&data, HEADER_LEN, chunk_count, hash)?;
+
+        let (names_at, names_len) = chunks.require(CHUNK_PACK_NAMES)?;
+        let pack_names: Vec<String> = data[names_at..names_at + names_len]
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        if pack_names.len() != pack_count {
+            return Err(format!(
+                "header lists {pack_count} packs but {} are named",
+                pack_names.len()
+            ));
+        }
+
+        let (fanout, fanout_len) = chunks.require(CHUNK_FANOUT)?;
+        if fanout_len != FANOUT_LEN {
+            return Err("OID fanout chunk has the wrong size".into());
+        }
+        let count = be32(&data, fanout + 255 * 4) as usize;
+        let (lookup, lookup_len) = chunks.require(CHUNK_LOOKUP)?;
+        if lookup_len != count * hash.raw_len() {
+            return Err("OID lookup chunk has the wrong size".into());
+        }
+        let (offsets, offsets_len) = chunks.require(CHUNK_OFFSETS)?;
+        if offsets_len != count * 8 {
+            return Err("object offsets chunk has the wrong size".into());
+        }
+        let large_offsets = chunks.get(CHUNK_LARGE_OFFSETS);
+
+        Ok(MultiPackIndex {
+            data,
+            hash,
+            pack_names,
+            count,
+            fanout,
+            lookup,
+            offsets,
+            large_offsets,
+        })
+    }
+
+    /// Names of the pack indexes covered; a pack's position here is the
+    /// pack id [`entry_at`](Self::entry_at) refers to it by.
+    pub fn pack_names(&self) -> &[String] {
+        &self.pack_names
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn checksum(&self) -> &[u8] {
+        &self.data[self.data.len() - self.hash.raw_len()..]
+    }
+
+    pub fn id_at(&self, n: usize) -> ObjectId {
+        ObjectId::from_bytes(self.id_bytes(n)).expect("fixed-size id slice")
+    }
+
+    /// The pack id and offset of the `n`th object.
+    pub fn entry_at(&self, n: usize) -> Result<(usize, u64)> {
+        let row = self.offsets + n * 8;
+        let pack = be32(&self.data, row) as usize;
+        let small = be32(&self.data, row + 4);
+        let offset = if small & LARGE_OFFSET_FLAG == 0 {
+            u64::from(small)
+        } else {
+            let slot = (small & !LARGE_OFFSET_FLAG) as usize;
+            match self.large_offsets {
+                Some((start, len)) if (slot + 1) * 8 <= len => be64(&self.data, start + slot * 8),
+                _ => return Err(Error::parse("multi-pack-index", "bad large offset")),
+            }
+        };
+        if pack >= self.pack_names.len() {
+            return Err(Error::parse(
+                "multi-pack-index",
+                format!("bad pack id {pack}"),
+            ));
+        }
+        Ok((pack, offset))
+    }
+
+    /// Position of `id` in the sorted id table.
+    pub fn position(&self, id: &ObjectId) -> Option<usize> {
+        let (mut lo, mut hi) = self.fanout_range(id.as_bytes()[0]);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.id_bytes(mid).cmp(id.as_bytes()) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// The pack id and offset of `id`, if it is covered.
+    pub fn lookup(&self, id: &ObjectId) -> Result<Option<(usize, u64)>> {
+        self.position(id).map(|n| self.entry_at(n)).transpose()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        (0..self.count).map(|n| self.id_at(n))
+    }
+
+    fn id_bytes(&self, n: usize) -> &[u8] {
+        let start = self.lookup + n * self.hash.raw_len();
+        &self.data[start..start + self.hash.raw_len()]
+    }
+
+    fn fanout_range(&self, first: u8) -> (usize, usize) {
+        let hi = be32(&self.data, self.fanout + usize::from(first) * 4) as usize;
+        let lo = match first {
+            0 => 0,
+            _ => be32(&self.data, self.fanout + (usize::from(first) - 1) * 4) as usize,
+        };
+        (lo, hi.min(self.count))
+    }
+
+    /// Checks the file against `packs` (in pack-id order), returning every
+    /// problem found: a bad checksum, unsorted names or ids, or objects
+    /// whose recorded offset disagrees with their pack's own index.
+    pub fn verify(&self, packs: &[&Pack]) -> Vec<String> {
+        let mut problems = Vec::new();
+        let body = &self.data[..self.data.len() - self.hash.raw_len()];
+        match self.hash.digest(body) {
+            Ok(sum) if sum == self.checksum() => {}
+            Ok(_) => problems.push("incorrect checksum".to_owned()),
+            Err(e) => problems.push(e.to_string()),
+        }
+        if self.pack_names.windows(2).any(|w| w[0] >= w[1]) {
+            problems.push("pack names out of order".to_owned());
+        }
+        for first in 1..=255u8 {
+            let (lo, hi) = self.fanout_range(first);
+            if lo > hi {
+                problems.push(format!(
+                    "oid fanout out of order: fanout[{}] > fanout[{first}]",
+                    first - 1
+                ));
+            }
+        }
+        for n in 1..self.count {
+            if self.id_bytes(n - 1) >= self.id_bytes(n) {
+                problems.push(format!(
+                    "oid lookup out of order: oid[{}] = {} >= {} = oid[{n}]",
+                    n - 1,
+                    self.id_at(n - 1),
+                    self.id_at(n)
+                ));
+            }
+        }
+        for n in 0..self.count {
+            let id = self.id_at(n);
+            let (pack, offset) = match self.entry_at(n) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    problems.push(format!("{id}: {e}"));
+                    continue;
+                }
+            };
+            match packs.get(pack).and_then(|p| p.index().lookup(&id)) {
+                Some(actual) if actual == offset => {}
+                Some(actual) => problems.push(format!(
+                    "incorrect object offset for oid[{n}] = {id}: {offset} != {actual}"
+                )),
+                None => problems.push(format!("failed to load pack entry for oid[{n}] = {id}")),
+            }
+        }
+        problems
+    }
+}
+
+/// Git's number for each object format in the header.
+fn hash_version(hash: HashAlgorithm) -> u8 {
+    match hash {
+        HashAlgorithm::Sha1 => 1,
+        HashAlgorithm::Sha256 => 2,
+    }
+}
+
+/// Writes a multi-pack index covering `packs` to `path`. Objects found in
+/// several packs are attributed to `preferred` (a pack index name) if it
+/// has them, and otherwise to the most recently modified pack.
+pub fn write(
+    path: &Path,
+    packs: &[&Pack],
+    preferred: Option<&str>,
+    hash: HashAlgorithm,
+) -> Result<()> {
+    let mut named: Vec<(String, &Pack)> = packs
+        .iter()
+        .map(|pack| {
+            let name = pack
+                .path()
+                .with_extension("idx")
+                .file_name()
+                .and_then(|n| n.to_str())
+                .map(str::to_owned)
+                .ok_or_else(|| Error::parse("pack", "pack file name is not UTF-8"))?;
+            Ok((name, *pack))
+        })
+        .collect::<Result<_>>()?;
+    named.sort_by(|a, b| a.0.cmp(&b.0));
+    if let Some(preferred) = preferred {
+        if !named.iter().any(|(name, _)| name == preferred) {
+            return Err(Error::Usage(format!(
+                "unknown preferred pack: '{preferred}'"
+            )));
+        }
+    }
+
+    struct Candidate {
+        id: ObjectId,
+        preferred: bool,
+        mtime: SystemTime,
+        pack: usize,
+        offset: u64,
+    }
+    let mut candidates = Vec::new();
+    for (pack_id, (name, pack)) in named.iter().enumerate() {
+        let mtime = fs::metadata(pack.path())?
+            .modified()
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let index = pack.index();
+        for n in 0..index.len() {
+            candidates.push(Candidate {
+                id: index.id_at(n),
+                preferred: preferred == Some(name.as_str()),
+                mtime,
+                pack: pack_id,
+                offset: index.offset_at(n),
+            });
+        }
+    }
+    // Same order as git: preferred pack first, then newest, then lowest id.
+    candidates.sort_by(|a, b| {
+        a.id.cmp(&b.id)
+            .then(b.preferred.cmp(&a.preferred))
+            .then(b.mtime.cmp(&a.mtime))
+            .then(a.pack.cmp(&b.pack))
+    });
+    candidates.dedup_by_key(|c| c.id);
+
+    let mut names = Vec::new();
+    for (name, _) in &named {
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+    }
+    names.resize(names.len().next_multiple_of(4), 0);
+
+    let fanout = chunk::fanout(candidates.iter().map(|c| &c.id));
+
+    let mut lookup = Vec::with_capacity(candidates.len() * hash.raw_len());
+    let mut offsets = Vec::with_capacity(candidates.len() * 8);
+    let mut large = Vec::new();
+    for c in &candidates {
+        lookup.extend_from_slice(c.id.as_bytes());
+        offsets.extend_from_slice(&(c.pack as u32).to_be_bytes());
+        let small = match u32::try_from(c.offset) {
+            Ok(offset) if offset & LARGE_OFFSET_FLAG == 0 => offset,
+            _ => {
+                large.extend_from_slice(&c.offset.to_be_bytes());
+                LARGE_OFFSET_FLAG | (large.len() / 8 - 1) as u32
+            }
+        };
+        offsets.extend_from_slice(&small.to_be_bytes());
+    }
+
+    let mut chunks = vec![
+        (CHUNK_PACK_NAMES, names),
+        (CHUNK_FANOUT, fanout),
+        (CHUNK_LOOKUP, lookup),
+        (CHUNK_OFFSETS, offsets),
+    ];
+    if !large.is_empty() {
+        chunks.push((CHUNK_LARGE_OFFSETS, large));
+    }
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(SIGNATURE);
+    header.extend_from_slice(&[VERSION, hash_version(hash), chunks.len() as u8, 0]);
+    header.extend_from_slice(&(named.len() as u32).to_be_bytes());
+    let out = chunk::assemble(&header, &chunks, hash)?;
+    crate::odb::write_atomically(path, &out)
+}
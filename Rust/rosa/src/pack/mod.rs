@@ -0,0 +1,394 @@
+//! Packfiles: many objects compressed into one `.pack`, located through its
+//! `.idx`.
+
+pub mod bitmap;
+mod cache;
+pub mod delta;
+pub mod ewah;
+mod index;
+pub mod midx;
+pub mod write;
+
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use flate2::read::ZlibDecoder;
+
+use crate::error::{Error, Result};
+use crate::object::{HashAlgorithm, ObjectId, ObjectKind, MAX_HASH_LEN};
+use crate::odb::RawStream;
+
+pub use bitmap::PackBitmap;
+pub use cache::{DeltaBaseCache, DEFAULT_CACHE_LIMIT};
+pub(crate) use index::{be32, be64};
+pub use index::{IndexEntry, PackIndex};
+pub use midx::{MultiPackIndex, MIDX_FILE_NAME};
+pub use write::{
+    name_hash, write_pack, write_pack_files, PackObject, PackOptions, WrittenPack,
+    DEFAULT_BIG_FILE_THRESHOLD,
+};
+
+const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+const PACK_HEADER_LEN: u64 = 12;
+/// The most reserved up front for an entry or a delta's result. Their sizes
+/// are read from the pack and only checked once the data has been, so a
+/// damaged one must not get to ask for terabytes first.
+const MAX_RESERVE: usize = 1 << 24;
+
+/// What a pack entry stores, as encoded in the type bits of its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    Object(ObjectKind),
+    /// A delta against the entry at the given absolute offset.
+    OfsDelta(u64),
+    /// A delta against the object with the given id.
+    RefDelta(ObjectId),
+}
+
+/// A decoded pack entry header.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryHeader {
+    pub kind: EntryKind,
+    /// Inflated size of the entry data (the delta itself for deltas).
+    pub size: u64,
+    /// Where the zlib stream starts.
+    pub data_offset: u64,
+}
+
+#[derive(Debug)]
+pub struct Pack {
+    path: PathBuf,
+    index: PackIndex,
+    file: File,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+impl Pack {
+    /// Opens a pack from the path of its `.idx` file.
+    pub fn open(idx_path: &Path, hash: HashAlgorithm) -> Result<Self> {
+        let index = PackIndex::open(idx_path, hash)?;
+        let path = idx_path.with_extension("pack");
+        let file = File::open(&path)?;
+
+        let mut header = [0u8; PACK_HEADER_LEN as usize];
+        read_exact_at(&file, &mut header, 0)?;
+        let bad = |reason: &str| Error::parse("pack", format!("{}: {reason}", path.display()));
+        if &header[..4] != PACK_SIGNATURE {
+            return Err(bad("bad signature"));
+        }
+        if !matches!(index::be32(&header, 4), 2 | 3) {
+            return Err(bad("unsupported pack version"));
+        }
+        if index::be32(&header, 8) as usize != index.len() {
+            return Err(bad("object count does not match its index"));
+        }
+        let len = file.metadata()?.len();
+        let trailer_len = hash.raw_len() as u64;
+        if len < PACK_HEADER_LEN + trailer_len {
+            return Err(bad("too short for its trailer"));
+        }
+        let mut trailer = vec![0u8; hash.raw_len()];
+        read_exact_at(&file, &mut trailer, len - trailer_len)?;
+        if trailer != index.pack_checksum() {
+            return Err(bad("checksum does not match its index"));
+        }
+
+        Ok(Pack {
+            path,
+            index,
+            file,
+            cache: RefCell::default(),
+        })
+    }
+
+    /// Bounds the memory used to keep reconstructed delta bases around.
+    pub fn set_cache_limit(&self, limit: usize) {
+        self.cache.borrow_mut().set_limit(limit);
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.index.position(id).is_some()
+    }
+
+    /// Reads an object from this pack, returning `None` if it is not here.
+    pub fn read(&self, id: &ObjectId) -> Result<Option<(ObjectKind, Vec<u8>)>> {
+        match self.index.lookup(id) {
+            Some(offset) => self.read_at(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the object stored at `offset`, resolving delta chains.
+    ///
+    /// The chain is first walked down to a full object (or a cached base),
+    /// then the deltas are applied on the way back up, so arbitrarily long
+    /// chains cost neither recursion depth nor repeated inflation of the
+    /// bases that end up in the cache.
+    pub fn read_at(&self, offset: u64) -> Result<(ObjectKind, Vec<u8>)> {
+        let mut chain: Vec<(u64, EntryHeader)> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut cursor = offset;
+
+        let (kind, mut data): (ObjectKind, Rc<[u8]>) = loop {
+            if let Some(hit) = self.cache.borrow_mut().get(cursor) {
+                break hit;
+            }
+            if !seen.insert(cursor) {
+                return Err(Error::parse(
+                    "pack",
+                    format!("delta chain at offset {offset} loops"),
+                ));
+            }
+
+            let header = self.entry_header(cursor)?;
+            match header.kind {
+                EntryKind::Object(kind) => break (kind, self.inflate(&header)?.into()),
+                EntryKind::OfsDelta(base) => {
+                    chain.push((cursor, header));
+                    cursor = base;
+                }
+                EntryKind::RefDelta(base) => {
+                    chain.push((cursor, header));
+                    cursor = self.index.lookup(&base).ok_or_else(|| {
+                        Error::parse("pack", format!("delta base {base} is not in this pack"))
+                    })?;
+                }
+            }
+        };
+
+        let mut base_offset = cursor;
+        while let Some((delta_offset, header)) = chain.pop() {
+            self.cache
+                .borrow_mut()
+                .insert(base_offset, kind, Rc::clone(&data));
+            data = delta::apply(&data, &self.inflate(&header)?)?.into();
+            base_offset = delta_offset;
+        }
+
+        Ok((kind, data.to_vec()))
+    }
+
+    /// The kind and size of the object at `offset`, without reconstructing
+    /// it: deltas only have their header inflated and their chain followed
+    /// down to the base to learn its kind.
+    pub fn read_header_at(&self, offset: u64) -> Result<(ObjectKind, u64)> {
+        let header = self.entry_header(offset)?;
+        let size = match header.kind {
+            EntryKind::Object(kind) => return Ok((kind, header.size)),
+            _ => self.delta_result_size(&header)?,
+        };
+
+        let mut seen = HashSet::new();
+        let mut cursor = header;
+        loop {
+            let base = match cursor.kind {
+                EntryKind::Object(kind) => return Ok((kind, size)),
+                EntryKind::OfsDelta(base) => base,
+                EntryKind::RefDelta(base) => self.index.lookup(&base).ok_or_else(|| {
+                    Error::parse("pack", format!("delta base {base} is not in this pack"))
+                })?,
+            };
+            if let Some((kind, _)) = self.cache.borrow_mut().get(base) {
+                return Ok((kind, size));
+            }
+            if !seen.insert(base) {
+                return Err(Error::parse(
+                    "pack",
+                    format!("delta chain at offset {offset} loops"),
+                ));
+            }
+            cursor = self.entry_header(base)?;
+        }
+    }
+
+    fn delta_result_size(&self, header: &EntryHeader) -> Result<u64> {
+        // Two sizes of at most ten bytes each open every delta.
+        let mut prefix = Vec::with_capacity(20);
+        ZlibDecoder::new(self.reader_at(header.data_offset))
+            .take(20)
+            .read_to_end(&mut prefix)?;
+        let mut pos = 0;
+        delta::read_size(&prefix, &mut pos)
+            .and_then(|_| delta::read_size(&prefix, &mut pos))
+            .ok_or_else(|| Error::parse("delta", "truncated header"))
+    }
+
+    /// Decodes the variable-length header of the entry at `offset`.
+    pub fn entry_header(&self, offset: u64) -> Result<EntryHeader> {
+        // Type and size, then at most a 10-byte offset or a raw id.
+        let mut buf = [0u8; 16 + MAX_HASH_LEN];
+        let n = read_up_to(&self.file, &mut buf, offset)?;
+        let buf = &buf[..n];
+        let truncated = || Error::parse("pack", format!("truncated entry at offset {offset}"));
+        let overflow = || Error::parse("pack", format!("entry at offset {offset} overflows"));
+
+        let mut pos = 0;
+        let mut byte = *buf.first().ok_or_else(truncated)?;
+        let type_bits = (byte >> 4) & 0b111;
+        let mut size = u64::from(byte & 0x0f);
+        let mut shift = 4u32;
+        while byte & 0x80 != 0 {
+            pos += 1;
+            byte = *buf.get(pos).ok_or_else(truncated)?;
+            // Bits shifted past the top would be silently dropped.
+            let bits = u64::from(byte & 0x7f);
+            let shifted = bits.checked_shl(shift).filter(|s| s >> shift == bits);
+            size |= shifted.ok_or_else(overflow)?;
+            shift += 7;
+        }
+        pos += 1;
+
+        let kind = match type_bits {
+            1 => EntryKind::Object(ObjectKind::Commit),
+            2 => EntryKind::Object(ObjectKind::Tree),
+            3 => EntryKind::Object(ObjectKind::Blob),
+            4 => EntryKind::Object(ObjectKind::Tag),
+            6 => {
+                // Big-endian base-128 where each continuation adds one, so
+                // that every offset has exactly one encoding.
+                let mut byte = *buf.get(pos).ok_or_else(truncated)?;
+                let mut distance = u64::from(byte & 0x7f);
+                while byte & 0x80 != 0 {
+                    pos += 1;
+                    byte = *buf.get(pos).ok_or_else(truncated)?;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|d| d.checked_mul(1 << 7))
+                        .ok_or_else(overflow)?
+                        | u64::from(byte & 0x7f);
+                }
+                pos += 1;
+                let base = offset
+                    .checked_sub(distance)
+                    .filter(|&b| b >= PACK_HEADER_LEN);
+                EntryKind::OfsDelta(base.ok_or_else(|| {
+                    Error::parse("pack", format!("bad delta base offset at {offset}"))
+                })?)
+            }
+            7 => {
+                let len = self.index.hash().raw_len();
+                let raw = buf.get(pos..pos + len).ok_or_else(truncated)?;
+                pos += len;
+                EntryKind::RefDelta(ObjectId::from_bytes(raw)?)
+            }
+            other => {
+                return Err(Error::parse(
+                    "pack",
+                    format!("unknown entry type {other} at offset {offset}"),
+                ))
+            }
+        };
+
+        Ok(EntryHeader {
+            kind,
+            size,
+            data_offset: offset + pos as u64,
+        })
+    }
+
+    /// Inflates the zlib stream of an entry.
+    pub fn inflate(&self, header: &EntryHeader) -> Result<Vec<u8>> {
+        let size = usize::try_from(header.size)
+            .map_err(|_| Error::parse("pack", "entry too large for memory"))?;
+        let mut out = Vec::with_capacity(size.min(MAX_RESERVE));
+        ZlibDecoder::new(self.reader_at(header.data_offset))
+            .take(header.size)
+            .read_to_end(&mut out)?;
+        if out.len() != size {
+            return Err(Error::parse(
+                "pack",
+                format!(
+                    "entry at offset {} inflated to the wrong size",
+                    header.data_offset
+                ),
+            ));
+        }
+        Ok(out)
+    }
+
+    /// Opens the object at `offset` for reading a chunk at a time, returning
+    /// its kind and size. Whole objects are inflated as they are read, which
+    /// is what keeps large blobs (stored undeltified) out of memory; deltas
+    /// still have to be resolved in memory first.
+    pub fn open_at(&self, offset: u64) -> Result<RawStream> {
+        let header = self.entry_header(offset)?;
+        match header.kind {
+            EntryKind::Object(kind) => {
+                let reader = FileReader {
+                    file: self.file.try_clone()?,
+                    pos: header.data_offset,
+                };
+                let stream = ZlibDecoder::new(reader).take(header.size);
+                Ok((kind, header.size, Box::new(stream)))
+            }
+            EntryKind::OfsDelta(_) | EntryKind::RefDelta(_) => {
+                let (kind, data) = self.read_at(offset)?;
+                Ok((kind, data.len() as u64, Box::new(io::Cursor::new(data))))
+            }
+        }
+    }
+
+    fn reader_at(&self, offset: u64) -> FileReader<&File> {
+        FileReader {
+            file: &self.file,
+            pos: offset,
+        }
+    }
+}
+
+/// A `Read` over a shared file starting at a fixed position, so several
+/// readers can use the same handle without seeking it.
+struct FileReader<F> {
+    file: F,
+    pos: u64,
+}
+
+impl<F: Borrow<File>> Read for FileReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = read_at(self.file.borrow(), buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// Fills as much of `buf` as the file allows, stopping early only at EOF.
+fn read_up_to(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match read_at(file, &mut buf[filled..], offset + filled as u64)? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    if read_up_to(file, buf, offset)? < buf.len() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,425 @@
+//! Producing packfiles.
+//!
+//! Objects are sorted by kind, by a hash of the path they were found at and
+//! by decreasing size, then each one is tried as a delta against the few
+//! objects before it in that order (the "window"), as git's pack-objects
+//! does. Deltas are stored as OFS_DELTA entries, so bases are always written
+//! before the objects that depend on them.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use super::bitmap;
+use super::index::{self, IndexEntry};
+use super::{delta, PACK_SIGNATURE};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::object::{copy_exact, hex, HashAlgorithm, Hasher, ObjectId, ObjectKind};
+use crate::odb::ObjectDatabase;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PackOptions {
+    /// How many preceding objects are tried as delta bases.
+    pub window: usize,
+    /// Longest delta chain allowed.
+    pub depth: usize,
+    /// Objects larger than this are stored whole, without being read into
+    /// memory to look for deltas (`core.bigFileThreshold`).
+    pub big_file_threshold: u64,
+    /// Also write a reachability bitmap (see [`bitmap`]) next to the pack.
+    pub write_bitmap: bool,
+}
+
+/// Git's default for `core.bigFileThreshold`.
+pub const DEFAULT_BIG_FILE_THRESHOLD: u64 = 512 << 20;
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        PackOptions {
+            window: 10,
+            depth: 50,
+            big_file_threshold: DEFAULT_BIG_FILE_THRESHOLD,
+            write_bitmap: false,
+        }
+    }
+}
+
+impl PackOptions {
+    /// The defaults, adjusted by the repository's configuration.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut options = PackOptions::default();
+        if let Some(threshold) = config.get_int("core.bigFileThreshold")? {
+            options.big_file_threshold = threshold.try_into().unwrap_or(0);
+        }
+        Ok(options)
+    }
+}
+
+/// An object to pack, with the hash of the path it was found at (see
+/// [`name_hash`]) so similar files end up next to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackObject {
+    pub id: ObjectId,
+    pub name_hash: u32,
+}
+
+/// Git's path hash: mostly the last sixteen non-space characters, so files
+/// with the same name in different directories sort together.
+pub fn name_hash(path: &[u8]) -> u32 {
+    path.iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .fold(0u32, |hash, b| (hash >> 2).wrapping_add(u32::from(b) << 24))
+}
+
+/// A pack on disk and its index.
+#[derive(Debug)]
+pub struct WrittenPack {
+    pub checksum: Vec<u8>,
+    pub pack_path: PathBuf,
+    pub index_path: PathBuf,
+    /// Set when a bitmap was asked for and could be written: it cannot be
+    /// when objects reachable from the packed commits were left out.
+    pub bitmap_path: Option<PathBuf>,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl WrittenPack {
+    pub fn name(&self) -> String {
+        hex(&self.checksum)
+    }
+}
+
+/// Writes `objects` as `<base_name>-<checksum>.pack` plus its `.idx`; the
+/// object store expects a base name of `objects/pack/pack`.
+pub fn write_pack_files(
+    odb: &ObjectDatabase,
+    objects: &[PackObject],
+    options: &PackOptions,
+    base_name: &Path,
+) -> Result<WrittenPack> {
+    let dir = match base_name.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = base_name
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| Error::Usage(format!("bad pack base name {}", base_name.display())))?;
+    fs::create_dir_all(dir)?;
+    let tmp = dir.join(format!("tmp_pack_{}", std::process::id()));
+
+    let written = (|| {
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        let (mut entries, checksum) = write_pack(odb, objects, options, &mut file)?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        let base = dir.join(format!("{prefix}-{}", hex(&checksum)));
+        let pack_path = base.with_extension("pack");
+        let index_path = base.with_extension("idx");
+        fs::rename(&tmp, &pack_path)?;
+        index::write(&index_path, &mut entries, &checksum, odb.hash())?;
+
+        let mut bitmap_path = None;
+        if options.write_bitmap {
+            let path = bitmap::bitmap_path(&pack_path);
+            let name_hashes: HashMap<ObjectId, u32> =
+                objects.iter().map(|o| (o.id, o.name_hash)).collect();
+            if bitmap::write(odb, &path, &entries, &name_hashes, &checksum, odb.hash())? {
+                bitmap_path = Some(path);
+            }
+        }
+        Ok(WrittenPack {
+            checksum,
+            pack_path,
+            index_path,
+            bitmap_path,
+            entries,
+        })
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+/// Streams a pack of `objects` to `out`, returning the index entries and
+/// the trailing checksum.
+pub fn write_pack(
+    odb: &ObjectDatabase,
+    objects: &[PackObject],
+    options: &PackOptions,
+    out: impl Write,
+) -> Result<(Vec<IndexEntry>, Vec<u8>)> {
+    // The caller's (recency) order decides the layout of the pack.
+    let mut sorted = objects.to_vec();
+    sorted.sort_by_key(|o| o.id);
+    sorted.dedup_by_key(|o| o.id);
+    let mut plan = Plan::new(odb, &sorted)?;
+    plan.find_deltas(odb, options)?;
+
+    let mut out = HashingWriter::new(out, odb.hash());
+    let count = u32::try_from(plan.entries.len())
+        .map_err(|_| Error::parse("pack", "too many objects for one pack"))?;
+    out.write_all(PACK_SIGNATURE)?;
+    out.write_all(&2u32.to_be_bytes())?;
+    out.write_all(&count.to_be_bytes())?;
+
+    let mut offsets: Vec<Option<u64>> = vec![None; plan.entries.len()];
+    let mut index_entries = Vec::with_capacity(plan.entries.len());
+    for object in objects {
+        let start = plan.position(&object.id);
+        // Write the delta chain below this object first, base-most first.
+        let mut chain = vec![start];
+        while let Some(base) = plan.entries[*chain.last().expect("non-empty")].base() {
+            if offsets[base].is_some() {
+                break;
+            }
+            chain.push(base);
+        }
+        for n in chain.into_iter().rev() {
+            if offsets[n].is_some() {
+                continue;
+            }
+            let offset = out.written;
+            let crc = plan.write_entry(odb, n, offset, &offsets, &mut out)?;
+            offsets[n] = Some(offset);
+            index_entries.push(IndexEntry {
+                id: plan.entries[n].id,
+                crc32: crc,
+                offset,
+            });
+        }
+    }
+
+    let HashingWriter {
+        mut inner, hasher, ..
+    } = out;
+    let checksum = hasher.finalize()?;
+    inner.write_all(&checksum)?;
+    inner.flush()?;
+    Ok((index_entries, checksum))
+}
+
+#[derive(Debug)]
+struct PlannedEntry {
+    id: ObjectId,
+    kind: ObjectKind,
+    size: u64,
+    name_hash: u32,
+    /// Chosen base (index into the plan) and the delta against it.
+    delta: Option<(usize, Vec<u8>)>,
+    depth: usize,
+}
+
+impl PlannedEntry {
+    fn base(&self) -> Option<usize> {
+        self.delta.as_ref().map(|(base, _)| *base)
+    }
+}
+
+struct Plan {
+    /// Sorted by object id, so entries can be found by binary search.
+    entries: Vec<PlannedEntry>,
+}
+
+impl Plan {
+    fn new(odb: &ObjectDatabase, objects: &[PackObject]) -> Result<Self> {
+        let entries = objects
+            .iter()
+            .map(|o| {
+                let (kind, size) = odb.read_header(&o.id)?;
+                Ok(PlannedEntry {
+                    id: o.id,
+                    kind,
+                    size,
+                    name_hash: o.name_hash,
+                    delta: None,
+                    depth: 0,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Plan { entries })
+    }
+
+    fn position(&self, id: &ObjectId) -> usize {
+        self.entries
+            .binary_search_by_key(id, |e| e.id)
+            .expect("every packed object is planned")
+    }
+
+    fn find_deltas(&mut self, odb: &ObjectDatabase, options: &PackOptions) -> Result<()> {
+        if options.window == 0 || options.depth == 0 {
+            return Ok(());
+        }
+
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&n| {
+            let e = &self.entries[n];
+            (kind_code(e.kind), e.name_hash, Reverse(e.size))
+        });
+
+        let mut window: VecDeque<(usize, Vec<u8>)> = VecDeque::new();
+        for n in order {
+            if self.entries[n].size > options.big_file_threshold {
+                continue;
+            }
+            let (kind, data) = odb.read_raw(&self.entries[n].id)?;
+
+            let mut best: Option<(usize, Vec<u8>)> = None;
+            for (base, base_data) in window.iter().rev() {
+                let base_entry = &self.entries[*base];
+                if base_entry.kind != kind || base_entry.depth >= options.depth {
+                    continue;
+                }
+                // A base much smaller than the target rarely pays off.
+                if (base_data.len() as u64) < self.entries[n].size / 32 {
+                    continue;
+                }
+                let limit = match &best {
+                    Some((_, d)) => d.len().saturating_sub(1),
+                    None => (data.len() / 2).saturating_sub(odb.hash().raw_len()),
+                };
+                if let Some(d) = delta::create(base_data, &data, limit) {
+                    best = Some((*base, d));
+                }
+            }
+
+            if let Some((base, d)) = best {
+                self.entries[n].depth = self.entries[base].depth + 1;
+                self.entries[n].delta = Some((base, d));
+            }
+
+            window.push_back((n, data));
+            if window.len() > options.window {
+                window.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes entry `n` at `offset`, returning the CRC32 of its bytes.
+    fn write_entry(
+        &self,
+        odb: &ObjectDatabase,
+        n: usize,
+        offset: u64,
+        offsets: &[Option<u64>],
+        out: &mut impl Write,
+    ) -> Result<u32> {
+        let entry = &self.entries[n];
+        let mut head = Vec::new();
+        // Whole objects are streamed from the object store, so that big
+        // blobs pass through without ever being held in memory.
+        let (payload, size): (Box<dyn Read>, u64) = match &entry.delta {
+            Some((base, d)) => {
+                let base_offset = offsets[*base].expect("bases are written first");
+                encode_entry_header(&mut head, 6, d.len() as u64);
+                encode_ofs_distance(&mut head, offset - base_offset);
+                (Box::new(d.as_slice()), d.len() as u64)
+            }
+            None => {
+                let stream = odb.open_stream(&entry.id)?;
+                encode_entry_header(&mut head, kind_code(stream.kind()), stream.size());
+                let size = stream.size();
+                (Box::new(stream), size)
+            }
+        };
+
+        let mut out = CrcWriter {
+            inner: out,
+            crc: crc32fast::Hasher::new(),
+        };
+        out.write_all(&head)?;
+        let mut encoder = ZlibEncoder::new(out, Compression::default());
+        copy_exact(payload, size, |chunk| Ok(encoder.write_all(chunk)?))?;
+        Ok(encoder.finish()?.crc.finalize())
+    }
+}
+
+fn kind_code(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Commit => 1,
+        ObjectKind::Tree => 2,
+        ObjectKind::Blob => 3,
+        ObjectKind::Tag => 4,
+    }
+}
+
+fn encode_entry_header(out: &mut Vec<u8>, type_code: u8, mut size: u64) {
+    let mut byte = (type_code << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size != 0 {
+        out.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    out.push(byte);
+}
+
+/// Inverse of the OFS_DELTA distance decoding in [`super::Pack::entry_header`].
+fn encode_ofs_distance(out: &mut Vec<u8>, mut distance: u64) {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    out.extend_from_slice(&bytes);
+}
+
+/// Computes the CRC32 of everything written through it.
+struct CrcWriter<W> {
+    inner: W,
+    crc: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hashes and counts everything written through it.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Hasher,
+    written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W, hash: HashAlgorithm) -> Self {
+        HashingWriter {
+            inner,
+            hasher: hash.hasher(),
+            written: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,122 @@
+//! Paths as git stores them: byte strings with `/` separators, in whatever
+//! encoding they were committed. They only become `OsStr`s at the
+//! filesystem boundary, and are quoted when shown.
+
+use std::borrow::Cow;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// The bytes of a filesystem name, or `None` where names are not bytes
+/// and this one is not Unicode.
+pub fn from_os_str(name: &OsStr) -> Option<Cow<'_, [u8]>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(Cow::Borrowed(name.as_bytes()))
+    }
+    #[cfg(not(unix))]
+    {
+        name.to_str().map(|name| Cow::Borrowed(name.as_bytes()))
+    }
+}
+
+/// The filesystem name for `name`.
+pub fn to_os_str(name: &[u8]) -> Result<Cow<'_, OsStr>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(Cow::Borrowed(OsStr::from_bytes(name)))
+    }
+    #[cfg(not(unix))]
+    {
+        std::str::from_utf8(name)
+            .map(|name| Cow::Owned(OsString::from(name)))
+            .map_err(|_| Error::Usage(format!("cannot represent path {}", quote(name))))
+    }
+}
+
+/// The `/`-separated name of `path` relative to `base`, if it is inside
+/// it and every component can be stored.
+pub fn relative(base: &Path, path: &Path) -> Option<Vec<u8>> {
+    let relative = path.strip_prefix(base).ok()?;
+    let mut out = Vec::new();
+    for part in relative {
+        if !out.is_empty() {
+            out.push(b'/');
+        }
+        out.extend_from_slice(&from_os_str(part)?);
+    }
+    Some(out)
+}
+
+/// `path` made absolute against the current directory, with `.` and `..`
+/// resolved by name alone. Unlike `fs::canonicalize` this does not follow
+/// a symlink at the end of the path, so the link itself can be staged.
+pub fn absolute(path: &Path) -> Result<PathBuf> {
+    let mut out = match path.is_absolute() {
+        true => PathBuf::new(),
+        false => fs::canonicalize(env::current_dir()?)?,
+    };
+    for part in path.components() {
+        match part {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            _ => out.push(part),
+        }
+    }
+    Ok(out)
+}
+
+/// A pathspec given on the command line.
+pub fn from_arg(arg: &OsString) -> Result<Vec<u8>> {
+    from_os_str(arg).map(Cow::into_owned).ok_or_else(|| {
+        Error::Usage(format!(
+            "pathspec is not Unicode: {}",
+            arg.to_string_lossy()
+        ))
+    })
+}
+
+/// `name` below `dir` (empty for the top level).
+pub fn join(dir: &[u8], name: &[u8]) -> Vec<u8> {
+    let mut out = dir.to_vec();
+    if !out.is_empty() {
+        out.push(b'/');
+    }
+    out.extend_from_slice(name);
+    out
+}
+
+/// `path` as git shows it with `core.quotePath` on: as is when it is plain
+/// printable ASCII, otherwise within double quotes with C-style escapes
+/// and the other bytes in octal.
+pub fn quote(path: &[u8]) -> Cow<'_, str> {
+    let plain = |b: u8| (0x20..0x7f).contains(&b) && b != b'"' && b != b'\\';
+    if path.iter().all(|&b| plain(b)) {
+        return String::from_utf8_lossy(path);
+    }
+    let mut out = String::from("\"");
+    for &b in path {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\x07' => out.push_str("\\a"),
+            b'\x08' => out.push_str("\\b"),
+            b'\t' => out.push_str("\\t"),
+            b'\n' => out.push_str("\\n"),
+            b'\x0b' => out.push_str("\\v"),
+            b'\x0c' => out.push_str("\\f"),
+            b'\r' => out.push_str("\\r"),
+            _ if plain(b) => out.push(char::from(b)),
+            _ => out.push_str(&format!("\\{b:03o}")),
+        }
+    }
+    out.push('"');
+    Cow::Owned(out)
+}
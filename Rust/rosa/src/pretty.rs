@@ -0,0 +1,690 @@
+//! Showing commits: the built-in formats of `--pretty`, the placeholders of
+//! `--format`, and the ref names commits are decorated with.
+//!
+//! [`Template`] only cuts a format string up and fills it in; what each
+//! placeholder stands for comes from a [`Fields`] implementation, so that
+//! other kinds of things than commits can be shown through it.
+
+use std::collections::HashMap;
+
+use crate::date::{self, DateMode};
+use crate::error::{Error, Result};
+use crate::mailmap::Mailmap;
+use crate::notes::Notes;
+use crate::object::{Commit, ObjectId, ObjectKind, Signature};
+use crate::refs::{self, RefTarget};
+use crate::repository::Repository;
+use crate::revision;
+
+/// A format string cut into literal text and placeholders.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Literal(Vec<u8>),
+    Field {
+        /// The placeholder without `%` or modifier: `an`, `(refname)`.
+        name: String,
+        modifier: Option<Modifier>,
+        /// The placeholder as written, shown as it is when unknown.
+        text: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Modifier {
+    /// `%+x`: a line break before the value, unless it is empty.
+    AddLine,
+    /// `%-x`: the line breaks right before dropped, if the value is empty.
+    DropLines,
+    /// `% x`: a space before the value, unless it is empty.
+    AddSpace,
+}
+
+/// What the placeholders of a [`Template`] stand for.
+pub trait Fields {
+    /// Appends the value of the placeholder `name` to `out`, or returns
+    /// `false` if there is no such placeholder.
+    fn expand(&self, name: &str, out: &mut Vec<u8>) -> Result<bool>;
+}
+
+impl Template {
+    /// Cuts `format` up. `%%`, `%n` and `%x<hex><hex>` stand for a `%`, a
+    /// line break and a byte; any other `%` starts a placeholder, which is
+    /// a name in parentheses, `C` and a color, two letters after `a`, `c`
+    /// or `g`, or a single letter.
+    pub fn parse(format: &str) -> Self {
+        let mut pieces = Vec::new();
+        let mut literal = Vec::new();
+        let mut rest = format;
+        while let Some(at) = rest.find('%') {
+            literal.extend_from_slice(&rest.as_bytes()[..at]);
+            let after = &rest[at + 1..];
+            let byte = after
+                .get(1..3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            let (used, modifier) = match after.as_bytes().first() {
+                Some(b'%') => {
+                    literal.push(b'%');
+                    rest = &after[1..];
+                    continue;
+                }
+                Some(b'n') => {
+                    literal.push(b'\n');
+                    rest = &after[1..];
+                    continue;
+                }
+                Some(b'x') if byte.is_some() => {
+                    literal.extend(byte);
+                    rest = &after[3..];
+                    continue;
+                }
+                Some(b'+') => (1, Some(Modifier::AddLine)),
+                Some(b'-') => (1, Some(Modifier::DropLines)),
+                Some(b' ') => (1, Some(Modifier::AddSpace)),
+                _ => (0, None),
+            };
+            let Some(len) = name_len(&after[used..]) else {
+                literal.push(b'%');
+                rest = after;
+                continue;
+            };
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            let end = used + len;
+            pieces.push(Piece::Field {
+                name: after[used..end].to_owned(),
+                modifier,
+                text: rest[at..at + 1 + end].to_owned(),
+            });
+            rest = &after[end..];
+        }
+        literal.extend_from_slice(rest.as_bytes());
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Template { pieces }
+    }
+
+    /// Whether the template gives nothing at all, as `--format=` does.
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Whether the placeholder `name` appears in the template.
+    pub fn uses(&self, name: &str) -> bool {
+        self.pieces
+            .iter()
+            .any(|piece| matches!(piece, Piece::Field { name: n, .. } if n == name))
+    }
+
+    /// Appends the template to `out`, its placeholders filled in from
+    /// `fields`. Unknown placeholders are kept as written.
+    pub fn expand(&self, fields: &impl Fields, out: &mut Vec<u8>) -> Result<()> {
+        for piece in &self.pieces {
+            let (name, modifier, text) = match piece {
+                Piece::Literal(bytes) => {
+                    out.extend_from_slice(bytes);
+                    continue;
+                }
+                Piece::Field {
+                    name,
+                    modifier,
+                    text,
+                } => (name, modifier, text),
+            };
+            let mut value = Vec::new();
+            if !fields.expand(name, &mut value)? {
+                out.extend_from_slice(text.as_bytes());
+                continue;
+            }
+            match modifier {
+                Some(Modifier::AddLine) if !value.is_empty() => out.push(b'\n'),
+                Some(Modifier::AddSpace) if !value.is_empty() => out.push(b' '),
+                Some(Modifier::DropLines) if value.is_empty() => {
+                    while out.last() == Some(&b'\n') {
+                        out.pop();
+                    }
+                }
+                _ => {}
+            }
+            out.extend_from_slice(&value);
+        }
+        Ok(())
+    }
+}
+
+/// How long the placeholder name at the start of `text` is.
+fn name_len(text: &str) -> Option<usize> {
+    const COLORS: [&str; 4] = ["red", "green", "blue", "reset"];
+    let first = text.chars().next()?;
+    Some(match first {
+        '(' => text.find(')')? + 1,
+        'C' if text[1..].starts_with('(') => text.find(')')? + 1,
+        'C' => {
+            1 + COLORS
+                .iter()
+                .find(|color| text[1..].starts_with(*color))
+                .map_or(0, |color| color.len())
+        }
+        'a' | 'c' | 'g' => 1 + text[1..].chars().next()?.len_utf8(),
+        _ => first.len_utf8(),
+    })
+}
+
+/// How `log` shows each commit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// `<id> <subject>` on one line.
+    Oneline,
+    /// The id, author and subject paragraph.
+    Short,
+    /// The id, author, date and whole message.
+    #[default]
+    Medium,
+    /// The id, author, committer and whole message.
+    Full,
+    /// The id, author and committer with their dates, and whole message.
+    Fuller,
+    /// A template; each entry is ended by a line break if `terminated`,
+    /// and separated from the next one by a line break otherwise.
+    Template {
+        template: Template,
+        terminated: bool,
+    },
+}
+
+impl Format {
+    /// The format `--pretty=<spec>` asks for: a built-in one by name, or a
+    /// template after `format:` or `tformat:`. Nothing at all, or anything
+    /// else with a `%` in it, is taken as a `tformat:`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        Ok(match spec {
+            "oneline" => Format::Oneline,
+            "short" => Format::Short,
+            "medium" => Format::Medium,
+            "full" => Format::Full,
+            "fuller" => Format::Fuller,
+            _ => {
+                let (template, terminated) = if let Some(t) = spec.strip_prefix("format:") {
+                    (t, false)
+                } else if let Some(t) = spec.strip_prefix("tformat:") {
+                    (t, true)
+                } else if spec.is_empty() || spec.contains('%') {
+                    (spec, true)
+                } else {
+                    return Err(Error::Usage(format!("invalid --pretty format: {spec}")));
+                };
+                Format::Template {
+                    template: Template::parse(template),
+                    terminated,
+                }
+            }
+        })
+    }
+
+    /// Whether every entry ends with a line break, rather than entries
+    /// being separated by one.
+    pub fn terminated(&self) -> bool {
+        match self {
+            Format::Oneline => true,
+            Format::Template { terminated, .. } => *terminated,
+            _ => false,
+        }
+    }
+
+    /// Whether entries are nothing at all, without even a line break to
+    /// end them: `--format=`.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Format::Template { template, .. } if template.is_empty())
+    }
+
+    /// Whether `%d` or `%D` ask for decorations.
+    pub fn uses_decorations(&self) -> bool {
+        self.uses_any(&["d", "D"])
+    }
+
+    /// Whether `%aN`, `%cE` and the like ask for the mailmap.
+    pub fn uses_mailmap(&self) -> bool {
+        self.uses_any(&["aN", "aE", "aL", "cN", "cE", "cL"])
+    }
+
+    /// Whether `%N` asks for notes.
+    pub fn uses_notes(&self) -> bool {
+        self.uses_any(&["N"])
+    }
+
+    fn uses_any(&self, names: &[&str]) -> bool {
+        matches!(self, Format::Template { template, .. } if names.iter().any(|name| template.uses(name)))
+    }
+}
+
+/// Shows commits in one [`Format`].
+pub struct Printer<'r> {
+    pub repo: &'r Repository,
+    pub format: Format,
+    pub dates: DateMode,
+    /// How many hex digits abbreviated ids start from.
+    pub abbrev: usize,
+    /// Whether the built-in formats abbreviate the id of the commit shown.
+    pub abbrev_commit: bool,
+    /// Whether the built-in formats show decorations after ids; `%d` and
+    /// `%D` show them regardless.
+    pub decorate: bool,
+    pub decorations: Decorations,
+    /// What `%aN`, `%aE` and the like map names and emails through.
+    pub mailmap: Mailmap,
+    /// What `%N` shows.
+    pub notes: Notes,
+    /// Where relative dates count back from.
+    pub now: i64,
+}
+
+impl Printer<'_> {
+    /// The entry for the commit `id`, without the line break that
+    /// [`Format::terminated`] formats end it with.
+    pub fn entry(&self, id: ObjectId, commit: &Commit) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        if let Format::Template { template, .. } = &self.format {
+            let fields = CommitFields {
+                printer: self,
+                id,
+                commit,
+            };
+            template.expand(&fields, &mut out)?;
+            return Ok(out);
+        }
+
+        let shown = match self.abbrev_commit {
+            true => self.abbreviate(&id)?,
+            false => id.to_hex(),
+        };
+        let decoration = match self.decorations.describe(&id) {
+            Some(names) if self.decorate => format!(" ({names})"),
+            _ => String::new(),
+        };
+        if self.format == Format::Oneline {
+            out.extend_from_slice(format!("{shown}{decoration} ").as_bytes());
+            out.extend_from_slice(&commit.subject());
+            return Ok(out);
+        }
+
+        out.extend_from_slice(format!("commit {shown}{decoration}\n").as_bytes());
+        if commit.parents.len() > 1 {
+            out.extend_from_slice(b"Merge:");
+            for parent in &commit.parents {
+                out.extend_from_slice(format!(" {}", self.abbreviate(parent)?).as_bytes());
+            }
+            out.push(b'\n');
+        }
+        let (author, committer) = (&commit.author, &commit.committer);
+        match self.format {
+            Format::Short => self.person(&mut out, "Author: ", author, None),
+            Format::Medium => self.person(&mut out, "Author: ", author, Some("Date:   ")),
+            Format::Full => {
+                self.person(&mut out, "Author: ", author, None);
+                self.person(&mut out, "Commit: ", committer, None);
+            }
+            _ => {
+                self.person(&mut out, "Author:     ", author, Some("AuthorDate: "));
+                self.person(&mut out, "Commit:     ", committer, Some("CommitDate: "));
+            }
+        }
+        out.push(b'\n');
+
+        let message = commit.message.as_deref().unwrap_or_default();
+        let lines = message
+            .split(|&b| b == b'\n')
+            .map(|line| line.trim_ascii_end())
+            .skip_while(|line| line.is_empty());
+        for line in lines {
+            if self.format == Format::Short && line.is_empty() {
+                break;
+            }
+            out.extend_from_slice(b"    ");
+            match self.format {
+                Format::Short => out.extend_from_slice(line),
+                _ => expand_tabs(line, &mut out),
+            }
+            out.push(b'\n');
+        }
+        let end = out.trim_ascii_end().len();
+        out.truncate(end);
+        out.push(b'\n');
+        Ok(out)
+    }
+
+    /// Writes `<label><name> <<email>>`, and a line with the date after
+    /// `date_label` if given.
+    fn person(&self, out: &mut Vec<u8>, label: &str, who: &Signature, date_label: Option<&str>) {
+        out.extend_from_slice(label.as_bytes());
+        out.extend_from_slice(&who.name);
+        out.extend_from_slice(b" <");
+        out.extend_from_slice(&who.email);
+        out.extend_from_slice(b">\n");
+        if let Some(date_label) = date_label {
+            let shown = date::format(who.time, who.offset, self.dates, self.now);
+            out.extend_from_slice(format!("{date_label}{shown}\n").as_bytes());
+        }
+    }
+
+    fn abbreviate(&self, id: &ObjectId) -> Result<String> {
+        revision::abbreviate(self.repo, id, self.abbrev)
+    }
+}
+
+/// Appends `line` with its tabs turned into spaces up to the next multiple
+/// of eight columns, counting characters as one column each.
+fn expand_tabs(line: &[u8], out: &mut Vec<u8>) {
+    let mut column = 0;
+    for &byte in line {
+        match byte {
+            b'\t' => {
+                let width = 8 - column % 8;
+                out.resize(out.len() + width, b' ');
+                column += width;
+            }
+            _ => {
+                out.push(byte);
+                // Continuation bytes add nothing to a character.
+                if byte & 0xc0 != 0x80 {
+                    column += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A commit's placeholders, as `git log --format` knows them.
+pub struct CommitFields<'a, 'r> {
+    pub printer: &'a Printer<'r>,
+    pub id: ObjectId,
+    pub commit: &'a Commit,
+}
+
+impl Fields for CommitFields<'_, '_> {
+    fn expand(&self, name: &str, out: &mut Vec<u8>) -> Result<bool> {
+        let commit = self.commit;
+        let message = commit.message.as_deref().unwrap_or_default();
+        let ids = |ids: &[ObjectId], short: bool| -> Result<String> {
+            let shown = ids
+                .iter()
+                .map(|id| match short {
+                    true => self.printer.abbreviate(id),
+                    false => Ok(id.to_hex()),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(shown.join(" "))
+        };
+        let text = match name {
+            "H" => self.id.to_hex(),
+            "h" => self.printer.abbreviate(&self.id)?,
+            "T" => commit.tree.to_hex(),
+            "t" => self.printer.abbreviate(&commit.tree)?,
+            "P" => ids(&commit.parents, false)?,
+            "p" => ids(&commit.parents, true)?,
+            "s" => {
+                out.extend_from_slice(&commit.subject());
+                return Ok(true);
+            }
+            "f" => {
+                // Only the first line of the subject, as in git.
+                let subject = skip_blank_lines(message);
+                let end = subject.iter().position(|&b| b == b'\n');
+                sanitize(&subject[..end.unwrap_or(subject.len())], out);
+                return Ok(true);
+            }
+            "b" => {
+                out.extend_from_slice(body(message));
+                return Ok(true);
+            }
+            "B" => {
+                out.extend_from_slice(message);
+                return Ok(true);
+            }
+            "e" => {
+                out.extend_from_slice(commit.encoding.as_deref().unwrap_or_default());
+                return Ok(true);
+            }
+            "d" => self
+                .printer
+                .decorations
+                .describe(&self.id)
+                .map(|names| format!(" ({names})"))
+                .unwrap_or_default(),
+            "D" => self
+                .printer
+                .decorations
+                .describe(&self.id)
+                .unwrap_or_default(),
+            "N" => {
+                if let Some(note) = self.printer.notes.get(self.printer.repo, &self.id)? {
+                    // One line break at the end, as git shows notes.
+                    let note = note.strip_suffix(b"\n").unwrap_or(&note);
+                    if !note.is_empty() {
+                        out.extend_from_slice(note);
+                        if !note.ends_with(b"\n") {
+                            out.push(b'\n');
+                        }
+                    }
+                }
+                return Ok(true);
+            }
+            // Nothing is shown in color.
+            _ if name.starts_with('C') => String::new(),
+            _ => {
+                let mut chars = name.chars();
+                let who = match chars.next() {
+                    Some('a') => &commit.author,
+                    Some('c') => &commit.committer,
+                    _ => return Ok(false),
+                };
+                let (name, email) = match chars.clone().next() {
+                    Some('N' | 'E' | 'L') => self.printer.mailmap.map(&who.name, &who.email),
+                    _ => (&who.name[..], &who.email[..]),
+                };
+                let mode = match chars.next() {
+                    Some('n' | 'N') => {
+                        out.extend_from_slice(name);
+                        return Ok(true);
+                    }
+                    Some('e' | 'E') => {
+                        out.extend_from_slice(email);
+                        return Ok(true);
+                    }
+                    Some('l' | 'L') => {
+                        let local = email.split(|&b| b == b'@').next();
+                        out.extend_from_slice(local.unwrap_or_default());
+                        return Ok(true);
+                    }
+                    Some('d') => self.printer.dates,
+                    Some('D') => DateMode::Rfc,
+                    Some('r') => DateMode::Relative,
+                    Some('t') => DateMode::Unix,
+                    Some('i') => DateMode::Iso,
+                    Some('I') => DateMode::IsoStrict,
+                    Some('s') => DateMode::Short,
+                    _ => return Ok(false),
+                };
+                date::format(who.time, who.offset, mode, self.printer.now)
+            }
+        };
+        out.extend_from_slice(text.as_bytes());
+        Ok(true)
+    }
+}
+
+/// Appends what `%f` makes of `subject` for a file name: runs of
+/// anything but ASCII letters, digits, `.` and `_` turned into one `-`,
+/// runs of `.` into one, and neither left at the ends.
+fn sanitize(subject: &[u8], out: &mut Vec<u8>) {
+    let start = out.len();
+    // Whether a `-` is owed before the next kept byte; none before the first.
+    let mut gap = false;
+    let mut bytes = subject.iter().peekable();
+    while let Some(&b) = bytes.next() {
+        if !(b.is_ascii_alphanumeric() || b == b'.' || b == b'_') {
+            gap = out.len() > start;
+            continue;
+        }
+        if gap {
+            out.push(b'-');
+            gap = false;
+        }
+        out.push(b);
+        if b == b'.' {
+            while bytes.next_if_eq(&&b'.').is_some() {}
+        }
+    }
+    while out.len() > start && matches!(out.last(), Some(b'.' | b'-')) {
+        out.pop();
+    }
+}
+
+/// What `%b` shows of a message: all after the subject paragraph and the
+/// blank lines following it.
+fn body(message: &[u8]) -> &[u8] {
+    let mut rest = skip_blank_lines(message);
+    while !rest.is_empty() {
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(rest.len(), |at| at + 1);
+        if rest[..end].trim_ascii().is_empty() {
+            break;
+        }
+        rest = &rest[end..];
+    }
+    skip_blank_lines(rest)
+}
+
+fn skip_blank_lines(mut text: &[u8]) -> &[u8] {
+    while !text.is_empty() {
+        let end = text
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(text.len(), |at| at + 1);
+        if !text[..end].trim_ascii().is_empty() {
+            break;
+        }
+        text = &text[end..];
+    }
+    text
+}
+
+/// The ref names objects are decorated with: HEAD, branches, remote
+/// branches, tags and the stash.
+#[derive(Clone, Debug, Default)]
+pub struct Decorations {
+    names: HashMap<ObjectId, Vec<Decoration>>,
+    /// The branch HEAD is on, in full.
+    head: Option<String>,
+    /// Whether names are shown in full rather than without their
+    /// `refs/heads/`, `refs/remotes/` or `refs/tags/`.
+    full: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Decoration {
+    name: String,
+    kind: RefKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RefKind {
+    Head,
+    Branch,
+    Remote,
+    Tag,
+    Stash,
+}
+
+impl Decorations {
+    /// Reads the refs. Annotated tags decorate what they point to as well
+    /// as themselves.
+    pub fn load(repo: &Repository, full: bool) -> Result<Self> {
+        let mut decorations = Decorations {
+            full,
+            ..Decorations::default()
+        };
+        let mut refs = refs::list(repo)?;
+        if let Some(id) = refs::resolve(repo, "HEAD")? {
+            refs.push(("HEAD".to_owned(), id));
+        }
+        for (name, mut id) in refs {
+            let kind = if name == "HEAD" {
+                RefKind::Head
+            } else if name.starts_with("refs/heads/") {
+                RefKind::Branch
+            } else if name.starts_with("refs/remotes/") {
+                RefKind::Remote
+            } else if name.starts_with("refs/tags/") {
+                RefKind::Tag
+            } else if name == "refs/stash" {
+                RefKind::Stash
+            } else {
+                continue;
+            };
+            decorations.add(id, &name, kind);
+            while repo.odb().read_header(&id)?.0 == ObjectKind::Tag {
+                id = repo.odb().read(&id)?.into_tag(id)?.object;
+                decorations.add(id, &name, RefKind::Tag);
+            }
+        }
+        if let Some(RefTarget::Symbolic(branch)) = refs::read(repo, "HEAD")? {
+            decorations.head = Some(branch);
+        }
+        Ok(decorations)
+    }
+
+    fn add(&mut self, id: ObjectId, name: &str, kind: RefKind) {
+        self.names.entry(id).or_default().push(Decoration {
+            name: name.to_owned(),
+            kind,
+        });
+    }
+
+    /// The names decorating `id`, as `git log` lists them: `HEAD -> main,
+    /// tag: v1, origin/main`.
+    pub fn describe(&self, id: &ObjectId) -> Option<String> {
+        let names = self.names.get(id)?;
+        // HEAD takes the place of the branch it is on.
+        let current = names
+            .iter()
+            .any(|d| d.kind == RefKind::Head)
+            .then_some(self.head.as_ref())
+            .flatten()
+            .filter(|&head| {
+                names
+                    .iter()
+                    .any(|d| d.kind == RefKind::Branch && &d.name == head)
+            });
+        let mut shown = Vec::new();
+        // Newest first, and HEAD was read last.
+        for decoration in names.iter().rev() {
+            if decoration.kind == RefKind::Branch && Some(&decoration.name) == current {
+                continue;
+            }
+            shown.push(match (decoration.kind, current) {
+                (RefKind::Head, Some(branch)) => format!("HEAD -> {}", self.short(branch)),
+                (RefKind::Tag, _) => format!("tag: {}", self.short(&decoration.name)),
+                _ => self.short(&decoration.name).to_owned(),
+            });
+        }
+        Some(shown.join(", "))
+    }
+
+    fn short<'a>(&self, name: &'a str) -> &'a str {
+        if self.full {
+            return name;
+        }
+        ["refs/heads/", "refs/remotes/", "refs/tags/"]
+            .iter()
+            .find_map(|prefix| name.strip_prefix(prefix))
+            .unwrap_or(name)
+    }
+}
@@ -0,0 +1,226 @@
+//! Enumerating every object reachable from a set of tips.
+//!
+//! When a local pack has a reachability bitmap, the objects it covers are
+//! counted from the bitmaps of the commits met on the way, and only what
+//! lies outside the pack or above the nearest bitmapped commits is walked.
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::error::Result;
+use crate::object::{Object, ObjectId, ObjectKind};
+use crate::pack::ewah::Bitmap;
+use crate::pack::{name_hash, PackBitmap};
+use crate::path;
+use crate::repository::Repository;
+
+/// An object found by the walk, with the [`name_hash`] of the path it was
+/// reached through when it lives inside a tree (0 otherwise). Packers use
+/// it to group similar blobs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reached {
+    pub id: ObjectId,
+    pub kind: ObjectKind,
+    pub name_hash: u32,
+}
+
+/// Walks the object graph, skipping everything reachable from `exclude`.
+///
+/// Commits and tags come first, in the order they were met, followed by
+/// trees and blobs, which is also the layout git prefers inside packs.
+/// Objects counted from a bitmap come in pack order instead.
+pub fn objects(repo: &Repository, tips: &[ObjectId], exclude: &[ObjectId]) -> Result<Vec<Reached>> {
+    if let Some(bitmap) = find_bitmap(repo)? {
+        return with_bitmap(repo, &bitmap, tips, exclude);
+    }
+
+    let mut seen = HashSet::new();
+    let mut ignored = Vec::new();
+    walk(repo, exclude, &mut seen, &mut ignored)?;
+
+    let mut found = Vec::new();
+    walk(repo, tips, &mut seen, &mut found)?;
+    found.sort_by_key(|r| !matches!(r.kind, ObjectKind::Commit | ObjectKind::Tag));
+    Ok(found)
+}
+
+fn walk(
+    repo: &Repository,
+    tips: &[ObjectId],
+    seen: &mut HashSet<ObjectId>,
+    out: &mut Vec<Reached>,
+) -> Result<()> {
+    let mut pending: Vec<Pending> = tips.iter().rev().map(|id| (*id, None)).collect();
+
+    while let Some((id, path)) = pending.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let (kind, object) = visit(repo, &id)?;
+        out.push(Reached {
+            id,
+            kind,
+            name_hash: path.as_deref().map_or(0, name_hash),
+        });
+        if let Some(object) = object {
+            push_children(&object, path.as_deref(), &mut pending)?;
+        }
+    }
+    Ok(())
+}
+
+/// An object still to visit: its id and the path it was found at inside
+/// a tree.
+type Pending = (ObjectId, Option<Vec<u8>>);
+
+/// Reads what the walks need of an object. Blobs lead nowhere and may be
+/// large, whether a tree names them or they are tips (as staged blobs are
+/// for gc), so only the header is read to check the object is there;
+/// anything else is parsed.
+fn visit(repo: &Repository, id: &ObjectId) -> Result<(ObjectKind, Option<Object>)> {
+    let (kind, _) = repo.odb().read_header(id)?;
+    if kind == ObjectKind::Blob {
+        return Ok((kind, None));
+    }
+    let object = repo.odb().read(id)?;
+    Ok((object.kind(), Some(object)))
+}
+
+/// Queues what `object`, found at `path`, points to, first child last.
+fn push_children(object: &Object, path: Option<&[u8]>, pending: &mut Vec<Pending>) -> Result<()> {
+    match object {
+        Object::Commit(commit) => {
+            for &parent in commit.parents.iter().rev() {
+                pending.push((parent, None));
+            }
+            pending.push((commit.tree, Some(Vec::new())));
+        }
+        Object::Tag(tag) => pending.push((tag.object, None)),
+        Object::Tree(tree) => {
+            for entry in tree.entries.iter().rev() {
+                // Submodule commits live in another repository.
+                if entry.kind() == Some(ObjectKind::Commit) {
+                    continue;
+                }
+                let child = path::join(path.unwrap_or_default(), &entry.name);
+                pending.push((entry.id, Some(child)));
+            }
+        }
+        Object::Blob(_) => {}
+    }
+    Ok(())
+}
+
+/// The bitmap of the first local pack that has a readable one, unless
+/// `pack.useBitmaps` is off. A damaged bitmap is ignored: the walk finds
+/// the same objects without it.
+fn find_bitmap(repo: &Repository) -> Result<Option<PackBitmap>> {
+    if !repo.config().get_bool("pack.useBitmaps")?.unwrap_or(true) {
+        return Ok(None);
+    }
+    Ok(repo
+        .odb()
+        .packs()
+        .into_iter()
+        .find_map(|pack| PackBitmap::open(Rc::clone(&pack)).ok().flatten()))
+}
+
+fn with_bitmap(
+    repo: &Repository,
+    bitmap: &PackBitmap,
+    tips: &[ObjectId],
+    exclude: &[ObjectId],
+) -> Result<Vec<Reached>> {
+    let mut haves = BitmapWalk::new(bitmap);
+    haves.walk(repo, exclude, None)?;
+    let mut wants = BitmapWalk::new(bitmap);
+    wants.walk(repo, tips, Some(&haves))?;
+    wants.bits.and_not(&haves.bits);
+
+    let mut found = wants.outside;
+    for bit in wants.bits.ones() {
+        let id = bitmap.id_at(bit);
+        let kind = match bitmap.kind_at(bit) {
+            Some(kind) => kind,
+            None => repo.odb().read_header(&id)?.0,
+        };
+        found.push(Reached {
+            id,
+            kind,
+            name_hash: bitmap.name_hash_at(bit),
+        });
+    }
+    found.sort_by_key(|r| !matches!(r.kind, ObjectKind::Commit | ObjectKind::Tag));
+    Ok(found)
+}
+
+/// What one side of a bitmap walk reached: bits for the objects in the
+/// bitmapped pack, a list for the others.
+struct BitmapWalk<'a> {
+    bitmap: &'a PackBitmap,
+    bits: Bitmap,
+    outside: Vec<Reached>,
+    seen_outside: HashSet<ObjectId>,
+}
+
+impl<'a> BitmapWalk<'a> {
+    fn new(bitmap: &'a PackBitmap) -> Self {
+        BitmapWalk {
+            bitmap,
+            bits: Bitmap::new(),
+            outside: Vec::new(),
+            seen_outside: HashSet::new(),
+        }
+    }
+
+    fn seen(&self, id: &ObjectId, bit: Option<usize>) -> bool {
+        match bit {
+            Some(bit) => self.bits.get(bit),
+            None => self.seen_outside.contains(id),
+        }
+    }
+
+    /// Adds everything reachable from `tips`, not going past what `stop`
+    /// has already reached.
+    fn walk(
+        &mut self,
+        repo: &Repository,
+        tips: &[ObjectId],
+        stop: Option<&BitmapWalk>,
+    ) -> Result<()> {
+        let mut pending: Vec<Pending> = tips.iter().rev().map(|id| (*id, None)).collect();
+
+        while let Some((id, path)) = pending.pop() {
+            let bit = self.bitmap.position(&id);
+            if self.seen(&id, bit) || stop.is_some_and(|stop| stop.seen(&id, bit)) {
+                continue;
+            }
+            match bit {
+                Some(bit) => {
+                    // Everything a bitmapped commit reaches is in its bitmap.
+                    if let Some(reached) = self.bitmap.commit_bitmap(&id) {
+                        self.bits.or(reached);
+                        continue;
+                    }
+                    self.bits.set(bit);
+                }
+                None => {
+                    self.seen_outside.insert(id);
+                }
+            }
+
+            let (kind, object) = visit(repo, &id)?;
+            if bit.is_none() {
+                self.outside.push(Reached {
+                    id,
+                    kind,
+                    name_hash: path.as_deref().map_or(0, name_hash),
+                });
+            }
+            if let Some(object) = object {
+                push_children(&object, path.as_deref(), &mut pending)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+//! Reflogs: the history of where each ref used to point, kept under
+//! `.git/logs/`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::object::ObjectId;
+use crate::repository::Repository;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub old: ObjectId,
+    pub new: ObjectId,
+    /// `Name <email> <time> <tz>` of whoever moved the ref.
+    pub committer: String,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    /// When the ref was moved, in seconds since the epoch.
+    pub fn time(&self) -> Option<i64> {
+        let (_, rest) = self.committer.rsplit_once('>')?;
+        rest.split_whitespace().next()?.parse().ok()
+    }
+}
+
+/// The entries of `name`'s reflog, oldest first. A ref without a reflog has
+/// no entries.
+pub fn read(repo: &Repository, name: &str) -> Result<Vec<ReflogEntry>> {
+    let data = match fs::read(repo.path("logs").join(name)) {
+        Ok(data) => data,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound
+                    | io::ErrorKind::IsADirectory
+                    | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    String::from_utf8_lossy(&data)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &str) -> Result<ReflogEntry> {
+    let bad = || Error::parse("reflog", format!("bad line {line}"));
+    let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+    let mut fields = head.splitn(3, ' ');
+    let old = fields.next().ok_or_else(bad)?.parse()?;
+    let new = fields.next().ok_or_else(bad)?.parse()?;
+    let committer = fields.next().ok_or_else(bad)?.to_owned();
+    Ok(ReflogEntry {
+        old,
+        new,
+        committer,
+        message: message.to_owned(),
+    })
+}
+
+/// Names of every ref that has a reflog, `HEAD` included.
+pub fn list(repo: &Repository) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    collect(&repo.path("logs"), "", &mut names)?;
+    names.sort();
+    Ok(names)
+}
+
+fn collect(dir: &Path, prefix: &str, out: &mut Vec<String>) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let Some(file_name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        let name = if prefix.is_empty() {
+            file_name
+        } else {
+            format!("{prefix}/{file_name}")
+        };
+        if entry.file_type()?.is_dir() {
+            collect(&entry.path(), &name, out)?;
+        } else {
+            out.push(name);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,353 @@
+//! References: names under `.git` that point at objects or other refs.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::object::{Object, ObjectId};
+use crate::repository::Repository;
+
+/// How deep symbolic refs may nest before we assume a loop.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The raw content of a ref file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefTarget {
+    Direct(ObjectId),
+    Symbolic(String),
+}
+
+/// An entry of the `packed-refs` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub id: ObjectId,
+    /// What an annotated tag ultimately points to, when git recorded it.
+    pub peeled: Option<ObjectId>,
+}
+
+/// Reads `packed-refs`, where `git gc` and `git clone` keep refs that have
+/// not been updated since.
+pub fn read_packed(repo: &Repository) -> Result<Vec<PackedRef>> {
+    let data = match fs::read_to_string(repo.path("packed-refs")) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut refs: Vec<PackedRef> = Vec::new();
+    for line in data.lines() {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(peeled) = line.strip_prefix('^') {
+            let last = refs
+                .last_mut()
+                .ok_or_else(|| Error::parse("packed-refs", "peeled line without a ref"))?;
+            last.peeled = Some(peeled.parse()?);
+            continue;
+        }
+        let (id, name) = line
+            .split_once(' ')
+            .ok_or_else(|| Error::parse("packed-refs", format!("bad line {line}")))?;
+        refs.push(PackedRef {
+            name: name.to_owned(),
+            id: id.parse()?,
+            peeled: None,
+        });
+    }
+    Ok(refs)
+}
+
+/// Reads a single ref without following symbolic links, looking at the
+/// loose file first and `packed-refs` second.
+pub fn read(repo: &Repository, name: &str) -> Result<Option<RefTarget>> {
+    if let Some(target) = read_loose(repo, name)? {
+        return Ok(Some(target));
+    }
+    Ok(read_packed(repo)?
+        .into_iter()
+        .find(|r| r.name == name)
+        .map(|r| RefTarget::Direct(r.id)))
+}
+
+/// Reads the loose file of a ref, ignoring `packed-refs`.
+fn read_loose(repo: &Repository, name: &str) -> Result<Option<RefTarget>> {
+    let data = match fs::read_to_string(repo.path(name)) {
+        Ok(data) => data,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound
+                    | io::ErrorKind::IsADirectory
+                    | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let data = data.trim();
+    Ok(Some(match data.strip_prefix("ref: ") {
+        Some(target) => RefTarget::Symbolic(target.to_owned()),
+        None => RefTarget::Direct(data.parse()?),
+    }))
+}
+
+/// Resolves a ref to an object id, following symbolic refs.
+///
+/// A dangling symbolic ref is not an error: it is what HEAD looks like in a
+/// repository without commits.
+pub fn resolve(repo: &Repository, name: &str) -> Result<Option<ObjectId>> {
+    let mut name = name.to_owned();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read(repo, &name)? {
+            Some(RefTarget::Direct(id)) => return Ok(Some(id)),
+            Some(RefTarget::Symbolic(target)) => name = target,
+            None => return Ok(None),
+        }
+    }
+    Err(Error::NoSuchRef(format!("{name} (symbolic ref loop)")))
+}
+
+/// Where a short name is looked for, in order of precedence, as prefix
+/// and suffix around it: git's `ref_rev_parse_rules`.
+const DWIM_RULES: [(&str, &str); 6] = [
+    ("", ""),
+    ("refs/", ""),
+    ("refs/tags/", ""),
+    ("refs/heads/", ""),
+    ("refs/remotes/", ""),
+    ("refs/remotes/", "/HEAD"),
+];
+
+/// Every existing ref `name` can be short for, with what it resolves to,
+/// most preferred first. Names are taken as they are only when they look
+/// like `HEAD` and the other all-caps files in the gitdir, or start with
+/// `refs/`.
+pub fn expand(repo: &Repository, name: &str) -> Result<Vec<(String, ObjectId)>> {
+    let mut found = Vec::new();
+    if !is_valid_name(name) {
+        return Ok(found);
+    }
+    let root = is_full_name(name);
+    for (prefix, suffix) in DWIM_RULES.into_iter().skip(usize::from(!root)) {
+        let full = format!("{prefix}{name}{suffix}");
+        if let Some(id) = resolve(repo, &full)? {
+            found.push((full, id));
+        }
+    }
+    Ok(found)
+}
+
+/// Whether `name` is a ref's whole name: under `refs/`, or one like
+/// `HEAD` and the other all-caps files in the gitdir.
+fn is_full_name(name: &str) -> bool {
+    name.starts_with("refs/") || name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_')
+}
+
+/// Refuses names that are no valid ref, by [`is_valid_name`] and, as
+/// git does for the names of new branches and tags, those starting with
+/// `-`. Names are checked before they become paths under the gitdir.
+pub fn check_ref_format(name: &str) -> Result<()> {
+    match !name.starts_with('-') && is_valid_name(name) {
+        true => Ok(()),
+        false => Err(Error::InvalidRefName(name.to_owned())),
+    }
+}
+
+/// Whether `name` could be a ref, as `git check-ref-format
+/// --allow-onelevel` sees it: no empty or dot-led components, no `..`,
+/// `@{`, control characters, spaces or `~^:?*[\`, and no trailing `.`
+/// or `.lock`.
+pub fn is_valid_name(name: &str) -> bool {
+    let bad_byte = |b: u8| b < 0x20 || b == 0x7f || b" ~^:?*[\\".contains(&b);
+    name != "@"
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.bytes().any(bad_byte)
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && !part.starts_with('.') && !part.ends_with(".lock"))
+}
+
+/// The branch HEAD points to, or `None` when HEAD is detached.
+pub fn current_branch(repo: &Repository) -> Result<Option<String>> {
+    Ok(match read(repo, "HEAD")? {
+        Some(RefTarget::Symbolic(target)) => target.strip_prefix("refs/heads/").map(str::to_owned),
+        _ => None,
+    })
+}
+
+/// Every ref under `refs/`, loose or packed, sorted by full name.
+pub fn list(repo: &Repository) -> Result<Vec<(String, ObjectId)>> {
+    let mut names = Vec::new();
+    collect_names(&repo.path("refs"), "refs", &mut names)?;
+    names.extend(read_packed(repo)?.into_iter().map(|r| r.name));
+    names.sort();
+    names.dedup();
+
+    let mut refs = Vec::with_capacity(names.len());
+    for name in names {
+        if let Some(id) = resolve(repo, &name)? {
+            refs.push((name, id));
+        }
+    }
+    Ok(refs)
+}
+
+fn collect_names(dir: &Path, prefix: &str, out: &mut Vec<String>) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let Some(file_name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        let name = format!("{prefix}/{file_name}");
+        if entry.file_type()?.is_dir() {
+            collect_names(&entry.path(), &name, out)?;
+        } else {
+            out.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// Points `name` (a full ref name such as `refs/tags/v1`) at `id`.
+pub fn update(repo: &Repository, name: &str, id: &ObjectId) -> Result<()> {
+    check_ref_format(name)?;
+    if !is_full_name(name) {
+        return Err(Error::InvalidRefName(name.to_owned()));
+    }
+    let path = repo.path(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    LockFile::acquire(&path)?.commit(format!("{id}\n").as_bytes())
+}
+
+/// Exclusive right to replace a file, taken as git does by creating
+/// `<path>.lock`: concurrent writers fail instead of racing. The new
+/// content is renamed over the file on [`commit`](LockFile::commit); a
+/// lock dropped without committing is removed.
+struct LockFile {
+    path: PathBuf,
+    lock: PathBuf,
+    file: fs::File,
+    committed: bool,
+}
+
+impl LockFile {
+    fn acquire(path: &Path) -> Result<Self> {
+        let mut lock = path.as_os_str().to_owned();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+        match fs::File::options().write(true).create_new(true).open(&lock) {
+            Ok(file) => Ok(LockFile {
+                path: path.to_owned(),
+                lock,
+                file,
+                committed: false,
+            }),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(Error::Locked(lock)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn commit(mut self, data: &[u8]) -> Result<()> {
+        self.file.write_all(data)?;
+        self.file.sync_all()?;
+        fs::rename(&self.lock, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
+}
+
+/// Moves every loose ref under `refs/` into `packed-refs`, recording the
+/// peeled target of annotated tags, and returns how many refs were packed.
+/// Symbolic refs stay loose.
+pub fn pack(repo: &Repository) -> Result<usize> {
+    let lock = LockFile::acquire(&repo.path("packed-refs"))?;
+    let mut loose_names = Vec::new();
+    collect_names(&repo.path("refs"), "refs", &mut loose_names)?;
+
+    let mut packed: Vec<PackedRef> = read_packed(repo)?;
+    let mut moved = Vec::new();
+    for name in loose_names {
+        if let Some(RefTarget::Direct(id)) = read_loose(repo, &name)? {
+            packed.retain(|r| r.name != name);
+            packed.push(PackedRef {
+                name: name.clone(),
+                id,
+                peeled: None,
+            });
+            moved.push((name, id));
+        }
+    }
+    packed.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut out = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for r in &mut packed {
+        r.peeled = peel(repo, &r.id)?;
+        out.push_str(&format!("{} {}\n", r.id, r.name));
+        if let Some(peeled) = r.peeled {
+            out.push_str(&format!("^{peeled}\n"));
+        }
+    }
+    lock.commit(out.as_bytes())?;
+
+    for (name, id) in &moved {
+        // Leave the loose ref alone if someone moved it in the meantime.
+        if read_loose(repo, name)? == Some(RefTarget::Direct(*id)) {
+            fs::remove_file(repo.path(name))?;
+            remove_empty_parents(repo, name);
+        }
+    }
+    Ok(moved.len())
+}
+
+/// What an annotated tag ultimately points to, or `None` for anything that
+/// is not a tag (or is missing).
+fn peel(repo: &Repository, id: &ObjectId) -> Result<Option<ObjectId>> {
+    let mut current = *id;
+    let mut peeled = None;
+    while repo.odb().contains(&current) {
+        match repo.odb().read(&current)? {
+            Object::Tag(tag) => {
+                current = tag.object;
+                peeled = Some(current);
+            }
+            _ => break,
+        }
+    }
+    Ok(peeled)
+}
+
+/// Removes the now-empty directories a deleted ref lived in, keeping the
+/// standard `refs/heads` and `refs/tags`.
+fn remove_empty_parents(repo: &Repository, name: &str) {
+    let mut dir = Path::new(name).parent();
+    while let Some(d) = dir {
+        if matches!(d.to_str(), Some("refs" | "refs/heads" | "refs/tags" | "")) {
+            break;
+        }
+        if fs::remove_dir(repo.path(d)).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
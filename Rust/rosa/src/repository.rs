@@ -0,0 +1,166 @@
+//! Locating, opening and creating repositories.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::object::HashAlgorithm;
+use crate::odb::ObjectDatabase;
+
+#[derive(Debug)]
+pub struct Repository {
+    worktree: PathBuf,
+    gitdir: PathBuf,
+    config: Config,
+    odb: ObjectDatabase,
+}
+
+impl Repository {
+    /// Opens the repository whose worktree is `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let worktree = fs::canonicalize(path.as_ref())?;
+        let gitdir = worktree.join(".git");
+        if !gitdir.is_dir() {
+            return Err(Error::NotARepository(worktree));
+        }
+
+        let config_path = gitdir.join("config");
+        if !config_path.is_file() {
+            return Err(Error::Config("configuration file missing".into()));
+        }
+        let config = Config::load(&config_path)?;
+
+        let hash = object_format(&config)?;
+        let odb = ObjectDatabase::open(gitdir.join("objects"), hash)?;
+        if let Some(limit) = config.get_int("core.deltaBaseCacheLimit")? {
+            odb.set_delta_base_cache_limit(limit.try_into().unwrap_or(0));
+        }
+
+        Ok(Repository {
+            odb,
+            worktree,
+            gitdir,
+            config,
+        })
+    }
+
+    /// Walks up from `path` until a directory containing `.git` is found.
+    pub fn discover(path: impl AsRef<Path>) -> Result<Self> {
+        let start = fs::canonicalize(path.as_ref())?;
+        let mut dir = start.as_path();
+        loop {
+            if dir.join(".git").is_dir() {
+                return Repository::open(dir);
+            }
+            dir = dir
+                .parent()
+                .ok_or_else(|| Error::NotARepository(start.clone()))?;
+        }
+    }
+
+    /// Creates a new repository at `path`, which must not exist or be an
+    /// empty directory (an empty `.git` is tolerated), naming its objects
+    /// with `hash`.
+    pub fn init(path: impl AsRef<Path>, hash: HashAlgorithm) -> Result<Self> {
+        let worktree = path.as_ref();
+        let gitdir = worktree.join(".git");
+
+        if worktree.exists() {
+            if !worktree.is_dir() {
+                return Err(Error::Config(format!(
+                    "{} is not a directory",
+                    worktree.display()
+                )));
+            }
+            if gitdir.exists() && fs::read_dir(&gitdir)?.next().is_some() {
+                return Err(Error::NotEmpty(worktree.to_path_buf()));
+            }
+        }
+
+        for dir in ["objects", "refs/tags", "refs/heads"] {
+            fs::create_dir_all(gitdir.join(dir))?;
+        }
+        fs::write(
+            gitdir.join("description"),
+            "Unnamed repository; edit this file 'description' to name the repository.\n",
+        )?;
+        fs::write(gitdir.join("HEAD"), "ref: refs/heads/master\n")?;
+        default_config(hash)?.write(&gitdir.join("config"))?;
+
+        Repository::open(worktree)
+    }
+
+    pub fn worktree(&self) -> &Path {
+        &self.worktree
+    }
+
+    pub fn gitdir(&self) -> &Path {
+        &self.gitdir
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn odb(&self) -> &ObjectDatabase {
+        &self.odb
+    }
+
+    /// A path under the gitdir.
+    pub fn path(&self, relative: impl AsRef<Path>) -> PathBuf {
+        self.gitdir.join(relative)
+    }
+}
+
+fn default_config(hash: HashAlgorithm) -> Result<Config> {
+    let mut config = Config::default();
+    // Anything but SHA-1 needs an extension, which needs version 1 so that
+    // older implementations refuse the repository instead of misreading it.
+    let version = if hash == HashAlgorithm::Sha1 {
+        "0"
+    } else {
+        "1"
+    };
+    config.set("core.repositoryformatversion", version)?;
+    config.set("core.filemode", "false")?;
+    config.set("core.bare", "false")?;
+    if hash != HashAlgorithm::Sha1 {
+        config.set("extensions.objectformat", hash.name())?;
+    }
+    Ok(config)
+}
+
+/// Repository extensions this implementation understands.
+const KNOWN_EXTENSIONS: &[&str] = &["noop", "objectformat"];
+
+/// Checks the repository format and returns the object format it uses.
+/// Version 0 repositories are always SHA-1; version 1 ones may choose with
+/// `extensions.objectFormat`, and must not use extensions we do not know.
+fn object_format(config: &Config) -> Result<HashAlgorithm> {
+    let extensions = config.keys("extensions");
+    match config.get_int("core.repositoryformatversion")? {
+        Some(0) => {
+            if extensions.contains(&"objectformat") {
+                return Err(Error::Config(
+                    "repo version is 0, but v1-only extension found: objectformat".into(),
+                ));
+            }
+            Ok(HashAlgorithm::Sha1)
+        }
+        Some(1) => {
+            if let Some(unknown) = extensions.iter().find(|e| !KNOWN_EXTENSIONS.contains(e)) {
+                return Err(Error::Config(format!(
+                    "unknown repository extension found: {unknown}"
+                )));
+            }
+            config
+                .get("extensions.objectFormat")
+                .map_or(Ok(HashAlgorithm::Sha1), str::parse)
+        }
+        Some(v) => Err(Error::Config(format!(
+            "unsupported repositoryformatversion: {v}"
+        ))),
+        None => Err(Error::Config("missing core.repositoryformatversion".into())),
+    }
+}
@@ -0,0 +1,826 @@
+//! Turning user-supplied names into object ids, as gitrevisions(7)
+//! describes them:
+//!
+//! - `@` for `HEAD`, object ids and their unambiguous prefixes, and ref
+//!   names, looked up in git's order (see [`refs::expand`])
+//! - `<ref>@{<n>}` and `<ref>@{<date>}` from reflogs, `@{-<n>}` for the
+//!   branch checked out `n` checkouts ago, and `<branch>@{upstream}` and
+//!   `<branch>@{push}`; without a name they apply to the current branch
+//! - `<rev>~<n>`, `<rev>^<n>`, `<rev>^{<type>}`, `<rev>^{}` and
+//!   `<rev>^{/<text>}`
+//! - `<rev>:<path>`, `:<path>` and `:<stage>:<path>` (from the index), and
+//!   `:/<text>`, the youngest commit whose message matches
+
+pub mod regex;
+
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::time::SystemTime;
+
+use self::regex::Regex;
+use crate::config::Config;
+use crate::date;
+use crate::error::{Error, Result};
+use crate::history::CommitCache;
+use crate::index::Index;
+use crate::object::{Object, ObjectId, ObjectKind};
+use crate::path;
+use crate::reflog;
+use crate::refs;
+use crate::repository::Repository;
+
+/// An object a name resolved to, and what git would warn about on the
+/// way (such as a ref name matching several refs), for the caller to show
+/// or not.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolved {
+    pub id: ObjectId,
+    pub warnings: Vec<String>,
+}
+
+/// Resolves `name` to a single object. A `kind` of commit or tree settles
+/// an id prefix several objects share in favour of what leads to one; when
+/// `follow` is also set, tags are peeled and commits are followed to their
+/// tree until an object of that kind is reached.
+pub fn resolve(
+    repo: &Repository,
+    name: &str,
+    kind: Option<ObjectKind>,
+    follow: bool,
+) -> Result<Resolved> {
+    let hint = match kind {
+        Some(ObjectKind::Commit) => Some(Hint::Committish),
+        Some(ObjectKind::Tree) => Some(Hint::Treeish),
+        _ => Hint::from_config(repo.config())?,
+    };
+    let resolver = Resolver {
+        repo,
+        spec: name,
+        hint,
+        warnings: RefCell::default(),
+    };
+    let mut id = resolver.object()?;
+    let warnings = resolver.warnings.into_inner();
+
+    let Some(kind) = kind else {
+        return Ok(Resolved { id, warnings });
+    };
+    loop {
+        // Only the header is needed to stop, which keeps large blobs unread.
+        let (found, _) = repo.odb().read_header(&id)?;
+        if found == kind || !follow || found == ObjectKind::Blob {
+            return Ok(Resolved { id, warnings });
+        }
+        id = match repo.odb().read(&id)? {
+            Object::Tag(tag) => tag.object,
+            Object::Commit(commit) if kind == ObjectKind::Tree => commit.tree,
+            _ => return Ok(Resolved { id, warnings }),
+        };
+    }
+}
+
+/// [`resolve`] for callers with no one to pass warnings on to.
+pub fn find(
+    repo: &Repository,
+    name: &str,
+    kind: Option<ObjectKind>,
+    follow: bool,
+) -> Result<ObjectId> {
+    resolve(repo, name, kind, follow).map(|resolved| resolved.id)
+}
+
+/// The fewest hex digits an abbreviated id may have.
+pub const MIN_ABBREV: usize = 4;
+
+/// How many hex digits abbreviations start from, by `core.abbrev`: a
+/// number, `auto` (the default) for a length that grows with the number of
+/// objects, or a false boolean for whole ids.
+pub fn abbrev_len(repo: &Repository) -> Result<usize> {
+    let hex_len = repo.odb().hash().hex_len();
+    let value = repo
+        .config()
+        .get("core.abbrev")
+        .map(str::to_ascii_lowercase);
+    match value.as_deref() {
+        None | Some("auto") => {
+            // With n significant bits of objects, collisions are expected
+            // around 2^(n/2), and a hex digit holds four bits.
+            let bits = repo.odb().approximate_count().checked_ilog2().unwrap_or(0) as usize + 1;
+            Ok(bits.div_ceil(2).max(7))
+        }
+        Some("false" | "no" | "off" | "") => Ok(hex_len),
+        Some(_) => {
+            let len = repo.config().get_int("core.abbrev")?.unwrap_or_default();
+            usize::try_from(len)
+                .ok()
+                .filter(|len| (MIN_ABBREV..=hex_len).contains(len))
+                .ok_or_else(|| Error::Config(format!("abbrev length out of range: {len}")))
+        }
+    }
+}
+
+/// `id` cut to `len` hex digits, or as many more as it takes to name no
+/// other object.
+pub fn abbreviate(repo: &Repository, id: &ObjectId, len: usize) -> Result<String> {
+    let len = repo.odb().unique_abbrev_len(id, len)?;
+    Ok(id.to_hex()[..len].to_owned())
+}
+
+/// The error for a prefix several objects share, with a line on each as
+/// git's hints have them: tags, then commits, trees and blobs, each kind
+/// in id order.
+fn ambiguous(repo: &Repository, prefix: &str, ids: &[ObjectId]) -> Result<Error> {
+    let len = abbrev_len(repo)?;
+    let mut described = Vec::new();
+    for id in ids {
+        let kind = repo.odb().read_header(id).ok().map(|(kind, _)| kind);
+        let rank = match kind {
+            Some(ObjectKind::Tag) => 0,
+            Some(ObjectKind::Commit) => 1,
+            Some(ObjectKind::Tree) => 2,
+            Some(ObjectKind::Blob) => 3,
+            None => 4,
+        };
+        described.push((rank, *id, describe(repo, id, kind, len)?));
+    }
+    described.sort();
+    Ok(Error::AmbiguousId {
+        prefix: prefix.to_owned(),
+        candidates: described.into_iter().map(|(_, _, line)| line).collect(),
+    })
+}
+
+/// `<abbreviated id> <kind>`, with the date and subject of commits and the
+/// date and name of tags.
+fn describe(
+    repo: &Repository,
+    id: &ObjectId,
+    kind: Option<ObjectKind>,
+    len: usize,
+) -> Result<String> {
+    let short = abbreviate(repo, id, len)?;
+    Ok(match kind {
+        Some(ObjectKind::Commit) => match repo.odb().read(id).and_then(|o| o.into_commit(*id)) {
+            Ok(commit) => format!(
+                "{short} commit {} - {}",
+                date::short(commit.author.time, commit.author.offset.minutes),
+                String::from_utf8_lossy(&commit.subject())
+            ),
+            Err(_) => format!("{short} commit [bad object]"),
+        },
+        Some(ObjectKind::Tag) => match repo.odb().read(id).and_then(|o| o.into_tag(*id)) {
+            Ok(tag) => format!(
+                "{short} tag {} - {}",
+                date::short(tag.tagger.map_or(0, |tagger| tagger.time), 0),
+                String::from_utf8_lossy(&tag.name)
+            ),
+            Err(_) => format!("{short} tag [bad object]"),
+        },
+        Some(kind) => format!("{short} {kind}"),
+        None => format!("{short} [bad object]"),
+    })
+}
+
+/// The kind of object a name is expected to be, which picks one of several
+/// objects sharing an id prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Hint {
+    Commit,
+    /// A commit, or a tag leading to one.
+    Committish,
+    /// A tree, or a tag leading to one.
+    Tree,
+    /// A tree or commit, or a tag leading to either.
+    Treeish,
+    /// A blob, or a tag leading to one.
+    Blob,
+}
+
+impl Hint {
+    /// The default, from `core.disambiguate`.
+    fn from_config(config: &Config) -> Result<Option<Self>> {
+        Ok(match config.get("core.disambiguate") {
+            None | Some("none") => None,
+            Some("commit") => Some(Hint::Commit),
+            Some("committish" | "commit-ish") => Some(Hint::Committish),
+            Some("tree") => Some(Hint::Tree),
+            Some("treeish" | "tree-ish") => Some(Hint::Treeish),
+            Some("blob") => Some(Hint::Blob),
+            Some(other) => {
+                return Err(Error::Config(format!(
+                    "unknown value for core.disambiguate: {other}"
+                )))
+            }
+        })
+    }
+
+    /// What the first step after a name needs it to be: `~` and `^` walk
+    /// commits, as does `^{/<text>}`, and `^{commit}` and `^{tree}` peel.
+    fn from_step(steps: &str) -> Option<Self> {
+        let peeled = steps
+            .strip_prefix("^{")
+            .map(|rest| rest.split('}').next().unwrap_or(rest));
+        match peeled {
+            Some("commit") => Some(Hint::Committish),
+            Some("tree") => Some(Hint::Treeish),
+            Some(inner) if inner.starts_with('/') => Some(Hint::Committish),
+            Some(_) => None,
+            None if steps.starts_with(['~', '^']) => Some(Hint::Committish),
+            None => None,
+        }
+    }
+
+    fn accepts(self, repo: &Repository, id: &ObjectId) -> Result<bool> {
+        let (kind, _) = repo.odb().read_header(id)?;
+        let kind = match (self, kind) {
+            (Hint::Commit, kind) => return Ok(kind == ObjectKind::Commit),
+            (_, ObjectKind::Tag) => peel_tags(repo, *id)?.1,
+            (_, kind) => kind,
+        };
+        Ok(match self {
+            Hint::Commit | Hint::Committish => kind == ObjectKind::Commit,
+            Hint::Tree => kind == ObjectKind::Tree,
+            Hint::Treeish => matches!(kind, ObjectKind::Tree | ObjectKind::Commit),
+            Hint::Blob => kind == ObjectKind::Blob,
+        })
+    }
+}
+
+/// Follows tags, and commits to their tree, until an object of `kind` is
+/// reached, failing if another kind of object comes first.
+pub fn peel(repo: &Repository, mut id: ObjectId, kind: ObjectKind) -> Result<ObjectId> {
+    loop {
+        let (found, _) = repo.odb().read_header(&id)?;
+        if found == kind {
+            return Ok(id);
+        }
+        id = match found {
+            ObjectKind::Tag => repo.odb().read(&id)?.into_tag(id)?.object,
+            ObjectKind::Commit if kind == ObjectKind::Tree => {
+                repo.odb().read(&id)?.into_commit(id)?.tree
+            }
+            _ => {
+                return Err(Error::UnexpectedObjectType {
+                    id,
+                    expected: kind.as_str(),
+                    actual: found.as_str(),
+                })
+            }
+        };
+    }
+}
+
+/// Follows tags to what they finally point at.
+fn peel_tags(repo: &Repository, mut id: ObjectId) -> Result<(ObjectId, ObjectKind)> {
+    loop {
+        match repo.odb().read_header(&id)?.0 {
+            ObjectKind::Tag => id = repo.odb().read(&id)?.into_tag(id)?.object,
+            kind => return Ok((id, kind)),
+        }
+    }
+}
+
+/// Resolution of one name, kept whole for error messages.
+struct Resolver<'r> {
+    repo: &'r Repository,
+    spec: &'r str,
+    /// What the caller expects the name to be.
+    hint: Option<Hint>,
+    warnings: RefCell<Vec<String>>,
+}
+
+impl Resolver<'_> {
+    fn unknown(&self) -> Error {
+        Error::NoSuchRef(self.spec.to_owned())
+    }
+
+    fn object(&self) -> Result<ObjectId> {
+        if let Some(rest) = self.spec.strip_prefix(':') {
+            if let Some(pattern) = rest.strip_prefix('/') {
+                let mut tips: Vec<ObjectId> =
+                    refs::resolve(self.repo, "HEAD")?.into_iter().collect();
+                tips.extend(refs::list(self.repo)?.into_iter().map(|(_, id)| id));
+                return self.search(tips, pattern);
+            }
+            return self.index_entry(rest);
+        }
+        match split_path(self.spec) {
+            Some((rev, path)) => {
+                let rev_id = self.revision(rev, Some(Hint::Treeish))?;
+                let tree = peel(self.repo, rev_id, ObjectKind::Tree)?;
+                self.tree_entry(tree, rev, path)
+            }
+            None => self.revision(self.spec, self.hint),
+        }
+    }
+
+    /// `:<path>` or `:<stage>:<path>`, from the index.
+    fn index_entry(&self, rest: &str) -> Result<ObjectId> {
+        let (stage, path) = match rest.as_bytes() {
+            [n @ b'0'..=b'3', b':', ..] => (u16::from(n - b'0'), &rest[2..]),
+            _ => (0, rest),
+        };
+        let name = self.path(path)?;
+        Index::read(self.repo)?
+            .entries
+            .into_iter()
+            .find(|entry| entry.stage == stage && entry.name == name)
+            .map(|entry| entry.id)
+            .ok_or_else(|| Error::NoSuchPath {
+                path: path.to_owned(),
+                within: match stage {
+                    0 => "the index".into(),
+                    n => format!("stage {n} of the index"),
+                },
+            })
+    }
+
+    /// The object at `path` below `tree`, which is `rev`'s.
+    fn tree_entry(&self, tree: ObjectId, rev: &str, path: &str) -> Result<ObjectId> {
+        let missing = || Error::NoSuchPath {
+            path: path.to_owned(),
+            within: format!("'{rev}'"),
+        };
+        let name = self.path(path)?;
+        let mut parts = name
+            .split(|&b| b == b'/')
+            .filter(|part| !part.is_empty())
+            .peekable();
+        let mut id = tree;
+        while let Some(part) = parts.next() {
+            let tree = self.repo.odb().read(&id)?.into_tree(id)?;
+            let entry = tree
+                .entries
+                .iter()
+                .find(|entry| entry.name == part)
+                .ok_or_else(missing)?;
+            if parts.peek().is_some() && !entry.is_tree() {
+                return Err(missing());
+            }
+            id = entry.id;
+        }
+        Ok(id)
+    }
+
+    /// The path from the top of the worktree that `path` names: itself, or
+    /// relative to the current directory when it starts with `./` or
+    /// `../`.
+    fn path(&self, path: &str) -> Result<Vec<u8>> {
+        let relative =
+            [".", ".."].contains(&path) || path.starts_with("./") || path.starts_with("../");
+        if !relative {
+            return Ok(path.trim_end_matches('/').as_bytes().to_vec());
+        }
+        let cwd = std::env::current_dir()?.canonicalize()?;
+        let prefix = path::relative(self.repo.worktree(), &cwd)
+            .ok_or_else(|| Error::Usage(format!("'{path}' is outside the repository")))?;
+        let mut parts: Vec<&[u8]> = prefix
+            .split(|&b| b == b'/')
+            .filter(|p| !p.is_empty())
+            .collect();
+        for part in path.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    parts.pop().ok_or_else(|| {
+                        Error::Usage(format!("'{path}' is outside the repository"))
+                    })?;
+                }
+                part => parts.push(part.as_bytes()),
+            }
+        }
+        Ok(parts.join(&b'/'))
+    }
+
+    /// A name followed by any number of `~<n>`, `^<n>` and `^{...}` steps.
+    /// The first step, if any, tells what the name must be instead of
+    /// `hint`.
+    fn revision(&self, text: &str, hint: Option<Hint>) -> Result<ObjectId> {
+        let (base, mut steps) = text.split_at(base_len(text));
+        let mut id = self.base(base, Hint::from_step(steps).or(hint))?;
+        while let Some(&op) = steps.as_bytes().first() {
+            let rest = &steps[1..];
+            if op == b'^' && rest.starts_with('{') {
+                let close = closing_brace(rest).ok_or_else(|| self.unknown())?;
+                id = self.peel_onion(id, &rest[1..close])?;
+                steps = &rest[close + 1..];
+                continue;
+            }
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let n: usize = match digits {
+                0 => 1,
+                _ => rest[..digits].parse().map_err(|_| self.unknown())?,
+            };
+            steps = &rest[digits..];
+            id = match op {
+                b'~' => {
+                    let id = peel(self.repo, id, ObjectKind::Commit)?;
+                    (0..n).try_fold(id, |id, _| self.parent(id, 1))?
+                }
+                b'^' if n == 0 => peel(self.repo, id, ObjectKind::Commit)?,
+                b'^' => self.parent(id, n)?,
+                _ => return Err(self.unknown()),
+            };
+        }
+        Ok(id)
+    }
+
+    /// The `n`th parent of the commit `id` peels to.
+    fn parent(&self, id: ObjectId, n: usize) -> Result<ObjectId> {
+        let id = peel(self.repo, id, ObjectKind::Commit)?;
+        let commit = self.repo.odb().read(&id)?.into_commit(id)?;
+        commit
+            .parents
+            .get(n - 1)
+            .copied()
+            .ok_or_else(|| self.unknown())
+    }
+
+    /// `^{<inner>}`.
+    fn peel_onion(&self, id: ObjectId, inner: &str) -> Result<ObjectId> {
+        if let Some(pattern) = inner.strip_prefix('/') {
+            let id = peel(self.repo, id, ObjectKind::Commit)?;
+            return self.search(vec![id], pattern);
+        }
+        match inner {
+            "" => Ok(peel_tags(self.repo, id)?.0),
+            "object" => {
+                self.repo.odb().read_header(&id)?;
+                Ok(id)
+            }
+            kind => {
+                let kind = kind.parse().map_err(|_| self.unknown())?;
+                peel(self.repo, id, kind)
+            }
+        }
+    }
+
+    /// The youngest commit reachable from `tips` whose message matches
+    /// `pattern`. A leading `!-` inverts the match, and `!!` stands for a
+    /// literal `!`.
+    fn search(&self, tips: Vec<ObjectId>, pattern: &str) -> Result<ObjectId> {
+        let (negate, pattern) = match pattern.strip_prefix('!') {
+            None => (false, pattern),
+            Some(rest) if rest.starts_with('!') => (false, rest),
+            Some(rest) => match rest.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => return Err(Error::Usage(format!("invalid search pattern :/{pattern}"))),
+            },
+        };
+        let regex = Regex::new(pattern)?;
+        let cache = CommitCache::new(self.repo)?;
+
+        let mut pending = Vec::new();
+        for tip in tips {
+            if let (id, ObjectKind::Commit) = peel_tags(self.repo, tip)? {
+                pending.push(id);
+            }
+        }
+        // Newest first; among equal dates, first come first.
+        let mut queue = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        let mut order = 0u64;
+        loop {
+            for id in pending.drain(..) {
+                if seen.insert(id) {
+                    queue.push((cache.get(&id)?.time, Reverse(order), id));
+                    order += 1;
+                }
+            }
+            let Some((_, _, id)) = queue.pop() else {
+                return Err(self.unknown());
+            };
+            let commit = self.repo.odb().read(&id)?.into_commit(id)?;
+            if regex.is_match(commit.message.as_deref().unwrap_or_default()) != negate {
+                return Ok(id);
+            }
+            pending.extend(commit.parents);
+        }
+    }
+
+    fn base(&self, name: &str, hint: Option<Hint>) -> Result<ObjectId> {
+        match name.find("@{") {
+            Some(at) => self.at_braces(&name[..at], &name[at..]),
+            None => self.named(name, hint),
+        }
+    }
+
+    /// `@`, an object id, a ref name, or an id prefix, in that order. A
+    /// prefix several objects share is settled by `hint` when exactly one
+    /// of them is of the kind it asks for.
+    fn named(&self, name: &str, hint: Option<Hint>) -> Result<ObjectId> {
+        let name = if name == "@" { "HEAD" } else { name };
+        let hex_len = self.repo.odb().hash().hex_len();
+        let is_hex = name.bytes().all(|b| b.is_ascii_hexdigit());
+        if name.len() == hex_len && is_hex {
+            return name.to_ascii_lowercase().parse();
+        }
+
+        let found = refs::expand(self.repo, name)?;
+        if let Some(&(_, id)) = found.first() {
+            let warn = self.repo.config().get_bool("core.warnAmbiguousRefs")?;
+            if found.len() > 1 && warn.unwrap_or(true) {
+                self.warnings
+                    .borrow_mut()
+                    .push(format!("refname '{name}' is ambiguous."));
+            }
+            return Ok(id);
+        }
+
+        if name.len() >= MIN_ABBREV && is_hex {
+            let candidates = self.repo.odb().find_prefix(&name.to_ascii_lowercase())?;
+            if let [id] = candidates.as_slice() {
+                return Ok(*id);
+            }
+            if !candidates.is_empty() {
+                let mut accepted = Vec::new();
+                if let Some(hint) = hint {
+                    for id in &candidates {
+                        if hint.accepts(self.repo, id)? {
+                            accepted.push(*id);
+                        }
+                    }
+                }
+                return match accepted.as_slice() {
+                    [id] => Ok(*id),
+                    [] => Err(ambiguous(self.repo, name, &candidates)?),
+                    _ => Err(ambiguous(self.repo, name, &accepted)?),
+                };
+            }
+        }
+        Err(self.unknown())
+    }
+
+    /// `<name>@{...}`, where `braces` holds one or more `@{...}`.
+    fn at_braces(&self, name: &str, mut braces: &str) -> Result<ObjectId> {
+        let mut selectors = Vec::new();
+        while !braces.is_empty() {
+            let (selector, rest) = braces
+                .strip_prefix("@{")
+                .and_then(|inner| inner.split_once('}'))
+                .ok_or_else(|| self.unknown())?;
+            selectors.push(selector);
+            braces = rest;
+        }
+        let mut selectors = selectors.into_iter().peekable();
+
+        // The full name of the ref the next selector applies to, `None`
+        // for the current branch.
+        let mut current = None;
+        if !name.is_empty() {
+            current = Some(self.full_name(name)?);
+        } else if let Some(n) = selectors.peek().and_then(|s| s.strip_prefix('-')) {
+            let n = n
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| self.unknown())?;
+            selectors.next();
+            let branch = self.previous_branch(n)?;
+            if selectors.peek().is_none() {
+                // Possibly a detached HEAD's commit rather than a branch.
+                return self.named(&branch, None);
+            }
+            current = Some(self.full_name(&branch)?);
+        }
+
+        while let Some(selector) = selectors.next() {
+            match selector.to_ascii_lowercase().as_str() {
+                "u" | "upstream" => {
+                    let branch = self.branch(current.as_deref())?;
+                    current = Some(upstream(self.repo.config(), &branch)?);
+                }
+                "push" => {
+                    let branch = self.branch(current.as_deref())?;
+                    current = Some(push_destination(self.repo.config(), &branch)?);
+                }
+                _ if selectors.peek().is_some() => return Err(self.unknown()),
+                _ => {
+                    let full = match current {
+                        Some(full) => full,
+                        None => match refs::current_branch(self.repo)? {
+                            Some(branch) => format!("refs/heads/{branch}"),
+                            None => "HEAD".to_owned(),
+                        },
+                    };
+                    return self.reflog_entry(&full, selector);
+                }
+            }
+        }
+        let full = current.ok_or_else(|| self.unknown())?;
+        refs::resolve(self.repo, &full)?.ok_or_else(|| self.unknown())
+    }
+
+    /// The full name of the ref `name` is short for.
+    fn full_name(&self, name: &str) -> Result<String> {
+        let name = if name == "@" { "HEAD" } else { name };
+        let found = refs::expand(self.repo, name)?;
+        found
+            .into_iter()
+            .next()
+            .map(|(full, _)| full)
+            .ok_or_else(|| self.unknown())
+    }
+
+    /// The short name of the branch `full` names, or of the current one.
+    fn branch(&self, full: Option<&str>) -> Result<String> {
+        match full {
+            Some(full) => full
+                .strip_prefix("refs/heads/")
+                .map(str::to_owned)
+                .ok_or_else(|| Error::Usage(format!("no such branch: '{full}'"))),
+            None => refs::current_branch(self.repo)?
+                .ok_or_else(|| Error::Usage("HEAD does not point to a branch".into())),
+        }
+    }
+
+    /// What `full` pointed to `selector` (a count of changes, or a date)
+    /// ago, from its reflog.
+    fn reflog_entry(&self, full: &str, selector: &str) -> Result<ObjectId> {
+        let entries = reflog::read(self.repo, full)?;
+        let shown = full.strip_prefix("refs/heads/").unwrap_or(full);
+        let oldest = entries
+            .first()
+            .ok_or_else(|| Error::Usage(format!("log for '{shown}' is empty")))?;
+
+        if !selector.is_empty() && selector.bytes().all(|b| b.is_ascii_digit()) {
+            let n: usize = selector.parse().map_err(|_| self.unknown())?;
+            return match entries.len().checked_sub(n + 1) {
+                Some(at) => Ok(entries[at].new),
+                // Just before the oldest entry.
+                None if n == entries.len() && !oldest.old.is_null() => Ok(oldest.old),
+                None => Err(Error::Usage(format!(
+                    "log for '{shown}' only has {} entries",
+                    entries.len()
+                ))),
+            };
+        }
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let time = date::parse(selector, now).ok_or_else(|| self.unknown())?;
+        Ok(
+            match entries
+                .iter()
+                .rev()
+                .find(|e| e.time().is_some_and(|t| t <= time))
+            {
+                Some(entry) => entry.new,
+                // Older than the log: the best guess is where it started.
+                None if oldest.old.is_null() => oldest.new,
+                None => oldest.old,
+            },
+        )
+    }
+
+    /// The branch (or detached commit) that was checked out `n` checkouts
+    /// ago, from the messages checkouts leave in HEAD's reflog.
+    fn previous_branch(&self, n: usize) -> Result<String> {
+        reflog::read(self.repo, "HEAD")?
+            .iter()
+            .rev()
+            .filter_map(|entry| {
+                let moved = entry.message.strip_prefix("checkout: moving from ")?;
+                moved.split_once(" to ").map(|(from, _)| from.to_owned())
+            })
+            .nth(n - 1)
+            .ok_or_else(|| self.unknown())
+    }
+}
+
+/// Where the name part of a revision ends: at the first `~` or `^`
+/// outside braces.
+fn base_len(text: &str) -> usize {
+    let mut depth = 0usize;
+    for (at, b) in text.bytes().enumerate() {
+        match b {
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            b'~' | b'^' if depth == 0 => return at,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Splits `<rev>:<path>` at the first colon outside braces, which may hold
+/// dates and search text.
+fn split_path(spec: &str) -> Option<(&str, &str)> {
+    let mut depth = 0usize;
+    for (at, b) in spec.bytes().enumerate() {
+        match b {
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            b':' if depth == 0 => return Some((&spec[..at], &spec[at + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The position of the brace closing the one `text` starts with.
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (at, b) in text.bytes().enumerate() {
+        match b {
+            b'{' => depth += 1,
+            b'}' if depth == 1 => return Some(at),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The remote-tracking ref of `branch`'s upstream, from its
+/// `branch.<name>.remote` and `branch.<name>.merge`.
+fn upstream(config: &Config, branch: &str) -> Result<String> {
+    let remote = config.get(&format!("branch.{branch}.remote"));
+    let merge = config.get(&format!("branch.{branch}.merge"));
+    let (Some(remote), Some(merge)) = (remote, merge) else {
+        return Err(Error::Usage(format!(
+            "no upstream configured for branch '{branch}'"
+        )));
+    };
+    tracking_ref(config, remote, merge).ok_or_else(|| {
+        Error::Usage(format!(
+            "upstream branch '{merge}' not stored as a remote-tracking branch"
+        ))
+    })
+}
+
+/// The remote-tracking ref `git push` would update for `branch`, following
+/// `branch.<name>.pushRemote`, `remote.pushDefault`, `remote.<name>.push`
+/// and `push.default`.
+fn push_destination(config: &Config, branch: &str) -> Result<String> {
+    let fetch_remote = config.get(&format!("branch.{branch}.remote"));
+    let remote = config
+        .get(&format!("branch.{branch}.pushRemote"))
+        .or_else(|| config.get("remote.pushDefault"))
+        .or(fetch_remote)
+        .ok_or_else(|| Error::Usage(format!("branch '{branch}' has no remote for pushing")))?;
+    let local = format!("refs/heads/{branch}");
+
+    let push_specs = config.get_all(&format!("remote.{remote}.push"));
+    let destination = if let Some(mapped) = push_specs
+        .into_iter()
+        .flatten()
+        .find_map(|spec| map_refspec(spec, &local))
+    {
+        mapped
+    } else {
+        match config.get("push.default").unwrap_or("simple") {
+            "nothing" => {
+                return Err(Error::Usage(
+                    "push has no destination (push.default is 'nothing')".into(),
+                ))
+            }
+            "current" | "matching" => local,
+            "upstream" | "tracking" => return upstream(config, branch),
+            _ if fetch_remote != Some(remote) => local,
+            _ => {
+                // `simple` pushes to an upstream of the same name only.
+                let merge = config.get(&format!("branch.{branch}.merge"));
+                if merge != Some(local.as_str()) {
+                    return Err(Error::Usage(
+                        "cannot resolve 'simple' push to a single destination".into(),
+                    ));
+                }
+                local
+            }
+        }
+    };
+    tracking_ref(config, remote, &destination).ok_or_else(|| {
+        Error::Usage(format!(
+            "push destination '{destination}' on remote '{remote}' has no local tracking branch"
+        ))
+    })
+}
+
+/// The local ref that tracks `name` on `remote`, through the remote's fetch
+/// refspecs. The remote `.` is the repository itself.
+fn tracking_ref(config: &Config, remote: &str, name: &str) -> Option<String> {
+    if remote == "." {
+        return Some(name.to_owned());
+    }
+    config
+        .get_all(&format!("remote.{remote}.fetch"))
+        .into_iter()
+        .flatten()
+        .find_map(|spec| map_refspec(spec, name))
+}
+
+/// Where the refspec `[+]<src>:<dst>` sends `name`, if it matches.
+fn map_refspec(spec: &str, name: &str) -> Option<String> {
+    let (src, dst) = spec.trim_start_matches('+').split_once(':')?;
+    match (src.split_once('*'), dst.split_once('*')) {
+        (Some((prefix, suffix)), Some((to_prefix, to_suffix))) => {
+            let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some(format!("{to_prefix}{matched}{to_suffix}"))
+        }
+        (None, None) if src == name => Some(dst.to_owned()),
+        _ => None,
+    }
+}
@@ -0,0 +1,355 @@
+//! The POSIX extended regular expressions `:/<text>` and `^{/<text>}`
+//! search commit messages with.
+//!
+//! Supports literals and `\` escapes, `.`, bracket expressions with ranges
+//! and `[:class:]` names, `^` and `$`, groups, `|`, and the `*`, `+`, `?`
+//! and `{m,n}` repetitions. As with `regexec` without `REG_NEWLINE`, `.`
+//! matches newlines and the anchors only match at the ends of the text.
+//! Matching simulates every alternative at once, so it takes time linear
+//! in the text whatever the pattern.
+
+use crate::error::{Error, Result};
+
+/// Bounds of `{m,n}`, as `RE_DUP_MAX`.
+const MAX_REPEAT: u32 = 255;
+
+#[derive(Clone, Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Byte(u8),
+    Any,
+    Set(Box<[bool; 256]>),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Byte(u8),
+    Any,
+    Set(Box<[bool; 256]>),
+    Start,
+    End,
+    /// Continue at both targets.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut parser = Parser {
+            pattern: pattern.as_bytes(),
+            at: 0,
+        };
+        let node = parser.alternation()?;
+        if parser.at < parser.pattern.len() {
+            return Err(parser.error("unmatched )"));
+        }
+        let mut program = Vec::new();
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        let mut seen = vec![usize::MAX; self.program.len()];
+        for at in 0..=text.len() {
+            // A match may start at any position.
+            if self.add(&mut current, &mut seen, 0, at, text.len()) {
+                return true;
+            }
+            let Some(&byte) = text.get(at) else { break };
+            for &pc in &current {
+                let advances = match &self.program[pc] {
+                    Inst::Byte(b) => *b == byte,
+                    Inst::Any => true,
+                    Inst::Set(set) => set[usize::from(byte)],
+                    _ => false,
+                };
+                if advances && self.add(&mut next, &mut seen, pc + 1, at + 1, text.len()) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+
+    /// Adds the threads reachable from `pc` without consuming input at
+    /// position `at`, and tells whether one of them matched.
+    fn add(
+        &self,
+        threads: &mut Vec<usize>,
+        seen: &mut [usize],
+        pc: usize,
+        at: usize,
+        len: usize,
+    ) -> bool {
+        if seen[pc] == at {
+            return false;
+        }
+        seen[pc] = at;
+        match self.program[pc] {
+            Inst::Match => true,
+            Inst::Jump(to) => self.add(threads, seen, to, at, len),
+            Inst::Split(a, b) => {
+                self.add(threads, seen, a, at, len) || self.add(threads, seen, b, at, len)
+            }
+            Inst::Start => at == 0 && self.add(threads, seen, pc + 1, at, len),
+            Inst::End => at == len && self.add(threads, seen, pc + 1, at, len),
+            _ => {
+                threads.push(pc);
+                false
+            }
+        }
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Byte(b) => program.push(Inst::Byte(*b)),
+        Node::Any => program.push(Inst::Any),
+        Node::Set(set) => program.push(Inst::Set(set.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Concat(nodes) => nodes.iter().for_each(|n| compile(n, program)),
+        Node::Alt(nodes) => {
+            let mut jumps = Vec::new();
+            for (i, n) in nodes.iter().enumerate() {
+                if i + 1 == nodes.len() {
+                    compile(n, program);
+                    break;
+                }
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(n, program);
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        }
+        Node::Repeat(n, min, max) => {
+            for _ in 0..*min {
+                compile(n, program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(n, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(n, program);
+                    }
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, program.len());
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'p> {
+    pattern: &'p [u8],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> Error {
+        Error::parse(
+            "regular expression",
+            format!("{reason} in {}", String::from_utf8_lossy(self.pattern)),
+        )
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.pattern.get(self.at).copied()
+    }
+
+    fn alternation(&mut self) -> Result<Node> {
+        let mut branches = vec![self.concatenation()?];
+        while self.peek() == Some(b'|') {
+            self.at += 1;
+            branches.push(self.concatenation()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.pop().unwrap(),
+            _ => Node::Alt(branches),
+        })
+    }
+
+    fn concatenation(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(b) = self.peek() {
+            if b == b'|' || b == b')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.repetitions(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn repetitions(&mut self, mut node: Node) -> Result<Node> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some(b'*') => (0, None),
+                Some(b'+') => (1, None),
+                Some(b'?') => (0, Some(1)),
+                Some(b'{') => match self.interval()? {
+                    Some(bounds) => bounds,
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            // Past the operator, or the `}` an interval stops on.
+            self.at += 1;
+            node = Node::Repeat(Box::new(node), min, max);
+        }
+    }
+
+    /// Parses `{m}`, `{m,}` or `{m,n}`, stopping on the closing brace, or
+    /// leaves a `{` that starts none of them to be read as a literal.
+    fn interval(&mut self) -> Result<Option<(u32, Option<u32>)>> {
+        let rest = &self.pattern[self.at + 1..];
+        let Some(close) = rest.iter().position(|&b| b == b'}') else {
+            return Ok(None);
+        };
+        let body = std::str::from_utf8(&rest[..close]).unwrap_or_default();
+        let number = |s: &str| s.parse::<u32>().ok().filter(|&n| n <= MAX_REPEAT);
+        let bounds = match body.split_once(',') {
+            None => number(body).map(|n| (n, Some(n))),
+            Some((min, "")) => number(min).map(|n| (n, None)),
+            Some((min, max)) => number(min).zip(number(max)).map(|(m, n)| (m, Some(n))),
+        };
+        match bounds {
+            Some((min, Some(max))) if min > max => Err(self.error("invalid interval")),
+            Some(bounds) => {
+                self.at += close + 1;
+                Ok(Some(bounds))
+            }
+            None if body.bytes().next().is_some_and(|b| b.is_ascii_digit()) => {
+                Err(self.error("invalid interval"))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let b = self.pattern[self.at];
+        self.at += 1;
+        Ok(match b {
+            b'.' => Node::Any,
+            b'^' => Node::Start,
+            b'$' => Node::End,
+            b'(' => {
+                let inner = self.alternation()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.error("unmatched ("));
+                }
+                self.at += 1;
+                inner
+            }
+            b'[' => self.bracket()?,
+            b'\\' => {
+                let escaped = self
+                    .peek()
+                    .ok_or_else(|| self.error("trailing backslash"))?;
+                self.at += 1;
+                Node::Byte(escaped)
+            }
+            b'*' | b'+' | b'?' => return Err(self.error("nothing to repeat")),
+            b => Node::Byte(b),
+        })
+    }
+
+    /// Parses a bracket expression after its `[`.
+    fn bracket(&mut self) -> Result<Node> {
+        let mut set = Box::new([false; 256]);
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.at += 1;
+        }
+        let mut first = true;
+        loop {
+            let b = self.peek().ok_or_else(|| self.error("unmatched ["))?;
+            self.at += 1;
+            if b == b']' && !first {
+                break;
+            }
+            first = false;
+            if b == b'[' && self.peek() == Some(b':') {
+                let rest = &self.pattern[self.at + 1..];
+                let end = rest
+                    .windows(2)
+                    .position(|w| w == b":]")
+                    .ok_or_else(|| self.error("unmatched [:"))?;
+                let name = String::from_utf8_lossy(&rest[..end]).into_owned();
+                let class = class(&name).ok_or_else(|| self.error("unknown character class"))?;
+                (0..=255u8)
+                    .filter(|&c| class(c))
+                    .for_each(|c| set[usize::from(c)] = true);
+                self.at += end + 3;
+                continue;
+            }
+            let range_end = match (self.peek(), self.pattern.get(self.at + 1)) {
+                (Some(b'-'), Some(&end)) if end != b']' => Some(end),
+                _ => None,
+            };
+            match range_end {
+                Some(end) => {
+                    if end < b {
+                        return Err(self.error("invalid range"));
+                    }
+                    (b..=end).for_each(|c| set[usize::from(c)] = true);
+                    self.at += 2;
+                }
+                None => set[usize::from(b)] = true,
+            }
+        }
+        if negated {
+            set.iter_mut().for_each(|member| *member = !*member);
+        }
+        Ok(Node::Set(set))
+    }
+}
+
+fn class(name: &str) -> Option<fn(u8) -> bool> {
+    Some(match name {
+        "alpha" => |c: u8| c.is_ascii_alphabetic(),
+        "digit" => |c: u8| c.is_ascii_digit(),
+        "alnum" => |c: u8| c.is_ascii_alphanumeric(),
+        "upper" => |c: u8| c.is_ascii_uppercase(),
+        "lower" => |c: u8| c.is_ascii_lowercase(),
+        "space" => |c: u8| c.is_ascii_whitespace() || c == 0x0b,
+        "blank" => |c: u8| c == b' ' || c == b'\t',
+        "punct" => |c: u8| c.is_ascii_punctuation(),
+        "xdigit" => |c: u8| c.is_ascii_hexdigit(),
+        "cntrl" => |c: u8| c.is_ascii_control(),
+        "print" => |c: u8| (0x20..0x7f).contains(&c),
+        "graph" => |c: u8| c.is_ascii_graphic(),
+        _ => return None,
+    })
+}
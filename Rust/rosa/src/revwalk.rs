@@ -0,0 +1,703 @@
+//! Revision walks: the commits reachable from some tips but not from
+//! others, in the orders `rev-list` shows them, and the trees, blobs and
+//! tags they bring in.
+//!
+//! The walk follows git's: commits come off a queue newest first, and once
+//! a tip is hidden the whole walk is made up front, so that commits first
+//! met as wanted can still turn out to be reachable from a hidden one. It
+//! stops a few commits after only hidden ones are left, which allows for
+//! some clock skew between the two sides.
+//!
+//! Limited to paths, the walk simplifies history as
+//! [`CommitCache::simplify`] does: commits that leave the paths as a
+//! parent had them are passed over, and the walk goes on through only
+//! that parent.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+use crate::error::{Error, Result};
+use crate::history::{self, CommitCache, PathLimit, Simplified};
+use crate::object::{ObjectId, ObjectKind};
+use crate::path;
+use crate::refs;
+use crate::repository::Repository;
+use crate::revision;
+
+/// Queued once.
+const SEEN: u8 = 1 << 0;
+/// Reachable from a hidden tip.
+const UNINTERESTING: u8 = 1 << 1;
+/// In the queue now.
+const QUEUED: u8 = 1 << 2;
+
+/// How many commits a limited walk goes on for once only uninteresting
+/// ones are queued.
+const SLOP: u32 = 5;
+
+/// The order commits come out of a walk in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    /// Most recent commit date first.
+    #[default]
+    Chronological,
+    /// By commit date, but no parent before all of its children.
+    Date,
+    /// No parent before all of its children, and lines of history kept
+    /// together rather than interleaved.
+    Topo,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WalkOptions {
+    pub order: Order,
+    /// Oldest first, after `max_count` has picked the newest.
+    pub reverse: bool,
+    /// Follow only the first parent of merges.
+    pub first_parent: bool,
+    pub max_count: Option<usize>,
+    /// Keep only the commits descending from a hidden tip.
+    pub ancestry_path: bool,
+    /// Also collect the tags, trees and blobs given as tips, for
+    /// [`RevWalk::objects`]; otherwise they are ignored.
+    pub objects: bool,
+    /// Only show commits that change these paths.
+    pub paths: Vec<Vec<u8>>,
+}
+
+/// A walk over the commits reachable from the pushed tips and not from the
+/// hidden ones. Iterating yields their ids.
+pub struct RevWalk<'r> {
+    repo: &'r Repository,
+    cache: CommitCache<'r>,
+    options: WalkOptions,
+    limit: PathLimit,
+    /// How each interesting commit met so far is simplified.
+    verdicts: HashMap<ObjectId, Rc<Simplified>>,
+    flags: HashMap<ObjectId, u8>,
+    /// Newest first, then first queued first.
+    queue: BinaryHeap<(i64, Reverse<u64>, ObjectId)>,
+    queued: u64,
+    /// Queued commits not known to be uninteresting.
+    interesting: usize,
+    /// Hidden commits given as tips, where ancestry paths start.
+    bottoms: Vec<ObjectId>,
+    /// Tags, trees and blobs given as tips, with the names
+    /// [`RevWalk::objects`] lists them under.
+    pending: Vec<(ObjectId, Vec<u8>)>,
+    limited: bool,
+    started: bool,
+    /// What a limited walk went through, before uninteresting commits
+    /// were dropped.
+    walked: Vec<ObjectId>,
+    /// What is left to return, once a limited walk has been made.
+    output: Option<VecDeque<ObjectId>>,
+    returned: Vec<ObjectId>,
+    /// What resolving the names given to [`RevWalk::add_args`] warned
+    /// about.
+    warnings: Vec<String>,
+}
+
+impl<'r> RevWalk<'r> {
+    pub fn new(repo: &'r Repository, options: WalkOptions) -> Result<Self> {
+        let limited =
+            options.order != Order::Chronological || options.reverse || options.ancestry_path;
+        let cache = CommitCache::new(repo)?;
+        let limit = cache.path_limit(&options.paths);
+        Ok(RevWalk {
+            repo,
+            cache,
+            options,
+            limit,
+            verdicts: HashMap::new(),
+            flags: HashMap::new(),
+            queue: BinaryHeap::new(),
+            queued: 0,
+            interesting: 0,
+            bottoms: Vec::new(),
+            pending: Vec::new(),
+            limited,
+            started: false,
+            walked: Vec::new(),
+            output: None,
+            returned: Vec::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Adds what is reachable from `id`, peeling tags.
+    pub fn push(&mut self, id: ObjectId) -> Result<()> {
+        self.add_tip(id, false)
+    }
+
+    /// Leaves out what is reachable from `id`, peeling tags.
+    pub fn hide(&mut self, id: ObjectId) -> Result<()> {
+        self.add_tip(id, true)
+    }
+
+    /// Adds tips as `rev-list` takes them on its command line: `<rev>`,
+    /// `^<rev>` to hide one, `<a>..<b>` for `^<a> <b>`, `<a>...<b>` for
+    /// both but not their merge bases (an empty side means `HEAD`), and
+    /// `--all` and `--branches` for HEAD and every ref or every branch.
+    /// `--not` flips whether the tips after it are hidden.
+    pub fn add_args(&mut self, args: &[String]) -> Result<()> {
+        let mut negated = false;
+        for arg in args {
+            match arg.as_str() {
+                "--not" => negated = !negated,
+                "--all" => {
+                    if let Some(head) = refs::resolve(self.repo, "HEAD")? {
+                        self.add_tip(head, negated)?;
+                    }
+                    for (_, id) in refs::list(self.repo)? {
+                        self.add_tip(id, negated)?;
+                    }
+                }
+                "--branches" => {
+                    for (name, id) in refs::list(self.repo)? {
+                        if name.starts_with("refs/heads/") {
+                            self.add_tip(id, negated)?;
+                        }
+                    }
+                }
+                arg => self.add_revision(arg, negated)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn add_revision(&mut self, arg: &str, negated: bool) -> Result<()> {
+        if let Some(at) = arg.find("..") {
+            // A name that only looks like a range is tried whole below.
+            let warned = self.warnings.len();
+            match self.range(&arg[..at], &arg[at + 2..]) {
+                Ok(range) => {
+                    for (id, hidden) in range {
+                        self.add_tip(id, hidden != negated)?;
+                    }
+                    return Ok(());
+                }
+                Err(_) => self.warnings.truncate(warned),
+            }
+        }
+        let (name, hidden) = match arg.strip_prefix('^') {
+            Some(name) => (name, true),
+            None => (arg, false),
+        };
+        // Committish, to settle ambiguous prefixes, but not peeled: tags
+        // given as tips are walked through, and kept with `objects`.
+        let resolved = revision::resolve(self.repo, name, Some(ObjectKind::Commit), false)?;
+        self.warnings.extend(resolved.warnings);
+        self.add_tip(resolved.id, hidden != negated)
+    }
+
+    /// Takes what resolving names has warned about so far.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// The tips of `<a>..<b>`, or `<a>...<b>` when `b` starts with a dot,
+    /// each with whether it is hidden.
+    fn range(&mut self, a: &str, b: &str) -> Result<Vec<(ObjectId, bool)>> {
+        let (b, symmetric) = match b.strip_prefix('.') {
+            Some(b) => (b, true),
+            None => (b, false),
+        };
+        let (a, b) = (self.range_end(a)?, self.range_end(b)?);
+        if !symmetric {
+            return Ok(vec![(a, true), (b, false)]);
+        }
+        let mut tips = vec![(a, false), (b, false)];
+        for base in history::merge_bases(&self.cache, a, &[b])? {
+            tips.push((base, true));
+        }
+        Ok(tips)
+    }
+
+    /// One end of a range, `HEAD` when left out.
+    fn range_end(&mut self, name: &str) -> Result<ObjectId> {
+        let name = if name.is_empty() { "HEAD" } else { name };
+        let resolved = revision::resolve(self.repo, name, Some(ObjectKind::Commit), true)?;
+        self.warnings.extend(resolved.warnings);
+        Ok(resolved.id)
+    }
+
+    fn add_tip(&mut self, mut id: ObjectId, hidden: bool) -> Result<()> {
+        loop {
+            let (kind, _) = self.repo.odb().read_header(&id)?;
+            match kind {
+                ObjectKind::Tag => {
+                    let tag = self.repo.odb().read(&id)?.into_tag(id)?;
+                    if hidden {
+                        self.mark(id, UNINTERESTING);
+                    } else if self.options.objects {
+                        self.pending.push((id, tag.name));
+                    }
+                    id = tag.object;
+                }
+                ObjectKind::Commit => {
+                    self.enqueue(id)?;
+                    if hidden {
+                        self.limited = true;
+                        self.bottoms.push(id);
+                        self.mark_uninteresting(id)?;
+                    }
+                    return Ok(());
+                }
+                _ if !self.options.objects => return Ok(()),
+                ObjectKind::Tree if hidden => return self.mark_tree_uninteresting(id),
+                _ if hidden => {
+                    self.mark(id, UNINTERESTING);
+                    return Ok(());
+                }
+                _ => {
+                    self.pending.push((id, Vec::new()));
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn flags(&self, id: &ObjectId) -> u8 {
+        self.flags.get(id).copied().unwrap_or(0)
+    }
+
+    fn mark(&mut self, id: ObjectId, flags: u8) {
+        let old = self.flags(&id);
+        if old & (QUEUED | UNINTERESTING) == QUEUED && flags & UNINTERESTING != 0 {
+            self.interesting -= 1;
+        }
+        self.flags.insert(id, old | flags);
+    }
+
+    fn unmark(&mut self, id: ObjectId, flags: u8) {
+        if let Some(old) = self.flags.get_mut(&id) {
+            *old &= !flags;
+        }
+    }
+
+    fn enqueue(&mut self, id: ObjectId) -> Result<()> {
+        if self.flags(&id) & SEEN != 0 {
+            return Ok(());
+        }
+        let time = self.cache.get(&id)?.time;
+        self.queue.push((time, Reverse(self.queued), id));
+        self.queued += 1;
+        self.mark(id, SEEN | QUEUED);
+        if self.flags(&id) & UNINTERESTING == 0 {
+            self.interesting += 1;
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<ObjectId> {
+        let (_, _, id) = self.queue.pop()?;
+        if self.flags(&id) & UNINTERESTING == 0 {
+            self.interesting -= 1;
+        }
+        self.unmark(id, QUEUED);
+        Some(id)
+    }
+
+    /// Marks `id` uninteresting, and what is below it as far as the walk
+    /// has already been.
+    fn mark_uninteresting(&mut self, id: ObjectId) -> Result<()> {
+        self.mark(id, UNINTERESTING);
+        let mut pending = self.seen_parents(&id)?;
+        while let Some(id) = pending.pop() {
+            if self.flags(&id) & UNINTERESTING != 0 {
+                continue;
+            }
+            self.mark(id, UNINTERESTING);
+            pending.extend(self.seen_parents(&id)?);
+        }
+        Ok(())
+    }
+
+    /// The parents of `id` if the walk has been there, none otherwise.
+    fn seen_parents(&self, id: &ObjectId) -> Result<Vec<ObjectId>> {
+        Ok(match self.flags(id) & SEEN {
+            0 => Vec::new(),
+            _ => self.cache.get(id)?.parents.clone(),
+        })
+    }
+
+    fn simplified(&mut self, id: ObjectId) -> Result<Rc<Simplified>> {
+        if let Some(verdict) = self.verdicts.get(&id) {
+            return Ok(verdict.clone());
+        }
+        let verdict = Rc::new(
+            self.cache
+                .simplify(&id, &self.limit, self.options.first_parent)?,
+        );
+        self.verdicts.insert(id, verdict.clone());
+        Ok(verdict)
+    }
+
+    /// Whether `id`, met by the walk, is one it yields.
+    pub fn shown(&mut self, id: ObjectId) -> Result<bool> {
+        Ok(self.flags(&id) & UNINTERESTING == 0 && self.simplified(id)?.shown)
+    }
+
+    /// The parents of `id` as far as the walk is concerned: all of them for
+    /// uninteresting commits, the ones simplification keeps otherwise.
+    fn walk_parents(&mut self, id: ObjectId) -> Result<Vec<ObjectId>> {
+        Ok(match self.flags(&id) & UNINTERESTING {
+            0 => self.simplified(id)?.parents.clone(),
+            _ => self.cache.get(&id)?.parents.clone(),
+        })
+    }
+
+    /// Queues the parents of `id`, passing on whether it is uninteresting.
+    fn add_parents(&mut self, id: ObjectId) -> Result<()> {
+        if self.flags(&id) & UNINTERESTING != 0 {
+            for parent in self.cache.get(&id)?.parents.iter().copied() {
+                self.mark_uninteresting(parent)?;
+                self.enqueue(parent)?;
+            }
+            return Ok(());
+        }
+        let mut followed = self.walk_parents(id)?;
+        if self.options.first_parent {
+            followed.truncate(1);
+        }
+        for parent in followed {
+            self.enqueue(parent)?;
+        }
+        Ok(())
+    }
+
+    /// The parents of `id` to show with it: the followed ones, each
+    /// replaced by its nearest shown ancestor when passed over by path
+    /// simplification. Uninteresting parents are kept as they are.
+    pub fn parents(&mut self, id: ObjectId) -> Result<Vec<ObjectId>> {
+        let mut followed = self.walk_parents(id)?;
+        if self.options.first_parent {
+            followed.truncate(1);
+        }
+        let mut parents = Vec::with_capacity(followed.len());
+        for mut parent in followed {
+            loop {
+                if self.flags(&parent) & UNINTERESTING != 0 {
+                    break;
+                }
+                let verdict = self.simplified(parent)?;
+                if verdict.shown {
+                    break;
+                }
+                // Passed over commits go on to a single parent, or none
+                // at the root.
+                match verdict.parents.first() {
+                    Some(&next) => parent = next,
+                    None => break,
+                }
+            }
+            let root = self.flags(&parent) & UNINTERESTING == 0 && !self.simplified(parent)?.shown;
+            if !root && !parents.contains(&parent) {
+                parents.push(parent);
+            }
+        }
+        Ok(parents)
+    }
+
+    fn next_commit(&mut self) -> Result<Option<ObjectId>> {
+        if !self.started {
+            self.started = true;
+            if self.limited {
+                let output = self.limit()?;
+                self.output = Some(output);
+            }
+        }
+        let id = match &mut self.output {
+            Some(output) => output.pop_front(),
+            None => self.stream()?,
+        };
+        if let Some(id) = id {
+            self.returned.push(id);
+        }
+        Ok(id)
+    }
+
+    /// The next commit of a walk that hides nothing and needs no sorting,
+    /// found as it goes.
+    fn stream(&mut self) -> Result<Option<ObjectId>> {
+        if self
+            .options
+            .max_count
+            .is_some_and(|max| self.returned.len() >= max)
+        {
+            return Ok(None);
+        }
+        while let Some(id) = self.pop() {
+            self.add_parents(id)?;
+            if self.shown(id)? {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Makes the whole walk and returns what it will yield.
+    fn limit(&mut self) -> Result<VecDeque<ObjectId>> {
+        if self.options.ancestry_path && self.bottoms.is_empty() {
+            return Err(Error::Usage(
+                "--ancestry-path given but there are no bottom commits".into(),
+            ));
+        }
+        let mut walked = Vec::new();
+        let mut date = i64::MAX;
+        let mut slop = SLOP;
+        while let Some(id) = self.pop() {
+            self.add_parents(id)?;
+            if self.flags(&id) & UNINTERESTING != 0 {
+                slop = self.still_interesting(date, slop);
+                if slop > 0 {
+                    continue;
+                }
+                break;
+            }
+            date = self.cache.get(&id)?.time;
+            walked.push(id);
+        }
+        if self.options.ancestry_path {
+            self.limit_to_ancestry(&walked)?;
+        }
+        walked = match self.options.order {
+            Order::Chronological => walked,
+            order => self.sort_topologically(walked, order)?,
+        };
+
+        let mut output = VecDeque::new();
+        for &id in &walked {
+            if self.shown(id)? {
+                output.push_back(id);
+            }
+        }
+        if let Some(max) = self.options.max_count {
+            output.truncate(max);
+        }
+        if self.options.reverse {
+            output.make_contiguous().reverse();
+        }
+        self.walked = walked;
+        Ok(output)
+    }
+
+    /// How much longer a limited walk goes on after an uninteresting
+    /// commit: in full while anything queued is interesting or newer than
+    /// the last interesting commit (`date`), then for `slop` more.
+    fn still_interesting(&self, date: i64, slop: u32) -> u32 {
+        let Some(&(newest, _, _)) = self.queue.peek() else {
+            return 0;
+        };
+        if date <= newest || self.interesting > 0 {
+            return SLOP;
+        }
+        slop - 1
+    }
+
+    /// Marks uninteresting the walked commits that do not descend from a
+    /// bottom.
+    fn limit_to_ancestry(&mut self, walked: &[ObjectId]) -> Result<()> {
+        let mut on_path: HashSet<ObjectId> = self.bottoms.iter().copied().collect();
+        loop {
+            let mut progress = false;
+            // Parents mostly come later, so this mostly takes one pass.
+            for &id in walked.iter().rev() {
+                if on_path.contains(&id) || self.flags(&id) & UNINTERESTING != 0 {
+                    continue;
+                }
+                if self.walk_parents(id)?.iter().any(|p| on_path.contains(p)) {
+                    on_path.insert(id);
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        for &id in walked {
+            if !on_path.contains(&id) {
+                self.mark(id, UNINTERESTING);
+            }
+        }
+        Ok(())
+    }
+
+    /// Orders `walked` so that each commit comes before its parents,
+    /// starting from the commits that are no others' parents in the order
+    /// they were walked. [`Order::Topo`] then goes down one line as far as
+    /// it can before another; [`Order::Date`] takes the newest commit whose
+    /// children have all been taken.
+    fn sort_topologically(&mut self, walked: Vec<ObjectId>, order: Order) -> Result<Vec<ObjectId>> {
+        let mut parents = HashMap::with_capacity(walked.len());
+        for &id in &walked {
+            parents.insert(id, self.walk_parents(id)?);
+        }
+        // One more than the number of children among the walked commits.
+        let mut indegree: HashMap<ObjectId, usize> = walked.iter().map(|&id| (id, 1)).collect();
+        for id in &walked {
+            for parent in &parents[id] {
+                if let Some(n) = indegree.get_mut(parent) {
+                    *n += 1;
+                }
+            }
+        }
+
+        let mut ready = ReadyQueue {
+            dated: order == Order::Date,
+            stack: Vec::new(),
+            heap: BinaryHeap::new(),
+            added: 0,
+        };
+        for id in walked.iter().filter(|id| indegree[id] == 1) {
+            ready.put(&self.cache, *id)?;
+        }
+        // Tips come out in walk order, which a stack would reverse.
+        ready.stack.reverse();
+
+        let mut sorted = Vec::with_capacity(walked.len());
+        while let Some(id) = ready.get() {
+            for parent in &parents[&id] {
+                let Some(n) = indegree.get_mut(parent) else {
+                    continue;
+                };
+                if *n == 0 {
+                    continue;
+                }
+                *n -= 1;
+                if *n == 1 {
+                    ready.put(&self.cache, *parent)?;
+                }
+            }
+            indegree.insert(id, 0);
+            sorted.push(id);
+        }
+        Ok(sorted)
+    }
+
+    /// The tags, trees and blobs the walk brings in, each with the path it
+    /// was found at (or a tag's name): first those given as tips, then the
+    /// trees of the commits returned so far, depth first. Objects in the
+    /// trees of the uninteresting commits at the edge of the walk are left
+    /// out.
+    pub fn objects(&mut self) -> Result<Vec<(ObjectId, Vec<u8>)>> {
+        for id in std::mem::take(&mut self.walked) {
+            let info = self.cache.get(&id)?;
+            if self.flags(&id) & UNINTERESTING != 0 {
+                self.mark_tree_uninteresting(info.tree)?;
+                continue;
+            }
+            for parent in &info.parents {
+                if self.flags(parent) & UNINTERESTING != 0 {
+                    let tree = self.cache.get(parent)?.tree;
+                    self.mark_tree_uninteresting(tree)?;
+                }
+            }
+        }
+
+        let mut found = Vec::new();
+        for (id, name) in std::mem::take(&mut self.pending) {
+            let (kind, _) = self.repo.odb().read_header(&id)?;
+            match kind {
+                ObjectKind::Tag if self.flags(&id) & (SEEN | UNINTERESTING) == 0 => {
+                    self.mark(id, SEEN);
+                    found.push((id, name));
+                }
+                ObjectKind::Tag => {}
+                _ => self.list_tree(id, name, &mut found)?,
+            }
+        }
+        for id in std::mem::take(&mut self.returned) {
+            let tree = self.cache.get(&id)?.tree;
+            self.list_tree(tree, Vec::new(), &mut found)?;
+        }
+        Ok(found)
+    }
+
+    /// Lists `id` and, if it is a tree, everything below it not yet seen.
+    fn list_tree(
+        &mut self,
+        id: ObjectId,
+        path: Vec<u8>,
+        found: &mut Vec<(ObjectId, Vec<u8>)>,
+    ) -> Result<()> {
+        let mut pending = vec![(id, path, true)];
+        while let Some((id, path, is_tree)) = pending.pop() {
+            if self.flags(&id) & (SEEN | UNINTERESTING) != 0 {
+                continue;
+            }
+            self.mark(id, SEEN);
+            let is_tree = is_tree && self.repo.odb().read_header(&id)?.0 == ObjectKind::Tree;
+            if is_tree {
+                let tree = self.repo.odb().read(&id)?.into_tree(id)?;
+                for entry in tree.entries.iter().rev() {
+                    // Submodule commits live in another repository.
+                    if entry.kind() == Some(ObjectKind::Commit) {
+                        continue;
+                    }
+                    pending.push((entry.id, path::join(&path, &entry.name), entry.is_tree()));
+                }
+            }
+            found.push((id, path));
+        }
+        Ok(())
+    }
+
+    fn mark_tree_uninteresting(&mut self, id: ObjectId) -> Result<()> {
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if self.flags(&id) & UNINTERESTING != 0 {
+                continue;
+            }
+            self.mark(id, UNINTERESTING);
+            let tree = self.repo.odb().read(&id)?.into_tree(id)?;
+            for entry in &tree.entries {
+                match entry.kind() {
+                    Some(ObjectKind::Tree) => pending.push(entry.id),
+                    Some(ObjectKind::Blob) => self.mark(entry.id, UNINTERESTING),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for RevWalk<'_> {
+    type Item = Result<ObjectId>;
+
+    fn next(&mut self) -> Option<Result<ObjectId>> {
+        self.next_commit().transpose()
+    }
+}
+
+/// Commits whose children have all been taken, for
+/// [`RevWalk::sort_topologically`]: a stack, or newest first when dated.
+struct ReadyQueue {
+    dated: bool,
+    stack: Vec<ObjectId>,
+    heap: BinaryHeap<(i64, Reverse<u64>, ObjectId)>,
+    added: u64,
+}
+
+impl ReadyQueue {
+    fn put(&mut self, cache: &CommitCache, id: ObjectId) -> Result<()> {
+        if self.dated {
+            self.heap
+                .push((cache.get(&id)?.time, Reverse(self.added), id));
+            self.added += 1;
+        } else {
+            self.stack.push(id);
+        }
+        Ok(())
+    }
+
+    fn get(&mut self) -> Option<ObjectId> {
+        match self.dated {
+            true => self.heap.pop().map(|(_, _, id)| id),
+            false => self.stack.pop(),
+        }
+    }
+}
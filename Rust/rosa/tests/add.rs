@@ -0,0 +1,73 @@
+//! `add` names paths as given, so a symlink is staged as a link rather
+//! than as whatever it points to.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rosa::index::{Index, MODE_TYPE_REGULAR, MODE_TYPE_SYMLINK};
+use rosa::object::HashAlgorithm;
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_staged_as_links() {
+    let (dir, repo) = common::scratch_repo("add-symlink", HashAlgorithm::Sha1);
+    fs::create_dir(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub/target.txt"), "content\n").unwrap();
+    std::os::unix::fs::symlink("sub/target.txt", dir.join("link")).unwrap();
+    // A link leading out of the worktree is still just a link.
+    std::os::unix::fs::symlink("/", dir.join("outside")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .args(["add", "link", "outside", "sub/../sub/./target.txt"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    let index = Index::read(&repo).unwrap();
+    let staged: Vec<_> = index
+        .entries
+        .iter()
+        .map(|e| {
+            (
+                &e.name[..],
+                e.mode_type,
+                repo.odb().read_raw(&e.id).unwrap().1,
+            )
+        })
+        .collect();
+    assert_eq!(
+        staged,
+        [
+            (&b"link"[..], MODE_TYPE_SYMLINK, b"sub/target.txt".to_vec()),
+            (b"outside", MODE_TYPE_SYMLINK, b"/".to_vec()),
+            (b"sub/target.txt", MODE_TYPE_REGULAR, b"content\n".to_vec()),
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn added_names_are_shown_quoted() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let (dir, _repo) = common::scratch_repo("add-quoted", HashAlgorithm::Sha1);
+    fs::write(dir.join(OsStr::from_bytes(b"caf\xe9.txt")), "x").unwrap();
+    fs::write(dir.join("plain.txt"), "y").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .args(["add", "."])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "add '\"caf\\351.txt\"'\nadd 'plain.txt'\n"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
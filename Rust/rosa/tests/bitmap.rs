@@ -0,0 +1,305 @@
+//! EWAH is what every `.bitmap` is made of, so its encoder must produce
+//! what git's decoder expects, and a pack's bitmaps must say exactly what
+//! a plain walk finds. `tests/bitmap` holds a pack git 2.39 wrote with
+//! `repack -adb` for a short history with a merge and an annotated tag.
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rosa::object::HashAlgorithm;
+use rosa::pack::ewah::Bitmap;
+use rosa::pack::{self, Pack, PackBitmap, PackObject, PackOptions};
+use rosa::reachable;
+use rosa::{Object, ObjectId, ObjectKind, Repository};
+
+fn from_words(words: &[u64]) -> Bitmap {
+    let mut bitmap = Bitmap::new();
+    for (n, word) in words.iter().enumerate() {
+        for bit in (0..64).filter(|bit| word & (1 << bit) != 0) {
+            bitmap.set(n * 64 + bit);
+        }
+    }
+    bitmap
+}
+
+/// The compressed words of `bitmap` and the index of its last marker.
+fn encode(bitmap: &Bitmap) -> (Vec<u64>, u32) {
+    let mut out = Vec::new();
+    bitmap.serialize(&mut out);
+    let count = u32::from_be_bytes(out[4..8].try_into().unwrap()) as usize;
+    assert_eq!(out.len(), 12 + count * 8);
+    let words = out[8..8 + count * 8]
+        .chunks(8)
+        .map(|w| u64::from_be_bytes(w.try_into().unwrap()))
+        .collect();
+    let last_marker = u32::from_be_bytes(out[8 + count * 8..].try_into().unwrap());
+    (words, last_marker)
+}
+
+fn round_trip(bitmap: &Bitmap) -> Bitmap {
+    let mut out = Vec::new();
+    bitmap.serialize(&mut out);
+    out.extend_from_slice(b"after");
+    let mut pos = 0;
+    let parsed = Bitmap::parse(&out, &mut pos, usize::MAX).unwrap();
+    assert_eq!(&out[pos..], b"after");
+    parsed
+}
+
+/// A marker word: `run` words of `ones` or zeros, then `literals` words.
+fn marker(ones: bool, run: u64, literals: u64) -> u64 {
+    u64::from(ones) | run << 1 | literals << 33
+}
+
+const ONES: u64 = u64::MAX;
+
+#[test]
+fn runs_and_literals_are_encoded_like_git() {
+    // What git wrote for the commit kind bitmap of the fixture.
+    assert_eq!(
+        encode(&from_words(&[0x3d])),
+        (vec![marker(false, 0, 1), 0x3d], 0)
+    );
+
+    let words = [0, 0, 5, ONES, ONES, 1 << 63, 0, ONES];
+    let expected = vec![
+        marker(false, 2, 1),
+        5,
+        marker(true, 2, 1),
+        1 << 63,
+        marker(false, 1, 0),
+        marker(true, 1, 0),
+    ];
+    assert_eq!(encode(&from_words(&words)), (expected, 5));
+
+    // A run of zeros straight after a run of ones needs its own marker.
+    assert_eq!(
+        encode(&from_words(&[ONES, 0, 0, 7])),
+        (vec![marker(true, 1, 0), marker(false, 2, 1), 7], 1)
+    );
+    // Trailing empty words are left out, and nothing at all is one marker.
+    assert_eq!(
+        encode(&from_words(&[9, 0, 0])),
+        (vec![marker(false, 0, 1), 9], 0)
+    );
+    assert_eq!(encode(&Bitmap::new()), (vec![0], 0));
+}
+
+#[test]
+fn encoded_bitmaps_decode_to_the_same_bits() {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..200 {
+        let len = (next() % 40) as usize;
+        // Mostly fills, so that runs of every length turn up.
+        let words: Vec<u64> = (0..len)
+            .map(|_| match next() % 4 {
+                0 => 0,
+                1 => ONES,
+                _ if next() % 2 == 0 => 0,
+                _ => next(),
+            })
+            .collect();
+        let bitmap = from_words(&words);
+        let parsed = round_trip(&bitmap);
+        assert!(parsed.ones().eq(bitmap.ones()), "{words:x?}");
+        for bit in 0..len * 64 + 64 {
+            assert_eq!(parsed.get(bit), bitmap.get(bit));
+        }
+    }
+}
+
+#[test]
+fn set_operations() {
+    let mut a = from_words(&[0b1100, ONES]);
+    let b = from_words(&[0b1010]);
+    let mut or = a.clone();
+    or.or(&b);
+    assert!(or.ones().eq([1, 2, 3].into_iter().chain(64..128)));
+    let mut xor = b.clone();
+    xor.xor(&a);
+    assert!(xor.ones().eq([1, 2].into_iter().chain(64..128)));
+    a.and_not(&b);
+    assert!(a.ones().eq([2].into_iter().chain(64..128)));
+}
+
+#[test]
+fn malformed_bitmaps_are_refused() {
+    let mut out = Vec::new();
+    from_words(&[0, 5, 6]).serialize(&mut out);
+    for len in [0, 7, 8, out.len() - 1] {
+        assert!(
+            Bitmap::parse(&out[..len], &mut 0, usize::MAX).is_err(),
+            "{len} bytes"
+        );
+    }
+
+    // A marker promising more literal words than the bitmap holds.
+    let mut out = Vec::new();
+    out.extend_from_slice(&64u32.to_be_bytes());
+    out.extend_from_slice(&2u32.to_be_bytes());
+    out.extend_from_slice(&marker(false, 0, 2).to_be_bytes());
+    out.extend_from_slice(&1u64.to_be_bytes());
+    out.extend_from_slice(&0u32.to_be_bytes());
+    assert!(Bitmap::parse(&out, &mut 0, usize::MAX).is_err());
+
+    // Runs longer than the bits the bitmap says it has, or than there are
+    // objects for, are refused rather than expanded.
+    let runs = |bits: u32, run: u64| {
+        let mut out = Vec::new();
+        out.extend_from_slice(&bits.to_be_bytes());
+        out.extend_from_slice(&1u32.to_be_bytes());
+        out.extend_from_slice(&marker(true, run, 0).to_be_bytes());
+        out.extend_from_slice(&0u32.to_be_bytes());
+        out
+    };
+    assert!(Bitmap::parse(&runs(128, 2), &mut 0, 128).is_ok());
+    assert!(Bitmap::parse(&runs(128, 3), &mut 0, usize::MAX).is_err());
+    assert!(Bitmap::parse(&runs(u32::MAX, 3), &mut 0, 128).is_err());
+    assert!(Bitmap::parse(&runs(64, 0xffff_ffff), &mut 0, usize::MAX).is_err());
+
+    // The same in a bitmap git wrote: it is refused, and walks do without.
+    let (dir, repo) = repo_with_git_pack("bitmap-long-run");
+    let pack = repo.odb().packs()[0].clone();
+    let bitmap = PackBitmap::open(pack.clone()).unwrap().unwrap();
+    let tip = (0..bitmap.len())
+        .map(|bit| bitmap.id_at(bit))
+        .find(|id| bitmap.commit_bitmap(id).is_some())
+        .unwrap();
+    let path = pack.path().with_extension("bitmap");
+    let mut data = fs::read(&path).unwrap();
+    // The first marker of the commit kind bitmap, after the header, the
+    // pack checksum and the bitmap's bit and word counts.
+    let at = 12 + 20 + 8;
+    let first = u64::from_be_bytes(data[at..at + 8].try_into().unwrap());
+    let long = first | marker(false, 0xffff_ffff, 0);
+    data[at..at + 8].copy_from_slice(&long.to_be_bytes());
+    fs::write(&path, data).unwrap();
+    let err = PackBitmap::open(pack).unwrap_err();
+    assert!(err.to_string().contains("runs past"), "{err}");
+    let reached: BTreeSet<ObjectId> = reachable::objects(&repo, &[tip], &[])
+        .unwrap()
+        .into_iter()
+        .map(|reached| reached.id)
+        .collect();
+    assert_eq!(reached, walk(&repo, tip));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Every object reachable from `tip`, found by reading each one.
+fn walk(repo: &Repository, tip: ObjectId) -> BTreeSet<ObjectId> {
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![tip];
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        match repo.odb().read(&id).unwrap() {
+            Object::Commit(commit) => {
+                pending.push(commit.tree);
+                pending.extend(commit.parents);
+            }
+            Object::Tree(tree) => pending.extend(
+                tree.entries
+                    .iter()
+                    .filter(|e| e.kind() != Some(ObjectKind::Commit))
+                    .map(|e| e.id),
+            ),
+            Object::Tag(tag) => pending.push(tag.object),
+            Object::Blob(_) => {}
+        }
+    }
+    seen
+}
+
+/// Checks each commit bitmap of `bitmap` against a walk, returning how
+/// many there are.
+fn check_against_walk(repo: &Repository, bitmap: &PackBitmap) -> usize {
+    let mut checked = 0;
+    for bit in 0..bitmap.len() {
+        let id = bitmap.id_at(bit);
+        assert_eq!(bitmap.position(&id), Some(bit));
+        let (kind, _) = repo.odb().read_header(&id).unwrap();
+        assert_eq!(bitmap.kind_at(bit), Some(kind), "{id}");
+        let Some(reach) = bitmap.commit_bitmap(&id) else {
+            continue;
+        };
+        let found: BTreeSet<ObjectId> = reach.ones().map(|bit| bitmap.id_at(bit)).collect();
+        assert_eq!(found, walk(repo, id), "objects reachable from {id}");
+        checked += 1;
+    }
+    assert_eq!(checked, bitmap.commit_count());
+    checked
+}
+
+/// A scratch repository holding nothing but the fixture pack.
+fn repo_with_git_pack(name: &str) -> (PathBuf, Repository) {
+    let (dir, repo) = common::scratch_repo(name, HashAlgorithm::Sha1);
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/bitmap");
+    let pack_dir = repo.odb().dir().join("pack");
+    fs::create_dir_all(&pack_dir).unwrap();
+    for entry in fs::read_dir(fixtures).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, pack_dir.join(path.file_name().unwrap())).unwrap();
+    }
+    repo.odb().refresh_packs().unwrap();
+    (dir, repo)
+}
+
+#[test]
+fn git_bitmaps_match_a_walk() {
+    let (dir, repo) = repo_with_git_pack("bitmap-git");
+    let bitmap = PackBitmap::open(repo.odb().packs()[0].clone())
+        .unwrap()
+        .unwrap();
+    assert_eq!(bitmap.len(), 21);
+    assert_eq!(check_against_walk(&repo, &bitmap), 5);
+
+    // The name hash cache holds what git hashed each path to.
+    let a_txt = (0..bitmap.len())
+        .filter(|&bit| bitmap.kind_at(bit) == Some(ObjectKind::Blob))
+        .filter(|&bit| bitmap.name_hash_at(bit) == pack::name_hash(b"a.txt"))
+        .count();
+    assert_eq!(a_txt, 3);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn written_bitmaps_match_a_walk() {
+    let (dir, repo) = repo_with_git_pack("bitmap-written");
+    let git = PackBitmap::open(repo.odb().packs()[0].clone())
+        .unwrap()
+        .unwrap();
+    let objects: Vec<PackObject> = (0..git.len())
+        .map(|bit| PackObject {
+            id: git.id_at(bit),
+            name_hash: git.name_hash_at(bit),
+        })
+        .collect();
+
+    let options = PackOptions {
+        write_bitmap: true,
+        ..PackOptions::default()
+    };
+    let written = pack::write_pack_files(repo.odb(), &objects, &options, &dir.join("out")).unwrap();
+    assert!(written.bitmap_path.is_some());
+    let pack = Pack::open(&written.index_path, HashAlgorithm::Sha1).unwrap();
+    let bitmap = PackBitmap::open(Rc::new(pack)).unwrap().unwrap();
+    assert_eq!(bitmap.len(), git.len());
+    assert!(check_against_walk(&repo, &bitmap) >= 1);
+    for bit in 0..bitmap.len() {
+        let id = bitmap.id_at(bit);
+        let at = git.position(&id).unwrap();
+        assert_eq!(bitmap.name_hash_at(bit), git.name_hash_at(at));
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
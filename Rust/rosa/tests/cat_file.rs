@@ -0,0 +1,139 @@
+//! The batch modes of `cat-file`, fed on stdin. Every expected output is
+//! what git 2.39 printed for a repository holding the same objects.
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use rosa::object::{HashAlgorithm, MODE_BLOB};
+use rosa::refs;
+
+/// A repository whose `master` is one commit of `hello.txt`, plus two
+/// loose blobs ("195\n" and "389\n") whose ids both start with `6bb2`.
+fn repo(name: &str) -> PathBuf {
+    let (dir, repo) = common::scratch_repo(name, HashAlgorithm::Sha1);
+    common::blob(&repo, b"195\n");
+    common::blob(&repo, b"389\n");
+    let hello = common::blob(&repo, b"hello\n");
+    let tree = common::tree(&repo, &[(MODE_BLOB, "hello.txt", hello)]);
+    let commit = common::commit(&repo, tree, &[], 1_700_000_000, "first\n");
+    refs::update(&repo, "refs/heads/master", &commit).unwrap();
+    dir
+}
+
+fn cat_file(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .arg("cat-file")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(dir: &Path, args: &[&str], stdin: &str) -> String {
+    let output = cat_file(dir, args, stdin);
+    assert!(output.status.success(), "{args:?}: {output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+const COMMIT: &str = "d5a6df4659c45f10c2fa9865ff3260abe9120078";
+const HELLO: &str = "ce013625030ba8dba906f756967f9e9ca394464a";
+
+#[test]
+fn batch_check_reports_missing_and_ambiguous_names() {
+    let dir = repo("cat-file-check");
+    let input = "master\nmaster:hello.txt\n6bb2\nnope\n\
+                 0000000000000000000000000000000000000000\n";
+    assert_eq!(
+        stdout(&dir, &["--batch-check"], input),
+        format!(
+            "{COMMIT} commit 169\n\
+             {HELLO} blob 6\n\
+             6bb2 ambiguous\n\
+             nope missing\n\
+             0000000000000000000000000000000000000000 missing\n"
+        )
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn batch_prints_contents_after_each_line() {
+    let dir = repo("cat-file-batch");
+    assert_eq!(
+        stdout(&dir, &["--batch"], "master:hello.txt\nnope\n"),
+        format!("{HELLO} blob 6\nhello\n\nnope missing\n")
+    );
+    // `--buffer` changes when output is written, not what it is.
+    assert_eq!(
+        stdout(&dir, &["--batch", "--buffer"], "master:hello.txt\r\n"),
+        format!("{HELLO} blob 6\nhello\n\n")
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn formats_expand_rest_and_keep_unknown_placeholders() {
+    let dir = repo("cat-file-format");
+    assert_eq!(
+        stdout(
+            &dir,
+            &["--batch-check=%(objecttype) %x %% [%(rest)]"],
+            "master  trailing text\nmaster:hello.txt\n"
+        ),
+        "commit %x % [trailing text]\nblob %x % []\n"
+    );
+    // Without `%(rest)` the whole line is the name.
+    assert_eq!(
+        stdout(
+            &dir,
+            &["--batch-check=%(objectname)"],
+            "master  trailing text\n"
+        ),
+        "master  trailing text missing\n"
+    );
+
+    let output = cat_file(&dir, &["--batch-check=%(foo)"], "");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("unknown format element: foo"), "{stderr}");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn batch_command_runs_info_contents_and_flush() {
+    let dir = repo("cat-file-command");
+    let input = "info master\ncontents master:hello.txt\nflush\ninfo nope\n";
+    assert_eq!(
+        stdout(&dir, &["--batch-command", "--buffer"], input),
+        format!("{COMMIT} commit 169\n{HELLO} blob 6\nhello\n\nnope missing\n")
+    );
+
+    // Flushing is only asked for when output is buffered.
+    let output = cat_file(&dir, &["--batch-command"], "info master\nflush\n");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{COMMIT} commit 169\n")
+    );
+    for bad in ["\n", "frobnicate master\n", "info\n"] {
+        assert!(
+            !cat_file(&dir, &["--batch-command"], bad).status.success(),
+            "{bad:?}"
+        );
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
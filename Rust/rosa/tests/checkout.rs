@@ -0,0 +1,157 @@
+//! Trees can come from anyone. Whatever names and links they hold,
+//! checkout must write below the directory it was given and never into
+//! a repository.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rosa::config::Config;
+use rosa::object::{HashAlgorithm, MODE_BLOB, MODE_BLOB_EXECUTABLE, MODE_SYMLINK, MODE_TREE};
+use rosa::{ObjectId, Repository};
+
+fn checkout(dir: &Path, tree: ObjectId) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .args(["checkout", &tree.to_hex(), "out"])
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// Every file below `dir`, skipping the repository's own `.git`.
+fn files_below(dir: &Path, gitdir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path == gitdir {
+                continue;
+            }
+            match fs::symlink_metadata(&path).unwrap().is_dir() {
+                true => pending.push(path),
+                false => found.push(path),
+            }
+        }
+    }
+    found
+}
+
+/// Checks out a tree holding a `pwned` file under `name` (as a directory)
+/// and one called `name` itself, and asserts both are refused before
+/// anything lands outside `out` or in a `.git`.
+fn assert_refused(repo: &Repository, dir: &Path, name: &str) {
+    let payload = common::blob(repo, b"pwned\n");
+    let sub = common::tree(repo, &[(MODE_BLOB, "pwned", payload)]);
+    let trees = [
+        common::bad_tree(repo, &[(MODE_TREE, name, sub)]),
+        common::bad_tree(repo, &[(MODE_BLOB, name, payload)]),
+    ];
+    for tree in trees {
+        let output = checkout(dir, tree);
+        assert!(!output.status.success(), "{name:?} was checked out");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid path"), "{name:?}: {stderr}");
+        assert_eq!(files_below(dir, repo.gitdir()), Vec::<PathBuf>::new());
+        assert!(!repo.gitdir().join("pwned").exists());
+        fs::remove_dir_all(dir.join("out")).unwrap();
+    }
+}
+
+#[test]
+fn names_escaping_the_worktree_are_refused() {
+    let (dir, repo) = common::scratch_repo("checkout-names", HashAlgorithm::Sha1);
+    for name in [
+        "..",
+        ".",
+        ".git",
+        ".GIT",
+        ".Git",
+        "a\\..\\..\\b",
+        ".git\\foo",
+    ] {
+        assert_refused(&repo, &dir, name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ntfs_spellings_of_dotgit_are_refused() {
+    let (dir, repo) = common::scratch_repo("checkout-ntfs", HashAlgorithm::Sha1);
+    for name in [
+        ".git.",
+        ".git. .",
+        "git~1",
+        "GIT~1",
+        ".git::$INDEX_ALLOCATION",
+    ] {
+        assert_refused(&repo, &dir, name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn hfs_spellings_of_dotgit_are_refused_when_protected() {
+    let (dir, repo) = common::scratch_repo("checkout-hfs", HashAlgorithm::Sha1);
+    let config_path = repo.gitdir().join("config");
+    let mut config = Config::load(&config_path).unwrap();
+    config.set("core.protectHFS", "true").unwrap();
+    config.write(&config_path).unwrap();
+    let repo = Repository::open(&dir).unwrap();
+    for name in [".g\u{200c}it", "\u{feff}.git", ".GI\u{200e}T"] {
+        assert_refused(&repo, &dir, name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_not_followed_by_later_entries() {
+    let (dir, repo) = common::scratch_repo("checkout-symlink", HashAlgorithm::Sha1);
+    let outside = dir.join("outside");
+    fs::create_dir(&outside).unwrap();
+
+    // `a` is first a link leading out, then a directory to write through it.
+    let target = common::blob(&repo, outside.to_str().unwrap().as_bytes());
+    let payload = common::blob(&repo, b"pwned\n");
+    let sub = common::tree(&repo, &[(MODE_BLOB, "pwned", payload)]);
+    let tree = common::bad_tree(&repo, &[(MODE_SYMLINK, "a", target), (MODE_TREE, "a", sub)]);
+
+    let output = checkout(&dir, tree);
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("refusing to overwrite"), "{stderr}");
+    assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+    assert!(!dir.join("out/a/pwned").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn executable_blobs_keep_their_execute_bit() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, repo) = common::scratch_repo("checkout-modes", HashAlgorithm::Sha1);
+    let script = common::blob(&repo, b"#!/bin/sh\n");
+    let tree = common::tree(
+        &repo,
+        &[
+            (MODE_BLOB_EXECUTABLE, "run.sh", script),
+            (MODE_BLOB, "plain", script),
+        ],
+    );
+    let output = checkout(&dir, tree);
+    assert!(output.status.success(), "{output:?}");
+
+    let mode = |name: &str| {
+        fs::metadata(dir.join("out").join(name))
+            .unwrap()
+            .permissions()
+            .mode()
+    };
+    assert_ne!(mode("run.sh") & 0o100, 0);
+    assert_eq!(mode("plain") & 0o111, 0);
+    fs::remove_dir_all(dir).unwrap();
+}
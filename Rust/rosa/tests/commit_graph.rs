@@ -0,0 +1,357 @@
+//! `tests/commit-graph/single` was written by git 2.39 (`commit-graph
+//! write --reachable --changed-paths`) for the history built by
+//! [`history`]: a merge, an octopus merge (which needs the EDGE chunk), a
+//! path that is not ASCII (which murmur3 version 1 hashes with signed
+//! bytes) and a commit changing more paths than a filter records.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rosa::commit_graph::bloom::{self, BloomKey, MAX_CHANGED_PATHS};
+use rosa::commit_graph::{self, BloomSettings, CommitGraph, Split, WriteOptions};
+use rosa::object::{HashAlgorithm, MODE_BLOB, MODE_TREE};
+use rosa::{ObjectId, ObjectKind, Repository};
+
+/// Commit ids git gave the history, `c1` first.
+const COMMITS: [&str; 8] = [
+    "5e1a84360526970a11636d4f110882c2f1a4b01c",
+    "65952a48a4dd51eb73c0ee90e375b993edb7251a",
+    "ef3ea75cbd77bec6ea51273ad35fd23da5d172f2",
+    "cf08c5115c2c9932c14f38609e5c6626917b4171",
+    "384a4d9b41be995c5a03662d4971e157934e0af3",
+    "68ffd3f77cce382de003e2b2f74ad9927a9a0408",
+    "efe377d8c3abdc07e59a48ffd5786dce0747b56e",
+    "c850b6228f361eacf41efece7024f8082ad2e4b0",
+];
+
+fn fixture() -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/commit-graph/single")).unwrap()
+}
+
+/// Commit `n` with the given tree and parents, dated `n` hundred seconds
+/// into the history.
+fn commit(repo: &Repository, n: u64, tree: ObjectId, parents: &[ObjectId]) -> ObjectId {
+    let time = 1_700_000_000 + n * 100;
+    let mut raw = format!("tree {tree}\n");
+    for parent in parents {
+        raw.push_str(&format!("parent {parent}\n"));
+    }
+    raw.push_str(&format!(
+        "author A U Thor <author@example.com> {time} +0000\n\
+         committer C O Mitter <committer@example.com> {time} +0000\n\nc{n}\n"
+    ));
+    repo.odb()
+        .write_raw(ObjectKind::Commit, raw.as_bytes())
+        .unwrap()
+}
+
+/// Builds the fixture's history, returning its commits, `c1` first:
+///
+/// ```text
+/// c1 - c2 - c4 - c7 - c8
+///   \- c3 -/    /  /
+///   \- c5 -----/  /
+///   \- c6 -------/
+/// ```
+fn history(repo: &Repository) -> Vec<ObjectId> {
+    let blob = |data: &str| common::blob(repo, data.as_bytes());
+    let tree = |entries: &[(u32, &str, ObjectId)]| common::tree(repo, entries);
+    let (a, b1, b2, e, x) = (
+        blob("a\n"),
+        blob("b1\n"),
+        blob("b2\n"),
+        blob("e\n"),
+        blob("x\n"),
+    );
+    let (o1, o2) = (blob("o1\n"), blob("o2\n"));
+
+    let d1 = tree(&[(MODE_BLOB, "b.txt", b1)]);
+    let d2 = tree(&[(MODE_BLOB, "b.txt", b2), (MODE_BLOB, "\u{e9}.txt", e)]);
+    let side = tree(&[(MODE_BLOB, "x.txt", x)]);
+    let oa = tree(&[(MODE_BLOB, "1", o1)]);
+    let ob = tree(&[(MODE_BLOB, "2", o2)]);
+    let oab = tree(&[(MODE_BLOB, "1", o1), (MODE_BLOB, "2", o2)]);
+    let names: Vec<String> = (0..600).map(|n| format!("f{n:03}")).collect();
+    let big_entries: Vec<_> = names.iter().map(|n| (MODE_BLOB, n.as_str(), a)).collect();
+    let big = tree(&big_entries);
+
+    let c1 = commit(
+        repo,
+        1,
+        tree(&[(MODE_BLOB, "a.txt", a), (MODE_TREE, "dir", d1)]),
+        &[],
+    );
+    let c2_tree = tree(&[(MODE_BLOB, "a.txt", a), (MODE_TREE, "dir", d2)]);
+    let c2 = commit(repo, 2, c2_tree, &[c1]);
+    let c3_tree = tree(&[
+        (MODE_BLOB, "a.txt", a),
+        (MODE_TREE, "dir", d1),
+        (MODE_TREE, "side", side),
+    ]);
+    let c3 = commit(repo, 3, c3_tree, &[c1]);
+    let c4_tree = tree(&[
+        (MODE_BLOB, "a.txt", a),
+        (MODE_TREE, "dir", d2),
+        (MODE_TREE, "side", side),
+    ]);
+    let c4 = commit(repo, 4, c4_tree, &[c2, c3]);
+    let c5_tree = tree(&[
+        (MODE_BLOB, "a.txt", a),
+        (MODE_TREE, "dir", d1),
+        (MODE_TREE, "o", oa),
+    ]);
+    let c5 = commit(repo, 5, c5_tree, &[c1]);
+    let c6_tree = tree(&[
+        (MODE_BLOB, "a.txt", a),
+        (MODE_TREE, "dir", d1),
+        (MODE_TREE, "o", ob),
+    ]);
+    let c6 = commit(repo, 6, c6_tree, &[c1]);
+    let c7_tree = tree(&[
+        (MODE_BLOB, "a.txt", a),
+        (MODE_TREE, "dir", d2),
+        (MODE_TREE, "o", oab),
+        (MODE_TREE, "side", side),
+    ]);
+    let c7 = commit(repo, 7, c7_tree, &[c4, c5, c6]);
+    let c8_tree = tree(&[
+        (MODE_BLOB, "a.txt", a),
+        (MODE_TREE, "big", big),
+        (MODE_TREE, "dir", d2),
+        (MODE_TREE, "o", oab),
+        (MODE_TREE, "side", side),
+    ]);
+    let c8 = commit(repo, 8, c8_tree, &[c7]);
+
+    let commits = vec![c1, c2, c3, c4, c5, c6, c7, c8];
+    let hex: Vec<String> = commits.iter().map(ObjectId::to_hex).collect();
+    assert_eq!(hex, COMMITS);
+    commits
+}
+
+fn with_filters() -> WriteOptions {
+    WriteOptions {
+        changed_paths: Some(true),
+        ..WriteOptions::default()
+    }
+}
+
+fn open(repo: &Repository) -> CommitGraph {
+    CommitGraph::open(repo.odb().dir(), HashAlgorithm::Sha1)
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn written_graph_matches_git() {
+    let (dir, repo) = common::scratch_repo("commit-graph-git", HashAlgorithm::Sha1);
+    let commits = history(&repo);
+    commit_graph::write(repo.odb(), &commits[7..], &with_filters())
+        .unwrap()
+        .unwrap();
+    let written = fs::read(commit_graph::graph_path(repo.odb().dir())).unwrap();
+    assert!(written == fixture(), "the graph differs from git's");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn generation_numbers() {
+    let (dir, repo) = common::scratch_repo("commit-graph-generations", HashAlgorithm::Sha1);
+    let commits = history(&repo);
+    fs::create_dir_all(repo.odb().dir().join("info")).unwrap();
+    fs::write(commit_graph::graph_path(repo.odb().dir()), fixture()).unwrap();
+    let graph = open(&repo);
+    assert_eq!(graph.len(), 8);
+    assert!(graph.has_corrected_dates());
+
+    let levels: Vec<u32> = commits
+        .iter()
+        .map(|id| graph.commit_at(graph.position(id).unwrap()).unwrap().level)
+        .collect();
+    assert_eq!(levels, [1, 2, 2, 3, 2, 2, 4, 5]);
+
+    for (n, id) in commits.iter().enumerate() {
+        let commit = graph.commit_at(graph.position(id).unwrap()).unwrap();
+        assert_eq!(commit.id, *id);
+        assert_eq!(commit.commit_time, 1_700_000_000 + (n as u64 + 1) * 100);
+        // Commit dates grow along every edge here, so the corrected dates
+        // are the dates themselves.
+        assert_eq!(commit.generation, commit.commit_time);
+        for &parent in &commit.parents {
+            assert!(graph.commit_at(parent).unwrap().generation < commit.generation);
+        }
+    }
+    let octopus = graph
+        .commit_at(graph.position(&commits[6]).unwrap())
+        .unwrap();
+    let parents: Vec<_> = octopus.parents.iter().map(|&p| graph.id_at(p)).collect();
+    assert_eq!(parents, [commits[3], commits[4], commits[5]]);
+    assert!(graph.verify(repo.odb()).is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn murmur3_seeds_and_signed_bytes() {
+    // Plain murmur3 on ASCII, where signedness makes no difference.
+    assert_eq!(bloom::murmur3_seeded(0, b""), 0);
+    assert_eq!(bloom::murmur3_seeded(0, b"Hello world!"), 0x627b_0c2c);
+    assert_eq!(
+        bloom::murmur3_seeded(0, b"The quick brown fox jumps over the lazy dog"),
+        0x2e4f_f723
+    );
+    // Version 1 sign-extends high bytes; proper murmur3 gives 0xa183ccfd.
+    let high = [0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+    assert_eq!(bloom::murmur3_seeded(0, &high), 0xdd92_776e);
+    // The seeds git derives the key hashes from.
+    assert_ne!(
+        bloom::murmur3_seeded(0x293a_e76f, b"a"),
+        bloom::murmur3_seeded(0x7e64_6e2c, b"a")
+    );
+}
+
+#[test]
+fn filters_record_changed_paths_and_their_directories() {
+    let (dir, repo) = common::scratch_repo("commit-graph-bloom", HashAlgorithm::Sha1);
+    let commits = history(&repo);
+    fs::create_dir_all(repo.odb().dir().join("info")).unwrap();
+    fs::write(commit_graph::graph_path(repo.odb().dir()), fixture()).unwrap();
+    let graph = open(&repo);
+    let settings = graph.bloom_settings().unwrap();
+    assert_eq!(settings, BloomSettings::default());
+
+    let filter = |n: usize| graph.bloom_filter(graph.position(&commits[n]).unwrap(), &settings);
+    let may_touch = |n: usize, path: &str| {
+        BloomKey::for_pathspec(path.as_bytes(), &settings)
+            .iter()
+            .all(|key| bloom::filter_contains(filter(n).unwrap(), key))
+    };
+
+    // c2 changed dir/b.txt and added dir/é.txt.
+    assert!(may_touch(1, "dir/b.txt"));
+    assert!(may_touch(1, "dir/\u{e9}.txt"));
+    assert!(may_touch(1, "dir"));
+    assert!(may_touch(1, "dir/"));
+    assert!(!may_touch(1, "a.txt"));
+    assert!(!may_touch(1, "side/x.txt"));
+    // The octopus merge is diffed against its first parent only.
+    assert!(may_touch(6, "o/1") && may_touch(6, "o/2"));
+    assert!(!may_touch(6, "side"));
+    // Too many changes: a filter that matches anything.
+    assert_eq!(filter(7), Some(&[0xff][..]));
+    assert!(may_touch(7, "anything/at/all"));
+
+    // A path's keys are itself and each leading directory.
+    let keys = BloomKey::for_pathspec(b"a/b/c//", &settings);
+    assert_eq!(
+        keys,
+        [
+            BloomKey::new(b"a/b/c", &settings),
+            BloomKey::new(b"a", &settings),
+            BloomKey::new(b"a/b", &settings),
+        ]
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn filters_are_built_from_path_sets() {
+    let settings = BloomSettings::default();
+    let paths = (0..=MAX_CHANGED_PATHS)
+        .map(|n| format!("p{n}").into_bytes())
+        .collect();
+    assert_eq!(bloom::build_filter(&paths, &settings), [0xff]);
+
+    // Nothing changed: one zero byte, which matches nothing.
+    let empty = bloom::build_filter(&Default::default(), &settings);
+    assert_eq!(empty, [0]);
+    assert!(!bloom::filter_contains(
+        &empty,
+        &BloomKey::new(b"x", &settings)
+    ));
+    // No filter at all means "not computed" and matches everything.
+    assert!(bloom::filter_contains(&[], &BloomKey::new(b"x", &settings)));
+
+    let paths = [b"one".to_vec(), b"two".to_vec()].into();
+    let filter = bloom::build_filter(&paths, &settings);
+    assert_eq!(filter.len(), 3);
+    for path in &paths {
+        assert!(bloom::filter_contains(
+            &filter,
+            &BloomKey::new(path, &settings)
+        ));
+    }
+}
+
+fn chain_files(repo: &Repository) -> Vec<PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(commit_graph::chain_dir(repo.odb().dir()))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "graph"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn split_chains_grow_and_merge() {
+    let (dir, repo) = common::scratch_repo("commit-graph-split", HashAlgorithm::Sha1);
+    let commits = history(&repo);
+    let split = |split, tips: &[ObjectId]| {
+        let options = WriteOptions {
+            split,
+            ..with_filters()
+        };
+        commit_graph::write(repo.odb(), tips, &options).unwrap()
+    };
+
+    // One layer per write, each holding only what the chain lacks.
+    let first = split(Split::NoMerge, &commits[1..2]).unwrap();
+    assert_eq!((first.commits, first.layers), (2, 1));
+    let second = split(Split::NoMerge, &commits[3..4]).unwrap();
+    assert_eq!((second.commits, second.layers), (2, 2));
+    assert!(split(Split::NoMerge, &commits[3..4]).is_none());
+    let third = split(Split::NoMerge, &commits[6..7]).unwrap();
+    assert_eq!((third.commits, third.layers), (3, 3));
+    assert_eq!(chain_files(&repo).len(), 3);
+
+    let graph = open(&repo);
+    assert_eq!((graph.len(), graph.layer_count()), (7, 3));
+    for id in &commits[..7] {
+        let commit = graph.commit_at(graph.position(id).unwrap()).unwrap();
+        // Parents may live in lower layers.
+        for &parent in &commit.parents {
+            assert!(graph.commit_at(parent).unwrap().generation < commit.generation);
+        }
+    }
+    assert!(graph.verify(repo.odb()).is_empty());
+
+    // The last layer is not merged into one more than twice its size...
+    let fourth = split(Split::Merge, &commits[7..]).unwrap();
+    assert_eq!((fourth.commits, fourth.layers), (1, 4));
+    // ...but replacing the chain merges every layer.
+    let replaced = split(Split::Replace, &commits[7..]).unwrap();
+    assert_eq!((replaced.commits, replaced.layers), (8, 1));
+    let graph = open(&repo);
+    assert_eq!((graph.len(), graph.layer_count()), (8, 1));
+    assert_eq!(chain_files(&repo).len(), 1);
+
+    // Merging loses nothing against a single file written from scratch.
+    let single = {
+        let (dir, repo) = common::scratch_repo("commit-graph-split-single", HashAlgorithm::Sha1);
+        history(&repo);
+        commit_graph::write(repo.odb(), &commits[7..], &with_filters()).unwrap();
+        let graph = open(&repo);
+        let rows: Vec<_> = (0..graph.len())
+            .map(|n| graph.commit_at(n).unwrap())
+            .collect();
+        fs::remove_dir_all(dir).unwrap();
+        rows
+    };
+    let rows: Vec<_> = (0..graph.len())
+        .map(|n| graph.commit_at(n).unwrap())
+        .collect();
+    assert_eq!(rows, single);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,72 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use rosa::object::{Blob, HashAlgorithm, Tree, TreeEntry};
+use rosa::{Object, ObjectId, ObjectKind, Repository};
+
+/// A fresh repository in a directory of its own under the temporary
+/// directory, named after the test so that tests can run in parallel.
+pub fn scratch_repo(name: &str, hash: HashAlgorithm) -> (PathBuf, Repository) {
+    let dir = env::temp_dir().join(format!("rosa-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let repo = Repository::init(&dir, hash).unwrap();
+    (dir, repo)
+}
+
+/// Writes a blob holding `data`.
+pub fn blob(repo: &Repository, data: &[u8]) -> ObjectId {
+    repo.odb()
+        .write(&Object::Blob(Blob::new(data.to_vec())))
+        .unwrap()
+}
+
+/// Writes a tree of `(mode, name, id)` entries, sorting them as git does.
+pub fn tree(repo: &Repository, entries: &[(u32, &str, ObjectId)]) -> ObjectId {
+    repo.odb().write(&sorted_tree(entries)).unwrap()
+}
+
+/// Writes a tree as [`tree`] does but without the checks writing makes, for
+/// trees git would refuse to store (and so to check out).
+pub fn bad_tree(repo: &Repository, entries: &[(u32, &str, ObjectId)]) -> ObjectId {
+    let tree = sorted_tree(entries);
+    repo.odb()
+        .write_literally(ObjectKind::Tree, &tree.serialize())
+        .unwrap()
+}
+
+fn sorted_tree(entries: &[(u32, &str, ObjectId)]) -> Object {
+    let mut entries: Vec<TreeEntry> = entries
+        .iter()
+        .map(|&(mode, name, id)| TreeEntry::new(mode, name, id))
+        .collect();
+    entries.sort_by(TreeEntry::cmp_git);
+    Object::Tree(Tree { entries })
+}
+
+/// Writes a commit of `tree` with the given parents, authored and
+/// committed at `time` (UTC) by fixed identities.
+pub fn commit(
+    repo: &Repository,
+    tree: ObjectId,
+    parents: &[ObjectId],
+    time: i64,
+    message: &str,
+) -> ObjectId {
+    let mut raw = format!("tree {tree}\n");
+    for parent in parents {
+        raw.push_str(&format!("parent {parent}\n"));
+    }
+    raw.push_str(&format!(
+        "author A U Thor <author@example.com> {time} +0000\n\
+         committer C O Mitter <committer@example.com> {time} +0000\n\n{message}"
+    ));
+    repo.odb()
+        .write_raw(ObjectKind::Commit, raw.as_bytes())
+        .unwrap()
+}
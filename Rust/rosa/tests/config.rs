@@ -0,0 +1,41 @@
+//! Setting configuration from user input must refuse malformed names
+//! rather than panic or write a file git cannot read back.
+
+use rosa::config::Config;
+use rosa::Error;
+
+#[test]
+fn malformed_names_are_refused() {
+    let mut config = Config::default();
+    for name in [
+        "foo",
+        "",
+        ".key",
+        "core.",
+        "core.1key",
+        "core.ke_y",
+        "co re.key",
+        "core.sub.",
+        "remote.a\nb.url",
+    ] {
+        assert!(
+            matches!(config.set(name, "bar"), Err(Error::Config(_))),
+            "{name:?} was accepted"
+        );
+    }
+    assert_eq!(config.to_string(), "");
+}
+
+#[test]
+fn names_are_set_and_read_back() {
+    let mut config = Config::default();
+    config.set("Core.Bare", "false").unwrap();
+    config.set("remote.my.origin.url", "a").unwrap();
+    config.set("core.bare", "true").unwrap();
+    config.set("user.name-2", "x").unwrap();
+
+    let reread = Config::parse(&config.to_string()).unwrap();
+    assert_eq!(reread.get("core.bare"), Some("true"));
+    assert_eq!(reread.get("remote.my.origin.url"), Some("a"));
+    assert_eq!(reread.get("user.name-2"), Some("x"));
+}
@@ -0,0 +1,449 @@
+//! Deltas are built by hand here, instruction by instruction, so that each
+//! opcode and each boundary of the format is exercised on its own.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rosa::object::HashAlgorithm;
+use rosa::pack::{delta, DeltaBaseCache, Pack};
+use rosa::{Error, ObjectId, ObjectKind};
+
+/// A delta header: base size and result size, as base-128 varints.
+fn header(base: usize, result: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    for mut n in [base, result] {
+        while n >= 0x80 {
+            out.push(0x80 | (n & 0x7f) as u8);
+            n >>= 7;
+        }
+        out.push(n as u8);
+    }
+    out
+}
+
+/// A copy instruction carrying only the nonzero bytes of offset and size.
+fn copy(offset: usize, size: usize) -> Vec<u8> {
+    let mut op = 0x80u8;
+    let mut args = Vec::new();
+    for i in 0..4 {
+        let byte = (offset >> (8 * i)) as u8;
+        if byte != 0 {
+            op |= 1 << i;
+            args.push(byte);
+        }
+    }
+    for i in 0..3 {
+        let byte = (size >> (8 * i)) as u8;
+        if byte != 0 {
+            op |= 0x10 << i;
+            args.push(byte);
+        }
+    }
+    let mut out = vec![op];
+    out.extend(args);
+    out
+}
+
+fn insert(data: &[u8]) -> Vec<u8> {
+    assert!((1..=0x7f).contains(&data.len()));
+    let mut out = vec![data.len() as u8];
+    out.extend_from_slice(data);
+    out
+}
+
+fn delta(base: usize, result: usize, ops: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = header(base, result);
+    out.extend(ops.concat());
+    out
+}
+
+fn assert_malformed(base: &[u8], delta_data: &[u8]) {
+    assert!(
+        matches!(delta::apply(base, delta_data), Err(Error::Parse { .. })),
+        "{delta_data:?} was applied"
+    );
+}
+
+#[test]
+fn copies_and_inserts_interleave() {
+    let base = b"0123456789abcdef";
+    let ops = [
+        copy(10, 6),
+        insert(b"-"),
+        copy(0, 4),
+        insert(b"+xyz"),
+        copy(15, 1),
+    ];
+    let out = delta::apply(base, &delta(16, 16, &ops)).unwrap();
+    assert_eq!(out, b"abcdef-0123+xyzf");
+}
+
+#[test]
+fn empty_deltas_and_results() {
+    assert_eq!(delta::apply(b"", &header(0, 0)).unwrap(), b"");
+    assert_eq!(delta::apply(b"base", &header(4, 0)).unwrap(), b"");
+    let out = delta::apply(b"", &delta(0, 3, &[insert(b"new")])).unwrap();
+    assert_eq!(out, b"new");
+}
+
+#[test]
+fn copy_boundaries() {
+    let base: Vec<u8> = (0..0x30000u32).map(|n| (n % 251) as u8).collect();
+    let n = base.len();
+
+    // Offset 0 and the last byte of the base.
+    let out = delta::apply(&base, &delta(n, 2, &[copy(0, 1), copy(n - 1, 1)])).unwrap();
+    assert_eq!(out, [base[0], base[n - 1]]);
+
+    // The whole base in one copy of a three-byte size.
+    let out = delta::apply(&base, &delta(n, n, &[copy(0, n)])).unwrap();
+    assert_eq!(out, base);
+
+    // A size of zero means 0x10000.
+    let out = delta::apply(&base, &delta(n, 0x10000, &[copy(0x100, 0)])).unwrap();
+    assert_eq!(out, &base[0x100..0x10100]);
+
+    // Offset bytes may be given for any subset of the four positions.
+    let out = delta::apply(&base, &delta(n, 3, &[copy(0x2_0001, 3)])).unwrap();
+    assert_eq!(out, &base[0x2_0001..0x2_0004]);
+
+    // An explicit zero offset byte reads the same as an omitted one.
+    let out = delta::apply(&base, &delta(n, 2, &[vec![0x91, 0x00, 0x02]])).unwrap();
+    assert_eq!(out, &base[..2]);
+}
+
+#[test]
+fn insert_boundaries() {
+    let literal = [b'x'; 0x7f];
+    let out = delta::apply(b"", &delta(0, 0x7f, &[insert(&literal)])).unwrap();
+    assert_eq!(out, literal);
+    let out = delta::apply(b"", &delta(0, 1, &[insert(b"y")])).unwrap();
+    assert_eq!(out, b"y");
+}
+
+#[test]
+fn malformed_deltas_are_refused() {
+    let base = b"0123456789";
+
+    // Headers: missing, truncated, or sizes that disagree.
+    assert_malformed(base, b"");
+    assert_malformed(base, &[0x0a]);
+    assert_malformed(base, &[0x8a]);
+    assert_malformed(base, &header(9, 0));
+    assert_malformed(base, &delta(10, 2, &[copy(0, 1)]));
+    assert_malformed(base, &delta(10, 1, &[copy(0, 2)]));
+    let mut huge = vec![0x0a];
+    huge.extend([0xff; 10]);
+    huge.push(0x01);
+    assert_malformed(base, &huge);
+
+    // Opcode 0 is reserved.
+    assert_malformed(base, &delta(10, 1, &[vec![0x00], insert(b"a")]));
+
+    // Copies past the end of the base, or missing their argument bytes.
+    assert_malformed(base, &delta(10, 1, &[copy(10, 1)]));
+    assert_malformed(base, &delta(10, 2, &[copy(9, 2)]));
+    assert_malformed(base, &delta(10, 0x10000, &[copy(0, 0)]));
+    assert_malformed(base, &delta(10, 1, &[vec![0x91, 0x00]]));
+    assert_malformed(base, &delta(10, 1, &[vec![0xff, 0, 0, 0]]));
+    let mut far = header(10, 1);
+    far.extend([0x98, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    assert_malformed(base, &far);
+
+    // Inserts running off the end of the delta.
+    assert_malformed(base, &delta(10, 3, &[vec![0x03, b'a', b'b']]));
+
+    // A result size no instruction comes near, which must not be reserved
+    // before the instructions have been looked at.
+    assert_malformed(base, &delta(10, 1 << 40, &[insert(b"a")]));
+    assert_malformed(base, &header(10, usize::MAX));
+}
+
+/// A xorshift generator, so failures can be replayed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, n: usize) -> Vec<u8> {
+        (0..n).map(|_| self.next() as u8).collect()
+    }
+}
+
+/// `base` with a few spans replaced, removed or inserted.
+fn edit(rng: &mut Rng, base: &[u8]) -> Vec<u8> {
+    let mut target = base.to_vec();
+    for _ in 0..rng.below(6) {
+        let at = rng.below(target.len() + 1);
+        let len = rng.below(64).min(target.len() - at);
+        let n = rng.below(200);
+        let with = rng.bytes(n);
+        target.splice(at..at + len, with);
+    }
+    target
+}
+
+#[test]
+fn created_deltas_apply() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..300 {
+        let n = rng.below(4096);
+        let base = rng.bytes(n);
+        let target = edit(&mut rng, &base);
+        let made = delta::create(&base, &target, usize::MAX).unwrap();
+        assert_eq!(delta::apply(&base, &made).unwrap(), target);
+    }
+}
+
+#[test]
+fn created_deltas_copy_shared_content() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let base = rng.bytes(64 << 10);
+    let mut target = base.clone();
+    target[1000] ^= 1;
+    target.extend_from_slice(b"appended");
+    let made = delta::create(&base, &target, usize::MAX).unwrap();
+    assert!(made.len() < 200, "{} bytes", made.len());
+    assert_eq!(delta::apply(&base, &made).unwrap(), target);
+
+    // Nothing in common: all inserts, still exact.
+    let other = rng.bytes(1000);
+    let made = delta::create(&base, &other, usize::MAX).unwrap();
+    assert_eq!(delta::apply(&base, &made).unwrap(), other);
+
+    // Empty sides.
+    for (base, target) in [(&b""[..], &b"abc"[..]), (b"abc", b""), (b"", b"")] {
+        let made = delta::create(base, target, usize::MAX).unwrap();
+        assert_eq!(delta::apply(base, &made).unwrap(), target);
+    }
+}
+
+#[test]
+fn copies_longer_than_one_instruction_are_split() {
+    let mut rng = Rng(0x1234_5678_9abc_def1);
+    // Past the 24-bit size one copy can carry.
+    let base = rng.bytes(0xff_ffff + 0x1000);
+    let made = delta::create(&base, &base, usize::MAX).unwrap();
+    assert!(made.len() < 32, "{} bytes", made.len());
+    assert_eq!(delta::apply(&base, &made).unwrap(), base);
+}
+
+#[test]
+fn created_deltas_respect_max_size() {
+    let mut rng = Rng(0x0f0f_1e1e_2d2d_3c3c);
+    let base = rng.bytes(2048);
+    let target = rng.bytes(2048);
+    assert_eq!(delta::create(&base, &target, 100), None);
+
+    let close = edit(&mut rng, &base);
+    let made = delta::create(&base, &close, usize::MAX).unwrap();
+    assert_eq!(delta::create(&base, &close, made.len()), Some(made.clone()));
+    assert_eq!(delta::create(&base, &close, made.len() - 1), None);
+}
+
+/// One entry of a pack built by [`write_pack`]: a whole blob, or a delta on
+/// an earlier entry named by position (as OFS_DELTA) or by id (REF_DELTA).
+enum Entry {
+    Blob(Vec<u8>),
+    Ofs(usize, Vec<u8>),
+    Ref(ObjectId, Vec<u8>),
+}
+
+/// Writes `entries` as `<dir>/hand.pack` with a version 2 index naming each
+/// by `ids[n]`, and returns the offsets they landed at. CRCs are left zero
+/// and the trailer is not a real checksum; reading checks neither.
+fn write_pack(dir: &Path, entries: &[Entry], ids: &[ObjectId]) -> Vec<u64> {
+    let mut pack = b"PACK\0\0\0\x02".to_vec();
+    pack.extend((entries.len() as u32).to_be_bytes());
+    let mut offsets = Vec::new();
+    for entry in entries {
+        let offset = pack.len() as u64;
+        let (kind, data) = match entry {
+            Entry::Blob(data) => (3, data),
+            Entry::Ofs(_, data) => (6, data),
+            Entry::Ref(_, data) => (7, data),
+        };
+        let mut size = data.len();
+        let mut byte = (kind << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+        while size > 0 {
+            pack.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        pack.push(byte);
+        match entry {
+            Entry::Ofs(base, _) => {
+                let mut distance = offset - offsets[*base];
+                let mut encoded = vec![(distance & 0x7f) as u8];
+                distance >>= 7;
+                while distance > 0 {
+                    distance -= 1;
+                    encoded.insert(0, 0x80 | (distance & 0x7f) as u8);
+                    distance >>= 7;
+                }
+                pack.extend(encoded);
+            }
+            Entry::Ref(base, _) => pack.extend_from_slice(base.as_bytes()),
+            Entry::Blob(_) => {}
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        pack.extend(encoder.finish().unwrap());
+        offsets.push(offset);
+    }
+    let trailer = [0x5a; 20];
+    pack.extend(trailer);
+    fs::write(dir.join("hand.pack"), pack).unwrap();
+
+    let mut sorted: Vec<(ObjectId, u64)> = ids.iter().copied().zip(offsets.clone()).collect();
+    sorted.sort();
+    let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+    for first in 0..=255u8 {
+        let total = sorted
+            .iter()
+            .filter(|(id, _)| id.as_bytes()[0] <= first)
+            .count();
+        idx.extend((total as u32).to_be_bytes());
+    }
+    for (id, _) in &sorted {
+        idx.extend_from_slice(id.as_bytes());
+    }
+    idx.extend(vec![0; sorted.len() * 4]);
+    for (_, offset) in &sorted {
+        idx.extend((*offset as u32).to_be_bytes());
+    }
+    idx.extend(trailer);
+    idx.extend([0; 20]);
+    fs::write(dir.join("hand.idx"), idx).unwrap();
+    offsets
+}
+
+fn blob_id(data: &[u8]) -> ObjectId {
+    ObjectId::hash_object(HashAlgorithm::Sha1, ObjectKind::Blob, data).unwrap()
+}
+
+fn hand_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rosa-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn ref_and_ofs_deltas_resolve() {
+    let dir = hand_dir("delta-kinds");
+    let base = b"the base blob\n".to_vec();
+    let middle = b"the base blob\nand a ref delta\n".to_vec();
+    let top = b"and a ref delta\nthen an offset delta\n".to_vec();
+    let entries = [
+        Entry::Blob(base.clone()),
+        Entry::Ref(
+            blob_id(&base),
+            delta(14, 30, &[copy(0, 14), insert(b"and a ref delta\n")]),
+        ),
+        Entry::Ofs(
+            1,
+            delta(30, 37, &[copy(14, 16), insert(b"then an offset delta\n")]),
+        ),
+    ];
+    let ids = [blob_id(&base), blob_id(&middle), blob_id(&top)];
+    let offsets = write_pack(&dir, &entries, &ids);
+
+    let pack = Pack::open(&dir.join("hand.idx"), HashAlgorithm::Sha1).unwrap();
+    for (id, data) in ids.iter().zip([&base, &middle, &top]) {
+        assert_eq!(
+            pack.read(id).unwrap().unwrap(),
+            (ObjectKind::Blob, data.clone())
+        );
+    }
+    assert_eq!(
+        pack.read_header_at(offsets[2]).unwrap(),
+        (ObjectKind::Blob, 37)
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn long_chains_resolve_without_recursing() {
+    let dir = hand_dir("delta-chain");
+    // Each link appends one byte to the one before, alternating delta kinds.
+    let mut data = b"x".to_vec();
+    let mut entries = vec![Entry::Blob(data.clone())];
+    let mut ids = vec![blob_id(&data)];
+    for n in 1..10_000 {
+        let ops = [copy(0, data.len()), insert(&[b'a' + (n % 26) as u8])];
+        let link = delta(data.len(), data.len() + 1, &ops);
+        entries.push(match n % 2 {
+            0 => Entry::Ofs(n - 1, link),
+            _ => Entry::Ref(ids[n - 1], link),
+        });
+        data.push(b'a' + (n % 26) as u8);
+        ids.push(blob_id(&data));
+    }
+    write_pack(&dir, &entries, &ids);
+
+    let pack = Pack::open(&dir.join("hand.idx"), HashAlgorithm::Sha1).unwrap();
+    // Without a cache every base along the way is rebuilt from scratch.
+    pack.set_cache_limit(0);
+    let last = *ids.last().unwrap();
+    assert_eq!(pack.read(&last).unwrap().unwrap().1, data);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn looping_chains_are_refused() {
+    let dir = hand_dir("delta-loop");
+    let (a, b) = (blob_id(b"a"), blob_id(b"b"));
+    let entries = [
+        Entry::Ref(b, delta(1, 1, &[insert(b"a")])),
+        Entry::Ref(a, delta(1, 1, &[insert(b"b")])),
+    ];
+    let offsets = write_pack(&dir, &entries, &[a, b]);
+
+    let pack = Pack::open(&dir.join("hand.idx"), HashAlgorithm::Sha1).unwrap();
+    assert!(matches!(pack.read(&a), Err(Error::Parse { .. })));
+    assert!(matches!(
+        pack.read_header_at(offsets[1]),
+        Err(Error::Parse { .. })
+    ));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn base_cache_evicts_least_recently_used() {
+    let mut cache = DeltaBaseCache::new(8);
+    let data = |b: u8| -> Rc<[u8]> { vec![b; 4].into() };
+    cache.insert(10, ObjectKind::Blob, data(1));
+    cache.insert(20, ObjectKind::Blob, data(2));
+    // Touching 10 makes 20 the one to go when 30 arrives.
+    assert!(cache.get(10).is_some());
+    cache.insert(30, ObjectKind::Blob, data(3));
+    assert!(cache.get(20).is_none());
+    assert_eq!(cache.get(10).unwrap().1, data(1));
+    assert_eq!(cache.get(30).unwrap().1, data(3));
+
+    // Bases larger than the whole cache are not kept at all, and do not
+    // push anything else out.
+    cache.insert(40, ObjectKind::Blob, vec![0; 9].into());
+    assert!(cache.get(40).is_none());
+    assert!(cache.get(10).is_some() && cache.get(30).is_some());
+
+    cache.set_limit(4);
+    assert!(cache.get(10).is_none());
+    assert!(cache.get(30).is_some());
+}
@@ -0,0 +1,511 @@
+//! Objects are checked where they are stored, with the severities git
+//! gives each problem unless `fsck.<msg-id>` or `--strict` say otherwise;
+//! refs, HEAD, the index and reflogs decide what is reachable, and what
+//! nothing points to is dangling. A link must lead to an object of the
+//! kind it names; a tree entry calling a blob a tree is a broken link even
+//! if the blob is fine. And what fsck would refuse must not be written in
+//! the first place, by any way of writing short of asking to write
+//! literally.
+
+mod common;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rosa::config::Config;
+use rosa::fsck::{self, BrokenLink, FsckOptions, MessageId, Problem, Severity};
+use rosa::index::{Index, IndexEntry};
+use rosa::object::{HashAlgorithm, Offset, Signature, Tag, Tree, TreeEntry, MODE_BLOB, MODE_TREE};
+use rosa::{refs, Error, Object, ObjectId, ObjectKind};
+
+#[test]
+fn entries_of_the_wrong_kind_are_broken_links() {
+    let (dir, repo) = common::scratch_repo("fsck-kinds", HashAlgorithm::Sha1);
+    let blob = common::blob(&repo, b"hi\n");
+    // The blob is reached as a blob first, then through the bad entry.
+    let tree = common::tree(&repo, &[(MODE_BLOB, "a", blob), (MODE_TREE, "sub", blob)]);
+    let tip = common::commit(&repo, tree, &[], 1_700_000_000, "c\n");
+    refs::update(&repo, "refs/heads/master", &tip).unwrap();
+
+    for connectivity_only in [false, true] {
+        let options = FsckOptions {
+            connectivity_only,
+            ..FsckOptions::default()
+        };
+        let outcome = fsck::fsck(&repo, &options).unwrap();
+        assert_eq!(
+            outcome.broken_links,
+            [BrokenLink {
+                from: (ObjectKind::Tree, tree),
+                to: (ObjectKind::Tree, blob),
+            }]
+        );
+        let message = format!("object {blob} is a blob, not a tree");
+        assert!(outcome.problems.contains(&Problem::Other(message)));
+        assert!(outcome.missing.is_empty());
+        assert!(outcome.error_count() > 0);
+    }
+
+    // A sound tree has nothing to report.
+    let good = common::tree(&repo, &[(MODE_BLOB, "a", blob)]);
+    refs::update(
+        &repo,
+        "refs/heads/master",
+        &common::commit(&repo, good, &[], 1_700_000_000, "c\n"),
+    )
+    .unwrap();
+    let outcome = fsck::fsck(&repo, &FsckOptions::default()).unwrap();
+    assert!(outcome.broken_links.is_empty());
+    assert_eq!(outcome.error_count(), 0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn malformed_tags_are_refused_before_writing() {
+    let tag = |name: &str, tagger: &str| Tag {
+        object: ObjectId::from_hex("5e1a84360526970a11636d4f110882c2f1a4b01c").unwrap(),
+        target_kind: ObjectKind::Commit,
+        name: name.as_bytes().to_vec(),
+        tagger: Some(Signature {
+            name: tagger.as_bytes().to_vec(),
+            email: b"tagger@example.com".to_vec(),
+            time: 1_700_000_000,
+            offset: Offset::from_minutes(0),
+            raw: None,
+        }),
+        extra_headers: Vec::new(),
+        message: Some(b"v1\n".to_vec()),
+    };
+    let check = |tag: Tag| {
+        fsck::check_before_writing(ObjectKind::Tag, &tag.serialize(), HashAlgorithm::Sha1)
+    };
+
+    check(tag("v1", "T A Gger")).unwrap();
+    for (name, tagger) in [
+        ("v1\nobject 0000", "T A Gger"),
+        ("v1", "T <A> Gger"),
+        ("v1", "T A\nGger"),
+        ("v1", "Gger<"),
+    ] {
+        let result = check(tag(name, tagger));
+        assert!(
+            matches!(result, Err(Error::MalformedObject { .. })),
+            "{name:?} tagged by {tagger:?}"
+        );
+    }
+}
+
+#[test]
+fn uppercase_ids_are_refused_before_writing() {
+    let (dir, repo) = common::scratch_repo("fsck-uppercase", HashAlgorithm::Sha1);
+    let tree = common::tree(&repo, &[]).to_hex().to_uppercase();
+    let raw = format!(
+        "tree {tree}\n\
+         author A U Thor <author@example.com> 1700000000 +0000\n\
+         committer C O Mitter <committer@example.com> 1700000000 +0000\n\nc\n"
+    );
+    fs::write(dir.join("commit"), &raw).unwrap();
+    let id = ObjectId::hash_object(HashAlgorithm::Sha1, ObjectKind::Commit, raw.as_bytes());
+
+    // The typed parser would not read it back, so fsck must not let it in.
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .args(["hash-object", "-t", "commit", "-w", "commit"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("bad sha1"), "{stderr}");
+    assert!(!repo.odb().contains(&id.unwrap()));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// A tree entry as stored: mode, name, NUL and the raw id.
+fn entry(mode: &str, name: &[u8], id: &ObjectId) -> Vec<u8> {
+    let mut out = format!("{mode} ").into_bytes();
+    out.extend_from_slice(name);
+    out.push(0);
+    out.extend_from_slice(id.as_bytes());
+    out
+}
+
+fn hash_object(dir: &Path, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .arg("hash-object")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn hash_object_refuses_what_fsck_would_unless_literally() {
+    let (dir, repo) = common::scratch_repo("fsck-hash-object", HashAlgorithm::Sha1);
+    let blob = common::blob(&repo, b"hi\n");
+    let empty = common::tree(&repo, &[]);
+    let cases: [(&str, Vec<u8>, &str); 9] = [
+        ("tree", entry("40000", b"..", &empty), "hasDotdot"),
+        ("tree", entry("40000", b".git", &empty), "hasDotgit"),
+        ("tree", entry("100644", b".GIT", &blob), "hasDotgit"),
+        ("tree", entry("100644", b"a\0b", &blob), "badTree"),
+        (
+            "tree",
+            [entry("100644", b"b", &blob), entry("100644", b"a", &blob)].concat(),
+            "treeNotSorted",
+        ),
+        (
+            "tree",
+            [entry("100644", b"a", &blob), entry("100644", b"a", &blob)].concat(),
+            "duplicateEntries",
+        ),
+        ("tree", entry("100645", b"a", &blob), "badFilemode"),
+        (
+            "tree",
+            entry("040000", b"sub", &empty),
+            "zeroPaddedFilemode",
+        ),
+        (
+            "commit",
+            format!(
+                "tree {empty}\n\
+                 author A U Thor <author@example.com 1700000000 +0000\n\
+                 committer C O Mitter <committer@example.com> 1700000000 +0000\n\nc\n"
+            )
+            .into_bytes(),
+            "badEmail",
+        ),
+    ];
+    for (kind, data, message) in cases {
+        fs::write(dir.join("object"), &data).unwrap();
+        let id = ObjectId::hash_object(HashAlgorithm::Sha1, kind.parse().unwrap(), &data).unwrap();
+
+        let output = hash_object(&dir, &["-t", kind, "-w", "object"]);
+        assert!(!output.status.success(), "{message}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(message), "{message}: {stderr}");
+        assert!(!repo.odb().contains(&id), "{message}");
+
+        let output = hash_object(&dir, &["-t", kind, "-w", "--literally", "object"]);
+        assert!(output.status.success(), "{message}: {output:?}");
+        assert_eq!(output.stdout, format!("{id}\n").as_bytes());
+        assert_eq!(repo.odb().read_raw(&id).unwrap().1, data, "{message}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn every_write_path_checks_objects() {
+    let (dir, repo) = common::scratch_repo("fsck-write-paths", HashAlgorithm::Sha1);
+    let blob = common::blob(&repo, b"hi\n");
+    let data = entry("40000", b".git", &blob);
+    let id = ObjectId::hash_object(HashAlgorithm::Sha1, ObjectKind::Tree, &data).unwrap();
+    let malformed = |result: rosa::Result<ObjectId>| {
+        assert!(
+            matches!(result, Err(Error::MalformedObject { .. })),
+            "{result:?}"
+        );
+    };
+
+    malformed(repo.odb().write_raw(ObjectKind::Tree, &data));
+    malformed(
+        repo.odb()
+            .write_stream(ObjectKind::Tree, data.len() as u64, &data[..]),
+    );
+    let tree = Tree {
+        entries: vec![TreeEntry::new(MODE_TREE, ".git", blob)],
+    };
+    malformed(repo.odb().write(&Object::Tree(tree)));
+    assert!(!repo.odb().contains(&id));
+
+    // Blobs hold anything, and the literal path takes what it is given.
+    let blob_id = repo
+        .odb()
+        .write_stream(ObjectKind::Blob, data.len() as u64, &data[..]);
+    assert!(repo.odb().contains(&blob_id.unwrap()));
+    assert_eq!(
+        repo.odb().write_literally(ObjectKind::Tree, &data).unwrap(),
+        id
+    );
+    assert!(repo.odb().contains(&id));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// The problems found in objects, by object, name and severity.
+fn reports(outcome: &fsck::FsckOutcome) -> Vec<(ObjectId, MessageId, Severity)> {
+    let mut out: Vec<_> = outcome
+        .problems
+        .iter()
+        .filter_map(|problem| match problem {
+            Problem::Object {
+                id,
+                severity,
+                report,
+                ..
+            } => Some((*id, report.id, *severity)),
+            Problem::Other(_) => None,
+        })
+        .collect();
+    out.sort_by_key(|&(id, message, _)| (id, message.name()));
+    out
+}
+
+#[test]
+fn objects_are_checked_where_they_are_stored() {
+    let (dir, repo) = common::scratch_repo("fsck-stored", HashAlgorithm::Sha1);
+    let a = common::blob(&repo, b"a\n");
+    let b = common::blob(&repo, b"b\n");
+    // b's file now holds a.
+    let path = repo.odb().loose().path(&b);
+    fs::remove_file(&path).unwrap();
+    fs::copy(repo.odb().loose().path(&a), &path).unwrap();
+
+    let outcome = fsck::fsck(&repo, &FsckOptions::default()).unwrap();
+    let message = format!("{a}: hash-path mismatch, found at: {}", path.display());
+    assert_eq!(outcome.problems, [Problem::Other(message)]);
+    assert_eq!(outcome.error_count(), 1);
+    // What cannot be trusted is not called dangling either.
+    assert_eq!(outcome.unreachable, [(ObjectKind::Blob, a, true)]);
+
+    // Only checking connectivity takes objects at their word.
+    let options = FsckOptions {
+        connectivity_only: true,
+        ..FsckOptions::default()
+    };
+    assert_eq!(fsck::fsck(&repo, &options).unwrap().error_count(), 0);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn tree_entries_are_checked_with_their_severities() {
+    let (dir, repo) = common::scratch_repo("fsck-trees", HashAlgorithm::Sha1);
+    let blob = common::blob(&repo, b"hi\n");
+    let empty = common::tree(&repo, &[]);
+    let literally = |data: Vec<u8>| repo.odb().write_literally(ObjectKind::Tree, &data).unwrap();
+    let unsorted = literally([entry("100644", b"b", &blob), entry("100644", b"a", &blob)].concat());
+    let duplicate =
+        literally([entry("100644", b"a", &blob), entry("100644", b"a", &blob)].concat());
+    let bad_mode = literally(entry("100645", b"a", &blob));
+    let group_writable = literally(entry("100664", b"a", &blob));
+    let zero_padded = literally(entry("040000", b"sub", &empty));
+    let check = |options: &FsckOptions| reports(&fsck::fsck(&repo, options).unwrap());
+    let expected = |mut found: Vec<(ObjectId, MessageId, Severity)>| {
+        found.sort_by_key(|&(id, message, _)| (id, message.name()));
+        found
+    };
+
+    assert_eq!(
+        check(&FsckOptions::default()),
+        expected(vec![
+            (unsorted, MessageId::TreeNotSorted, Severity::Error),
+            (duplicate, MessageId::DuplicateEntries, Severity::Error),
+            (bad_mode, MessageId::BadFilemode, Severity::Info),
+            (
+                zero_padded,
+                MessageId::ZeroPaddedFilemode,
+                Severity::Warning
+            ),
+        ])
+    );
+
+    // --strict makes warnings errors, minds group-writable modes, but
+    // leaves what is only informative alone.
+    let strict = FsckOptions {
+        strict: true,
+        ..FsckOptions::default()
+    };
+    assert_eq!(
+        check(&strict),
+        expected(vec![
+            (unsorted, MessageId::TreeNotSorted, Severity::Error),
+            (duplicate, MessageId::DuplicateEntries, Severity::Error),
+            (bad_mode, MessageId::BadFilemode, Severity::Info),
+            (group_writable, MessageId::BadFilemode, Severity::Info),
+            (zero_padded, MessageId::ZeroPaddedFilemode, Severity::Error),
+        ])
+    );
+
+    // fsck.<msg-id> wins over the defaults, and over --strict.
+    let config_path = repo.path("config");
+    let mut config = Config::load(&config_path).unwrap();
+    config.set("fsck.treeNotSorted", "warn").unwrap();
+    config.set("fsck.duplicateEntries", "ignore").unwrap();
+    config.set("fsck.badFilemode", "error").unwrap();
+    config.set("fsck.zeroPaddedFilemode", "warn").unwrap();
+    config.write(&config_path).unwrap();
+    let configured = FsckOptions {
+        strict: true,
+        ..FsckOptions::from_config(&Config::load(&config_path).unwrap()).unwrap()
+    };
+    assert_eq!(
+        check(&configured),
+        expected(vec![
+            (unsorted, MessageId::TreeNotSorted, Severity::Warning),
+            (bad_mode, MessageId::BadFilemode, Severity::Error),
+            (group_writable, MessageId::BadFilemode, Severity::Error),
+            (
+                zero_padded,
+                MessageId::ZeroPaddedFilemode,
+                Severity::Warning
+            ),
+        ])
+    );
+
+    // And so for the command, whose exit status follows the errors.
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .arg("fsck")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!(
+        "warning in tree {unsorted}: treeNotSorted: not properly sorted"
+    )));
+    assert!(stderr.contains(&format!(
+        "error in tree {bad_mode}: badFilemode: contains bad file modes"
+    )));
+    assert!(!stderr.contains(&duplicate.to_string()), "{stderr}");
+
+    config.set("fsck.badFilemode", "fatal").unwrap();
+    config.write(&config_path).unwrap();
+    let refused = FsckOptions::from_config(&Config::load(&config_path).unwrap());
+    assert!(matches!(refused, Err(Error::Config(_))));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn fsck_output(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .arg("fsck")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn only_what_nothing_points_to_is_dangling() {
+    let (dir, repo) = common::scratch_repo("fsck-dangling", HashAlgorithm::Sha1);
+    let kept = common::blob(&repo, b"kept\n");
+    let tip = common::commit(
+        &repo,
+        common::tree(&repo, &[(MODE_BLOB, "kept", kept)]),
+        &[],
+        1_700_000_000,
+        "c\n",
+    );
+    refs::update(&repo, "refs/heads/master", &tip).unwrap();
+    // A tree nothing reaches, holding a blob only it points to.
+    let inner = common::blob(&repo, b"inner\n");
+    let lost = common::tree(&repo, &[(MODE_BLOB, "inner", inner)]);
+    let alone = common::blob(&repo, b"alone\n");
+
+    let outcome = fsck::fsck(&repo, &FsckOptions::default()).unwrap();
+    let mut expected = vec![
+        (ObjectKind::Blob, inner, false),
+        (ObjectKind::Tree, lost, true),
+        (ObjectKind::Blob, alone, true),
+    ];
+    expected.sort_by_key(|&(_, id, _)| id);
+    assert_eq!(outcome.unreachable, expected);
+    assert_eq!(outcome.error_count(), 0);
+    assert_eq!(outcome.roots, [tip]);
+
+    let lines = |kinds: &[(&str, ObjectKind, ObjectId)]| {
+        let mut lines: Vec<_> = kinds
+            .iter()
+            .map(|(what, kind, id)| format!("{what} {kind} {id}\n"))
+            .collect();
+        lines.sort_by_key(|line| line.rsplit(' ').next().unwrap().to_owned());
+        lines.concat()
+    };
+    assert_eq!(
+        fsck_output(&dir, &[]),
+        lines(&[
+            ("dangling", ObjectKind::Tree, lost),
+            ("dangling", ObjectKind::Blob, alone),
+        ])
+    );
+    assert_eq!(
+        fsck_output(&dir, &["--unreachable"]),
+        lines(&[
+            ("unreachable", ObjectKind::Blob, inner),
+            ("unreachable", ObjectKind::Tree, lost),
+            ("unreachable", ObjectKind::Blob, alone),
+        ])
+    );
+    assert_eq!(fsck_output(&dir, &["--no-dangling"]), "");
+    assert_eq!(
+        fsck_output(&dir, &["--no-dangling", "--root"]),
+        format!("root {tip}\n")
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_index_and_reflogs_keep_objects_reachable() {
+    let (dir, repo) = common::scratch_repo("fsck-roots", HashAlgorithm::Sha1);
+    let staged = common::blob(&repo, b"staged\n");
+    let old_blob = common::blob(&repo, b"old\n");
+    let old = common::commit(
+        &repo,
+        common::tree(&repo, &[(MODE_BLOB, "old", old_blob)]),
+        &[],
+        1_700_000_000,
+        "c\n",
+    );
+    let tip = common::commit(&repo, common::tree(&repo, &[]), &[], 1_700_000_000, "c\n");
+    refs::update(&repo, "refs/heads/master", &tip).unwrap();
+
+    fs::write(dir.join("file"), "staged\n").unwrap();
+    let meta = fs::symlink_metadata(dir.join("file")).unwrap();
+    let entry = |name: &str, id| IndexEntry::from_metadata(name.into(), id, &meta);
+    let index = |entries| Index {
+        entries,
+        ..Index::default()
+    };
+    index(vec![entry("file", staged)]).write(&repo).unwrap();
+    let log = |lines: &[(ObjectId, ObjectId)]| {
+        let path = repo.path("logs/refs/heads/master");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let lines: String = lines
+            .iter()
+            .map(|(from, to)| {
+                format!("{from} {to} C O Mitter <committer@example.com> 1700000000 +0000\tc\n")
+            })
+            .collect();
+        fs::write(path, lines).unwrap();
+    };
+    let null = ObjectId::null(HashAlgorithm::Sha1);
+    log(&[(null, old), (old, tip)]);
+
+    let outcome = fsck::fsck(&repo, &FsckOptions::default()).unwrap();
+    assert!(outcome.unreachable.is_empty(), "{:?}", outcome.unreachable);
+    assert_eq!(outcome.error_count(), 0);
+
+    // Without them, the same objects are lost.
+    index(Vec::new()).write(&repo).unwrap();
+    log(&[(null, tip)]);
+    let outcome = fsck::fsck(&repo, &FsckOptions::default()).unwrap();
+    let lost: HashSet<ObjectId> = outcome.unreachable.iter().map(|&(_, id, _)| id).collect();
+    assert!(lost.contains(&staged) && lost.contains(&old) && lost.contains(&old_blob));
+
+    // And what they point to must be there.
+    let gone = ObjectId::from_hex("5e1a84360526970a11636d4f110882c2f1a4b01c").unwrap();
+    index(vec![entry("file", gone)]).write(&repo).unwrap();
+    log(&[(null, gone), (gone, tip)]);
+    let outcome = fsck::fsck(&repo, &FsckOptions::default()).unwrap();
+    assert_eq!(
+        outcome.problems,
+        [
+            Problem::Other("file: invalid sha1 pointer in index".to_owned()),
+            Problem::Other(format!("refs/heads/master: invalid reflog entry {gone}")),
+            Problem::Other(format!("refs/heads/master: invalid reflog entry {gone}")),
+        ]
+    );
+    assert_eq!(outcome.error_count(), 3);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,231 @@
+//! gc packs what is reachable, prunes what is not once it is old enough,
+//! and packs refs, while respecting what marks data as not its own to
+//! rewrite: `.keep` packs, and lockfiles of concurrent writers.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use rosa::gc::{self, GcOptions};
+use rosa::object::{HashAlgorithm, MODE_BLOB};
+use rosa::pack::{self, PackObject, PackOptions};
+use rosa::{refs, Error, ObjectId, Repository};
+
+fn options() -> GcOptions {
+    GcOptions {
+        prune_expire: None,
+        pack: PackOptions::default(),
+        write_commit_graph: false,
+    }
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Makes the loose copy of `id` look `days` old.
+fn backdate(repo: &Repository, id: &ObjectId, days: u64) {
+    let path = repo.odb().loose().path(id);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(days * DAY))
+        .unwrap();
+}
+
+#[test]
+fn reachable_objects_are_packed_and_refs_too() {
+    let (dir, repo) = common::scratch_repo("gc-pack", HashAlgorithm::Sha1);
+    let blob = common::blob(&repo, b"kept\n");
+    let tree = common::tree(&repo, &[(MODE_BLOB, "kept.txt", blob)]);
+    let commit = common::commit(&repo, tree, &[], 1_700_000_000, "c\n");
+    refs::update(&repo, "refs/heads/master", &commit).unwrap();
+    refs::update(&repo, "refs/tags/v1", &commit).unwrap();
+
+    let report = gc::gc(&repo, &options()).unwrap();
+    assert_eq!(report.packed_objects, 3);
+    assert_eq!(report.removed_loose, 3);
+    assert_eq!(report.packed_refs, 2);
+    assert!(repo.odb().loose().list().unwrap().is_empty());
+    let packs = repo.odb().packs();
+    assert_eq!(packs.len(), 1);
+    let name = format!("pack-{}.pack", report.pack.unwrap());
+    assert_eq!(packs[0].path().file_name().unwrap(), &name[..]);
+    for id in [blob, tree, commit] {
+        assert!(packs[0].contains(&id), "{id} was not packed");
+    }
+
+    // Refs move to packed-refs, where HEAD still finds the branch.
+    assert!(!repo.path("refs/heads/master").exists());
+    assert!(!repo.path("refs/tags/v1").exists());
+    let packed: Vec<_> = refs::read_packed(&repo)
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.name, r.id))
+        .collect();
+    assert_eq!(
+        packed,
+        [
+            ("refs/heads/master".to_owned(), commit),
+            ("refs/tags/v1".to_owned(), commit)
+        ]
+    );
+    assert_eq!(refs::resolve(&repo, "HEAD").unwrap(), Some(commit));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn only_old_unreachable_objects_are_pruned() {
+    let (dir, repo) = common::scratch_repo("gc-prune", HashAlgorithm::Sha1);
+    let reachable = common::blob(&repo, b"tagged\n");
+    refs::update(&repo, "refs/tags/v1", &reachable).unwrap();
+    let old = common::blob(&repo, b"old and unreachable\n");
+    let recent = common::blob(&repo, b"recent and unreachable\n");
+    backdate(&repo, &reachable, 30);
+    backdate(&repo, &old, 30);
+    backdate(&repo, &recent, 13);
+
+    let options = GcOptions {
+        prune_expire: gc::parse_expiry("2.weeks.ago", SystemTime::now()).unwrap(),
+        ..options()
+    };
+    let report = gc::gc(&repo, &options).unwrap();
+    assert_eq!(report.pruned, 1);
+    assert_eq!(report.removed_loose, 1);
+    assert!(!repo.odb().contains(&old));
+    assert!(repo.odb().loose().contains(&recent));
+    assert!(repo.odb().packs()[0].contains(&reachable));
+
+    // Without an expiry nothing unreachable goes, however old.
+    backdate(&repo, &recent, 365);
+    let report = gc::gc(&repo, &self::options()).unwrap();
+    assert_eq!(report.pruned, 0);
+    assert!(repo.odb().loose().contains(&recent));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn expiry_dates() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let ago = |seconds: u64| Some(now - Duration::from_secs(seconds));
+    for (spec, expected) in [
+        ("never", None),
+        ("false", None),
+        ("now", Some(now)),
+        ("all", Some(now)),
+        (
+            "1600000000",
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+        ),
+        (
+            "@1600000000",
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+        ),
+        ("2.weeks.ago", ago(14 * DAY)),
+        ("1 week ago", ago(7 * DAY)),
+        ("90.minutes.ago", ago(90 * 60)),
+        ("3.days.ago", ago(3 * DAY)),
+        ("1.month.ago", ago(30 * DAY)),
+        ("1.year.ago", ago(365 * DAY)),
+    ] {
+        assert_eq!(gc::parse_expiry(spec, now).unwrap(), expected, "{spec}");
+    }
+    for spec in [
+        "",
+        "soon",
+        "2.weeks",
+        "weeks.ago",
+        "2.fortnights.ago",
+        "-1.days.ago",
+    ] {
+        assert!(
+            matches!(gc::parse_expiry(spec, now), Err(Error::Usage(_))),
+            "{spec}"
+        );
+    }
+}
+
+#[test]
+fn kept_packs_are_left_alone() {
+    let (dir, repo) = common::scratch_repo("gc-keep", HashAlgorithm::Sha1);
+    let kept_id = common::blob(&repo, b"in a kept pack\n");
+    refs::update(&repo, "refs/tags/kept", &kept_id).unwrap();
+    let kept = pack::write_pack_files(
+        repo.odb(),
+        &[PackObject {
+            id: kept_id,
+            name_hash: 0,
+        }],
+        &PackOptions::default(),
+        &repo.odb().dir().join("pack/pack"),
+    )
+    .unwrap();
+    let keep_file = kept.pack_path.with_extension("keep");
+    fs::write(&keep_file, "").unwrap();
+    repo.odb().refresh_packs().unwrap();
+
+    let loose_id = common::blob(&repo, b"loose\n");
+    refs::update(&repo, "refs/tags/loose", &loose_id).unwrap();
+
+    let report = gc::gc(&repo, &options()).unwrap();
+    assert_eq!(report.kept_packs, 1);
+    assert_eq!(report.removed_packs, 0);
+    assert_eq!(report.packed_objects, 1);
+    assert!(kept.pack_path.exists() && kept.index_path.exists() && keep_file.exists());
+
+    let packs = repo.odb().packs();
+    assert_eq!(packs.len(), 2);
+    let new = packs.iter().find(|p| p.path() != kept.pack_path).unwrap();
+    assert!(new.contains(&loose_id) && !new.contains(&kept_id));
+
+    // A second run leaves both packs in place.
+    let report = gc::gc(&repo, &options()).unwrap();
+    assert_eq!(report.kept_packs, 1);
+    assert!(kept.pack_path.exists() && keep_file.exists());
+    assert!(repo.odb().contains(&kept_id) && repo.odb().contains(&loose_id));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn version_4_index_entries_are_roots() {
+    let (dir, repo) = common::scratch_repo("gc-index-v4", HashAlgorithm::Sha1);
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/index/v4");
+    fs::copy(fixture, repo.path("index")).unwrap();
+    let roots = gc::roots(&repo).unwrap();
+    assert_eq!(roots.len(), 5);
+    assert!(roots
+        .iter()
+        .any(|id| id.to_string() == "4bcfe98e640c8284511312660fb8709b0afa888e"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn packed_refs_are_written_under_a_lock() {
+    let (dir, repo) = common::scratch_repo("gc-packed-refs", HashAlgorithm::Sha1);
+    let id = common::blob(&repo, b"tagged\n");
+    refs::update(&repo, "refs/tags/v1", &id).unwrap();
+
+    let lock = repo.path("packed-refs.lock");
+    fs::write(&lock, "").unwrap();
+    assert!(matches!(refs::pack(&repo), Err(Error::Locked(_))));
+    assert!(lock.exists() && !repo.path("packed-refs").exists());
+    assert!(repo.path("refs/tags/v1").exists());
+    assert!(matches!(
+        refs::update(&repo, "refs/tags/v1.lock", &id),
+        Err(Error::InvalidRefName(_))
+    ));
+
+    fs::remove_file(&lock).unwrap();
+    assert_eq!(refs::pack(&repo).unwrap(), 1);
+    assert!(!lock.exists() && !repo.path("refs/tags/v1").exists());
+    assert_eq!(refs::resolve(&repo, "refs/tags/v1").unwrap(), Some(id));
+
+    // Loose refs take the same lock.
+    fs::write(repo.path("refs/tags/v2.lock"), "").unwrap();
+    assert!(matches!(
+        refs::update(&repo, "refs/tags/v2", &id),
+        Err(Error::Locked(_))
+    ));
+    fs::remove_dir_all(dir).unwrap();
+}
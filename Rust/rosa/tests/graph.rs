@@ -0,0 +1,120 @@
+//! `log --graph` must draw what git draws, down to the trailing spaces.
+//! Each history here is made of the same commits, dates included, as the
+//! one git 2.39 drew `tests/graph/<name>.txt` from.
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use rosa::object::HashAlgorithm;
+use rosa::refs;
+
+/// Commits named by their subject, oldest first, each with its parents.
+type History<'a> = &'a [(&'a str, &'a [&'a str])];
+
+/// Builds `history` in a scratch repository with `master` at its last
+/// commit, and returns what `mygit log --graph` prints with `format`.
+fn draw(name: &str, history: History, format: &str) -> String {
+    let (dir, repo) = common::scratch_repo(&format!("graph-{name}"), HashAlgorithm::Sha1);
+    let empty = common::tree(&repo, &[]);
+    let mut ids = HashMap::new();
+    for (at, &(subject, parents)) in history.iter().enumerate() {
+        let parents: Vec<_> = parents.iter().map(|parent| ids[parent]).collect();
+        let time = 1_700_000_000 + at as i64;
+        let id = common::commit(&repo, empty, &parents, time, &format!("{subject}\n"));
+        ids.insert(subject, id);
+    }
+    let tip = ids[history.last().unwrap().0];
+    refs::update(&repo, "refs/heads/master", &tip).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .args(["log", "--graph", "--no-decorate", format])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    fs::remove_dir_all(dir).unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn expected(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/graph")
+        .join(format!("{name}.txt"));
+    fs::read_to_string(path).unwrap()
+}
+
+/// Two side branches merged in turn, one forking from an older commit
+/// than the other, so that lines cross on their way back.
+const MERGES: History = &[
+    ("base", &[]),
+    ("m1", &["base"]),
+    ("b1", &["base"]),
+    ("m2", &["m1"]),
+    ("b2", &["b1"]),
+    ("merge1", &["m2", "b2"]),
+    ("c1", &["m1"]),
+    ("m3", &["merge1"]),
+    ("merge2", &["m3", "c1"]),
+];
+
+#[test]
+fn merges() {
+    assert_eq!(draw("merges", MERGES, "--format=%s"), expected("merges"));
+}
+
+#[test]
+fn merges_with_entries_of_several_lines() {
+    // Entries are separated rather than ended by line breaks, so the
+    // lines between commits are drawn as padding.
+    assert_eq!(
+        draw("unterminated", MERGES, "--format=format:%s%n%an"),
+        expected("merges-unterminated")
+    );
+}
+
+#[test]
+fn octopus_merges() {
+    // A three- and a four-parent merge, the latter with a line to its
+    // right it must make room past.
+    let history: History = &[
+        ("base", &[]),
+        ("side", &["base"]),
+        ("x", &["base"]),
+        ("y", &["base"]),
+        ("z", &["base"]),
+        ("oct3", &["x", "y", "z"]),
+        ("p", &["oct3"]),
+        ("q", &["oct3"]),
+        ("r", &["oct3"]),
+        ("s", &["oct3"]),
+        ("oct4", &["p", "q", "r", "s"]),
+        ("merge", &["oct4", "side"]),
+    ];
+    assert_eq!(draw("octopus", history, "--format=%s"), expected("octopus"));
+}
+
+#[test]
+fn lanes_collapsing() {
+    // A five-parent merge whose parents share lines with commits shown
+    // before it: several lines move left at once, with `_` runs.
+    let history: History = &[
+        ("base", &[]),
+        ("a", &["base"]),
+        ("b", &["base"]),
+        ("c", &["base"]),
+        ("d", &["base"]),
+        ("e", &["base"]),
+        ("tip", &["a", "b", "c", "d", "e"]),
+        ("f", &["a"]),
+        ("g", &["b"]),
+        ("top", &["tip", "f", "g"]),
+    ];
+    assert_eq!(
+        draw("collapse", history, "--format=%s"),
+        expected("collapse")
+    );
+}
@@ -0,0 +1,131 @@
+//! Ignore rules are read from the worktree as git reads them; every
+//! expectation here is what `git check-ignore` says of the same tree.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use rosa::ignore::{self, IgnoreRules};
+use rosa::object::HashAlgorithm;
+
+/// Writes `files` (worktree-relative, with their contents) into a scratch
+/// repository and reads its rules back.
+fn rules(name: &str, files: &[(&str, &str)]) -> (std::path::PathBuf, IgnoreRules) {
+    let (dir, repo) = common::scratch_repo(name, HashAlgorithm::Sha1);
+    for (path, text) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+    (dir, IgnoreRules::read(&repo).unwrap())
+}
+
+fn cleanup(dir: &Path) {
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn negation_re_includes_unless_a_directory_is_out() {
+    let (dir, rules) = rules(
+        "ignore-negation",
+        &[(".gitignore", "*.log\n!keep.log\nout/\n!out/kept\n\\!bang\n")],
+    );
+    assert!(rules.is_ignored(b"debug.log", false));
+    assert!(rules.is_ignored(b"sub/trace.log", false));
+    assert!(!rules.is_ignored(b"keep.log", false));
+    assert!(!rules.is_ignored(b"sub/keep.log", false));
+    // Files below an ignored directory stay ignored whatever follows.
+    assert!(rules.is_ignored(b"out/kept", false));
+    // A backslash makes a leading `!` literal.
+    assert!(rules.is_ignored(b"!bang", false));
+    assert!(!rules.is_ignored(b"bang", false));
+    cleanup(&dir);
+}
+
+#[test]
+fn directory_only_patterns() {
+    let (dir, rules) = rules("ignore-dirs", &[(".gitignore", "build/\n")]);
+    assert!(rules.is_ignored(b"build", true));
+    assert!(rules.is_ignored(b"build/app", false));
+    assert!(rules.is_ignored(b"src/build", true));
+    assert!(rules.is_ignored(b"src/build/app.o", false));
+    // A file called `build` is not a directory.
+    assert!(!rules.is_ignored(b"build", false));
+    assert!(!rules.is_ignored(b"src/build", false));
+    cleanup(&dir);
+}
+
+#[test]
+fn slashes_anchor_patterns() {
+    let (dir, rules) = rules(
+        "ignore-anchored",
+        &[(".gitignore", "/root.txt\ndoc/*.html\nname.txt\n")],
+    );
+    assert!(rules.is_ignored(b"root.txt", false));
+    assert!(!rules.is_ignored(b"sub/root.txt", false));
+    assert!(rules.is_ignored(b"doc/index.html", false));
+    assert!(!rules.is_ignored(b"sub/doc/index.html", false));
+    // `*` stops at slashes.
+    assert!(!rules.is_ignored(b"doc/api/index.html", false));
+    // Without a slash, a pattern matches at any depth.
+    assert!(rules.is_ignored(b"a/b/name.txt", false));
+    cleanup(&dir);
+}
+
+#[test]
+fn double_stars_span_directories() {
+    let (dir, rules) = rules(
+        "ignore-double-star",
+        &[(".gitignore", "**/cache\nlogs/**\na/**/z.txt\n")],
+    );
+    assert!(rules.is_ignored(b"cache", false));
+    assert!(rules.is_ignored(b"x/y/cache", true));
+    assert!(rules.is_ignored(b"x/y/cache/entry", false));
+    assert!(rules.is_ignored(b"logs/today", false));
+    assert!(rules.is_ignored(b"logs/2024/01.txt", false));
+    assert!(!rules.is_ignored(b"logs", true));
+    assert!(rules.is_ignored(b"a/z.txt", false));
+    assert!(rules.is_ignored(b"a/b/c/z.txt", false));
+    assert!(!rules.is_ignored(b"b/a/z.txt", false));
+    cleanup(&dir);
+}
+
+#[test]
+fn nearer_gitignore_files_take_precedence() {
+    let (dir, rules) = rules(
+        "ignore-nested",
+        &[
+            (".gitignore", "*.tmp\n!*.md\n"),
+            ("src/.gitignore", "!*.tmp\n*.md\n"),
+            ("src/deep/.gitignore", "/local.tmp\n"),
+            (".git/info/exclude", "*.bak\nsrc/*.tmp\n"),
+        ],
+    );
+    assert!(rules.is_ignored(b"a.tmp", false));
+    assert!(!rules.is_ignored(b"src/a.tmp", false));
+    assert!(rules.is_ignored(b"src/README.md", false));
+    assert!(!rules.is_ignored(b"README.md", false));
+    // The deepest file is only anchored to its own directory, and defers
+    // to `src/.gitignore` for anything it has no opinion on.
+    assert!(rules.is_ignored(b"src/deep/local.tmp", false));
+    assert!(!rules.is_ignored(b"src/deep/other.tmp", false));
+    // `.gitignore` files outrank `info/exclude`.
+    assert!(rules.is_ignored(b"src/x.bak", false));
+    cleanup(&dir);
+}
+
+#[test]
+fn patterns_parse_and_match_bytes() {
+    let parsed = ignore::parse(b"# comment\n\n  spaced  \n!not\n\\#hash\n");
+    let patterns: Vec<_> = parsed.iter().map(|r| (&r.pattern[..], r.ignore)).collect();
+    assert_eq!(
+        patterns,
+        [(&b"spaced"[..], true), (b"not", false), (b"#hash", true)]
+    );
+
+    assert!(ignore::fnmatch(b"[a-c]?[!x]", b"bzy"));
+    assert!(!ignore::fnmatch(b"[a-c]?[!x]", b"bzx"));
+    assert!(ignore::fnmatch(b"\xff*", b"\xff\xfe"));
+    assert!(ignore::fnmatch(b"[]]", b"]"));
+}
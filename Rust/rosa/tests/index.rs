@@ -0,0 +1,91 @@
+//! The indexes in `tests/index` were written by git: `v3` holds an
+//! intent-to-add entry, which needs the extended flags of version 3, and
+//! `v4` is the same index with prefix-compressed names.
+
+use std::fs;
+use std::path::Path;
+
+use rosa::index::Index;
+use rosa::object::HashAlgorithm;
+
+const NAMES: [&str; 5] = [
+    "README",
+    "new.txt",
+    "src/deep/mod.rs",
+    "src/lib.rs",
+    "src/main.rs",
+];
+
+fn fixture(name: &str) -> Index {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/index")
+        .join(name);
+    Index::parse(&fs::read(path).unwrap(), HashAlgorithm::Sha1).unwrap()
+}
+
+fn names(index: &Index) -> Vec<String> {
+    index
+        .entries
+        .iter()
+        .map(|e| String::from_utf8(e.name.clone()).unwrap())
+        .collect()
+}
+
+#[test]
+fn versions_3_and_4_are_read() {
+    let v3 = fixture("v3");
+    let v4 = fixture("v4");
+    assert_eq!((v3.version, v4.version), (3, 4));
+    assert_eq!(names(&v3), NAMES);
+    assert_eq!(v3.entries, v4.entries);
+
+    let ita: Vec<_> = v3.entries.iter().map(|e| e.intent_to_add).collect();
+    assert_eq!(ita, [false, true, false, false, false]);
+    assert!(v3.entries.iter().all(|e| !e.skip_worktree));
+    assert_eq!(
+        v3.entries[3].id.to_string(),
+        "61780798228d17af2d34fce4cfbdf35556832472"
+    );
+}
+
+#[test]
+fn extended_flags_survive_a_rewrite() {
+    for name in ["v3", "v4"] {
+        let index = fixture(name);
+        let raw = index.serialize(HashAlgorithm::Sha1).unwrap();
+        let reread = Index::parse(&raw, HashAlgorithm::Sha1).unwrap();
+        assert_eq!(reread.version, 3);
+        assert_eq!(reread.entries, index.entries);
+    }
+
+    let mut plain = fixture("v4");
+    for entry in &mut plain.entries {
+        entry.intent_to_add = false;
+    }
+    let raw = plain.serialize(HashAlgorithm::Sha1).unwrap();
+    assert_eq!(Index::parse(&raw, HashAlgorithm::Sha1).unwrap().version, 3);
+    plain.version = 2;
+    let raw = plain.serialize(HashAlgorithm::Sha1).unwrap();
+    assert_eq!(Index::parse(&raw, HashAlgorithm::Sha1).unwrap().version, 2);
+}
+
+#[test]
+fn corrupt_version_4_names_are_refused() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/index/v4");
+    let mut raw = fs::read(path).unwrap();
+    // The second entry's prefix length: strip more than "README" has.
+    let at = raw.windows(8).position(|w| w == b"new.txt\0").unwrap() - 1;
+    raw[at] = 0x20;
+    assert!(Index::parse(&raw, HashAlgorithm::Sha1).is_err());
+    raw[at] = 0x80;
+    assert!(Index::parse(&raw, HashAlgorithm::Sha1).is_err());
+}
+
+#[test]
+fn entry_counts_beyond_the_file_are_refused() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/index/v3");
+    let mut raw = fs::read(path).unwrap();
+    // Found truncated, not reserved for first.
+    raw[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(Index::parse(&raw, HashAlgorithm::Sha1).is_err());
+}
@@ -0,0 +1,333 @@
+//! Commits and tags must serialize back to the bytes they were parsed
+//! from, or their ids would change on a read-modify-write. The objects in
+//! `tests/objects` come from real repositories (signed commits and tags,
+//! a merge of a signed tag, a non-UTF-8 encoding) and are named by id.
+
+use std::fs;
+use std::path::Path;
+
+use rosa::object::{Commit, HashAlgorithm, Kvlm, ObjectId, ObjectKind, Offset, Signature, Tag};
+
+/// Mutations tried on each object.
+const ROUNDS: usize = 5_000;
+
+/// Bytes that matter to the format, so mutations hit interesting cases.
+const ALPHABET: &[u8] = b" \n\n<>+-0179aAzgpsig\t\r\0\xe9";
+
+/// A xorshift generator, so failures can be replayed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn byte(&mut self) -> u8 {
+        ALPHABET[self.below(ALPHABET.len())]
+    }
+}
+
+fn fixtures() -> Vec<(ObjectId, ObjectKind, Vec<u8>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/objects");
+    let mut objects: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let id = path.file_stem().unwrap().to_str().unwrap().parse().unwrap();
+            let kind = path.extension().unwrap().to_str().unwrap().parse().unwrap();
+            (id, kind, fs::read(&path).unwrap())
+        })
+        .collect();
+    objects.sort_by_key(|(id, _, _)| *id);
+    assert!(!objects.is_empty());
+    objects
+}
+
+/// Headers always parse and give back exactly `raw` when serialized;
+/// commits and tags do too when their headers are well formed. Returns
+/// whether the commit or tag parsed.
+fn assert_exact(kind: ObjectKind, raw: &[u8]) -> bool {
+    let show = || String::from_utf8_lossy(raw).into_owned();
+    let kvlm = Kvlm::parse(raw).unwrap_or_else(|e| panic!("{e}: {:?}", show()));
+    assert_eq!(kvlm.serialize(), raw, "kvlm of {:?}", show());
+    let serialized = match kind {
+        ObjectKind::Commit => Commit::parse(raw).map(|commit| commit.serialize()),
+        ObjectKind::Tag => Tag::parse(raw).map(|tag| tag.serialize()),
+        _ => unreachable!("only commits and tags are kvlm"),
+    };
+    match serialized {
+        Ok(serialized) => {
+            assert_eq!(serialized, raw, "{kind} {:?}", show());
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[test]
+fn real_objects_keep_their_ids() {
+    for (id, kind, raw) in fixtures() {
+        let serialized = match kind {
+            ObjectKind::Commit => Commit::parse(&raw).unwrap().serialize(),
+            ObjectKind::Tag => Tag::parse(&raw).unwrap().serialize(),
+            _ => unreachable!(),
+        };
+        let rehashed = ObjectId::hash_object(HashAlgorithm::Sha1, kind, &serialized).unwrap();
+        assert_eq!(rehashed, id);
+    }
+}
+
+#[test]
+fn signed_and_merged_tag_headers_are_kept() {
+    let objects = fixtures();
+    let commits: Vec<Commit> = objects
+        .iter()
+        .filter(|(_, kind, _)| *kind == ObjectKind::Commit)
+        .map(|(_, _, raw)| Commit::parse(raw).unwrap())
+        .collect();
+
+    let signed = commits
+        .iter()
+        .find_map(|c| c.extra_header(b"gpgsig"))
+        .expect("a signed commit");
+    assert!(signed.starts_with(b"-----BEGIN "));
+    assert!(signed.contains(&b'\n') && !signed.contains(&b'\r'));
+
+    let mergetag = commits
+        .iter()
+        .find_map(|c| c.extra_header(b"mergetag"))
+        .expect("a merge of a signed tag");
+    let tag = Tag::parse(mergetag).unwrap();
+    assert_eq!(tag.name, b"v1.0");
+    assert!(tag
+        .message
+        .unwrap()
+        .starts_with(b"Release v1.0\n-----BEGIN PGP"));
+
+    assert!(commits
+        .iter()
+        .any(|c| c.encoding.as_deref() == Some(b"ISO-8859-1")));
+}
+
+#[test]
+fn odd_signatures_are_read_and_written_back() {
+    let check = |raw: &[u8], time: i64, minutes: i32| {
+        let signature = Signature::parse(raw).unwrap();
+        let show = String::from_utf8_lossy(raw);
+        assert_eq!(signature.name, b"A U Thor", "{show}");
+        assert_eq!(signature.email, b"a@example.com", "{show}");
+        assert_eq!(signature.time, time, "{show}");
+        assert_eq!(signature.offset, Offset::from_minutes(minutes), "{show}");
+        assert_eq!(signature.to_bytes(), raw, "{show}");
+    };
+    // All found in real histories; git reads every one of them.
+    check(
+        b"A U Thor <a@example.com> 0001700000000 +0000",
+        1_700_000_000,
+        0,
+    );
+    check(
+        b"A U Thor <a@example.com> 1700000000 +05030",
+        1_700_000_000,
+        3030,
+    );
+    check(
+        b"A U Thor<a@example.com> 1700000000 -0130",
+        1_700_000_000,
+        -90,
+    );
+    check(
+        b"A U Thor  <a@example.com>  1700000000  +0100",
+        1_700_000_000,
+        60,
+    );
+    check(b"A U Thor <a@example.com>", 0, 0);
+
+    // The email is what the first angle brackets hold.
+    let raw = b"A <U> Thor <a@example.com> 1700000000 +0000";
+    let signature = Signature::parse(raw).unwrap();
+    assert_eq!(
+        (&signature.name[..], &signature.email[..]),
+        (&b"A"[..], &b"U"[..])
+    );
+    assert_eq!(signature.to_bytes(), raw);
+
+    // Well-formed signatures need no copy of their bytes.
+    let plain = Signature::parse(b"A U Thor <a@example.com> 1700000000 +0100").unwrap();
+    assert_eq!(plain.raw, None);
+    for raw in [&b"A U Thor"[..], b"A U Thor <a@example.com", b""] {
+        assert!(Signature::parse(raw).is_err());
+    }
+
+    let raw = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        author A U Thor<a@example.com> 01700000000 +05030\n\
+        committer C O Mitter <c@example.com> 1700000000 +0000\n\nodd\n";
+    let commit = Commit::parse(raw).unwrap();
+    assert_eq!(commit.author.email, b"a@example.com");
+    assert_eq!(commit.serialize(), raw);
+}
+
+#[test]
+fn mutated_objects_round_trip() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for (id, kind, raw) in fixtures() {
+        let mut parsed = 0;
+        for _ in 0..ROUNDS {
+            let mut data = raw.clone();
+            for _ in 0..=rng.below(3) {
+                let at = rng.below(data.len() + 1);
+                match rng.below(4) {
+                    0 if at < data.len() => data[at] = rng.byte(),
+                    1 if at < data.len() => {
+                        data.remove(at);
+                    }
+                    2 => {
+                        // Repeats a line, to interleave repeated keys.
+                        let start = data[..at]
+                            .iter()
+                            .rposition(|&b| b == b'\n')
+                            .map_or(0, |n| n + 1);
+                        let end = data[at..]
+                            .iter()
+                            .position(|&b| b == b'\n')
+                            .map_or(data.len(), |n| at + n + 1);
+                        let line = data[start..end].to_vec();
+                        data.splice(start..start, line);
+                    }
+                    _ => data.insert(at, rng.byte()),
+                }
+            }
+            parsed += usize::from(assert_exact(kind, &data));
+        }
+        // Many mutations land in the message or in a signature, which are
+        // read whatever they hold; most others break an id.
+        assert!(
+            parsed > ROUNDS / 4,
+            "only {parsed} mutations of {id} parsed"
+        );
+    }
+}
+
+#[test]
+fn odd_headers_are_kept_as_read() {
+    let raw = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        author A U Thor <a@example.com> 1700000000 +0000\n\
+        committer C O Mitter <c@example.com> 1700000000 +0000\n\
+        bare \n\
+        folded\n first\n second\n\
+        plain value\n\
+        last";
+    let kvlm = Kvlm::parse(raw).unwrap();
+    assert_eq!(kvlm.message, None);
+    let odd: Vec<_> = kvlm.headers[3..]
+        .iter()
+        .map(|h| (&h.key[..], &h.value[..], h.raw.is_some()))
+        .collect();
+    assert_eq!(
+        odd,
+        [
+            (&b"bare"[..], &b""[..], true),
+            (b"folded", b"\nfirst\nsecond", true),
+            (b"plain", b"value", false),
+            (b"last", b"", true),
+        ]
+    );
+    assert_eq!(kvlm.serialize(), raw);
+    let commit = Commit::parse(raw).unwrap();
+    assert_eq!(
+        commit.extra_header(b"folded"),
+        Some(&b"\nfirst\nsecond"[..])
+    );
+    assert_eq!(commit.serialize(), raw);
+
+    // Headers the commit is rebuilt from must be written the usual way.
+    let raw = b"tree\n 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\n";
+    assert_eq!(Kvlm::parse(raw).unwrap().serialize(), raw);
+    assert!(Commit::parse(raw).is_err());
+}
+
+#[test]
+fn generated_headers_survive_a_round_trip() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..ROUNDS {
+        let mut kvlm = Kvlm::default();
+        for _ in 0..rng.below(6) {
+            let key: Vec<u8> = (0..=rng.below(8))
+                .map(|_| b"abcgpsig-"[rng.below(9)])
+                .collect();
+            let value: Vec<u8> = (0..rng.below(12)).map(|_| rng.byte()).collect();
+            kvlm.push(&key, value);
+        }
+        if rng.below(4) != 0 {
+            kvlm.message = Some((0..rng.below(12)).map(|_| rng.byte()).collect());
+        }
+        let raw = kvlm.serialize();
+        assert_eq!(
+            Kvlm::parse(&raw).unwrap(),
+            kvlm,
+            "{:?}",
+            String::from_utf8_lossy(&raw)
+        );
+    }
+}
+
+#[test]
+fn edited_signatures_are_written_from_their_fields() {
+    let raw = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        author A U Thor<a@example.com> 01700000000 +05030\n\
+        committer C O Mitter <c@example.com> 1700000000 +0000\n\nodd\n";
+    let mut commit = Commit::parse(raw).unwrap();
+    assert!(commit.author.raw.is_some());
+
+    // Setting a field to what it already was keeps the odd spelling.
+    commit.author.time = 1_700_000_000;
+    assert_eq!(commit.serialize(), raw);
+
+    commit.author.name = b"A N Other".to_vec();
+    commit.author.offset = Offset::from_minutes(-60);
+    let expected = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        author A N Other <a@example.com> 1700000000 -0100\n\
+        committer C O Mitter <c@example.com> 1700000000 +0000\n\nodd\n";
+    assert_eq!(commit.serialize(), expected);
+
+    let mut tagger =
+        Signature::parse(b"T A Gger <t@example.com> 1700000000 +0000 trailing").unwrap();
+    tagger.email = b"new@example.com".to_vec();
+    assert_eq!(
+        tagger.to_bytes(),
+        b"T A Gger <new@example.com> 1700000000 +0000"
+    );
+}
+
+#[test]
+fn edited_headers_are_written_from_their_values() {
+    let raw = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        bare \n\
+        folded\n first\n second\n\
+        last";
+    let mut kvlm = Kvlm::parse(raw).unwrap();
+    assert!(kvlm.headers[1..].iter().all(|h| h.raw.is_some()));
+
+    kvlm.headers[1].value = b"now set".to_vec();
+    kvlm.headers[2].key = b"unfolded".to_vec();
+    kvlm.headers[3].value = b"ends here".to_vec();
+    let expected = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        bare now set\n\
+        unfolded \n first\n second\n\
+        last ends here\n";
+    assert_eq!(kvlm.serialize(), expected);
+    assert_eq!(Kvlm::parse(expected).unwrap().serialize(), expected);
+
+    // Put back as they were, the headers are written as read again.
+    kvlm.headers[1].value = Vec::new();
+    kvlm.headers[2].key = b"folded".to_vec();
+    kvlm.headers[3].value = Vec::new();
+    assert_eq!(kvlm.serialize(), raw);
+}
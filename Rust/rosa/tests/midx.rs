@@ -0,0 +1,199 @@
+//! A multi-pack index must agree with the packs it covers, pick one copy
+//! of objects packed twice, and be what the object database searches.
+
+mod common;
+
+use std::fs::{self, File};
+use std::process::Command;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use rosa::object::HashAlgorithm;
+use rosa::pack::{
+    self, MultiPackIndex, Pack, PackObject, PackOptions, WrittenPack, MIDX_FILE_NAME,
+};
+use rosa::{ObjectId, Repository};
+
+/// Packs blobs of `contents` into the repository's pack directory, with
+/// the pack's modification time set `age` seconds in the past.
+fn add_pack(repo: &Repository, contents: &[&str], age: u64) -> WrittenPack {
+    let objects: Vec<PackObject> = contents
+        .iter()
+        .map(|text| PackObject {
+            id: common::blob(repo, text.as_bytes()),
+            name_hash: 0,
+        })
+        .collect();
+    let written = pack::write_pack_files(
+        repo.odb(),
+        &objects,
+        &PackOptions::default(),
+        &repo.odb().dir().join("pack/pack"),
+    )
+    .unwrap();
+    File::options()
+        .write(true)
+        .open(&written.pack_path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(age))
+        .unwrap();
+    repo.odb().refresh_packs().unwrap();
+    written
+}
+
+fn midx_path(repo: &Repository) -> std::path::PathBuf {
+    repo.odb().dir().join("pack").join(MIDX_FILE_NAME)
+}
+
+fn write_midx(repo: &Repository, preferred: Option<&str>) -> MultiPackIndex {
+    let packs = repo.odb().packs();
+    let packs: Vec<&Pack> = packs.iter().map(Rc::as_ref).collect();
+    pack::midx::write(&midx_path(repo), &packs, preferred, HashAlgorithm::Sha1).unwrap();
+    repo.odb().refresh_packs().unwrap();
+    MultiPackIndex::open(&midx_path(repo), HashAlgorithm::Sha1).unwrap()
+}
+
+fn idx_name(written: &WrittenPack) -> String {
+    let name = written.index_path.file_name().unwrap();
+    name.to_str().unwrap().to_owned()
+}
+
+/// Where `id` lives according to `midx`: the `.idx` name and the offset.
+fn located(midx: &MultiPackIndex, id: &ObjectId) -> (String, u64) {
+    let (pack, offset) = midx.lookup(id).unwrap().unwrap();
+    (midx.pack_names()[pack].clone(), offset)
+}
+
+fn offset_in(written: &WrittenPack, id: &ObjectId) -> u64 {
+    written.entries.iter().find(|e| e.id == *id).unwrap().offset
+}
+
+#[test]
+fn covers_every_object_of_both_packs() {
+    let (dir, repo) = common::scratch_repo("midx-round-trip", HashAlgorithm::Sha1);
+    let old = add_pack(&repo, &["one\n", "two\n", "shared\n"], 60);
+    let new = add_pack(&repo, &["three\n", "shared\n"], 0);
+    let midx = write_midx(&repo, None);
+
+    let mut names = vec![idx_name(&old), idx_name(&new)];
+    names.sort();
+    assert_eq!(midx.pack_names(), names);
+    assert_eq!(midx.len(), 4);
+    let ids: Vec<ObjectId> = midx.ids().collect();
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+    for written in [&old, &new] {
+        for entry in &written.entries {
+            let (name, offset) = located(&midx, &entry.id);
+            if name == idx_name(written) {
+                assert_eq!(offset, entry.offset);
+            }
+        }
+    }
+    let packs = repo.odb().packs();
+    let by_id: Vec<&Pack> = midx
+        .pack_names()
+        .iter()
+        .map(|name| {
+            let found = packs
+                .iter()
+                .find(|p| p.path().with_extension("idx").ends_with(name));
+            found.unwrap().as_ref()
+        })
+        .collect();
+    assert_eq!(midx.verify(&by_id), Vec::<String>::new());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn objects_in_both_packs_come_from_the_newest() {
+    let (dir, repo) = common::scratch_repo("midx-duplicates", HashAlgorithm::Sha1);
+    let shared = common::blob(&repo, b"shared\n");
+    let old = add_pack(&repo, &["shared\n", "old only\n"], 60);
+    let new = add_pack(&repo, &["shared\n"], 0);
+
+    let midx = write_midx(&repo, None);
+    assert_eq!(
+        located(&midx, &shared),
+        (idx_name(&new), offset_in(&new, &shared))
+    );
+
+    // Unless another pack is preferred.
+    let midx = write_midx(&repo, Some(&idx_name(&old)));
+    assert_eq!(
+        located(&midx, &shared),
+        (idx_name(&old), offset_in(&old, &shared))
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verify_reports_a_corrupt_chunk() {
+    let (dir, repo) = common::scratch_repo("midx-verify", HashAlgorithm::Sha1);
+    add_pack(&repo, &["a\n", "b\n"], 60);
+    add_pack(&repo, &["c\n"], 0);
+    write_midx(&repo, None);
+
+    let verify = || {
+        Command::new(env!("CARGO_BIN_EXE_mygit"))
+            .args(["multi-pack-index", "verify"])
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+    };
+    let output = verify();
+    assert!(output.status.success(), "{output:?}");
+
+    // Move the first object's offset in the OOFF chunk on by one.
+    let mut data = fs::read(midx_path(&repo)).unwrap();
+    let row = (12..)
+        .step_by(12)
+        .find(|&row| &data[row..row + 4] == b"OOFF")
+        .unwrap();
+    let chunk = u64::from_be_bytes(data[row + 4..row + 12].try_into().unwrap()) as usize;
+    data[chunk + 7] += 1;
+    fs::write(midx_path(&repo), data).unwrap();
+
+    let output = verify();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: incorrect checksum"), "{stderr}");
+    assert!(
+        stderr.contains("incorrect object offset for oid[0]"),
+        "{stderr}"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn object_lookups_go_through_the_index() {
+    let (dir, repo) = common::scratch_repo("midx-odb", HashAlgorithm::Sha1);
+    let shared = common::blob(&repo, b"shared\n");
+    let old = add_pack(&repo, &["shared\n"], 60);
+    let new = add_pack(&repo, &["shared\n", "new\n"], 0);
+    write_midx(&repo, Some(&idx_name(&old)));
+    // Written after the index, so searched on its own.
+    let later = add_pack(&repo, &["later\n"], 0);
+    for id in repo.odb().loose().list().unwrap() {
+        repo.odb().loose().remove(&id).unwrap();
+    }
+
+    // Break the newer copy, which a search of the packs alone would find
+    // first; the index sends readers to the preferred pack's.
+    let mut data = fs::read(&new.pack_path).unwrap();
+    let at = offset_in(&new, &shared) as usize;
+    data[at + 2..at + 6].fill(0);
+    fs::write(&new.pack_path, data).unwrap();
+
+    let repo = Repository::open(&dir).unwrap();
+    assert!(repo.odb().multi_pack_index().is_some());
+    assert_eq!(repo.odb().read_raw(&shared).unwrap().1, b"shared\n");
+    let later_id = later.entries[0].id;
+    assert_eq!(repo.odb().read_raw(&later_id).unwrap().1, b"later\n");
+
+    // Without the index, the broken copy is the one read.
+    fs::remove_file(midx_path(&repo)).unwrap();
+    let repo = Repository::open(&dir).unwrap();
+    assert!(repo.odb().read_raw(&shared).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,124 @@
+//! Object ids of both algorithms, checked against ids git computed for the
+//! same objects.
+
+mod common;
+
+use std::fs;
+
+use rosa::object::{Commit, HashAlgorithm, MODE_BLOB};
+use rosa::{ObjectId, ObjectKind, Repository};
+
+const KNOWN: &[(HashAlgorithm, ObjectKind, &[u8], &str)] = &[
+    (
+        HashAlgorithm::Sha1,
+        ObjectKind::Blob,
+        b"",
+        "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391",
+    ),
+    (
+        HashAlgorithm::Sha1,
+        ObjectKind::Blob,
+        b"hello\n",
+        "ce013625030ba8dba906f756967f9e9ca394464a",
+    ),
+    (
+        HashAlgorithm::Sha1,
+        ObjectKind::Tree,
+        b"",
+        "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+    ),
+    (
+        HashAlgorithm::Sha256,
+        ObjectKind::Blob,
+        b"",
+        "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813",
+    ),
+    (
+        HashAlgorithm::Sha256,
+        ObjectKind::Blob,
+        b"hello\n",
+        "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4",
+    ),
+    (
+        HashAlgorithm::Sha256,
+        ObjectKind::Tree,
+        b"",
+        "6ef19b41225c5369f1c104d45d8d85efa9b057b53b14b4b9b939dd74decc5321",
+    ),
+];
+
+#[test]
+fn known_ids() {
+    for &(hash, kind, data, hex) in KNOWN {
+        let id = ObjectId::hash_object(hash, kind, data).unwrap();
+        assert_eq!(id.to_hex(), hex);
+        assert_eq!(id.hash(), hash);
+        assert_eq!(id.as_bytes().len(), hash.raw_len());
+
+        let streamed = ObjectId::hash_reader(hash, kind, data.len() as u64, data).unwrap();
+        assert_eq!(streamed, id);
+    }
+}
+
+#[test]
+fn hex_tells_the_algorithm_by_length() {
+    for &(hash, _, _, hex) in KNOWN {
+        let id: ObjectId = hex.parse().unwrap();
+        assert_eq!(id.hash(), hash);
+        assert_eq!(id.to_string(), hex);
+        assert_eq!(ObjectId::from_bytes(id.as_bytes()).unwrap(), id);
+        assert_eq!(hex.to_ascii_uppercase().parse::<ObjectId>().unwrap(), id);
+    }
+
+    let sha256 = KNOWN[3].3;
+    for bad in [
+        &sha256[..63],
+        &sha256[..62],
+        &sha256[..39],
+        "",
+        &format!("{sha256}00"),
+        &sha256.replace('4', "g"),
+    ] {
+        assert!(bad.parse::<ObjectId>().is_err(), "{bad:?}");
+    }
+    assert!(ObjectId::from_bytes(&[0; 31]).is_err());
+
+    let null = ObjectId::null(HashAlgorithm::Sha256);
+    assert!(null.is_null() && null.to_hex() == "0".repeat(64));
+    assert_ne!(null, ObjectId::null(HashAlgorithm::Sha1));
+}
+
+#[test]
+fn sha256_repositories_store_sha256_objects() {
+    let (dir, repo) = common::scratch_repo("object-id-sha256", HashAlgorithm::Sha256);
+    let config = fs::read_to_string(repo.path("config")).unwrap();
+    assert!(config.contains("repositoryformatversion = 1"));
+    assert!(config.contains("objectformat = sha256"));
+
+    let repo = Repository::open(&dir).unwrap();
+    assert_eq!(repo.odb().hash(), HashAlgorithm::Sha256);
+    let blob = common::blob(&repo, b"hi\n");
+    let tree = common::tree(&repo, &[(MODE_BLOB, "f", blob)]);
+    assert_eq!(
+        tree.to_hex(),
+        "c6ce6a5532a3239a5e75c7c69cfc44bc1f633cc5731fb5e4b541e9d6d6bb884a"
+    );
+    assert_eq!(repo.odb().read(&tree).unwrap().kind(), ObjectKind::Tree);
+
+    let raw = format!(
+        "tree {tree}\nauthor a <a@b> 1792088140 +0000\ncommitter a <a@b> 1792088140 +0000\n\nm\n"
+    );
+    let commit = Commit::parse(raw.as_bytes()).unwrap();
+    assert_eq!(commit.tree, tree);
+    let id = ObjectId::hash_object(
+        HashAlgorithm::Sha256,
+        ObjectKind::Commit,
+        &commit.serialize(),
+    )
+    .unwrap();
+    assert_eq!(
+        id.to_hex(),
+        "73dd24d360a3dbb4e1cced2d15e68b5cf361a03092e1686d0b827a9c4ba40dc4"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,183 @@
+//! Packs written behind an open object store's back are found, but only
+//! after a miss and only when the pack directory has changed. Objects are
+//! borrowed from alternates, theirs in turn, and those named in the
+//! environment.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use rosa::object::HashAlgorithm;
+use rosa::odb::{ObjectDatabase, ALTERNATES_ENV};
+use rosa::pack::{self, PackObject, PackOptions};
+use rosa::{Error, ObjectId, Repository};
+
+fn set_mtime(dir: &Path, time: SystemTime) {
+    fs::File::open(dir).unwrap().set_modified(time).unwrap();
+}
+
+#[test]
+fn packs_written_elsewhere_are_found_on_a_miss() {
+    let (dir, repo) = common::scratch_repo("odb-repack", HashAlgorithm::Sha1);
+    let id = common::blob(&repo, b"repacked away\n");
+
+    // Another process packs the object and drops the loose copy.
+    let other = Repository::open(&dir).unwrap();
+    pack::write_pack_files(
+        other.odb(),
+        &[PackObject { id, name_hash: 0 }],
+        &PackOptions::default(),
+        &other.odb().dir().join("pack/pack"),
+    )
+    .unwrap();
+    other.odb().loose().remove(&id).unwrap();
+
+    assert!(repo.odb().packs().is_empty());
+    assert_eq!(repo.odb().read_raw(&id).unwrap().1, b"repacked away\n");
+    assert_eq!(repo.odb().packs().len(), 1);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unchanged_pack_directories_are_not_rescanned() {
+    let (dir, repo) = common::scratch_repo("odb-rescan", HashAlgorithm::Sha1);
+    let pack_dir = repo.odb().dir().join("pack");
+    fs::create_dir_all(&pack_dir).unwrap();
+    repo.odb().refresh_packs().unwrap();
+    let missing = common::blob(&repo, b"soon gone\n");
+    repo.odb().loose().remove(&missing).unwrap();
+
+    // A pack that cannot be opened shows whether the directory is read.
+    let scanned = fs::metadata(&pack_dir).unwrap().modified().unwrap();
+    fs::write(pack_dir.join("pack-bad.idx"), b"not an index").unwrap();
+    fs::write(pack_dir.join("pack-bad.pack"), b"not a pack").unwrap();
+    set_mtime(&pack_dir, scanned);
+    for _ in 0..3 {
+        assert!(matches!(
+            repo.odb().read_raw(&missing),
+            Err(Error::ObjectNotFound(_))
+        ));
+    }
+
+    set_mtime(&pack_dir, scanned + Duration::from_secs(1));
+    assert!(matches!(
+        repo.odb().read_raw(&missing),
+        Err(Error::Parse { .. })
+    ));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// A scratch repository holding one blob, returned with its objects
+/// directory and the blob's id.
+fn store(name: &str) -> (PathBuf, PathBuf, ObjectId) {
+    let (dir, repo) = common::scratch_repo(name, HashAlgorithm::Sha1);
+    let id = common::blob(&repo, format!("only in {name}\n").as_bytes());
+    (dir, repo.odb().dir().to_path_buf(), id)
+}
+
+/// Lists `lines` in the `info/alternates` of `objects`.
+fn borrow_from(objects: &Path, lines: &[&str]) {
+    fs::create_dir_all(objects.join("info")).unwrap();
+    fs::write(objects.join("info/alternates"), lines.join("\n") + "\n").unwrap();
+}
+
+fn open(objects: &Path) -> ObjectDatabase {
+    ObjectDatabase::open(objects, HashAlgorithm::Sha1).unwrap()
+}
+
+#[test]
+fn alternates_of_alternates_are_followed() {
+    let (a_dir, a, a_id) = store("odb-chain-a");
+    let (b_dir, b, b_id) = store("odb-chain-b");
+    let (c_dir, c, c_id) = store("odb-chain-c");
+    // c borrows from b by a path relative to its objects directory, b from
+    // a by an absolute one; comments and blank lines are skipped.
+    let relative = format!(
+        "../../../{}/.git/objects",
+        b_dir.file_name().unwrap().to_str().unwrap()
+    );
+    borrow_from(&c, &["# borrowed", "", &relative]);
+    borrow_from(&b, &[a.to_str().unwrap()]);
+
+    let odb = open(&c);
+    let dirs: Vec<&Path> = odb.alternates().iter().map(|alt| alt.dir()).collect();
+    assert_eq!(
+        dirs,
+        [fs::canonicalize(&b).unwrap(), fs::canonicalize(&a).unwrap()]
+    );
+    for id in [a_id, b_id, c_id] {
+        assert!(odb.contains(&id));
+        odb.read_raw(&id).unwrap();
+    }
+    assert!(odb.contains_local(&c_id) && !odb.contains_local(&a_id));
+    // The store borrowed from does not see its borrower's objects.
+    assert!(!open(&a).contains(&c_id));
+    for dir in [a_dir, b_dir, c_dir] {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn alternate_cycles_and_missing_stores_are_skipped() {
+    let (a_dir, a, a_id) = store("odb-cycle-a");
+    let (c_dir, c, c_id) = store("odb-cycle-c");
+    borrow_from(&a, &[c.to_str().unwrap(), "/nonexistent/objects"]);
+    borrow_from(&c, &[a.to_str().unwrap()]);
+
+    let odb = open(&a);
+    assert_eq!(odb.alternates().len(), 1);
+    assert_eq!(odb.alternates()[0].dir(), fs::canonicalize(&c).unwrap());
+    assert!(odb.contains(&c_id));
+    let odb = open(&c);
+    assert_eq!(odb.alternates().len(), 1);
+    assert!(odb.contains(&a_id));
+    for dir in [a_dir, c_dir] {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn alternates_nest_at_most_six_deep() {
+    let stores: Vec<_> = (0..8).map(|n| store(&format!("odb-depth-{n}"))).collect();
+    for pair in stores.windows(2) {
+        borrow_from(&pair[0].1, &[pair[1].1.to_str().unwrap()]);
+    }
+    // As in git: the alternates of the store at depth six are not read.
+    let odb = open(&stores[0].1);
+    assert_eq!(odb.alternates().len(), 6);
+    assert!(odb.contains(&stores[6].2));
+    assert!(!odb.contains(&stores[7].2));
+    for (dir, _, _) in stores {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn alternates_can_be_named_in_the_environment() {
+    let (dir, objects, _) = store("odb-env");
+    let (a_dir, a, a_id) = store("odb-env-a");
+    let (b_dir, b, b_id) = store("odb-env-b");
+    borrow_from(&objects, &[b.to_str().unwrap()]);
+
+    let cat = |id: &ObjectId, alternates: &[&Path]| {
+        Command::new(env!("CARGO_BIN_EXE_mygit"))
+            .args(["cat-file", "-p", &id.to_hex()])
+            .env(ALTERNATES_ENV, std::env::join_paths(alternates).unwrap())
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+    };
+    let output = cat(&a_id, &[&a]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, b"only in odb-env-a\n");
+    // Those in info/alternates still count, and listing one twice is fine.
+    let output = cat(&b_id, &[&a, &b]);
+    assert_eq!(output.stdout, b"only in odb-env-b\n");
+    assert!(!cat(&a_id, &[]).status.success());
+    for dir in [dir, a_dir, b_dir] {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
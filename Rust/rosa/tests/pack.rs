@@ -0,0 +1,306 @@
+//! Pack entries are read from files that may be truncated or corrupt, so
+//! their headers must fail cleanly rather than overflow or read past the end.
+
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::process::Command;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rosa::object::HashAlgorithm;
+use rosa::pack::{self, EntryKind, Pack, PackObject, PackOptions, WrittenPack};
+use rosa::{Error, ObjectId, ObjectKind, Repository};
+
+/// A pack of a few blobs, written next to (not inside) a scratch repository.
+fn blob_pack(name: &str) -> (std::path::PathBuf, WrittenPack) {
+    let (dir, repo) = common::scratch_repo(name, HashAlgorithm::Sha1);
+    let objects: Vec<PackObject> = (0..3)
+        .map(|n| PackObject {
+            id: common::blob(&repo, format!("blob number {n}\n").as_bytes()),
+            name_hash: 0,
+        })
+        .collect();
+    let written = pack::write_pack_files(
+        repo.odb(),
+        &objects,
+        &PackOptions::default(),
+        &dir.join("pack"),
+    )
+    .unwrap();
+    (dir, written)
+}
+
+/// Overwrites the pack at `offset` and reopens it.
+fn patch(written: &WrittenPack, offset: u64, bytes: &[u8]) -> Pack {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(&written.pack_path)
+        .unwrap();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(bytes).unwrap();
+    Pack::open(&written.index_path, HashAlgorithm::Sha1).unwrap()
+}
+
+fn assert_corrupt(pack: &Pack, offset: u64) {
+    assert!(
+        matches!(pack.entry_header(offset), Err(Error::Parse { .. })),
+        "entry at {offset} was accepted"
+    );
+}
+
+#[test]
+fn intact_entries_decode() {
+    let (dir, written) = blob_pack("pack-intact");
+    let pack = Pack::open(&written.index_path, HashAlgorithm::Sha1).unwrap();
+    for entry in &written.entries {
+        let header = pack.entry_header(entry.offset).unwrap();
+        assert_eq!(header.kind, EntryKind::Object(rosa::ObjectKind::Blob));
+        assert_eq!(pack.read(&entry.id).unwrap().unwrap().1.len(), 14);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn oversized_size_varint_is_corrupt() {
+    let (dir, written) = blob_pack("pack-size");
+    let offset = written.entries[0].offset;
+    // A blob whose size keeps going for eleven continuation bytes.
+    let mut bytes = vec![0xbf];
+    bytes.extend([0xff; 10]);
+    bytes.push(0x7f);
+    let pack = patch(&written, offset, &bytes);
+    assert_corrupt(&pack, offset);
+
+    // Ten bytes whose last one carries bits beyond the 64th.
+    let mut bytes = vec![0xbf];
+    bytes.extend([0xff; 8]);
+    bytes.push(0x7f);
+    let pack = patch(&written, offset, &bytes);
+    assert_corrupt(&pack, offset);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn overflowing_delta_offset_is_corrupt() {
+    let (dir, written) = blob_pack("pack-ofs");
+    let offset = written.entries.iter().map(|e| e.offset).max().unwrap();
+    // An OFS_DELTA of size 1 whose base distance needs more than 64 bits.
+    let mut bytes = vec![0x61];
+    bytes.extend([0xff; 10]);
+    bytes.push(0x7f);
+    let pack = patch(&written, offset, &bytes);
+    assert_corrupt(&pack, offset);
+
+    // A distance reaching back before the start of this small pack.
+    let pack = patch(&written, offset, &[0x61, 0x80, 0x7f]);
+    assert_corrupt(&pack, offset);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn truncated_entries_are_corrupt() {
+    let (dir, written) = blob_pack("pack-truncated");
+    let offset = written.entries.iter().map(|e| e.offset).max().unwrap();
+    let pack = patch(&written, offset, &[0xbf, 0xff]);
+    OpenOptions::new()
+        .write(true)
+        .open(&written.pack_path)
+        .unwrap()
+        .set_len(offset + 2)
+        .unwrap();
+    assert_corrupt(&pack, offset);
+    assert_corrupt(&pack, offset + 2);
+
+    // A header that is whole but whose data is cut short.
+    let (dir2, written) = blob_pack("pack-truncated-data");
+    let entry = written.entries.iter().max_by_key(|e| e.offset).unwrap();
+    OpenOptions::new()
+        .write(true)
+        .open(&written.pack_path)
+        .unwrap()
+        .set_len(entry.offset + 4)
+        .unwrap();
+    // Cutting the data short also cuts off the trailer the index names.
+    assert!(Pack::open(&written.index_path, HashAlgorithm::Sha1).is_err());
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_dir_all(dir2).unwrap();
+}
+
+/// Rewrites the index with `edit` applied and tries to open it again.
+fn reopen_with_index(written: &WrittenPack, edit: impl FnOnce(&mut Vec<u8>)) -> rosa::Result<Pack> {
+    let mut data = fs::read(&written.index_path).unwrap();
+    edit(&mut data);
+    fs::write(&written.index_path, data).unwrap();
+    Pack::open(&written.index_path, HashAlgorithm::Sha1)
+}
+
+#[test]
+fn corrupt_indexes_are_refused() {
+    const FANOUT: usize = 8;
+    // Header and fan-out, then three 20-byte ids and three CRCs.
+    const OFFSETS: usize = FANOUT + 256 * 4 + 3 * 20 + 3 * 4;
+
+    let (dir, written) = blob_pack("pack-idx-fanout");
+    let err = reopen_with_index(&written, |data| {
+        // Claim more objects under 0x00 than under all of 0x00..=0xff.
+        data[FANOUT..FANOUT + 4].copy_from_slice(&9u32.to_be_bytes());
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("monotonic"), "{err}");
+    fs::remove_dir_all(dir).unwrap();
+
+    let (dir, written) = blob_pack("pack-idx-slot");
+    let err = reopen_with_index(&written, |data| {
+        // One large offset in the table, but an entry pointing at slot 5.
+        data[OFFSETS..OFFSETS + 4].copy_from_slice(&0x8000_0005u32.to_be_bytes());
+        let trailer = data.len() - 40;
+        data.splice(trailer..trailer, [0; 8]);
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("outside its table"), "{err}");
+    fs::remove_dir_all(dir).unwrap();
+
+    let (dir, written) = blob_pack("pack-idx-checksum");
+    let err = reopen_with_index(&written, |data| {
+        let at = data.len() - 40;
+        data[at] ^= 0xff;
+    })
+    .unwrap_err();
+    assert!(
+        err.to_string().contains("does not match its index"),
+        "{err}"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn packed_deltas_read_back() {
+    let (dir, repo) = common::scratch_repo("pack-deltas", HashAlgorithm::Sha1);
+    let mut text: Vec<u8> = (0..400)
+        .flat_map(|n| format!("line {n} of a file that changes a little\n").into_bytes())
+        .collect();
+    let mut versions = Vec::new();
+    for n in 0..8 {
+        text.splice(n * 500..n * 500, format!("edit {n}\n").into_bytes());
+        versions.push((common::blob(&repo, &text), text.clone()));
+    }
+    let objects: Vec<PackObject> = versions
+        .iter()
+        .map(|(id, _)| PackObject {
+            id: *id,
+            name_hash: pack::name_hash(b"file.txt"),
+        })
+        .collect();
+    let written = pack::write_pack_files(
+        repo.odb(),
+        &objects,
+        &PackOptions::default(),
+        &dir.join("pack"),
+    )
+    .unwrap();
+
+    let pack = Pack::open(&written.index_path, HashAlgorithm::Sha1).unwrap();
+    let deltas = written
+        .entries
+        .iter()
+        .filter(|e| {
+            !matches!(
+                pack.entry_header(e.offset).unwrap().kind,
+                EntryKind::Object(_)
+            )
+        })
+        .count();
+    assert!(deltas >= versions.len() / 2, "only {deltas} deltas");
+    for (id, data) in &versions {
+        let (kind, read) = pack.read(id).unwrap().unwrap();
+        assert_eq!(kind, rosa::ObjectKind::Blob);
+        assert_eq!(&read, data);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn streamed_writes_skip_packed_objects() {
+    let (dir, repo) = common::scratch_repo("pack-stream", HashAlgorithm::Sha1);
+    let data = b"packed already\n";
+    let id = common::blob(&repo, data);
+    pack::write_pack_files(
+        repo.odb(),
+        &[PackObject { id, name_hash: 0 }],
+        &PackOptions::default(),
+        &repo.odb().dir().join("pack/pack"),
+    )
+    .unwrap();
+    repo.odb().refresh_packs().unwrap();
+    repo.odb().loose().remove(&id).unwrap();
+
+    let written = repo
+        .odb()
+        .write_stream(rosa::ObjectKind::Blob, data.len() as u64, &data[..])
+        .unwrap();
+    assert_eq!(written, id);
+    assert!(!repo.odb().loose().contains(&id));
+    assert!(repo.odb().loose().list().unwrap().is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Writes a pack of one blob `data` into `repo`, its header claiming `size`
+/// bytes, with an index and checksums that are all in order.
+fn one_blob_pack(repo: &Repository, data: &[u8], size: u64) -> ObjectId {
+    let hash = repo.odb().hash();
+    let mut pack = b"PACK\0\0\0\x02\0\0\0\x01".to_vec();
+    let mut byte = 0x30 | (size & 0x0f) as u8;
+    let mut rest = size >> 4;
+    while rest > 0 {
+        pack.push(byte | 0x80);
+        byte = (rest & 0x7f) as u8;
+        rest >>= 7;
+    }
+    pack.push(byte);
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    pack.extend(encoder.finish().unwrap());
+    let checksum = hash.digest(&pack).unwrap();
+    pack.extend(&checksum);
+    let pack_dir = repo.path("objects/pack");
+    fs::create_dir_all(&pack_dir).unwrap();
+    fs::write(pack_dir.join("pack-one.pack"), pack).unwrap();
+
+    let id = ObjectId::hash_object(hash, ObjectKind::Blob, data).unwrap();
+    let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+    for first in 0..=255u8 {
+        idx.extend(u32::from(id.as_bytes()[0] <= first).to_be_bytes());
+    }
+    idx.extend_from_slice(id.as_bytes());
+    idx.extend([0; 4]);
+    idx.extend(12u32.to_be_bytes());
+    idx.extend(&checksum);
+    idx.extend(hash.digest(&idx).unwrap());
+    fs::write(pack_dir.join("pack-one.idx"), idx).unwrap();
+    id
+}
+
+#[test]
+fn announced_sizes_are_not_reserved_up_front() {
+    let (dir, repo) = common::scratch_repo("pack-huge-entry", HashAlgorithm::Sha1);
+    let id = one_blob_pack(&repo, b"hello\n", 1 << 40);
+
+    let repo = Repository::open(&dir).unwrap();
+    let err = repo.odb().read_raw(&id).unwrap_err();
+    assert!(
+        err.to_string().contains("inflated to the wrong size"),
+        "{err}"
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .arg("fsck")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    // Reported, rather than aborting on the allocation.
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("is corrupt"), "{stderr}");
+    fs::remove_dir_all(dir).unwrap();
+}
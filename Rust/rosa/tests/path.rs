@@ -0,0 +1,74 @@
+//! Paths are bytes in no particular encoding. They must survive trees
+//! and checkout untouched, and be shown quoted the way git quotes them.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rosa::object::{HashAlgorithm, Tree, TreeEntry, MODE_BLOB, MODE_TREE};
+use rosa::{path, Object};
+
+#[test]
+fn quoting_matches_git() {
+    assert_eq!(path::quote(b"plain/name.txt"), "plain/name.txt");
+    assert_eq!(path::quote(b"with space"), "with space");
+    assert_eq!(path::quote(b"caf\xe9.txt"), r#""caf\351.txt""#);
+    assert_eq!(path::quote("café".as_bytes()), r#""caf\303\251""#);
+    assert_eq!(
+        path::quote(b"a\tb\\c\"d\x01\x7f"),
+        r#""a\tb\\c\"d\001\177""#
+    );
+    assert_eq!(
+        path::quote(b"\x07\x08\n\x0b\x0c\r\x1b"),
+        r#""\a\b\n\v\f\r\033""#
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_names_survive_trees_and_checkout() {
+    let (dir, repo) = common::scratch_repo("path-bytes", HashAlgorithm::Sha1);
+    let x = common::blob(&repo, b"x");
+    let y = common::blob(&repo, b"y");
+    let odd = TreeEntry::new(MODE_BLOB, &b"a\tb\\c\"d\x01\x7f"[..], y);
+    let latin1 = TreeEntry::new(MODE_BLOB, &b"caf\xe9.txt"[..], x);
+    let tree = repo
+        .odb()
+        .write(&Object::Tree(Tree {
+            entries: vec![odd, latin1],
+        }))
+        .unwrap();
+    // What `git write-tree` gives for the same two files.
+    assert_eq!(tree.to_hex(), "478802147da13db290864e59fa336046eab3ebb3");
+
+    let (_, raw) = repo.odb().read_raw(&tree).unwrap();
+    let parsed = Tree::parse(&raw, HashAlgorithm::Sha1).unwrap();
+    assert_eq!(parsed.entries[1].name, b"caf\xe9.txt");
+    assert_eq!(parsed.serialize(), raw);
+
+    let outer = common::tree(&repo, &[(MODE_TREE, "dir", tree)]);
+    let mygit = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?}: {output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(
+        mygit(&["ls-tree", &tree.to_hex()]),
+        format!(
+            "100644 blob {y}\t\"a\\tb\\\\c\\\"d\\001\\177\"\n\
+             100644 blob {x}\t\"caf\\351.txt\"\n"
+        )
+    );
+
+    mygit(&["checkout", &outer.to_hex(), "out"]);
+    let checked_out = path::to_os_str(b"out/dir/caf\xe9.txt").unwrap();
+    assert_eq!(fs::read(dir.join(checked_out)).unwrap(), b"x");
+    let checked_out = path::to_os_str(b"out/dir/a\tb\\c\"d\x01\x7f").unwrap();
+    assert_eq!(fs::read(dir.join(checked_out)).unwrap(), b"y");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,179 @@
+//! `--format` placeholders that look beyond the commit itself: the mailmap
+//! behind `%aN` and friends, notes for `%N`, and the file-name-safe
+//! subject of `%f`. Expected outputs are what git 2.39 prints for the same
+//! commits.
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rosa::date::DateMode;
+use rosa::mailmap::Mailmap;
+use rosa::notes::Notes;
+use rosa::object::{HashAlgorithm, MODE_BLOB, MODE_TREE};
+use rosa::pretty::{Decorations, Format, Printer};
+use rosa::{refs, ObjectId, Repository};
+
+fn show(repo: &Repository, format: &str, id: ObjectId) -> String {
+    let format = Format::parse(format).unwrap();
+    let printer = Printer {
+        repo,
+        mailmap: match format.uses_mailmap() {
+            true => Mailmap::load(repo).unwrap(),
+            false => Mailmap::default(),
+        },
+        notes: match format.uses_notes() {
+            true => Notes::load(repo).unwrap(),
+            false => Notes::default(),
+        },
+        format,
+        dates: DateMode::Default,
+        abbrev: 7,
+        abbrev_commit: false,
+        decorate: false,
+        decorations: Decorations::default(),
+        now: 0,
+    };
+    let commit = repo.odb().read(&id).unwrap().into_commit(id).unwrap();
+    String::from_utf8(printer.entry(id, &commit).unwrap()).unwrap()
+}
+
+#[test]
+fn sanitized_subjects() {
+    let (dir, repo) = common::scratch_repo("pretty-subjects", HashAlgorithm::Sha1);
+    let empty = common::tree(&repo, &[]);
+    for (message, expected) in [
+        ("  Fix: the...parser (v2.0)!  \n", "Fix-the.parser-v2.0"),
+        ("..lead. and trail.-\n", ".lead.-and-trail"),
+        ("über café_x\n", "ber-caf-_x"),
+        ("\n\na\nb\n\nbody\n", "a"),
+        ("", ""),
+    ] {
+        let id = common::commit(&repo, empty, &[], 1_700_000_000, message);
+        assert_eq!(show(&repo, "%f", id), expected, "{message:?}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn mailmap_names_and_emails() {
+    let (dir, repo) = common::scratch_repo("pretty-mailmap", HashAlgorithm::Sha1);
+    let id = common::commit(&repo, common::tree(&repo, &[]), &[], 1_700_000_000, "x\n");
+    let format = "%aN|%aE|%aL|%cN|%cE|%cL|%an|%ae";
+
+    // Without a mailmap, the plain values.
+    assert_eq!(
+        show(&repo, format, id),
+        "A U Thor|author@example.com|author|C O Mitter|committer@example.com|committer|\
+         A U Thor|author@example.com"
+    );
+
+    fs::write(
+        dir.join(".mailmap"),
+        "Proper <proper@example.com> <AUTHOR@example.com>\n\
+         # Other Name <author@example.com>\n\
+         Other Name <Committer@Example.com>\n\
+         Named <named@example.com> c o mitter <committer@example.com>\n",
+    )
+    .unwrap();
+    assert_eq!(
+        show(&repo, format, id),
+        "Proper|proper@example.com|proper|Named|named@example.com|named|\
+         A U Thor|author@example.com"
+    );
+
+    // Entries for a name only apply to it; the others fall back to those
+    // for the email alone, later ones winning.
+    let mut mailmap = Mailmap::default();
+    mailmap.add(
+        b"<first@example.com> <a@example.com>\n\
+          Any <a@example.com>\n\
+          <second@example.com> <a@example.com>\n\
+          Him <him@example.com> Some One <a@example.com>\n\
+          no email here\n\
+          Empty <> <a@example.com>\n",
+    );
+    assert_eq!(
+        mailmap.map(b"Else", b"A@example.com"),
+        (&b"Any"[..], &b"second@example.com"[..])
+    );
+    assert_eq!(
+        mailmap.map(b"some one", b"a@example.com"),
+        (&b"Him"[..], &b"him@example.com"[..])
+    );
+    assert_eq!(
+        mailmap.map(b"Some One", b"b@example.com"),
+        (&b"Some One"[..], &b"b@example.com"[..])
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn notes() {
+    let (dir, repo) = common::scratch_repo("pretty-notes", HashAlgorithm::Sha1);
+    let empty = common::tree(&repo, &[]);
+    let first = common::commit(&repo, empty, &[], 1_700_000_000, "first\n");
+    let second = common::commit(&repo, empty, &[first], 1_700_000_001, "second\n");
+    let third = common::commit(&repo, empty, &[second], 1_700_000_002, "third\n");
+    assert_eq!(show(&repo, "[%N]", first), "[]");
+
+    // One note at the top, one fanned out, and entries that are no notes.
+    let hex = second.to_hex();
+    let fanout = common::tree(
+        &repo,
+        &[(
+            MODE_BLOB,
+            &hex[2..],
+            common::blob(&repo, b"two\n\nlines\n\n"),
+        )],
+    );
+    let notes = common::tree(
+        &repo,
+        &[
+            (
+                MODE_BLOB,
+                &first.to_hex(),
+                common::blob(&repo, b"first note"),
+            ),
+            (MODE_TREE, &hex[..2], fanout),
+            (MODE_BLOB, "README", common::blob(&repo, b"not a note")),
+        ],
+    );
+    let notes = common::commit(&repo, notes, &[], 1_700_000_000, "Notes added\n");
+    refs::update(&repo, "refs/notes/commits", &notes).unwrap();
+
+    assert_eq!(show(&repo, "[%N]", first), "[first note\n]");
+    assert_eq!(show(&repo, "[%N]", second), "[two\n\nlines\n]");
+    assert_eq!(show(&repo, "[%N]", third), "[]");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn counts_given_as_dash_number() {
+    let (dir, repo) = common::scratch_repo("pretty-counts", HashAlgorithm::Sha1);
+    let empty = common::tree(&repo, &[]);
+    let mut tip = common::commit(&repo, empty, &[], 1_700_000_000, "c0\n");
+    for n in 1..5 {
+        tip = common::commit(&repo, empty, &[tip], 1_700_000_000 + n, &format!("c{n}\n"));
+    }
+    refs::update(&repo, "refs/heads/master", &tip).unwrap();
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{args:?}: {output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(run(&["log", "-2", "--format=%s"]), "c4\nc3\n");
+    assert_eq!(
+        run(&["log", "--format=%s", "-3", "master~1"]),
+        "c3\nc2\nc1\n"
+    );
+    assert_eq!(run(&["rev-list", "-1", "master"]), format!("{tip}\n"));
+    assert_eq!(run(&["log", "-n", "1", "--format=%s"]), "c4\n");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,89 @@
+//! Reachability only needs the headers of blobs, but must still notice
+//! one that is missing or is not what its tree entry says.
+
+mod common;
+
+use std::fs;
+
+use rosa::object::{HashAlgorithm, MODE_BLOB, MODE_TREE};
+use rosa::{reachable, ObjectKind};
+
+#[test]
+fn trees_reach_their_blobs() {
+    let (dir, repo) = common::scratch_repo("reachable-trees", HashAlgorithm::Sha1);
+    let a = common::blob(&repo, b"a\n");
+    let b = common::blob(&repo, b"b\n");
+    let sub = common::tree(&repo, &[(MODE_BLOB, "b.txt", b)]);
+    let root = common::tree(&repo, &[(MODE_BLOB, "a.txt", a), (MODE_TREE, "sub", sub)]);
+
+    let found = reachable::objects(&repo, &[root], &[]).unwrap();
+    let kinds: Vec<_> = found.iter().map(|r| (r.id, r.kind)).collect();
+    assert_eq!(kinds.len(), 4);
+    for (id, kind) in [
+        (root, ObjectKind::Tree),
+        (sub, ObjectKind::Tree),
+        (a, ObjectKind::Blob),
+        (b, ObjectKind::Blob),
+    ] {
+        assert!(kinds.contains(&(id, kind)), "{id} as a {kind}");
+    }
+    let b_found = found.iter().find(|r| r.id == b).unwrap();
+    assert_eq!(b_found.name_hash, rosa::pack::name_hash(b"sub/b.txt"));
+
+    // Excluding the subtree leaves the rest.
+    let found = reachable::objects(&repo, &[root], &[sub]).unwrap();
+    let ids: Vec<_> = found.iter().map(|r| r.id).collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&root) && ids.contains(&a));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn blob_entries_are_checked_without_being_read() {
+    let (dir, repo) = common::scratch_repo("reachable-blobs", HashAlgorithm::Sha1);
+    let a = common::blob(&repo, b"a\n");
+    let inner = common::tree(&repo, &[(MODE_BLOB, "a.txt", a)]);
+
+    // An entry calling a tree a blob is still followed into.
+    let odd = common::tree(&repo, &[(MODE_BLOB, "odd", inner)]);
+    let found = reachable::objects(&repo, &[odd], &[]).unwrap();
+    let kinds: Vec<_> = found.iter().map(|r| (r.id, r.kind)).collect();
+    assert!(kinds.contains(&(inner, ObjectKind::Tree)));
+    assert!(kinds.contains(&(a, ObjectKind::Blob)));
+
+    // A missing blob is an error, not a silent gap.
+    repo.odb().loose().remove(&a).unwrap();
+    assert!(reachable::objects(&repo, &[inner], &[]).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn blob_tips_are_not_inflated() {
+    let (dir, repo) = common::scratch_repo("reachable-blob-tips", HashAlgorithm::Sha1);
+    // Incompressible data, so cutting the file in half cuts the content.
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    let data: Vec<u8> = (0..1 << 16)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    let id = common::blob(&repo, &data);
+    let file = repo.odb().loose().path(&id);
+    let len = fs::metadata(&file).unwrap().len();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_len(len / 2)
+        .unwrap();
+    assert!(repo.odb().read(&id).is_err());
+
+    // Staged blobs are tips of gc's walk; only their header is needed.
+    let found = reachable::objects(&repo, &[id], &[]).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!((found[0].id, found[0].kind), (id, ObjectKind::Blob));
+    fs::remove_dir_all(dir).unwrap();
+}
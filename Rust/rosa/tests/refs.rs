@@ -0,0 +1,78 @@
+//! Ref names become paths under the gitdir, so a name must never lead out
+//! of `refs/` or onto another file there.
+
+mod common;
+
+use std::fs;
+
+use rosa::object::HashAlgorithm;
+use rosa::{refs, Error};
+
+#[test]
+fn traversal_and_malformed_names_are_refused() {
+    let bad = [
+        "../../config",
+        "refs/tags/../../config",
+        "refs/heads/..",
+        "/etc/passwd",
+        "refs//heads/x",
+        "-v1",
+        "refs/heads/x.lock",
+        "refs/heads/.hidden",
+        "refs/heads/x.",
+        "refs/heads/a\nb",
+        "refs/heads/a\tb",
+        "refs/heads/a\x7fb",
+        "refs/heads/a b",
+        "refs/heads/a~1",
+        "refs/heads/a^",
+        "refs/heads/a:b",
+        "refs/heads/a?",
+        "refs/heads/a*",
+        "refs/heads/a[b",
+        "refs/heads/a\\b",
+        "refs/heads/a@{1}",
+        "@",
+        "",
+    ];
+    for name in bad {
+        assert!(
+            matches!(refs::check_ref_format(name), Err(Error::InvalidRefName(_))),
+            "{name:?} was accepted"
+        );
+    }
+    for name in [
+        "refs/heads/main",
+        "refs/tags/v1.0",
+        "feature/a-b",
+        "HEAD",
+        "x@y",
+    ] {
+        refs::check_ref_format(name).unwrap();
+    }
+}
+
+#[test]
+fn update_stays_inside_refs() {
+    let (dir, repo) = common::scratch_repo("refs-update", HashAlgorithm::Sha1);
+    let id = common::blob(&repo, b"tagged\n");
+    let config = fs::read(repo.path("config")).unwrap();
+
+    for name in [
+        "refs/tags/../../config",
+        "refs/tags/../config",
+        "config",
+        "objects/info/alternates",
+    ] {
+        assert!(
+            refs::update(&repo, name, &id).is_err(),
+            "{name:?} was written"
+        );
+    }
+    assert_eq!(fs::read(repo.path("config")).unwrap(), config);
+    assert!(!repo.path("objects/info/alternates").exists());
+
+    refs::update(&repo, "refs/tags/v1", &id).unwrap();
+    assert_eq!(refs::resolve(&repo, "refs/tags/v1").unwrap(), Some(id));
+    fs::remove_dir_all(dir).unwrap();
+}
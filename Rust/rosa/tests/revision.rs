@@ -0,0 +1,490 @@
+//! Revision names as gitrevisions(7) spells them, the regular expressions
+//! `:/` and `^{/}` search messages with, and the dates `@{}` accepts.
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::process::Command;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rosa::config::Config;
+use rosa::date;
+use rosa::object::{HashAlgorithm, MODE_BLOB};
+use rosa::revision::{self, regex::Regex};
+use rosa::revwalk::{RevWalk, WalkOptions};
+use rosa::{refs, Error, ObjectId, ObjectKind, Repository};
+
+/// 2023-11-14 22:13:20 UTC.
+const T0: i64 = 1_700_000_000;
+const DAY: i64 = 86_400;
+
+struct History {
+    c1: ObjectId,
+    c2: ObjectId,
+    s1: ObjectId,
+    c3: ObjectId,
+    merge: ObjectId,
+}
+
+/// ```text
+/// c1 - c2 - c3 - merge    (master)
+///   \          /
+///    s1 ------'           (side)
+/// ```
+///
+/// with `v1` an annotated tag of `c2`, commit `n` (in the order written)
+/// made `n` hundred seconds after [`T0`], and reflogs for master and HEAD.
+fn history(repo: &Repository) -> History {
+    let tree = common::tree(repo, &[(MODE_BLOB, "a", common::blob(repo, b"a\n"))]);
+    let commit = |n: i64, parents: &[ObjectId], message: &str| {
+        common::commit(repo, tree, parents, T0 + n * 100, message)
+    };
+    let c1 = commit(1, &[], "init: add readme\n");
+    let c2 = commit(2, &[c1], "fix: Parse dates\n");
+    let s1 = commit(3, &[c1], "side: try things\n");
+    let c3 = commit(4, &[c2], "feat: regex support\n\nSee also: Parse.\n");
+    let merge = commit(5, &[c3, s1], "Merge branch 'side'\n");
+    refs::update(repo, "refs/heads/master", &merge).unwrap();
+    refs::update(repo, "refs/heads/side", &s1).unwrap();
+    let tag = format!(
+        "object {c2}\ntype commit\ntag v1\n\
+         tagger T A Gger <tagger@example.com> {} +0000\n\nv1\n",
+        T0 + 600
+    );
+    let tag = repo
+        .odb()
+        .write_raw(ObjectKind::Tag, tag.as_bytes())
+        .unwrap();
+    refs::update(repo, "refs/tags/v1", &tag).unwrap();
+
+    let null = ObjectId::null(HashAlgorithm::Sha1);
+    let line = |old: ObjectId, new: ObjectId, n: i64, message: &str| {
+        format!(
+            "{old} {new} C O Mitter <committer@example.com> {} +0000\t{message}\n",
+            T0 + n * 100
+        )
+    };
+    let logs = repo.path("logs");
+    fs::create_dir_all(logs.join("refs/heads")).unwrap();
+    let master = [
+        line(null, c1, 1, "commit (initial): init"),
+        line(c1, c2, 2, "commit: fix"),
+        line(c2, c3, 4, "commit: feat"),
+        line(c3, merge, 5, "merge side"),
+    ];
+    fs::write(logs.join("refs/heads/master"), master.concat()).unwrap();
+    let head = [
+        line(null, c1, 1, "commit (initial): init"),
+        line(c1, s1, 3, "checkout: moving from master to side"),
+        line(s1, c2, 3, "checkout: moving from side to master"),
+    ];
+    fs::write(logs.join("HEAD"), head.concat()).unwrap();
+
+    History {
+        c1,
+        c2,
+        s1,
+        c3,
+        merge,
+    }
+}
+
+fn find(repo: &Repository, name: &str) -> ObjectId {
+    revision::find(repo, name, None, false).unwrap_or_else(|e| panic!("{name}: {e}"))
+}
+
+#[test]
+fn ancestry_chains() {
+    let (dir, repo) = common::scratch_repo("revision-chains", HashAlgorithm::Sha1);
+    let h = history(&repo);
+    for (name, expected) in [
+        ("@", h.merge),
+        ("HEAD", h.merge),
+        ("master", h.merge),
+        ("HEAD^0", h.merge),
+        ("master~", h.c3),
+        ("master~1", h.c3),
+        ("master~2", h.c2),
+        ("master~3", h.c1),
+        ("master^", h.c3),
+        ("master^^", h.c2),
+        ("master^2", h.s1),
+        ("master^2~1", h.c1),
+        ("master~1^1~1", h.c1),
+        ("@~2^", h.c1),
+        // Tags are peeled to the commit before walking.
+        ("v1~1", h.c1),
+        ("v1^{}", h.c2),
+        ("v1^{commit}", h.c2),
+    ] {
+        assert_eq!(find(&repo, name), expected, "{name}");
+    }
+    for name in ["master^3", "master~4", "side^2", "master~x"] {
+        assert!(revision::find(&repo, name, None, false).is_err(), "{name}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn message_searches() {
+    let (dir, repo) = common::scratch_repo("revision-search", HashAlgorithm::Sha1);
+    let h = history(&repo);
+    for (name, expected) in [
+        (":/fix", h.c2),
+        (":/^feat", h.c3),
+        // The youngest match wins, bodies included.
+        (":/Parse", h.c3),
+        (":/side", h.merge),
+        (":/^side", h.s1),
+        (":/!-^Merge", h.c3),
+        (":/[[:upper:]]arse", h.c3),
+        ("master^{/^fix}", h.c2),
+        ("side^{/init}", h.c1),
+        ("master^{/(fix|side):}", h.s1),
+        ("v1^{/Parse}", h.c2),
+    ] {
+        assert_eq!(find(&repo, name), expected, "{name}");
+    }
+    for name in [":/nowhere", "side^{/fix}", ":/!x", ":/(unclosed"] {
+        assert!(revision::find(&repo, name, None, false).is_err(), "{name}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ambiguous_refnames_warn_the_caller() {
+    let (dir, repo) = common::scratch_repo("revision-ambiguous", HashAlgorithm::Sha1);
+    let h = history(&repo);
+    refs::update(&repo, "refs/heads/v1", &h.c1).unwrap();
+
+    // The tag wins, and the warning goes back to whoever asked.
+    let resolved = revision::resolve(&repo, "v1^{}", None, false).unwrap();
+    assert_eq!(resolved.id, h.c2);
+    assert_eq!(resolved.warnings, ["refname 'v1' is ambiguous."]);
+    assert!(revision::resolve(&repo, "side", None, false)
+        .unwrap()
+        .warnings
+        .is_empty());
+
+    let mut walk = RevWalk::new(&repo, WalkOptions::default()).unwrap();
+    walk.add_args(&["side..v1".to_owned()]).unwrap();
+    assert_eq!(walk.take_warnings(), ["refname 'v1' is ambiguous."]);
+    assert!(walk.take_warnings().is_empty());
+
+    // The command line is where it gets shown.
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .args(["rev-parse", "v1^{}"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(output.stdout, format!("{}\n", h.c2.to_hex()).as_bytes());
+    assert_eq!(output.stderr, b"warning: refname 'v1' is ambiguous.\n");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Packs blobs holding `n` and a newline for each `n` in `range`, written
+/// straight into a pack so that tens of thousands stay quick.
+fn pack_numbers(repo: &Repository, range: Range<u32>) {
+    let hash = repo.odb().hash();
+    let mut pack = b"PACK\0\0\0\x02".to_vec();
+    pack.extend((range.len() as u32).to_be_bytes());
+    let mut entries = Vec::new();
+    for n in range {
+        let data = format!("{n}\n");
+        let offset = pack.len() as u32;
+        pack.push(0x30 | data.len() as u8);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data.as_bytes()).unwrap();
+        pack.extend(encoder.finish().unwrap());
+        let id = ObjectId::hash_object(hash, ObjectKind::Blob, data.as_bytes()).unwrap();
+        entries.push((id, offset));
+    }
+    let checksum = hash.digest(&pack).unwrap();
+    pack.extend(&checksum);
+    let name: String = checksum.iter().map(|b| format!("{b:02x}")).collect();
+    fs::create_dir_all(repo.path("objects/pack")).unwrap();
+    let base = repo.path(format!("objects/pack/pack-{name}"));
+    fs::write(base.with_extension("pack"), pack).unwrap();
+
+    // A version 2 index, CRCs left zero as reading does not check them.
+    entries.sort();
+    let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+    for first in 0..=255u8 {
+        let below = entries.partition_point(|(id, _)| id.as_bytes()[0] <= first);
+        idx.extend((below as u32).to_be_bytes());
+    }
+    for (id, _) in &entries {
+        idx.extend_from_slice(id.as_bytes());
+    }
+    idx.extend(vec![0; entries.len() * 4]);
+    for (_, offset) in &entries {
+        idx.extend(offset.to_be_bytes());
+    }
+    idx.extend(&checksum);
+    idx.extend(hash.digest(&idx).unwrap());
+    fs::write(base.with_extension("idx"), idx).unwrap();
+}
+
+fn rev_parse(dir: &Path, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .arg("rev-parse")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// The hint lines naming the objects an ambiguous `prefix` could be.
+fn hints(dir: &Path, prefix: &str) -> String {
+    let output = rev_parse(dir, &[prefix]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    stderr
+        .lines()
+        .filter(|line| line.starts_with("hint:   "))
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// Sets `core.abbrev`, for the repository as opened afterwards.
+fn set_abbrev(repo: &Repository, value: &str) {
+    let path = repo.path("config");
+    let mut config = Config::load(&path).unwrap();
+    config.set("core.abbrev", value).unwrap();
+    config.write(&path).unwrap();
+}
+
+/// Expected outputs are git's, for the same objects packed the same way.
+#[test]
+fn abbreviations_grow_with_packed_objects() {
+    let (dir, repo) = common::scratch_repo("revision-abbrev", HashAlgorithm::Sha1);
+    let tree = common::tree(&repo, &[(MODE_BLOB, "a", common::blob(&repo, b"a\n"))]);
+    let head = common::commit(&repo, tree, &[], T0, "c\n");
+    refs::update(&repo, "refs/heads/master", &head).unwrap();
+    // Loose objects are not counted.
+    assert_eq!(revision::abbrev_len(&repo).unwrap(), 7);
+
+    // 8300 objects take 14 bits to count: still seven digits.
+    pack_numbers(&repo, 0..8300);
+    let repo = Repository::open(&dir).unwrap();
+    assert_eq!(repo.odb().approximate_count(), 8300);
+    assert_eq!(revision::abbrev_len(&repo).unwrap(), 7);
+    assert_eq!(rev_parse(&dir, &["--short", "HEAD"]).stdout, b"6b612f1\n");
+    assert_eq!(
+        hints(&dir, "6b61"),
+        "hint:   6b612f1 commit 2023-11-14 - c\n\
+         hint:   6b61c08 blob\n"
+    );
+    assert_eq!(
+        hints(&dir, "cf41"),
+        "hint:   cf41362 blob\n\
+         hint:   cf41364 blob\n\
+         hint:   cf415a7 blob\n\
+         hint:   cf41a14 blob\n"
+    );
+
+    // 33000 take 16: eight.
+    pack_numbers(&repo, 8300..33_000);
+    let repo = Repository::open(&dir).unwrap();
+    assert_eq!(repo.odb().approximate_count(), 33_000);
+    assert_eq!(revision::abbrev_len(&repo).unwrap(), 8);
+    assert_eq!(rev_parse(&dir, &["--short", "HEAD"]).stdout, b"6b612f17\n");
+    assert_eq!(
+        hints(&dir, "6b61"),
+        "hint:   6b612f17 commit 2023-11-14 - c\n\
+         hint:   6b616228 blob\n\
+         hint:   6b61c08d blob\n"
+    );
+    assert_eq!(
+        hints(&dir, "cf41"),
+        "hint:   cf41362d blob\n\
+         hint:   cf41364a blob\n\
+         hint:   cf415a7f blob\n\
+         hint:   cf419ec2 blob\n\
+         hint:   cf41a14a blob\n"
+    );
+
+    // A configured length is where abbreviations start; they still grow
+    // until they name one object.
+    set_abbrev(&repo, "5");
+    assert_eq!(rev_parse(&dir, &["--short", "HEAD"]).stdout, b"6b612\n");
+    assert_eq!(
+        hints(&dir, "cf41"),
+        "hint:   cf41362 blob\n\
+         hint:   cf41364 blob\n\
+         hint:   cf415 blob\n\
+         hint:   cf419 blob\n\
+         hint:   cf41a blob\n"
+    );
+    assert_eq!(revision::abbreviate(&repo, &head, 4).unwrap(), "6b612");
+    set_abbrev(&repo, "false");
+    assert_eq!(
+        rev_parse(&dir, &["--short", "HEAD"]).stdout,
+        format!("{head}\n").as_bytes()
+    );
+    set_abbrev(&repo, "3");
+    assert!(matches!(
+        revision::abbrev_len(&Repository::open(&dir).unwrap()),
+        Err(Error::Config(_))
+    ));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reflog_selectors() {
+    let (dir, repo) = common::scratch_repo("revision-reflog", HashAlgorithm::Sha1);
+    let h = history(&repo);
+    for (name, expected) in [
+        ("master@{0}", h.merge),
+        ("master@{1}", h.c3),
+        ("master@{3}", h.c1),
+        ("@{1}", h.c3),
+        ("@{2}~1", h.c1),
+        // The last entry made at or before the date.
+        ("master@{2023-11-14 22:18:30 +0000}", h.c2),
+        ("master@{2023-11-14T22:20:00Z}", h.c3),
+        (&format!("master@{{@{}}}", T0 + 500), h.merge),
+        // Older than the log: where it started.
+        ("master@{2000-01-01}", h.c1),
+        ("@{-1}", h.s1),
+        // Branches checked out before: as they are now.
+        ("@{-2}", h.merge),
+    ] {
+        assert_eq!(find(&repo, name), expected, "{name}");
+    }
+    let err = revision::find(&repo, "master@{4}", None, false).unwrap_err();
+    assert!(matches!(err, Error::Usage(ref m) if m.contains("only has 4 entries")));
+    assert!(revision::find(&repo, "side@{0}", None, false).is_err());
+    assert!(revision::find(&repo, "@{-3}", None, false).is_err());
+    assert!(revision::find(&repo, "master@{not a date}", None, false).is_err());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn regex_syntax() {
+    for (pattern, text, matches) in [
+        ("abc", "xabcx", true),
+        ("^abc", "abcd", true),
+        ("^abc", "xabc", false),
+        ("abc$", "xabc", true),
+        ("abc$", "abcx", false),
+        ("^$", "", true),
+        // No REG_NEWLINE: `.` takes newlines, anchors only the ends.
+        ("a.c", "a\nc", true),
+        ("^b", "a\nb", false),
+        ("colou?r", "color", true),
+        ("colou?r", "colour", true),
+        ("ab+c", "ac", false),
+        ("ab*c", "ac", true),
+        ("(fix|feat):", "feat: x", true),
+        ("(fix|feat):", "fox:", false),
+        ("x(ab)+y", "xababy", true),
+        ("[[:digit:]]{3}", "ab123", true),
+        ("x[[:digit:]]{3}", "x12", false),
+        ("^a{2,}$", "aaa", true),
+        ("^a{2,3}$", "aaaa", false),
+        ("^a{2}$", "aa", true),
+        ("a{,2}", "a{,2}", true),
+        ("a{", "a{", true),
+        ("[^a-z]", "abc", false),
+        ("[^a-z]", "abC", true),
+        ("[]a]", "]", true),
+        ("[a-]", "-", true),
+        ("[[:space:]]", "a\tb", true),
+        ("a\\.b", "a.b", true),
+        ("a\\.b", "axb", false),
+        ("\\(x\\)", "(x)", true),
+        ("\u{e9}t\u{e9}", "\u{e9}t\u{e9}", true),
+    ] {
+        let regex = Regex::new(pattern).unwrap_or_else(|e| panic!("{pattern}: {e}"));
+        assert_eq!(
+            regex.is_match(text.as_bytes()),
+            matches,
+            "{pattern} on {text:?}"
+        );
+    }
+    for pattern in [
+        "(",
+        "a)",
+        "[a",
+        "*a",
+        "a|+",
+        "a{3,1}",
+        "a{256}",
+        "[[:nope:]]",
+        "[z-a]",
+        "a\\",
+    ] {
+        assert!(Regex::new(pattern).is_err(), "{pattern}");
+    }
+}
+
+#[test]
+fn regex_matching_takes_linear_time() {
+    let text = "a".repeat(20_000);
+    for pattern in ["(a*)*b", "(a|aa)*c", "(a?){100}a{100}b"] {
+        assert!(!Regex::new(pattern).unwrap().is_match(text.as_bytes()));
+    }
+    assert!(Regex::new("(a|aa)*$").unwrap().is_match(text.as_bytes()));
+}
+
+#[test]
+fn absolute_dates() {
+    let now = T0;
+    let time_of_day = now.rem_euclid(DAY);
+    let day = |y, m, d| date::days_from_civil(y, m, d) * DAY;
+    for (text, expected) in [
+        ("now", Some(now)),
+        ("Yesterday", Some(now - DAY)),
+        ("@123", Some(123)),
+        ("1234567890", Some(1_234_567_890)),
+        // A date alone keeps the time of day of now.
+        ("2024-02-29", Some(day(2024, 2, 29) + time_of_day)),
+        ("2024-01-02 03:04:05", Some(day(2024, 1, 2) + 11_045)),
+        ("2024-01-02T03:04:05Z", Some(day(2024, 1, 2) + 11_045)),
+        (
+            "2024-01-02 03:04 +01:30",
+            Some(day(2024, 1, 2) + 11_040 - 5_400),
+        ),
+        (
+            "2024-01-02 03:04:05 -0800",
+            Some(day(2024, 1, 2) + 11_045 + 28_800),
+        ),
+        ("2023-02-29", None),
+        ("2023-13-01", None),
+        ("2024-01-02 24:00", None),
+        ("2024-01-02 03:04 +1", None),
+        ("12345", None),
+        ("next tuesday", None),
+        ("", None),
+    ] {
+        assert_eq!(date::parse(text, now), expected, "{text:?}");
+    }
+}
+
+#[test]
+fn relative_dates() {
+    // 2024-05-31 12:00 UTC.
+    let now = date::days_from_civil(2024, 5, 31) * DAY + 12 * 3600;
+    let at = |y, m, d| date::days_from_civil(y, m, d) * DAY + 12 * 3600;
+    for (text, expected) in [
+        ("2.weeks.ago", Some(now - 14 * DAY)),
+        ("1 day 3 hours ago", Some(now - DAY - 3 * 3600)),
+        ("90 seconds ago", Some(now - 90)),
+        ("5.minutes", Some(now - 300)),
+        // Months keep the day, clamped to the month reached.
+        ("3 months ago", Some(at(2024, 2, 29))),
+        ("1.month.ago", Some(at(2024, 4, 30))),
+        ("1 year 3 months ago", Some(at(2023, 2, 28))),
+        ("ago", None),
+        ("3 fortnights ago", None),
+        ("3", None),
+    ] {
+        assert_eq!(date::parse(text, now), expected, "{text:?}");
+    }
+}
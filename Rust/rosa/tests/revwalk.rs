@@ -0,0 +1,273 @@
+//! Walks as rev-list makes them, over a history with a merge, an octopus
+//! merge and a commit dated before its parent; expected outputs are git's
+//! for the same objects. Tips on the command line are commits first: an id
+//! prefix shared with a blob or tree must still name the commit.
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+
+use rosa::error::Result;
+use rosa::object::{HashAlgorithm, MODE_BLOB};
+use rosa::revwalk::{Order, RevWalk, WalkOptions};
+use rosa::{refs, revision, Error, ObjectId, ObjectKind, Repository};
+
+fn walk(repo: &Repository, args: &[&str]) -> Result<Vec<ObjectId>> {
+    let mut walk = RevWalk::new(repo, WalkOptions::default())?;
+    walk.add_args(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())?;
+    walk.collect()
+}
+
+#[test]
+fn ambiguous_prefixes_pick_the_commit() {
+    let (dir, repo) = common::scratch_repo("revwalk-prefix", HashAlgorithm::Sha1);
+    let tree = common::tree(&repo, &[(MODE_BLOB, "a", common::blob(&repo, b"a\n"))]);
+    let first = common::commit(&repo, tree, &[], 1_700_000_000, "first\n");
+    let second = common::commit(&repo, tree, &[first], 1_700_000_100, "second\n");
+
+    // A blob whose id starts like the second commit's.
+    let prefix = &second.to_hex()[..4];
+    let data = (0u32..)
+        .map(|n| format!("{n}\n").into_bytes())
+        .find(|data| {
+            let id = ObjectId::hash_object(HashAlgorithm::Sha1, ObjectKind::Blob, data).unwrap();
+            id.to_hex().starts_with(prefix)
+        })
+        .unwrap();
+    common::blob(&repo, &data);
+
+    let err = revision::find(&repo, prefix, None, false).unwrap_err();
+    assert!(matches!(err, Error::AmbiguousId { .. }));
+    assert_eq!(
+        revision::find(&repo, prefix, Some(ObjectKind::Commit), false).unwrap(),
+        second
+    );
+
+    assert_eq!(walk(&repo, &[prefix]).unwrap(), [second, first]);
+    let range = format!("{first}..{prefix}");
+    assert_eq!(walk(&repo, &[&range]).unwrap(), [second]);
+    let hidden = format!("^{prefix}");
+    assert_eq!(walk(&repo, &[&second.to_hex(), &hidden]).unwrap(), []);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// 2023-11-14 22:13:20 UTC.
+const T0: i64 = 1_700_000_000;
+
+/// ```text
+///               s1 ---- s2            (side)
+///              /          \
+/// a1 - a2 --- a3 - a4 ---- m1 - a5 - oct    (master)
+///   \          \    \                /  /
+///    \          \    y1 (y) --------'  /
+///     \          x1 (x) --------------'
+///      l1 (tag lone)
+/// ```
+///
+/// with each commit made the listed number of hundreds of seconds after
+/// [`T0`] (`y1` before its parent), holding one file named after it, and
+/// `v1` a tag of `a3`. Returns every object by name: commits by their own,
+/// their trees and blobs as `tree:` and `blob:` followed by it.
+fn history(repo: &Repository) -> HashMap<String, ObjectId> {
+    let spec: [(&str, &[&str], i64); 12] = [
+        ("a1", &[], 1),
+        ("a2", &["a1"], 2),
+        ("s1", &["a2"], 3),
+        ("a3", &["a2"], 4),
+        ("a4", &["a3"], 5),
+        ("s2", &["s1"], 6),
+        ("m1", &["a4", "s2"], 7),
+        ("y1", &["a4"], 1),
+        ("l1", &["a1"], 8),
+        ("x1", &["a3"], 9),
+        ("a5", &["m1"], 10),
+        ("oct", &["a5", "x1", "y1"], 11),
+    ];
+    let mut ids = HashMap::new();
+    for (name, parents, n) in spec {
+        let blob = common::blob(repo, format!("{name}\n").as_bytes());
+        let tree = common::tree(repo, &[(MODE_BLOB, &format!("{name}.txt"), blob)]);
+        let parents: Vec<ObjectId> = parents.iter().map(|p| ids[*p]).collect();
+        let commit = common::commit(repo, tree, &parents, T0 + n * 100, &format!("{name}\n"));
+        ids.insert(format!("blob:{name}"), blob);
+        ids.insert(format!("tree:{name}"), tree);
+        ids.insert(name.to_owned(), commit);
+    }
+    for (name, commit) in [
+        ("refs/heads/master", "oct"),
+        ("refs/heads/side", "s2"),
+        ("refs/heads/x", "x1"),
+        ("refs/heads/y", "y1"),
+        ("refs/tags/v1", "a3"),
+        ("refs/tags/lone", "l1"),
+    ] {
+        refs::update(repo, name, &ids[commit]).unwrap();
+    }
+    ids
+}
+
+/// What `mygit rev-list` prints for `args`, with the commit names of
+/// [`history`] in them spelled as ids going in and coming back out, one
+/// line after another separated by ` | `.
+fn rev_list(dir: &Path, ids: &HashMap<String, ObjectId>, args: &[&str]) -> String {
+    let id_of = |name: &str| ids.get(name).map_or(name.to_owned(), |id| id.to_hex());
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| match arg.strip_prefix('^') {
+            Some(name) => format!("^{}", id_of(name)),
+            None => arg.split("..").map(id_of).collect::<Vec<_>>().join(".."),
+        })
+        .collect();
+    let output = Command::new(env!("CARGO_BIN_EXE_mygit"))
+        .arg("rev-list")
+        .args(&args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{args:?}: {output:?}");
+    let names: HashMap<String, &str> = ids
+        .iter()
+        .map(|(name, id)| (id.to_hex(), &name[..]))
+        .collect();
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| match line.split_once(' ') {
+            Some((id, path)) => format!("{} {path}", names[id]),
+            None => names
+                .get(line)
+                .map_or(line.to_owned(), |name| name.to_string()),
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+#[test]
+fn ranges_and_exclusions() {
+    let (dir, repo) = common::scratch_repo("revwalk-ranges", HashAlgorithm::Sha1);
+    let ids = history(&repo);
+    for (args, expected) in [
+        (
+            &["master"][..],
+            "oct | a5 | x1 | m1 | s2 | a4 | a3 | s1 | a2 | y1 | a1",
+        ),
+        (&["side..master"], "oct | a5 | x1 | m1 | a4 | a3 | y1"),
+        (&["master..side"], ""),
+        (&["side...x"], "x1 | s2 | a3 | s1"),
+        (&["master", "^a4"], "oct | a5 | x1 | m1 | s2 | s1 | y1"),
+        (&["x", "--not", "side", "y"], "x1"),
+        (
+            &["--all"],
+            "oct | a5 | x1 | l1 | m1 | s2 | a4 | a3 | s1 | a2 | y1 | a1",
+        ),
+        (
+            &["--branches"],
+            "oct | a5 | x1 | m1 | s2 | a4 | a3 | s1 | a2 | y1 | a1",
+        ),
+        (&["--count", "master"], "11"),
+        (&["--count", "side...master"], "7"),
+    ] {
+        assert_eq!(rev_list(&dir, &ids, args), expected, "{args:?}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn orders_and_limits() {
+    let (dir, repo) = common::scratch_repo("revwalk-orders", HashAlgorithm::Sha1);
+    let ids = history(&repo);
+    for (args, expected) in [
+        (
+            &["--topo-order", "master"][..],
+            "oct | y1 | x1 | a5 | m1 | s2 | s1 | a4 | a3 | a2 | a1",
+        ),
+        // y1 still comes before its parent, unlike in the default order.
+        (
+            &["--date-order", "master"],
+            "oct | a5 | x1 | m1 | s2 | s1 | y1 | a4 | a3 | a2 | a1",
+        ),
+        (
+            &["--reverse", "master"],
+            "a1 | y1 | a2 | s1 | a3 | a4 | s2 | m1 | x1 | a5 | oct",
+        ),
+        (
+            &["--topo-order", "--reverse", "a2..master"],
+            "a3 | a4 | s1 | s2 | m1 | a5 | x1 | y1 | oct",
+        ),
+        (
+            &["--first-parent", "master"],
+            "oct | a5 | m1 | a4 | a3 | a2 | a1",
+        ),
+        (&["--max-count=3", "master"], "oct | a5 | x1"),
+        (&["-2", "--topo-order", "master"], "oct | y1"),
+        // The count applies before reversing.
+        (
+            &["--first-parent", "--reverse", "--max-count=2", "master"],
+            "a5 | oct",
+        ),
+        (&["--ancestry-path", "s1..master"], "oct | a5 | m1 | s2"),
+        (
+            &["--ancestry-path", "--first-parent", "a2..master"],
+            "oct | a5 | m1 | a4 | a3",
+        ),
+    ] {
+        assert_eq!(rev_list(&dir, &ids, args), expected, "{args:?}");
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn objects_follow_their_commits() {
+    let (dir, repo) = common::scratch_repo("revwalk-objects", HashAlgorithm::Sha1);
+    let ids = history(&repo);
+    assert_eq!(
+        rev_list(&dir, &ids, &["--objects", "a4..master"]),
+        "oct | a5 | x1 | m1 | s2 | s1 | y1 | \
+         tree:oct  | blob:oct oct.txt | tree:a5  | blob:a5 a5.txt | \
+         tree:x1  | blob:x1 x1.txt | tree:m1  | blob:m1 m1.txt | \
+         tree:s2  | blob:s2 s2.txt | tree:s1  | blob:s1 s1.txt | \
+         tree:y1  | blob:y1 y1.txt"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// A chain far longer than a recursive walk could follow on a small stack.
+#[test]
+fn deep_histories_are_walked_iteratively() {
+    let (dir, repo) = common::scratch_repo("revwalk-deep", HashAlgorithm::Sha1);
+    let tree = common::tree(&repo, &[(MODE_BLOB, "a", common::blob(&repo, b"a\n"))]);
+    let mut chain = Vec::new();
+    for n in 0..3000 {
+        let parents: Vec<ObjectId> = chain.last().copied().into_iter().collect();
+        chain.push(common::commit(&repo, tree, &parents, T0 + n, "c\n"));
+    }
+    let tip = chain.last().unwrap().to_hex();
+    chain.reverse();
+    let walked = thread::Builder::new()
+        .stack_size(256 << 10)
+        .spawn(move || {
+            let repo = Repository::open(&dir).unwrap();
+            let all = walk(&repo, &[&tip]).unwrap();
+            let mut walk = RevWalk::new(
+                &repo,
+                WalkOptions {
+                    order: Order::Topo,
+                    ..WalkOptions::default()
+                },
+            )
+            .unwrap();
+            walk.add_args(&[tip]).unwrap();
+            let topo: Vec<ObjectId> = walk.collect::<Result<_>>().unwrap();
+            fs::remove_dir_all(dir).unwrap();
+            (all, topo)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(walked.0, chain);
+    assert_eq!(walked.1, chain);
+}